@@ -0,0 +1,223 @@
+//! Helpers for calling the BeanCounter service: deadlines, retries, and
+//! reading health checks.
+use crate::error::BeanCounterError;
+use crate::proto::{
+    health_check_response, DeepCheckResponse, DependencyStatus, HealthCheckResponse,
+};
+use crate::tower_grpc::metadata::MetadataValue;
+use crate::tower_grpc::Request;
+use std::time::Duration;
+
+/// Format a timeout for the grpc-timeout header, in the finest unit which
+/// fits its 8 digit limit.
+pub fn format_grpc_timeout(timeout: Duration) -> String {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    [
+        (1, "n"),
+        (1_000, "u"),
+        (1_000_000, "m"),
+        (1_000_000_000, "S"),
+        (60_000_000_000, "M"),
+        (3_600_000_000_000, "H"),
+    ]
+    .iter()
+    .find(|&&(per_unit, _)| (nanos + per_unit - 1) / per_unit <= MAX)
+    .map(|&(per_unit, unit)| format!("{}{}", (nanos + per_unit - 1) / per_unit, unit))
+    .unwrap_or_else(|| format!("{}H", MAX))
+}
+
+/// Tell the service when the caller will give up on the request, so it stops
+/// working on it then too.
+pub fn with_deadline<T>(mut request: Request<T>, timeout: Duration) -> Request<T> {
+    if let Ok(value) = MetadataValue::from_str(&format_grpc_timeout(timeout)) {
+        request.metadata_mut().insert("grpc-timeout", value);
+    }
+    request
+}
+
+/// How many times to try a request, and how long to wait between attempts.
+/// The wait doubles after each attempt, up to `max_backoff`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait after the given attempt, counting from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << std::cmp::min(attempt.saturating_sub(1), 16);
+        std::cmp::min(self.initial_backoff * factor, self.max_backoff)
+    }
+
+    /// How long to wait before trying again after the given attempt failed,
+    /// or `None` if the request shouldn't be retried
+    pub fn retry_after(&self, attempt: u32, err: &BeanCounterError) -> Option<Duration> {
+        if attempt < self.max_attempts && err.is_retryable() {
+            Some(self.backoff(attempt))
+        } else {
+            None
+        }
+    }
+
+    /// Run a blocking request until it succeeds, fails with an error which
+    /// isn't retryable, or runs out of attempts.
+    pub fn run<T, F>(&self, mut f: F) -> Result<T, BeanCounterError>
+    where
+        F: FnMut() -> Result<T, BeanCounterError>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Ok(result) => return Ok(result),
+                Err(err) => match self.retry_after(attempt, &err) {
+                    Some(backoff) => std::thread::sleep(backoff),
+                    None => return Err(err),
+                },
+            }
+            attempt += 1;
+        }
+    }
+}
+
+impl HealthCheckResponse {
+    pub fn is_serving(&self) -> bool {
+        self.status == health_check_response::ServingStatus::Serving as i32
+    }
+}
+
+impl DeepCheckResponse {
+    pub fn is_serving(&self) -> bool {
+        self.status == health_check_response::ServingStatus::Serving as i32
+    }
+
+    /// The dependencies which failed their checks
+    pub fn unhealthy(&self) -> impl Iterator<Item = &DependencyStatus> {
+        self.dependencies
+            .iter()
+            .filter(|dependency| !dependency.healthy)
+    }
+}
+
+/// Tracks the results of repeated health checks, and only reports a change in
+/// health once it has lasted `threshold` checks in a row, so a single failed
+/// check doesn't take the service out of rotation.
+#[derive(Clone, Debug)]
+pub struct HealthWatch {
+    threshold: u32,
+    serving: Option<bool>,
+    streak: u32,
+}
+
+impl HealthWatch {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: std::cmp::max(threshold, 1),
+            serving: None,
+            streak: 0,
+        }
+    }
+
+    /// Whether the service is serving, or `None` before enough checks
+    pub fn serving(&self) -> Option<bool> {
+        self.serving
+    }
+
+    /// Record a check, returning the new health if it changed
+    pub fn observe(&mut self, serving: bool) -> Option<bool> {
+        if self.serving == Some(serving) {
+            self.streak = 0;
+            return None;
+        }
+        self.streak += 1;
+        if self.streak < self.threshold {
+            return None;
+        }
+        self.streak = 0;
+        self.serving = Some(serving);
+        self.serving
+    }
+
+    /// Record the result of a health check call. Errors count as not serving.
+    pub fn observe_response<E>(&mut self, result: &Result<HealthCheckResponse, E>) -> Option<bool> {
+        self.observe(
+            result
+                .as_ref()
+                .map_or(false, HealthCheckResponse::is_serving),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_grpc_timeout() {
+        assert_eq!(format_grpc_timeout(Duration::from_nanos(500)), "500n");
+        assert_eq!(format_grpc_timeout(Duration::from_millis(250)), "250000u");
+        assert_eq!(format_grpc_timeout(Duration::from_secs(30)), "30000000u");
+        assert_eq!(format_grpc_timeout(Duration::from_secs(300)), "300000m");
+        assert_eq!(
+            format_grpc_timeout(Duration::from_secs(86_400 * 365)),
+            "31536000S"
+        );
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(10), Duration::from_secs(2));
+
+        assert_eq!(
+            policy.retry_after(1, &BeanCounterError::SerializationFailure),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.retry_after(3, &BeanCounterError::SerializationFailure),
+            None
+        );
+        assert_eq!(
+            policy.retry_after(1, &BeanCounterError::InsufficientBalance),
+            None
+        );
+
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let mut attempts = 0;
+        let result: Result<(), _> = policy.run(|| {
+            attempts += 1;
+            Err(BeanCounterError::ReadOnly)
+        });
+        assert_eq!(result, Err(BeanCounterError::ReadOnly));
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_health_watch() {
+        let mut watch = HealthWatch::new(2);
+        assert_eq!(watch.observe(true), None);
+        assert_eq!(watch.observe(true), Some(true));
+        assert_eq!(watch.observe(false), None);
+        assert_eq!(watch.observe(true), None);
+        assert_eq!(watch.observe(false), None);
+        assert_eq!(watch.observe(false), Some(false));
+        assert_eq!(watch.serving(), Some(false));
+    }
+}
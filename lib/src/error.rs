@@ -0,0 +1,256 @@
+//! Errors returned by the BeanCounter service, parsed from the gRPC status.
+//! The variants match the service's `RequestError`, so callers can match on
+//! them rather than on error messages.
+use crate::tower_grpc::{Code, Status};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BeanCounterError {
+    NotFound,
+    DatabaseError {
+        err: String,
+    },
+    InvalidUuid {
+        err: String,
+    },
+    BadArguments,
+    StripeError {
+        err: String,
+    },
+    InsufficientBalance,
+    InvalidDestination {
+        err: String,
+    },
+    ReadOnly,
+    DeadlineExceeded,
+    AlreadyReversed,
+    Unauthenticated {
+        err: String,
+    },
+    PermissionDenied {
+        err: String,
+    },
+    InvalidCurrency {
+        err: String,
+    },
+    SerializationFailure,
+    LedgerLocked,
+    Denied,
+    /// Any other status, including transport errors from the client itself
+    Other {
+        code: Code,
+        message: String,
+    },
+}
+
+const NOT_FOUND: &str = "not found";
+const DATABASE_ERROR: &str = "database error: ";
+const INVALID_UUID: &str = "invalid UUID: ";
+const BAD_ARGUMENTS: &str = "Bad arguments specified for request";
+const STRIPE_ERROR: &str = "stripe error: ";
+const INSUFFICIENT_BALANCE: &str = "insufficient balance";
+const INVALID_DESTINATION: &str = "invalid payout destination: ";
+const READ_ONLY: &str = "service is in read-only mode for maintenance, try again later";
+const DEADLINE_EXCEEDED: &str = "deadline exceeded";
+const ALREADY_REVERSED: &str = "operation has already been reversed";
+const UNAUTHENTICATED: &str = "unauthenticated: ";
+const PERMISSION_DENIED: &str = "permission denied: ";
+const INVALID_CURRENCY: &str = "invalid currency: ";
+const SERIALIZATION_FAILURE: &str = "conflicting concurrent update, try again";
+const LEDGER_LOCKED: &str = "client's ledger is locked for maintenance, try again later";
+const DENIED: &str = "client is on the denylist";
+
+impl BeanCounterError {
+    /// Whether the same request may succeed if it's sent again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BeanCounterError::ReadOnly | BeanCounterError::SerializationFailure => true,
+            BeanCounterError::Other { code, .. } => *code == Code::Unavailable,
+            _ => false,
+        }
+    }
+
+    /// The status code the service returns for this error
+    pub fn code(&self) -> Code {
+        match self {
+            BeanCounterError::ReadOnly
+            | BeanCounterError::AlreadyReversed
+            | BeanCounterError::LedgerLocked
+            | BeanCounterError::Denied => Code::FailedPrecondition,
+            BeanCounterError::DeadlineExceeded => Code::DeadlineExceeded,
+            BeanCounterError::Unauthenticated { .. } => Code::Unauthenticated,
+            BeanCounterError::PermissionDenied { .. } => Code::PermissionDenied,
+            BeanCounterError::SerializationFailure => Code::Aborted,
+            BeanCounterError::Other { code, .. } => *code,
+            _ => Code::InvalidArgument,
+        }
+    }
+}
+
+impl fmt::Display for BeanCounterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BeanCounterError::NotFound => write!(f, "{}", NOT_FOUND),
+            BeanCounterError::DatabaseError { err } => write!(f, "{}{}", DATABASE_ERROR, err),
+            BeanCounterError::InvalidUuid { err } => write!(f, "{}{}", INVALID_UUID, err),
+            BeanCounterError::BadArguments => write!(f, "{}", BAD_ARGUMENTS),
+            BeanCounterError::StripeError { err } => write!(f, "{}{}", STRIPE_ERROR, err),
+            BeanCounterError::InsufficientBalance => write!(f, "{}", INSUFFICIENT_BALANCE),
+            BeanCounterError::InvalidDestination { err } => {
+                write!(f, "{}{}", INVALID_DESTINATION, err)
+            }
+            BeanCounterError::ReadOnly => write!(f, "{}", READ_ONLY),
+            BeanCounterError::DeadlineExceeded => write!(f, "{}", DEADLINE_EXCEEDED),
+            BeanCounterError::AlreadyReversed => write!(f, "{}", ALREADY_REVERSED),
+            BeanCounterError::Unauthenticated { err } => write!(f, "{}{}", UNAUTHENTICATED, err),
+            BeanCounterError::PermissionDenied { err } => {
+                write!(f, "{}{}", PERMISSION_DENIED, err)
+            }
+            BeanCounterError::InvalidCurrency { err } => write!(f, "{}{}", INVALID_CURRENCY, err),
+            BeanCounterError::SerializationFailure => write!(f, "{}", SERIALIZATION_FAILURE),
+            BeanCounterError::LedgerLocked => write!(f, "{}", LEDGER_LOCKED),
+            BeanCounterError::Denied => write!(f, "{}", DENIED),
+            BeanCounterError::Other { code, message } => write!(f, "{:?}: {}", code, message),
+        }
+    }
+}
+
+impl std::error::Error for BeanCounterError {}
+
+impl From<&Status> for BeanCounterError {
+    fn from(status: &Status) -> Self {
+        let message = status.message();
+        let detail = |prefix: &str| {
+            if message.starts_with(prefix) {
+                Some(message[prefix.len()..].to_string())
+            } else {
+                None
+            }
+        };
+
+        let parsed = match status.code() {
+            Code::FailedPrecondition if message == READ_ONLY => Some(BeanCounterError::ReadOnly),
+            Code::FailedPrecondition if message == ALREADY_REVERSED => {
+                Some(BeanCounterError::AlreadyReversed)
+            }
+            Code::FailedPrecondition if message == LEDGER_LOCKED => {
+                Some(BeanCounterError::LedgerLocked)
+            }
+            Code::FailedPrecondition if message == DENIED => Some(BeanCounterError::Denied),
+            // Also returned when the client gives up waiting
+            Code::DeadlineExceeded => Some(BeanCounterError::DeadlineExceeded),
+            Code::Unauthenticated => Some(BeanCounterError::Unauthenticated {
+                err: detail(UNAUTHENTICATED).unwrap_or_else(|| message.to_string()),
+            }),
+            Code::PermissionDenied => Some(BeanCounterError::PermissionDenied {
+                err: detail(PERMISSION_DENIED).unwrap_or_else(|| message.to_string()),
+            }),
+            Code::Aborted if message == SERIALIZATION_FAILURE => {
+                Some(BeanCounterError::SerializationFailure)
+            }
+            Code::InvalidArgument => match message {
+                NOT_FOUND => Some(BeanCounterError::NotFound),
+                BAD_ARGUMENTS => Some(BeanCounterError::BadArguments),
+                INSUFFICIENT_BALANCE => Some(BeanCounterError::InsufficientBalance),
+                _ => detail(DATABASE_ERROR)
+                    .map(|err| BeanCounterError::DatabaseError { err })
+                    .or_else(|| {
+                        detail(INVALID_UUID).map(|err| BeanCounterError::InvalidUuid { err })
+                    })
+                    .or_else(|| {
+                        detail(STRIPE_ERROR).map(|err| BeanCounterError::StripeError { err })
+                    })
+                    .or_else(|| {
+                        detail(INVALID_DESTINATION)
+                            .map(|err| BeanCounterError::InvalidDestination { err })
+                    })
+                    .or_else(|| {
+                        detail(INVALID_CURRENCY)
+                            .map(|err| BeanCounterError::InvalidCurrency { err })
+                    }),
+            },
+            _ => None,
+        };
+
+        parsed.unwrap_or_else(|| BeanCounterError::Other {
+            code: status.code(),
+            message: message.to_string(),
+        })
+    }
+}
+
+impl From<Status> for BeanCounterError {
+    fn from(status: Status) -> Self {
+        BeanCounterError::from(&status)
+    }
+}
+
+impl From<BeanCounterError> for Status {
+    fn from(err: BeanCounterError) -> Self {
+        match err {
+            BeanCounterError::Other { code, message } => Status::new(code, message),
+            _ => Status::new(err.code(), err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let errors = vec![
+            BeanCounterError::NotFound,
+            BeanCounterError::DatabaseError {
+                err: "connection refused".into(),
+            },
+            BeanCounterError::InvalidUuid {
+                err: "invalid length: expected one of [36, 32], found 3".into(),
+            },
+            BeanCounterError::BadArguments,
+            BeanCounterError::StripeError {
+                err: "card declined".into(),
+            },
+            BeanCounterError::InsufficientBalance,
+            BeanCounterError::InvalidDestination {
+                err: "no such account".into(),
+            },
+            BeanCounterError::ReadOnly,
+            BeanCounterError::DeadlineExceeded,
+            BeanCounterError::AlreadyReversed,
+            BeanCounterError::Unauthenticated {
+                err: "missing token".into(),
+            },
+            BeanCounterError::PermissionDenied {
+                err: "GetStats".into(),
+            },
+            BeanCounterError::InvalidCurrency { err: "XYZ".into() },
+            BeanCounterError::SerializationFailure,
+            BeanCounterError::LedgerLocked,
+            BeanCounterError::Denied,
+            BeanCounterError::Other {
+                code: Code::Unavailable,
+                message: "connection reset".into(),
+            },
+        ];
+
+        for err in errors {
+            assert_eq!(BeanCounterError::from(Status::from(err.clone())), err);
+        }
+    }
+
+    #[test]
+    fn test_unrecognized() {
+        let err = BeanCounterError::from(Status::new(Code::InvalidArgument, "something new"));
+        assert_eq!(
+            err,
+            BeanCounterError::Other {
+                code: Code::InvalidArgument,
+                message: "something new".into(),
+            }
+        );
+        assert!(!err.is_retryable());
+        assert!(BeanCounterError::ReadOnly.is_retryable());
+    }
+}
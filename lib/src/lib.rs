@@ -7,10 +7,40 @@ pub mod tower_grpc {
     pub use tower_grpc::*;
 }
 
+pub mod client;
+pub mod error;
+
+pub use error::BeanCounterError;
+
 pub mod proto {
     extern crate tower_grpc;
     include!(concat!(env!("OUT_DIR"), "/beancounter.rs"));
 
+    // Range of seconds allowed by the Timestamp spec, from
+    // 0001-01-01T00:00:00Z to 9999-12-31T23:59:59Z
+    const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+    const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+
+    impl Timestamp {
+        /// Returns `None` if the timestamp is outside the range allowed by the
+        /// spec, or its nanos aren't within 0..=999,999,999.
+        pub fn to_naive_date_time(&self) -> Option<chrono::NaiveDateTime> {
+            if self.seconds < MIN_TIMESTAMP_SECONDS
+                || self.seconds > MAX_TIMESTAMP_SECONDS
+                || self.nanos < 0
+                || self.nanos > 999_999_999
+            {
+                return None;
+            }
+            chrono::NaiveDateTime::from_timestamp_opt(self.seconds, self.nanos as u32)
+        }
+
+        pub fn to_date_time(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+            self.to_naive_date_time()
+                .map(|timestamp| chrono::DateTime::from_utc(timestamp, chrono::Utc))
+        }
+    }
+
     impl From<chrono::NaiveDateTime> for Timestamp {
         fn from(timestamp: chrono::NaiveDateTime) -> Self {
             Timestamp {
@@ -19,4 +49,124 @@ pub mod proto {
             }
         }
     }
+
+    impl From<chrono::DateTime<chrono::Utc>> for Timestamp {
+        fn from(timestamp: chrono::DateTime<chrono::Utc>) -> Self {
+            timestamp.naive_utc().into()
+        }
+    }
+
+    // Currencies without a minor unit, whose smallest unit is a whole unit
+    const ZERO_DECIMAL_CURRENCIES: &[&str] = &[
+        "BIF", "CLP", "DJF", "GNF", "JPY", "KMF", "KRW", "MGA", "PYG", "RWF", "UGX", "VND", "VUV",
+        "XAF", "XOF", "XPF",
+    ];
+
+    const NANOS_PER_UNIT: i64 = 1_000_000_000;
+
+    /// Whether the currency's smallest unit is a whole unit, i.e., yen
+    pub fn is_zero_decimal_currency(currency_code: &str) -> bool {
+        ZERO_DECIMAL_CURRENCIES.contains(&currency_code.to_uppercase().as_str())
+    }
+
+    impl Money {
+        /// An amount in the smallest unit of the currency, i.e., cents
+        pub fn from_minor_units(amount: i64, currency_code: &str) -> Self {
+            let per_unit = Self::minor_units_per_unit(currency_code);
+            Money {
+                currency_code: currency_code.to_uppercase(),
+                units: amount / per_unit,
+                nanos: ((amount % per_unit) * (NANOS_PER_UNIT / per_unit)) as i32,
+            }
+        }
+
+        /// Returns the amount in the smallest unit of the currency, or `None`
+        /// if it isn't valid per the spec, isn't a whole number of the
+        /// smallest unit, or overflows.
+        pub fn to_minor_units(&self) -> Option<i64> {
+            if self.nanos <= -NANOS_PER_UNIT as i32
+                || self.nanos >= NANOS_PER_UNIT as i32
+                || (self.units > 0 && self.nanos < 0)
+                || (self.units < 0 && self.nanos > 0)
+            {
+                return None;
+            }
+            let per_unit = Self::minor_units_per_unit(&self.currency_code);
+            let nanos_per_minor_unit = NANOS_PER_UNIT / per_unit;
+            if i64::from(self.nanos) % nanos_per_minor_unit != 0 {
+                return None;
+            }
+            self.units
+                .checked_mul(per_unit)?
+                .checked_add(i64::from(self.nanos) / nanos_per_minor_unit)
+        }
+
+        /// The upper case currency code, with empty meaning USD
+        pub fn currency(&self) -> String {
+            if self.currency_code.is_empty() {
+                "USD".into()
+            } else {
+                self.currency_code.to_uppercase()
+            }
+        }
+
+        fn minor_units_per_unit(currency_code: &str) -> i64 {
+            if is_zero_decimal_currency(currency_code) {
+                1
+            } else {
+                100
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::*;
+
+    #[test]
+    fn test_money_minor_units() {
+        let usd = Money::from_minor_units(1234, "usd");
+        assert_eq!(usd.currency_code, "USD");
+        assert_eq!(usd.units, 12);
+        assert_eq!(usd.nanos, 340_000_000);
+        assert_eq!(usd.to_minor_units(), Some(1234));
+
+        let negative = Money::from_minor_units(-1234, "USD");
+        assert_eq!(negative.units, -12);
+        assert_eq!(negative.nanos, -340_000_000);
+        assert_eq!(negative.to_minor_units(), Some(-1234));
+
+        let yen = Money::from_minor_units(500, "JPY");
+        assert_eq!(yen.units, 500);
+        assert_eq!(yen.nanos, 0);
+        assert_eq!(yen.to_minor_units(), Some(500));
+
+        // An empty currency is USD
+        let cents = Money {
+            currency_code: String::new(),
+            units: 0,
+            nanos: 50_000_000,
+        };
+        assert_eq!(cents.to_minor_units(), Some(5));
+        assert_eq!(cents.currency(), "USD");
+    }
+
+    #[test]
+    fn test_money_invalid() {
+        let money = |currency_code: &str, units: i64, nanos: i32| Money {
+            currency_code: currency_code.into(),
+            units,
+            nanos,
+        };
+        // Fractions of a cent or a yen
+        assert_eq!(money("USD", 1, 5_000_000).to_minor_units(), None);
+        assert_eq!(money("JPY", 1, 500_000_000).to_minor_units(), None);
+        // Nanos out of range, or of the wrong sign
+        assert_eq!(money("USD", 0, 1_000_000_000).to_minor_units(), None);
+        assert_eq!(money("USD", 1, -10_000_000).to_minor_units(), None);
+        assert_eq!(money("USD", -1, 10_000_000).to_minor_units(), None);
+        // Overflow
+        assert_eq!(money("USD", std::i64::MAX, 0).to_minor_units(), None);
+    }
 }
@@ -0,0 +1,22 @@
+CREATE TABLE stripe_connect_destinations (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  stripe_user_id TEXT NOT NULL,
+  is_primary BOOLEAN NOT NULL DEFAULT FALSE,
+  split_percent INTEGER NOT NULL DEFAULT 0 CHECK (split_percent >= 0 AND split_percent <= 100),
+  UNIQUE (client_id, stripe_user_id));
+
+CREATE UNIQUE INDEX stripe_connect_destinations_primary_idx
+  ON stripe_connect_destinations (client_id)
+  WHERE is_primary;
+
+SELECT diesel_manage_updated_at('stripe_connect_destinations');
+
+-- Every existing connected account becomes the primary destination for its
+-- client.
+INSERT INTO stripe_connect_destinations (client_id, stripe_user_id, is_primary)
+SELECT client_id, stripe_user_id, TRUE
+FROM stripe_connect_accounts
+WHERE stripe_user_id IS NOT NULL;
@@ -0,0 +1,5 @@
+DROP TABLE settlement_stats;
+
+DROP TABLE payment_outcomes;
+
+DROP TYPE payment_outcome;
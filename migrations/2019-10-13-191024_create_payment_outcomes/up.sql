@@ -0,0 +1,29 @@
+CREATE TYPE PAYMENT_OUTCOME AS ENUM (
+  'settled',
+  'expired'
+);
+
+CREATE TABLE payment_outcomes (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  payment_created_at TIMESTAMP NOT NULL,
+  client_id_from UUID NOT NULL,
+  client_id_to UUID NOT NULL,
+  payment_cents INTEGER NOT NULL,
+  is_promo BOOLEAN NOT NULL,
+  outcome PAYMENT_OUTCOME NOT NULL);
+
+CREATE INDEX payment_outcomes_payment_created_at_idx ON payment_outcomes (payment_created_at);
+
+CREATE TABLE settlement_stats (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ds DATE UNIQUE NOT NULL,
+  payment_count BIGINT NOT NULL,
+  settled_count BIGINT NOT NULL,
+  expired_count BIGINT NOT NULL,
+  pending_count BIGINT NOT NULL,
+  median_settlement_seconds DOUBLE PRECISION);
+
+SELECT diesel_manage_updated_at('settlement_stats');
@@ -0,0 +1,4 @@
+ALTER TABLE stripe_connect_accounts
+  DROP COLUMN requirements_currently_due,
+  DROP COLUMN requirements_disabled_reason,
+  DROP COLUMN requirements_updated_at
@@ -0,0 +1,4 @@
+ALTER TABLE stripe_connect_accounts
+  ADD COLUMN requirements_currently_due TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN requirements_disabled_reason TEXT,
+  ADD COLUMN requirements_updated_at TIMESTAMP
@@ -0,0 +1,3 @@
+DROP TABLE payment_refunds;
+
+DROP TYPE refund_reason;
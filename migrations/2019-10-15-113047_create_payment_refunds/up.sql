@@ -0,0 +1,21 @@
+CREATE TYPE REFUND_REASON AS ENUM (
+  'expired'
+);
+
+CREATE TABLE payment_refunds (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  payment_id BIGINT UNIQUE NOT NULL,
+  payment_created_at TIMESTAMP NOT NULL,
+  client_id_from UUID NOT NULL,
+  client_id_to UUID NOT NULL,
+  payment_cents INTEGER NOT NULL,
+  message_hash TEXT NOT NULL,
+  is_promo BOOLEAN NOT NULL,
+  reason REFUND_REASON NOT NULL,
+  cron_run_id UUID NOT NULL,
+  transaction_id BIGINT NOT NULL REFERENCES transactions (id));
+
+CREATE INDEX payment_refunds_client_id_from_idx ON payment_refunds (client_id_from);
+
+CREATE INDEX payment_refunds_cron_run_id_idx ON payment_refunds (cron_run_id);
@@ -0,0 +1,5 @@
+DROP TABLE auto_reload_charges;
+
+DROP TABLE auto_reload_prefs;
+
+DROP TYPE auto_reload_state;
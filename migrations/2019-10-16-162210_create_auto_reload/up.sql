@@ -0,0 +1,37 @@
+CREATE TYPE AUTO_RELOAD_STATE AS ENUM (
+  'pending',
+  'succeeded',
+  'failed'
+);
+
+CREATE TABLE auto_reload_prefs (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID UNIQUE NOT NULL,
+  enabled BOOLEAN NOT NULL DEFAULT FALSE,
+  threshold_cents BIGINT NOT NULL,
+  reload_amount_cents INTEGER NOT NULL,
+  daily_cap_cents BIGINT NOT NULL,
+  stripe_customer_id TEXT,
+  consecutive_failures INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMP);
+
+SELECT diesel_manage_updated_at('auto_reload_prefs');
+
+CREATE TABLE auto_reload_charges (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  amount_cents INTEGER NOT NULL,
+  state AUTO_RELOAD_STATE NOT NULL DEFAULT 'pending',
+  charge JSON,
+  error TEXT);
+
+-- At most one pending reload per client
+CREATE UNIQUE INDEX auto_reload_charges_pending_idx
+  ON auto_reload_charges (client_id)
+  WHERE state = 'pending';
+
+SELECT diesel_manage_updated_at('auto_reload_charges');
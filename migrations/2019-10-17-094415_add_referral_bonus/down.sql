@@ -0,0 +1,21 @@
+ALTER TABLE balances
+  DROP COLUMN referral_cents;
+
+ALTER TABLE payments
+  DROP COLUMN referrer_client_id;
+
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout'
+);
+
+ALTER TABLE transactions
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
@@ -0,0 +1,22 @@
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus'
+);
+
+ALTER TABLE transactions
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+ALTER TABLE payments
+  ADD COLUMN referrer_client_id UUID;
+
+ALTER TABLE balances
+  ADD COLUMN referral_cents BIGINT NOT NULL DEFAULT 0;
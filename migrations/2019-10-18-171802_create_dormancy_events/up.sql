@@ -0,0 +1,38 @@
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated'
+);
+
+ALTER TABLE transactions
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+CREATE TYPE DORMANCY_ACTION AS ENUM (
+  'warned',
+  'promo_expired',
+  'escheated'
+);
+
+CREATE TABLE dormancy_events (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  action DORMANCY_ACTION NOT NULL,
+  last_activity_at TIMESTAMP NOT NULL,
+  amount_cents BIGINT NOT NULL,
+  cron_run_id UUID NOT NULL,
+  transaction_id BIGINT REFERENCES transactions (id));
+
+CREATE INDEX dormancy_events_client_id_idx ON dormancy_events (client_id, created_at);
+
+CREATE INDEX dormancy_events_cron_run_id_idx ON dormancy_events (cron_run_id);
@@ -0,0 +1,7 @@
+DROP TRIGGER check_ledger_day_open ON transactions;
+
+DROP FUNCTION check_ledger_day_open();
+
+DROP TABLE ledger_day_totals;
+
+DROP TABLE ledger_days;
@@ -0,0 +1,42 @@
+CREATE TABLE ledger_days (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ds DATE UNIQUE NOT NULL,
+  tx_count BIGINT NOT NULL,
+  credit_cents BIGINT NOT NULL,
+  debit_cents BIGINT NOT NULL,
+  balanced BOOLEAN NOT NULL);
+
+CREATE TABLE ledger_day_totals (
+  id BIGSERIAL PRIMARY KEY,
+  ds DATE NOT NULL REFERENCES ledger_days (ds),
+  tx_type TRANSACTION_TYPE NOT NULL,
+  tx_reason TRANSACTION_REASON NOT NULL,
+  tx_count BIGINT NOT NULL,
+  amount_cents BIGINT NOT NULL,
+  UNIQUE (ds, tx_type, tx_reason));
+
+-- Once a day is closed, its transactions are frozen: nothing may be inserted
+-- into, modified in, or removed from it.
+CREATE FUNCTION check_ledger_day_open() RETURNS TRIGGER AS $$
+DECLARE
+  closed_through DATE;
+BEGIN
+  SELECT MAX(ds) INTO closed_through FROM ledger_days;
+  IF closed_through IS NOT NULL THEN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND DATE(OLD.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(OLD.created_at);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND DATE(NEW.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(NEW.created_at);
+    END IF;
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER check_ledger_day_open BEFORE INSERT OR UPDATE OR DELETE ON transactions
+  FOR EACH ROW EXECUTE PROCEDURE check_ledger_day_open();
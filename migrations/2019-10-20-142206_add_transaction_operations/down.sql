@@ -0,0 +1,3 @@
+ALTER TABLE transactions
+  DROP COLUMN reverses_operation_id,
+  DROP COLUMN operation_id;
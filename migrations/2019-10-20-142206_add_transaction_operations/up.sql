@@ -0,0 +1,9 @@
+-- Transactions written together as one operation share an operation_id.
+-- Transactions written before this column existed have none.
+ALTER TABLE transactions
+  ADD COLUMN operation_id UUID,
+  ADD COLUMN reverses_operation_id UUID;
+
+CREATE INDEX transactions_operation_id_idx ON transactions (operation_id);
+
+CREATE INDEX transactions_reverses_operation_id_idx ON transactions (reverses_operation_id);
@@ -0,0 +1,35 @@
+ALTER TYPE PAYMENT_OUTCOME RENAME TO PAYMENT_OUTCOME_OLD;
+
+CREATE TYPE PAYMENT_OUTCOME AS ENUM (
+  'settled',
+  'expired'
+);
+
+ALTER TABLE payment_outcomes
+  ALTER COLUMN outcome TYPE PAYMENT_OUTCOME
+  USING outcome::text::PAYMENT_OUTCOME;
+
+DROP TYPE PAYMENT_OUTCOME_OLD;
+
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated'
+);
+
+ALTER TABLE transactions
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+ALTER TABLE ledger_day_totals
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
@@ -0,0 +1,18 @@
+CREATE TABLE stripe_connect_payouts (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  stripe_user_id TEXT NOT NULL,
+  transfer_id BIGINT NOT NULL REFERENCES stripe_connect_transfers (id),
+  stripe_payout_id TEXT UNIQUE,
+  amount_cents INT NOT NULL,
+  method TEXT NOT NULL,
+  status TEXT NOT NULL,
+  failure_code TEXT,
+  failure_message TEXT,
+  connect_payout JSON);
+
+CREATE INDEX stripe_connect_payouts_client_id_idx ON stripe_connect_payouts (client_id, created_at);
+
+SELECT diesel_manage_updated_at('stripe_connect_payouts');
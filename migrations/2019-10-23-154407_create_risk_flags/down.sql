@@ -0,0 +1,3 @@
+DROP TABLE risk_flags;
+
+DROP TYPE RISK_FLAG_KIND;
@@ -0,0 +1,17 @@
+CREATE TYPE RISK_FLAG_KIND AS ENUM (
+  'credit_velocity',
+  'payout_velocity'
+);
+
+CREATE TABLE risk_flags (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  kind RISK_FLAG_KIND NOT NULL,
+  ds DATE NOT NULL,
+  amount_cents BIGINT NOT NULL,
+  baseline_cents DOUBLE PRECISION NOT NULL,
+  cron_run_id UUID NOT NULL,
+  UNIQUE (client_id, kind, ds));
+
+CREATE INDEX risk_flags_created_at_idx ON risk_flags (created_at);
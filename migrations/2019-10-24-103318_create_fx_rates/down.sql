@@ -0,0 +1,7 @@
+ALTER TABLE transactions
+  DROP COLUMN original_currency,
+  DROP COLUMN original_amount_cents,
+  DROP COLUMN fx_rate,
+  DROP COLUMN fx_rate_id;
+
+DROP TABLE fx_rates;
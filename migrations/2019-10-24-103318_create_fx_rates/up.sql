@@ -0,0 +1,17 @@
+-- Snapshots of the USD value of one whole unit of each currency (i.e., 1 EUR
+-- or 1 JPY), as fetched from the configured FX source.
+CREATE TABLE fx_rates (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  source TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  rate DOUBLE PRECISION NOT NULL CHECK (rate > 0),
+  cron_run_id UUID);
+
+CREATE INDEX fx_rates_currency_created_at_idx ON fx_rates (currency, created_at);
+
+ALTER TABLE transactions
+  ADD COLUMN original_currency TEXT,
+  ADD COLUMN original_amount_cents INTEGER,
+  ADD COLUMN fx_rate DOUBLE PRECISION,
+  ADD COLUMN fx_rate_id BIGINT REFERENCES fx_rates (id);
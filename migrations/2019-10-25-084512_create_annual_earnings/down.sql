@@ -0,0 +1,3 @@
+DROP INDEX payment_outcomes_client_id_to_created_at_idx;
+
+DROP TABLE annual_earnings;
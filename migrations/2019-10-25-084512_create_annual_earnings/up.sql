@@ -0,0 +1,22 @@
+-- Payments received per client per calendar year, as needed for 1099-K
+-- reporting. Rebuilt by the cron from payment_outcomes.
+CREATE TABLE annual_earnings (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  year INTEGER NOT NULL,
+  gross_cents BIGINT NOT NULL,
+  fee_cents BIGINT NOT NULL,
+  payment_count BIGINT NOT NULL,
+  -- Indexed by month, January first
+  monthly_gross_cents BIGINT[] NOT NULL,
+  monthly_payment_counts BIGINT[] NOT NULL,
+  cron_run_id UUID NOT NULL,
+  UNIQUE (client_id, year));
+
+CREATE INDEX annual_earnings_year_idx ON annual_earnings (year);
+
+CREATE INDEX payment_outcomes_client_id_to_created_at_idx ON payment_outcomes (client_id_to, created_at);
+
+SELECT diesel_manage_updated_at('annual_earnings');
@@ -0,0 +1,9 @@
+ALTER TABLE payment_outcomes
+  DROP COLUMN payment_split_id;
+
+ALTER TABLE payments
+  DROP COLUMN payment_split_id;
+
+DROP TABLE payment_split_shares;
+
+DROP TABLE payment_splits;
@@ -0,0 +1,25 @@
+-- A payment divided among several recipients. Each share is added as its own
+-- payment, which settles or expires independently.
+CREATE TABLE payment_splits (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id_from UUID NOT NULL,
+  message_hash TEXT NOT NULL,
+  payment_cents INTEGER NOT NULL,
+  fee_cents INTEGER NOT NULL);
+
+CREATE INDEX payment_splits_client_id_from_idx ON payment_splits (client_id_from);
+
+CREATE TABLE payment_split_shares (
+  id BIGSERIAL PRIMARY KEY,
+  payment_split_id BIGINT NOT NULL REFERENCES payment_splits (id),
+  client_id_to UUID NOT NULL,
+  share INTEGER NOT NULL CHECK (share > 0),
+  payment_cents INTEGER NOT NULL,
+  UNIQUE (payment_split_id, client_id_to));
+
+ALTER TABLE payments
+  ADD COLUMN payment_split_id BIGINT REFERENCES payment_splits (id);
+
+ALTER TABLE payment_outcomes
+  ADD COLUMN payment_split_id BIGINT REFERENCES payment_splits (id);
@@ -0,0 +1,5 @@
+DROP INDEX stripe_charges_client_id_idx;
+
+ALTER TABLE stripe_charges
+  DROP COLUMN transaction_id,
+  ADD CONSTRAINT stripe_charges_client_id_key UNIQUE (client_id);
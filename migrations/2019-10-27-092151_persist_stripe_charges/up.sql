@@ -0,0 +1,7 @@
+-- Clients may be charged more than once, so charges are no longer unique per
+-- client. Each charge is linked to the transaction which credited it.
+ALTER TABLE stripe_charges
+  DROP CONSTRAINT stripe_charges_client_id_key,
+  ADD COLUMN transaction_id BIGINT REFERENCES transactions (id);
+
+CREATE INDEX stripe_charges_client_id_idx ON stripe_charges (client_id);
@@ -0,0 +1 @@
+DROP TABLE bigquery_exports;
@@ -0,0 +1,10 @@
+-- How far each table has been streamed to BigQuery
+CREATE TABLE bigquery_exports (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  table_name TEXT NOT NULL UNIQUE,
+  last_id BIGINT NOT NULL DEFAULT 0
+);
+
+SELECT diesel_manage_updated_at('bigquery_exports');
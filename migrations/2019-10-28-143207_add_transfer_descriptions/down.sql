@@ -0,0 +1,3 @@
+ALTER TABLE stripe_connect_transfers
+  DROP COLUMN description,
+  DROP COLUMN statement_descriptor;
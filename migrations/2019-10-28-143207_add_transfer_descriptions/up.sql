@@ -0,0 +1,4 @@
+-- Shown to the client for payouts, i.e., on their bank statement
+ALTER TABLE stripe_connect_transfers
+  ADD COLUMN description TEXT,
+  ADD COLUMN statement_descriptor TEXT;
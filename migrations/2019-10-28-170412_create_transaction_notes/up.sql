@@ -0,0 +1,11 @@
+-- Notes left on transactions by support staff, i.e., for investigations
+CREATE TABLE transaction_notes (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  transaction_id BIGINT NOT NULL REFERENCES transactions (id),
+  author TEXT NOT NULL,
+  note TEXT NOT NULL,
+  tags TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX transaction_notes_transaction_id_idx ON transaction_notes (transaction_id);
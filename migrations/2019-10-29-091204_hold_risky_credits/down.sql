@@ -0,0 +1,9 @@
+ALTER TABLE balances DROP COLUMN pending_cents;
+
+DROP TABLE held_credits;
+
+DROP TYPE HELD_CREDIT_STATE;
+
+ALTER TABLE stripe_charges
+  DROP COLUMN risk_level,
+  DROP COLUMN seller_message;
@@ -0,0 +1,35 @@
+-- Outcome of Stripe's risk evaluation (Radar) for each charge
+ALTER TABLE stripe_charges
+  ADD COLUMN risk_level TEXT,
+  ADD COLUMN seller_message TEXT;
+
+CREATE TYPE HELD_CREDIT_STATE AS ENUM (
+  'held',
+  'released',
+  'rejected'
+);
+
+-- Credits from risky charges. They're in the ledger, but aren't spendable
+-- until they're released, either after a delay or by review.
+CREATE TABLE held_credits (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  transaction_id BIGINT NOT NULL REFERENCES transactions (id),
+  stripe_charge_id TEXT NOT NULL,
+  amount_cents INTEGER NOT NULL,
+  risk_level TEXT NOT NULL,
+  -- Only released by review when null
+  release_at TIMESTAMP,
+  state HELD_CREDIT_STATE NOT NULL DEFAULT 'held',
+  reviewed_by TEXT,
+  reviewed_at TIMESTAMP
+);
+
+CREATE INDEX held_credits_client_id_idx ON held_credits (client_id) WHERE state = 'held';
+CREATE INDEX held_credits_release_at_idx ON held_credits (release_at) WHERE state = 'held';
+
+SELECT diesel_manage_updated_at('held_credits');
+
+ALTER TABLE balances ADD COLUMN pending_cents BIGINT NOT NULL DEFAULT 0;
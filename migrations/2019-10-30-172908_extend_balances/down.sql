@@ -0,0 +1,7 @@
+DROP INDEX payments_client_id_from_idx;
+
+ALTER TABLE balances
+  DROP COLUMN pending_settlement_cents,
+  DROP COLUMN lifetime_earned_cents;
+
+ALTER TABLE balances RENAME COLUMN held_cents TO pending_cents;
@@ -0,0 +1,30 @@
+-- Credits held for review are the first kind of hold
+ALTER TABLE balances RENAME COLUMN pending_cents TO held_cents;
+
+ALTER TABLE balances
+  -- Sent payments which haven't been settled yet
+  ADD COLUMN pending_settlement_cents BIGINT NOT NULL DEFAULT 0,
+  -- Payments read, tips and referral bonuses received, less any reversed
+  ADD COLUMN lifetime_earned_cents BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX payments_client_id_from_idx ON payments (client_id_from);
+
+UPDATE balances AS b
+SET
+  pending_settlement_cents = COALESCE((
+    SELECT
+      SUM(p.payment_cents)
+    FROM
+      payments AS p
+    WHERE
+      p.client_id_from = b.client_id), 0),
+  lifetime_earned_cents = COALESCE((
+    SELECT
+      SUM(t.amount_cents)
+    FROM
+      transactions AS t
+    WHERE
+      t.client_id = b.client_id
+      AND t.tx_reason IN ('message_read', 'tip', 'referral_bonus')
+      AND ((t.tx_type = 'credit' AND t.reverses_operation_id IS NULL)
+        OR (t.tx_type = 'debit' AND t.reverses_operation_id IS NOT NULL))), 0);
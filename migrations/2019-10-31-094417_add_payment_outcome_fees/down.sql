@@ -0,0 +1,2 @@
+ALTER TABLE payment_outcomes
+  DROP COLUMN fee_cents;
@@ -0,0 +1,13 @@
+-- The read fee withheld when each payment settled, so earnings don't depend
+-- on the fee schedule in effect when they're reported
+ALTER TABLE payment_outcomes
+  ADD COLUMN fee_cents INTEGER NOT NULL DEFAULT 0;
+
+-- Settled payments so far were charged 7%, rounded down
+UPDATE
+  payment_outcomes
+SET
+  fee_cents = FLOOR(payment_cents * 0.07)
+WHERE
+  outcome = 'settled'
+  AND NOT is_promo;
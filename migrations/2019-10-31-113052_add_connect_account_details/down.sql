@@ -0,0 +1,3 @@
+ALTER TABLE stripe_connect_accounts
+  DROP COLUMN email,
+  DROP COLUMN country;
@@ -0,0 +1,14 @@
+-- Copied out of connect_account when it's refreshed, so they can be read
+-- without parsing the account
+ALTER TABLE stripe_connect_accounts
+  ADD COLUMN email TEXT,
+  -- ISO 3166-1 alpha-2
+  ADD COLUMN country TEXT;
+
+UPDATE
+  stripe_connect_accounts
+SET
+  email = connect_account ->> 'email',
+  country = connect_account ->> 'country'
+WHERE
+  connect_account IS NOT NULL;
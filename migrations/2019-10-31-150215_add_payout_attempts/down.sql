@@ -0,0 +1,3 @@
+DROP TABLE payout_attempts;
+
+DROP TYPE PAYOUT_ATTEMPT_STATE;
@@ -0,0 +1,31 @@
+CREATE TYPE PAYOUT_ATTEMPT_STATE AS ENUM (
+  'pending',
+  'confirmed',
+  'expired',
+  'failed'
+);
+
+-- Payouts which must be confirmed before they're made. Every attempt is kept,
+-- including those which were abandoned and expired.
+CREATE TABLE payout_attempts (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  confirmation_token UUID NOT NULL UNIQUE,
+  -- The quote: what's debited from the client, and the tax withheld from it
+  amount_cents INTEGER NOT NULL,
+  withheld_cents INTEGER NOT NULL,
+  description TEXT,
+  statement_descriptor TEXT,
+  expires_at TIMESTAMP NOT NULL,
+  state PAYOUT_ATTEMPT_STATE NOT NULL DEFAULT 'pending',
+  confirmed_at TIMESTAMP,
+  -- Why the payout failed when it was confirmed
+  failure_reason TEXT
+);
+
+CREATE INDEX payout_attempts_client_id_idx ON payout_attempts (client_id);
+CREATE INDEX payout_attempts_expires_at_idx ON payout_attempts (expires_at) WHERE state = 'pending';
+
+SELECT diesel_manage_updated_at('payout_attempts');
@@ -0,0 +1,47 @@
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+-- Promo payments read. The recipient is paid in promo credit, which can be
+-- spent on messages but never withdrawn.
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated',
+  'message_declined',
+  'tip',
+  'internal_transfer',
+  'tax_withheld',
+  'promo_message_read'
+);
+
+ALTER TABLE transactions
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+ALTER TABLE ledger_day_totals
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+-- Promo payments read, less any reversed. Before promo_message_read they were
+-- the only promo credits for message_read.
+ALTER TABLE balances
+  ADD COLUMN promo_earned_cents BIGINT NOT NULL DEFAULT 0;
+
+UPDATE balances AS b
+SET
+  promo_earned_cents = COALESCE((
+    SELECT
+      SUM(t.amount_cents)
+    FROM
+      transactions AS t
+    WHERE
+      t.client_id = b.client_id
+      AND t.tx_reason = 'message_read'
+      AND ((t.tx_type = 'promo_credit' AND t.reverses_operation_id IS NULL)
+        OR (t.tx_type = 'promo_debit' AND t.reverses_operation_id IS NOT NULL))), 0);
@@ -0,0 +1,70 @@
+ALTER TABLE transactions RENAME TO transactions_partitioned;
+
+ALTER TABLE transactions_partitioned RENAME CONSTRAINT transactions_pkey TO transactions_partitioned_pkey;
+
+DROP INDEX transactions_client_id_created_at_idx;
+
+DROP INDEX transactions_operation_id_idx;
+
+DROP INDEX transactions_reverses_operation_id_idx;
+
+CREATE TABLE transactions (
+  id BIGINT PRIMARY KEY DEFAULT nextval('transactions_id_seq'),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID,
+  tx_type TRANSACTION_TYPE NOT NULL,
+  tx_reason TRANSACTION_REASON NOT NULL,
+  amount_cents INTEGER NOT NULL,
+  operation_id UUID,
+  reverses_operation_id UUID,
+  original_currency TEXT,
+  original_amount_cents INTEGER,
+  fx_rate DOUBLE PRECISION,
+  fx_rate_id BIGINT REFERENCES fx_rates (id));
+
+ALTER SEQUENCE transactions_id_seq OWNED BY transactions.id;
+
+INSERT INTO transactions
+SELECT
+  id,
+  created_at,
+  client_id,
+  tx_type,
+  tx_reason,
+  amount_cents,
+  operation_id,
+  reverses_operation_id,
+  original_currency,
+  original_amount_cents,
+  fx_rate,
+  fx_rate_id
+FROM
+  transactions_partitioned;
+
+DROP TABLE transactions_partitioned;
+
+DROP FUNCTION create_transactions_partition(DATE);
+
+CREATE INDEX transactions_operation_id_idx ON transactions (operation_id);
+
+CREATE INDEX transactions_reverses_operation_id_idx ON transactions (reverses_operation_id);
+
+CREATE TRIGGER check_ledger_day_open BEFORE INSERT OR UPDATE OR DELETE ON transactions
+  FOR EACH ROW EXECUTE PROCEDURE check_ledger_day_open();
+
+SELECT diesel_manage_updated_at('transactions');
+
+ALTER TABLE payment_refunds
+  ADD CONSTRAINT payment_refunds_transaction_id_fkey FOREIGN KEY (transaction_id) REFERENCES transactions (id);
+
+ALTER TABLE dormancy_events
+  ADD CONSTRAINT dormancy_events_transaction_id_fkey FOREIGN KEY (transaction_id) REFERENCES transactions (id);
+
+ALTER TABLE stripe_charges
+  ADD CONSTRAINT stripe_charges_transaction_id_fkey FOREIGN KEY (transaction_id) REFERENCES transactions (id);
+
+ALTER TABLE transaction_notes
+  ADD CONSTRAINT transaction_notes_transaction_id_fkey FOREIGN KEY (transaction_id) REFERENCES transactions (id);
+
+ALTER TABLE held_credits
+  ADD CONSTRAINT held_credits_transaction_id_fkey FOREIGN KEY (transaction_id) REFERENCES transactions (id);
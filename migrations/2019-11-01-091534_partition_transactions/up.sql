@@ -0,0 +1,107 @@
+-- Transactions are partitioned by the month they were created in, so that the
+-- hot partition stays small and closed months can be archived on their own.
+--
+-- The primary key of a partitioned table has to include the partition key, so
+-- IDs are no longer unique on their own (they're still drawn from the same
+-- sequence), and other tables can't reference transactions with foreign keys.
+ALTER TABLE payment_refunds
+  DROP CONSTRAINT payment_refunds_transaction_id_fkey;
+
+ALTER TABLE dormancy_events
+  DROP CONSTRAINT dormancy_events_transaction_id_fkey;
+
+ALTER TABLE stripe_charges
+  DROP CONSTRAINT stripe_charges_transaction_id_fkey;
+
+ALTER TABLE transaction_notes
+  DROP CONSTRAINT transaction_notes_transaction_id_fkey;
+
+ALTER TABLE held_credits
+  DROP CONSTRAINT held_credits_transaction_id_fkey;
+
+ALTER TABLE transactions RENAME TO transactions_unpartitioned;
+
+ALTER TABLE transactions_unpartitioned RENAME CONSTRAINT transactions_pkey TO transactions_unpartitioned_pkey;
+
+DROP INDEX transactions_operation_id_idx;
+
+DROP INDEX transactions_reverses_operation_id_idx;
+
+CREATE TABLE transactions (
+  id BIGINT NOT NULL DEFAULT nextval('transactions_id_seq'),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID,
+  tx_type TRANSACTION_TYPE NOT NULL,
+  tx_reason TRANSACTION_REASON NOT NULL,
+  amount_cents INTEGER NOT NULL,
+  operation_id UUID,
+  reverses_operation_id UUID,
+  original_currency TEXT,
+  original_amount_cents INTEGER,
+  fx_rate DOUBLE PRECISION,
+  fx_rate_id BIGINT REFERENCES fx_rates (id),
+  PRIMARY KEY (id, created_at))
+PARTITION BY RANGE (created_at);
+
+ALTER SEQUENCE transactions_id_seq OWNED BY transactions.id;
+
+-- Create the partition for the month containing `month`, named like
+-- transactions_y2019m11. Returns the name of the partition, or NULL if it
+-- already exists.
+--
+-- There's no default partition, so inserts into a month without a partition
+-- fail. The cron creates partitions a few months ahead.
+CREATE FUNCTION create_transactions_partition(month DATE) RETURNS TEXT AS $$
+DECLARE
+  partition_start DATE := date_trunc('month', month);
+  partition_name TEXT := 'transactions_' || to_char(partition_start, '"y"YYYY"m"MM');
+BEGIN
+  IF to_regclass(partition_name) IS NOT NULL THEN
+    RETURN NULL;
+  END IF;
+  EXECUTE format('CREATE TABLE %I PARTITION OF transactions FOR VALUES FROM (%L) TO (%L)',
+    partition_name, partition_start, partition_start + interval '1 month');
+  RETURN partition_name;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT
+  create_transactions_partition(month::DATE)
+FROM
+  generate_series(
+    date_trunc('month', (SELECT COALESCE(MIN(created_at), NOW()) FROM transactions_unpartitioned)),
+    date_trunc('month', NOW()) + interval '3 months',
+    interval '1 month') AS month;
+
+INSERT INTO transactions (id, created_at, client_id, tx_type, tx_reason, amount_cents,
+  operation_id, reverses_operation_id, original_currency, original_amount_cents, fx_rate, fx_rate_id)
+SELECT
+  id,
+  created_at,
+  client_id,
+  tx_type,
+  tx_reason,
+  amount_cents,
+  operation_id,
+  reverses_operation_id,
+  original_currency,
+  original_amount_cents,
+  fx_rate,
+  fx_rate_id
+FROM
+  transactions_unpartitioned;
+
+DROP TABLE transactions_unpartitioned;
+
+-- Indexes on the partitioned table are created on every partition, including
+-- ones created later
+CREATE INDEX transactions_client_id_created_at_idx ON transactions (client_id, created_at);
+
+CREATE INDEX transactions_operation_id_idx ON transactions (operation_id);
+
+CREATE INDEX transactions_reverses_operation_id_idx ON transactions (reverses_operation_id);
+
+-- Partitioned tables only support AFTER row triggers. The check raises, so a
+-- write to a closed day is still rolled back.
+CREATE TRIGGER check_ledger_day_open AFTER INSERT OR UPDATE OR DELETE ON transactions
+  FOR EACH ROW EXECUTE PROCEDURE check_ledger_day_open();
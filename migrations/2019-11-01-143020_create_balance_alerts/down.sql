@@ -0,0 +1,5 @@
+DROP TABLE outbox_events;
+
+DROP TYPE OUTBOX_EVENT_TYPE;
+
+DROP TABLE balance_alert_prefs;
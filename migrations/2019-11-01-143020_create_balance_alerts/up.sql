@@ -0,0 +1,28 @@
+-- Each client's balance alerts. An alert which isn't set is NULL. Alerts fire
+-- once when the balance crosses the threshold, and are re-armed when it
+-- crosses back.
+CREATE TABLE balance_alert_prefs (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID UNIQUE NOT NULL,
+  low_balance_cents BIGINT CHECK (low_balance_cents > 0),
+  withdrawable_cents BIGINT CHECK (withdrawable_cents > 0),
+  low_balance_alerted BOOLEAN NOT NULL DEFAULT FALSE,
+  withdrawable_alerted BOOLEAN NOT NULL DEFAULT FALSE);
+
+SELECT diesel_manage_updated_at('balance_alert_prefs');
+
+CREATE TYPE OUTBOX_EVENT_TYPE AS ENUM (
+  'low_balance',
+  'withdrawable_above_threshold'
+);
+
+-- Events for other services, written in the same transaction as the change
+-- they describe. Consumers read them in ID order.
+CREATE TABLE outbox_events (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID,
+  event_type OUTBOX_EVENT_TYPE NOT NULL,
+  payload JSON NOT NULL);
@@ -0,0 +1,68 @@
+CREATE OR REPLACE FUNCTION create_transactions_partition(month DATE) RETURNS TEXT AS $$
+DECLARE
+  partition_start DATE := date_trunc('month', month);
+  partition_name TEXT := 'transactions_' || to_char(partition_start, '"y"YYYY"m"MM');
+BEGIN
+  IF to_regclass(partition_name) IS NOT NULL THEN
+    RETURN NULL;
+  END IF;
+  EXECUTE format('CREATE TABLE %I PARTITION OF transactions FOR VALUES FROM (%L) TO (%L)',
+    partition_name, partition_start, partition_start + interval '1 month');
+  RETURN partition_name;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Test data is dropped
+DROP VIEW balances;
+
+DELETE FROM balances_all WHERE NOT livemode;
+
+ALTER TABLE balances_all
+  DROP CONSTRAINT balances_client_id_livemode_key,
+  ADD CONSTRAINT balances_client_id_key UNIQUE (client_id),
+  DROP COLUMN livemode;
+
+ALTER TABLE balances_all RENAME TO balances;
+
+DROP VIEW payments;
+
+DELETE FROM payments_all WHERE NOT livemode;
+
+ALTER TABLE payments_all
+  DROP CONSTRAINT payments_message_hash_livemode_key,
+  ADD CONSTRAINT payments_message_hash_key UNIQUE (message_hash),
+  DROP COLUMN livemode;
+
+ALTER TABLE payments_all RENAME TO payments;
+
+DROP VIEW transactions;
+
+DELETE FROM transactions_all WHERE NOT livemode;
+
+ALTER TABLE transactions_all DROP COLUMN livemode;
+
+CREATE OR REPLACE FUNCTION check_ledger_day_open() RETURNS TRIGGER AS $$
+DECLARE
+  closed_through DATE;
+BEGIN
+  SELECT MAX(ds) INTO closed_through FROM ledger_days;
+  IF closed_through IS NOT NULL THEN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND DATE(OLD.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(OLD.created_at);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND DATE(NEW.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(NEW.created_at);
+    END IF;
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE transactions_all RENAME TO transactions;
+
+DROP FUNCTION create_livemode_view(TEXT);
+
+DROP FUNCTION current_livemode();
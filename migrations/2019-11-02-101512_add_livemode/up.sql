@@ -0,0 +1,87 @@
+-- Live and test data share tables, and are told apart by livemode, as in
+-- Stripe. The mode is set for each request with the beancounter.livemode
+-- setting, which defaults to live.
+--
+-- Each table is renamed to <name>_all, and read and written through a view
+-- with its old name which only has the current mode's rows, so no query can
+-- mix them. Views don't pick up changes to the table: to change one, drop the
+-- view first, and recreate it with create_livemode_view().
+CREATE FUNCTION current_livemode() RETURNS BOOLEAN AS $$
+  SELECT COALESCE(NULLIF(current_setting('beancounter.livemode', TRUE), ''), 'on')::BOOLEAN;
+$$ LANGUAGE SQL STABLE;
+
+CREATE FUNCTION create_livemode_view(name TEXT) RETURNS VOID AS $$
+BEGIN
+  EXECUTE format('CREATE VIEW %I AS SELECT * FROM %I WHERE livemode = current_livemode()
+    WITH CASCADED CHECK OPTION', name, name || '_all');
+  EXECUTE format('ALTER VIEW %I ALTER COLUMN livemode SET DEFAULT current_livemode()', name);
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE transactions RENAME TO transactions_all;
+
+ALTER TABLE transactions_all
+  ADD COLUMN livemode BOOLEAN NOT NULL DEFAULT TRUE;
+
+SELECT create_livemode_view('transactions');
+
+-- Only live days are closed, so test transactions can always be written
+CREATE OR REPLACE FUNCTION check_ledger_day_open() RETURNS TRIGGER AS $$
+DECLARE
+  closed_through DATE;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    IF NOT OLD.livemode THEN
+      RETURN OLD;
+    END IF;
+  ELSIF NOT NEW.livemode THEN
+    RETURN NEW;
+  END IF;
+  SELECT MAX(ds) INTO closed_through FROM ledger_days;
+  IF closed_through IS NOT NULL THEN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND DATE(OLD.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(OLD.created_at);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND DATE(NEW.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(NEW.created_at);
+    END IF;
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE payments RENAME TO payments_all;
+
+ALTER TABLE payments_all
+  ADD COLUMN livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  DROP CONSTRAINT payments_message_hash_key,
+  ADD CONSTRAINT payments_message_hash_livemode_key UNIQUE (message_hash, livemode);
+
+SELECT create_livemode_view('payments');
+
+-- A client has a balance in each mode
+ALTER TABLE balances RENAME TO balances_all;
+
+ALTER TABLE balances_all
+  ADD COLUMN livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  DROP CONSTRAINT balances_client_id_key,
+  ADD CONSTRAINT balances_client_id_livemode_key UNIQUE (client_id, livemode);
+
+SELECT create_livemode_view('balances');
+
+CREATE OR REPLACE FUNCTION create_transactions_partition(month DATE) RETURNS TEXT AS $$
+DECLARE
+  partition_start DATE := date_trunc('month', month);
+  partition_name TEXT := 'transactions_' || to_char(partition_start, '"y"YYYY"m"MM');
+BEGIN
+  IF to_regclass(partition_name) IS NOT NULL THEN
+    RETURN NULL;
+  END IF;
+  EXECUTE format('CREATE TABLE %I PARTITION OF transactions_all FOR VALUES FROM (%L) TO (%L)',
+    partition_name, partition_start, partition_start + interval '1 month');
+  RETURN partition_name;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,21 @@
+DELETE FROM outbox_events WHERE event_type = 'payment_expiring';
+
+ALTER TYPE OUTBOX_EVENT_TYPE RENAME TO OUTBOX_EVENT_TYPE_OLD;
+
+CREATE TYPE OUTBOX_EVENT_TYPE AS ENUM (
+  'low_balance',
+  'withdrawable_above_threshold'
+);
+
+ALTER TABLE outbox_events
+  ALTER COLUMN event_type TYPE OUTBOX_EVENT_TYPE
+  USING event_type::text::OUTBOX_EVENT_TYPE;
+
+DROP TYPE OUTBOX_EVENT_TYPE_OLD;
+
+DROP VIEW payments;
+
+ALTER TABLE payments_all
+  DROP COLUMN reminded_at;
+
+SELECT create_livemode_view('payments');
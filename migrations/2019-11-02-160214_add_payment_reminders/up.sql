@@ -0,0 +1,22 @@
+-- When the recipient was reminded that the payment is about to expire
+DROP VIEW payments;
+
+ALTER TABLE payments_all
+  ADD COLUMN reminded_at TIMESTAMP;
+
+SELECT create_livemode_view('payments');
+
+ALTER TYPE OUTBOX_EVENT_TYPE RENAME TO OUTBOX_EVENT_TYPE_OLD;
+
+-- Unread payments to the client which expire soon
+CREATE TYPE OUTBOX_EVENT_TYPE AS ENUM (
+  'low_balance',
+  'withdrawable_above_threshold',
+  'payment_expiring'
+);
+
+ALTER TABLE outbox_events
+  ALTER COLUMN event_type TYPE OUTBOX_EVENT_TYPE
+  USING event_type::text::OUTBOX_EVENT_TYPE;
+
+DROP TYPE OUTBOX_EVENT_TYPE_OLD;
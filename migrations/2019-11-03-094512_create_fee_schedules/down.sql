@@ -0,0 +1,3 @@
+DROP TABLE fee_schedules;
+
+DROP TYPE FEE_PLAN;
@@ -0,0 +1,15 @@
+CREATE TYPE FEE_PLAN AS ENUM (
+  'standard',
+  'sender_pays_read_fee'
+);
+
+-- The fee plan of each sender on a contract. Senders without a row are on
+-- the standard plan, where the read fee is withheld from the recipient.
+CREATE TABLE fee_schedules (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID UNIQUE NOT NULL,
+  plan FEE_PLAN NOT NULL DEFAULT 'standard');
+
+SELECT diesel_manage_updated_at('fee_schedules');
@@ -0,0 +1,5 @@
+DROP INDEX payout_attempts_failed_idx;
+
+ALTER TABLE payout_attempts
+  DROP COLUMN failure_code,
+  DROP COLUMN decline_code;
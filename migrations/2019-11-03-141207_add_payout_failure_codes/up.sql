@@ -0,0 +1,7 @@
+-- Stripe's code for a failed transfer, i.e., 'balance_insufficient', and the
+-- bank's reason when it declined
+ALTER TABLE payout_attempts
+  ADD COLUMN failure_code TEXT,
+  ADD COLUMN decline_code TEXT;
+
+CREATE INDEX payout_attempts_failed_idx ON payout_attempts (client_id, created_at) WHERE state = 'failed';
@@ -0,0 +1,19 @@
+-- Maintenance locks on a client's ledger. While a lock is active, requests
+-- which would change the client's ledger are refused. Locks are never
+-- deleted, so they're also the record of who locked a ledger, when, and why.
+CREATE TABLE client_ledger_locks (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  reason TEXT NOT NULL,
+  locked_by TEXT NOT NULL,
+  -- The lock is released at this time if it isn't unlocked first
+  expires_at TIMESTAMP NOT NULL,
+  unlocked_at TIMESTAMP,
+  unlocked_by TEXT
+);
+
+CREATE INDEX client_ledger_locks_client_id_idx ON client_ledger_locks (client_id) WHERE unlocked_at IS NULL;
+
+SELECT diesel_manage_updated_at('client_ledger_locks');
@@ -0,0 +1,19 @@
+-- One row per cron payout run, for the ops dashboard
+CREATE TABLE payout_runs (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  cron_run_id UUID NOT NULL,
+  started_at TIMESTAMP NOT NULL,
+  finished_at TIMESTAMP NOT NULL,
+  -- Accounts eligible for a payout when the run started
+  candidates INTEGER NOT NULL,
+  succeeded INTEGER NOT NULL,
+  failed INTEGER NOT NULL,
+  skipped INTEGER NOT NULL,
+  -- Count of skipped payouts per reason, i.e. {"insufficient_balance": 2}
+  skipped_reasons JSON NOT NULL DEFAULT '{}',
+  -- Total amount paid out by the run, including tax withheld
+  total_cents BIGINT NOT NULL
+);
+
+CREATE INDEX payout_runs_started_at_idx ON payout_runs (started_at);
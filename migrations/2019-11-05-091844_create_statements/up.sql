@@ -0,0 +1,32 @@
+-- Each client's totals for a calendar month, recorded once the month is over
+CREATE TABLE statements (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  -- The first day of the month
+  period DATE NOT NULL,
+  -- Settled payments received, before the read fee
+  gross_cents BIGINT NOT NULL,
+  fee_cents BIGINT NOT NULL,
+  -- Tax withheld from payouts
+  withheld_cents BIGINT NOT NULL,
+  payment_count BIGINT NOT NULL,
+  cron_run_id UUID NOT NULL,
+  UNIQUE (client_id, period)
+);
+
+ALTER TYPE OUTBOX_EVENT_TYPE RENAME TO OUTBOX_EVENT_TYPE_OLD;
+
+-- The client's statement for last month can be downloaded
+CREATE TYPE OUTBOX_EVENT_TYPE AS ENUM (
+  'low_balance',
+  'withdrawable_above_threshold',
+  'payment_expiring',
+  'statement_ready'
+);
+
+ALTER TABLE outbox_events
+  ALTER COLUMN event_type TYPE OUTBOX_EVENT_TYPE
+  USING event_type::text::OUTBOX_EVENT_TYPE;
+
+DROP TYPE OUTBOX_EVENT_TYPE_OLD;
@@ -0,0 +1,34 @@
+DROP VIEW transactions;
+
+ALTER TABLE transactions_all
+  DROP CONSTRAINT transactions_amount_sign;
+
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated',
+  'message_declined',
+  'tip',
+  'internal_transfer',
+  'tax_withheld',
+  'promo_message_read'
+);
+
+ALTER TABLE transactions_all
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+ALTER TABLE ledger_day_totals
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+SELECT create_livemode_view('transactions');
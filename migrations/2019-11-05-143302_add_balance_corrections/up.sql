@@ -0,0 +1,41 @@
+DROP VIEW transactions;
+
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+-- Signed adjustments made by an admin with CorrectBalance, for errors which
+-- can't be fixed by reversing an operation
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated',
+  'message_declined',
+  'tip',
+  'internal_transfer',
+  'tax_withheld',
+  'promo_message_read',
+  'correction'
+);
+
+ALTER TABLE transactions_all
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+ALTER TABLE ledger_day_totals
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+-- Credits are never negative and debits never positive, so a negated amount
+-- can't silently turn one into the other
+ALTER TABLE transactions_all
+  ADD CONSTRAINT transactions_amount_sign CHECK (
+    (tx_type IN ('credit', 'promo_credit') AND amount_cents >= 0)
+    OR (tx_type IN ('debit', 'promo_debit') AND amount_cents <= 0));
+
+SELECT create_livemode_view('transactions');
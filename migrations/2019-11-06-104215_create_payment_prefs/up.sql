@@ -0,0 +1,11 @@
+-- The payments each client accepts as a recipient. AddPayment declines
+-- payments from blocked senders, and payments below the minimum.
+CREATE TABLE payment_prefs (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID UNIQUE NOT NULL,
+  min_payment_cents INTEGER NOT NULL DEFAULT 0 CHECK (min_payment_cents >= 0),
+  blocked_client_ids UUID[] NOT NULL DEFAULT '{}');
+
+SELECT diesel_manage_updated_at('payment_prefs');
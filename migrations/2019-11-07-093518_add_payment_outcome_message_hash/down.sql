@@ -0,0 +1,4 @@
+DROP INDEX payment_outcomes_client_id_to_message_hash_idx;
+
+ALTER TABLE payment_outcomes
+  DROP COLUMN message_hash;
@@ -0,0 +1,6 @@
+-- The settled payment's message hash, so settling it again can be told apart
+-- from settling a payment which never existed. Older outcomes don't have one.
+ALTER TABLE payment_outcomes
+  ADD COLUMN message_hash TEXT;
+
+CREATE INDEX payment_outcomes_client_id_to_message_hash_idx ON payment_outcomes (client_id_to, message_hash);
@@ -0,0 +1,11 @@
+ALTER TABLE payout_attempts
+  DROP COLUMN request_id,
+  DROP COLUMN caller;
+
+ALTER TABLE client_ledger_locks
+  DROP COLUMN request_id,
+  DROP COLUMN caller;
+
+ALTER TABLE transaction_notes
+  DROP COLUMN request_id,
+  DROP COLUMN caller;
@@ -0,0 +1,14 @@
+-- The caller and request ID of the call which wrote each row, so it can be
+-- matched with the request log and with objects created in Stripe. Rows
+-- written outside of a call, i.e., by the cron, have neither.
+ALTER TABLE transaction_notes
+  ADD COLUMN caller TEXT,
+  ADD COLUMN request_id TEXT;
+
+ALTER TABLE client_ledger_locks
+  ADD COLUMN caller TEXT,
+  ADD COLUMN request_id TEXT;
+
+ALTER TABLE payout_attempts
+  ADD COLUMN caller TEXT,
+  ADD COLUMN request_id TEXT;
@@ -0,0 +1,9 @@
+DROP TRIGGER balances_record_history_on_update ON balances_all;
+
+DROP TRIGGER balances_record_history_on_insert ON balances_all;
+
+DROP FUNCTION record_balance_history();
+
+DROP VIEW balance_history;
+
+DROP TABLE balance_history_all;
@@ -0,0 +1,83 @@
+-- A snapshot of a client's balance each time it changes, so its history can
+-- be charted without recomputing it from transactions. Written by a trigger
+-- on balances_all, so no write to a balance can skip it.
+CREATE TABLE balance_history_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  balance_cents BIGINT NOT NULL,
+  promo_cents BIGINT NOT NULL,
+  withdrawable_cents BIGINT NOT NULL,
+  held_cents BIGINT NOT NULL,
+  lifetime_earned_cents BIGINT NOT NULL,
+  promo_earned_cents BIGINT NOT NULL,
+  livemode BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX balance_history_all_client_id_livemode_created_at_idx ON balance_history_all (client_id, livemode, created_at);
+
+SELECT create_livemode_view('balance_history');
+
+CREATE FUNCTION record_balance_history() RETURNS TRIGGER AS $$
+BEGIN
+  INSERT INTO balance_history_all (
+    client_id,
+    balance_cents,
+    promo_cents,
+    withdrawable_cents,
+    held_cents,
+    lifetime_earned_cents,
+    promo_earned_cents,
+    livemode)
+  VALUES (
+    NEW.client_id,
+    NEW.balance_cents,
+    NEW.promo_cents,
+    NEW.withdrawable_cents,
+    NEW.held_cents,
+    NEW.lifetime_earned_cents,
+    NEW.promo_earned_cents,
+    NEW.livemode);
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER balances_record_history_on_insert
+  AFTER INSERT ON balances_all
+  FOR EACH ROW
+  EXECUTE PROCEDURE record_balance_history();
+
+-- Balances are recomputed and written on every request which may change
+-- them, so only writes which actually change one are recorded
+CREATE TRIGGER balances_record_history_on_update
+  AFTER UPDATE ON balances_all
+  FOR EACH ROW
+  WHEN ((OLD.balance_cents, OLD.promo_cents, OLD.withdrawable_cents, OLD.held_cents,
+    OLD.lifetime_earned_cents, OLD.promo_earned_cents) IS DISTINCT FROM
+    (NEW.balance_cents, NEW.promo_cents, NEW.withdrawable_cents, NEW.held_cents,
+    NEW.lifetime_earned_cents, NEW.promo_earned_cents))
+  EXECUTE PROCEDURE record_balance_history();
+
+-- Existing balances start their history as of their last update
+INSERT INTO balance_history_all (
+  created_at,
+  client_id,
+  balance_cents,
+  promo_cents,
+  withdrawable_cents,
+  held_cents,
+  lifetime_earned_cents,
+  promo_earned_cents,
+  livemode)
+SELECT
+  updated_at,
+  client_id,
+  balance_cents,
+  promo_cents,
+  withdrawable_cents,
+  held_cents,
+  lifetime_earned_cents,
+  promo_earned_cents,
+  livemode
+FROM
+  balances_all;
@@ -0,0 +1,7 @@
+ALTER TABLE payment_refunds
+  DROP COLUMN fee_transaction_id,
+  DROP COLUMN fee_refunded_cents,
+  DROP COLUMN fee_retained_cents,
+  DROP COLUMN policy;
+
+DROP TYPE expiry_refund_policy;
@@ -0,0 +1,17 @@
+CREATE TYPE EXPIRY_REFUND_POLICY AS ENUM (
+  'full_refund',
+  'fee_retained',
+  'sliding_scale'
+);
+
+-- How much of the send fee was kept on each expired payment, and by which
+-- policy. The send fee was always kept before, but the amount wasn't
+-- recorded.
+ALTER TABLE payment_refunds
+  ADD COLUMN policy EXPIRY_REFUND_POLICY NOT NULL DEFAULT 'fee_retained',
+  ADD COLUMN fee_retained_cents INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN fee_refunded_cents INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN fee_transaction_id BIGINT;
+
+ALTER TABLE payment_refunds
+  ALTER COLUMN policy DROP DEFAULT;
@@ -0,0 +1,3 @@
+DROP TABLE account_tiers;
+
+DROP TYPE ACCOUNT_TIER;
@@ -0,0 +1,16 @@
+CREATE TYPE ACCOUNT_TIER AS ENUM (
+  'standard',
+  'pro',
+  'enterprise'
+);
+
+-- The account tier of each client, which sets the fees and limits they're
+-- on. Clients without a row are on the standard tier.
+CREATE TABLE account_tiers (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID UNIQUE NOT NULL,
+  tier ACCOUNT_TIER NOT NULL DEFAULT 'standard');
+
+SELECT diesel_manage_updated_at('account_tiers');
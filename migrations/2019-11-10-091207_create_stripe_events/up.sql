@@ -0,0 +1,15 @@
+-- Stripe events which have been processed, from webhooks or replayed from
+-- the Events API, so that no event is applied twice.
+CREATE TABLE stripe_events (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  event_id TEXT UNIQUE NOT NULL,
+  event_type TEXT NOT NULL,
+  -- The connected account the event happened on, if any
+  stripe_account TEXT,
+  -- Whether the event was replayed, rather than received by webhook
+  replayed BOOLEAN NOT NULL DEFAULT FALSE,
+  handled BOOLEAN NOT NULL DEFAULT FALSE);
+
+SELECT diesel_manage_updated_at('stripe_events');
@@ -0,0 +1,10 @@
+DROP INDEX payment_outcomes_all_created_at_idx;
+
+-- Test data is dropped
+DROP VIEW payment_outcomes;
+
+DELETE FROM payment_outcomes_all WHERE NOT livemode;
+
+ALTER TABLE payment_outcomes_all DROP COLUMN livemode;
+
+ALTER TABLE payment_outcomes_all RENAME TO payment_outcomes;
@@ -0,0 +1,12 @@
+-- Payment outcomes are kept apart by mode like the payments they're for, so
+-- reports over them only count the current ledger. Outcomes recorded before
+-- now are taken to be live.
+ALTER TABLE payment_outcomes RENAME TO payment_outcomes_all;
+
+ALTER TABLE payment_outcomes_all
+  ADD COLUMN livemode BOOLEAN NOT NULL DEFAULT TRUE;
+
+SELECT create_livemode_view('payment_outcomes');
+
+-- For finding the payments which were in escrow during a window
+CREATE INDEX payment_outcomes_all_created_at_idx ON payment_outcomes_all (created_at);
@@ -0,0 +1,80 @@
+-- Payout transactions carry the ID of the Stripe transfer which moved the
+-- money out, so a client's payout debit joins to its transfer, and through
+-- stripe_connect_payouts to the payout to their bank.
+DROP VIEW transactions;
+
+ALTER TABLE transactions_all
+  ADD COLUMN reference TEXT;
+
+CREATE INDEX transactions_reference_idx ON transactions_all (reference)
+WHERE
+  reference IS NOT NULL;
+
+CREATE INDEX stripe_connect_transfers_stripe_id_idx ON stripe_connect_transfers ((connect_transfer ->> 'id'));
+
+SELECT create_livemode_view('transactions');
+
+-- A reference can be filled in on a closed day, since it doesn't change any
+-- amount. Nothing else about the transaction may change with it.
+CREATE OR REPLACE FUNCTION check_ledger_day_open() RETURNS TRIGGER AS $$
+DECLARE
+  closed_through DATE;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    IF NOT OLD.livemode THEN
+      RETURN OLD;
+    END IF;
+  ELSIF NOT NEW.livemode THEN
+    RETURN NEW;
+  END IF;
+  IF TG_OP = 'UPDATE' AND OLD.reference IS NULL
+    AND to_jsonb(NEW) - 'reference' = to_jsonb(OLD) - 'reference' THEN
+    RETURN NEW;
+  END IF;
+  SELECT MAX(ds) INTO closed_through FROM ledger_days;
+  IF closed_through IS NOT NULL THEN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND DATE(OLD.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(OLD.created_at);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND DATE(NEW.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(NEW.created_at);
+    END IF;
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Backfill from the transfers recorded with each payout, which were written
+-- in the same database transaction. Payouts split across several destinations
+-- were debited as a single amount, so they can't be matched to one transfer
+-- and are left without a reference.
+WITH matched AS (
+  SELECT DISTINCT ON (t.id)
+    t.id,
+    t.operation_id,
+    s.connect_transfer ->> 'id' AS reference
+  FROM
+    transactions_all AS t
+    JOIN stripe_connect_transfers AS s ON s.client_id = t.client_id
+      AND s.amount_cents = - t.amount_cents
+      AND t.created_at BETWEEN s.created_at - INTERVAL '1 minute'
+      AND s.created_at + INTERVAL '1 minute'
+  WHERE
+    t.tx_reason = 'payout'
+    AND t.tx_type = 'debit'
+  ORDER BY
+    t.id,
+    ABS(EXTRACT(EPOCH FROM t.created_at - s.created_at)))
+UPDATE
+  transactions_all AS t
+SET
+  reference = matched.reference
+FROM
+  matched
+WHERE
+  t.tx_reason = 'payout'
+  AND (t.id = matched.id
+    OR t.operation_id = matched.operation_id);
@@ -0,0 +1,3 @@
+DROP INDEX stripe_connect_payouts_transfer_id_idx;
+
+DROP INDEX stripe_connect_transfers_client_id_idx;
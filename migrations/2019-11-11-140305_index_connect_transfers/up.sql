@@ -0,0 +1,4 @@
+-- For listing a client's transfers a page at a time, with their payouts
+CREATE INDEX stripe_connect_transfers_client_id_idx ON stripe_connect_transfers (client_id, id);
+
+CREATE INDEX stripe_connect_payouts_transfer_id_idx ON stripe_connect_payouts (transfer_id);
@@ -0,0 +1,2 @@
+ALTER TABLE stripe_charges
+  DROP COLUMN statement_descriptor_suffix;
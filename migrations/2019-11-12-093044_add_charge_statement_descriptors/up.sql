@@ -0,0 +1,4 @@
+-- The suffix a charge was made with, shown after the account's statement
+-- descriptor prefix on the card statement
+ALTER TABLE stripe_charges
+  ADD COLUMN statement_descriptor_suffix TEXT;
@@ -0,0 +1,5 @@
+DROP TABLE ledger_hash_anchors;
+
+DROP TABLE ledger_hashes;
+
+DROP FUNCTION reject_append_only_change();
@@ -0,0 +1,40 @@
+-- A hash chain over the live ledger, for tamper evidence. The cron seals each
+-- transaction once it's settled, in order of (created_at, id): the row's own
+-- hash, chained to the previous row's globally and to the previous row of the
+-- same client. Test transactions aren't chained.
+--
+-- The hashes are kept beside the transactions rather than on them, since the
+-- partitioned table only supports AFTER row triggers, and closed days can't be
+-- updated.
+CREATE TABLE ledger_hashes (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  transaction_id BIGINT UNIQUE NOT NULL,
+  transaction_created_at TIMESTAMP NOT NULL,
+  client_id UUID,
+  -- Hex encoded SHA-256 hashes
+  content_hash TEXT NOT NULL,
+  client_hash TEXT NOT NULL,
+  chain_hash TEXT NOT NULL);
+
+CREATE INDEX ledger_hashes_client_id_id_idx ON ledger_hashes (client_id, id);
+
+-- The head of the chain as of each cron run, which is also logged, so a chain
+-- rewritten after the fact no longer matches what was recorded at the time
+CREATE TABLE ledger_hash_anchors (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ledger_hash_id BIGINT NOT NULL REFERENCES ledger_hashes (id),
+  chain_hash TEXT NOT NULL);
+
+CREATE FUNCTION reject_append_only_change() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION '% is append-only', TG_TABLE_NAME;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER ledger_hashes_append_only BEFORE UPDATE OR DELETE ON ledger_hashes
+  FOR EACH ROW EXECUTE PROCEDURE reject_append_only_change();
+
+CREATE TRIGGER ledger_hash_anchors_append_only BEFORE UPDATE OR DELETE ON ledger_hash_anchors
+  FOR EACH ROW EXECUTE PROCEDURE reject_append_only_change();
@@ -0,0 +1,3 @@
+DROP VIEW payment_links;
+
+DROP TABLE payment_links_all;
@@ -0,0 +1,24 @@
+-- Links clients share to request payment off-platform. Each pays its client
+-- payment_cents once redeemed, by the payer, as a regular payment. A link is
+-- claimed when it's redeemed, and released if the payment isn't made.
+CREATE TABLE payment_links_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  payment_cents INTEGER NOT NULL CHECK (payment_cents > 0),
+  description TEXT,
+  expires_at TIMESTAMP NOT NULL,
+  -- The payer, and the hash of the message they paid with, once redeemed
+  redeemed_at TIMESTAMP,
+  redeemed_by UUID,
+  message_hash TEXT,
+  livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  caller TEXT,
+  request_id TEXT);
+
+CREATE INDEX payment_links_all_client_id_livemode_idx ON payment_links_all (client_id, livemode);
+
+SELECT diesel_manage_updated_at('payment_links_all');
+
+SELECT create_livemode_view('payment_links');
@@ -0,0 +1,10 @@
+DROP VIEW payment_outcomes;
+
+ALTER TABLE payment_outcomes_all
+  DROP COLUMN payout_cents,
+  DROP COLUMN referral_cents,
+  DROP COLUMN read_fee_paid_by_sender,
+  DROP COLUMN refund_cents,
+  DROP COLUMN tip_cents;
+
+SELECT create_livemode_view('payment_outcomes');
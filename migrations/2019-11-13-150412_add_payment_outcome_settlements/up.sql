@@ -0,0 +1,30 @@
+-- What each outcome paid out, so settling a payment again can return the
+-- original settlement rather than just that it's gone
+DROP VIEW payment_outcomes;
+
+ALTER TABLE payment_outcomes_all
+  ADD COLUMN payout_cents INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN referral_cents INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN read_fee_paid_by_sender BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN refund_cents INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN tip_cents INTEGER NOT NULL DEFAULT 0;
+
+-- Older settlements are taken to have withheld the fee from the payout, and
+-- declines to have refunded the payment without a tip. Referral bonuses
+-- weren't recorded.
+UPDATE
+  payment_outcomes_all
+SET
+  payout_cents = payment_cents - fee_cents
+WHERE
+  outcome = 'settled';
+
+UPDATE
+  payment_outcomes_all
+SET
+  refund_cents = payment_cents
+WHERE
+  outcome = 'declined'
+  AND NOT is_promo;
+
+SELECT create_livemode_view('payment_outcomes');
@@ -0,0 +1,5 @@
+DROP VIEW payout_holds;
+
+DROP TABLE payout_holds_all;
+
+DROP TYPE PAYOUT_HOLD_STATE;
@@ -0,0 +1,27 @@
+CREATE TYPE PAYOUT_HOLD_STATE AS ENUM (
+  'held',
+  'paid',
+  'released'
+);
+
+-- The amount of each payout being made, held from the client's balance from
+-- before Stripe is called until the payout is debited ('paid') or fails
+-- ('released'), so nothing spent in the meantime can overdraw it. A hold is
+-- left 'held' if the payout never finished, since the transfer may have been
+-- made.
+CREATE TABLE payout_holds_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  amount_cents INTEGER NOT NULL CHECK (amount_cents > 0),
+  state PAYOUT_HOLD_STATE NOT NULL DEFAULT 'held',
+  livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  caller TEXT,
+  request_id TEXT);
+
+CREATE INDEX payout_holds_all_client_id_idx ON payout_holds_all (client_id, livemode) WHERE state = 'held';
+
+SELECT diesel_manage_updated_at('payout_holds_all');
+
+SELECT create_livemode_view('payout_holds');
@@ -0,0 +1,3 @@
+DROP INDEX transactions_client_id_tx_type_tx_reason_created_at_idx;
+
+DROP INDEX payments_all_client_id_to_message_hash_idx;
@@ -0,0 +1,5 @@
+-- Settling and looking up a payment finds it by its recipient and message
+CREATE INDEX payments_all_client_id_to_message_hash_idx ON payments_all (client_id_to, message_hash);
+
+-- Recomputing a balance sums each client's transactions by type and reason
+CREATE INDEX transactions_client_id_tx_type_tx_reason_created_at_idx ON transactions_all (client_id, tx_type, tx_reason, created_at);
@@ -0,0 +1,2 @@
+ALTER TABLE stripe_connect_accounts
+  DROP COLUMN identity_verified;
@@ -0,0 +1,12 @@
+-- Whether Stripe has verified the account holder's identity, copied out of
+-- connect_account when it's refreshed. Payouts to clients who've earned over
+-- the KYC threshold wait on it.
+ALTER TABLE stripe_connect_accounts
+  ADD COLUMN identity_verified BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE
+  stripe_connect_accounts
+SET
+  identity_verified = (connect_account #>> '{individual,verification,status}') = 'verified'
+WHERE
+  (connect_account #>> '{individual,verification,status}') IS NOT NULL;
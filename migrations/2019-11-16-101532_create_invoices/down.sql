@@ -0,0 +1,40 @@
+DROP TABLE invoice_line_items;
+
+DROP VIEW invoices;
+
+DROP TABLE invoices_all;
+
+DROP TYPE INVOICE_STATE;
+
+DROP VIEW transactions;
+
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated',
+  'message_declined',
+  'tip',
+  'internal_transfer',
+  'tax_withheld',
+  'promo_message_read',
+  'correction'
+);
+
+ALTER TABLE transactions_all
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+ALTER TABLE ledger_day_totals
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+SELECT create_livemode_view('transactions');
@@ -0,0 +1,75 @@
+DROP VIEW transactions;
+
+ALTER TYPE TRANSACTION_REASON RENAME TO TRANSACTION_REASON_OLD;
+
+-- Payments received off-platform for a sender's invoice, recorded with
+-- RecordInvoicePayment
+CREATE TYPE TRANSACTION_REASON AS ENUM (
+  'message_read',
+  'message_unread',
+  'message_sent',
+  'credit_added',
+  'payout',
+  'referral_bonus',
+  'promo_expired',
+  'escheated',
+  'message_declined',
+  'tip',
+  'internal_transfer',
+  'tax_withheld',
+  'promo_message_read',
+  'correction',
+  'invoice_payment'
+);
+
+ALTER TABLE transactions_all
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+ALTER TABLE ledger_day_totals
+  ALTER COLUMN tx_reason TYPE TRANSACTION_REASON
+  USING tx_reason::text::TRANSACTION_REASON;
+
+DROP TYPE TRANSACTION_REASON_OLD;
+
+SELECT create_livemode_view('transactions');
+
+CREATE TYPE INVOICE_STATE AS ENUM (
+  'open',
+  'paid'
+);
+
+-- Senders on a tier with a credit limit send on credit terms, and are
+-- invoiced for each calendar month's usage once it's over: what they sent,
+-- fees included, less what was refunded to them. Invoices are recorded once
+-- per client and month.
+CREATE TABLE invoices_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  -- The first day of the month
+  period DATE NOT NULL,
+  amount_cents BIGINT NOT NULL CHECK (amount_cents > 0),
+  paid_cents BIGINT NOT NULL DEFAULT 0 CHECK (paid_cents >= 0 AND paid_cents <= amount_cents),
+  state INVOICE_STATE NOT NULL DEFAULT 'open',
+  due_at TIMESTAMP NOT NULL,
+  livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  cron_run_id UUID NOT NULL,
+  UNIQUE (client_id, period, livemode)
+);
+
+SELECT diesel_manage_updated_at('invoices_all');
+
+SELECT create_livemode_view('invoices');
+
+-- An invoice's usage for each day of the month which had any
+CREATE TABLE invoice_line_items (
+  id BIGSERIAL PRIMARY KEY,
+  invoice_id BIGINT NOT NULL REFERENCES invoices_all (id),
+  ds DATE NOT NULL,
+  sent_cents BIGINT NOT NULL,
+  refunded_cents BIGINT NOT NULL,
+  amount_cents BIGINT NOT NULL,
+  UNIQUE (invoice_id, ds)
+);
@@ -0,0 +1,37 @@
+DROP VIEW transactions;
+
+CREATE OR REPLACE FUNCTION check_ledger_day_open() RETURNS TRIGGER AS $$
+DECLARE
+  closed_through DATE;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    IF NOT OLD.livemode THEN
+      RETURN OLD;
+    END IF;
+  ELSIF NOT NEW.livemode THEN
+    RETURN NEW;
+  END IF;
+  IF TG_OP = 'UPDATE' AND OLD.reference IS NULL
+    AND to_jsonb(NEW) - 'reference' = to_jsonb(OLD) - 'reference' THEN
+    RETURN NEW;
+  END IF;
+  SELECT MAX(ds) INTO closed_through FROM ledger_days;
+  IF closed_through IS NOT NULL THEN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND DATE(OLD.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(OLD.created_at);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND DATE(NEW.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(NEW.created_at);
+    END IF;
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE transactions_all
+  DROP COLUMN amount_cents_i64;
+
+SELECT create_livemode_view('transactions');
@@ -0,0 +1,46 @@
+-- amount_cents moves to a BIGINT without a maintenance window. The new column
+-- is dual-written while `transactions_amount_i64` is enabled in [dual_write],
+-- and the cron backfills the rows written before and compares the two. See
+-- src/dual_write.rs.
+DROP VIEW transactions;
+
+ALTER TABLE transactions_all
+  ADD COLUMN amount_cents_i64 BIGINT;
+
+SELECT create_livemode_view('transactions');
+
+-- The backfill fills in amount_cents_i64 on closed days too, as it doesn't
+-- change any amount. A reference can still be filled in as well, and nothing
+-- else about the transaction may change with either.
+CREATE OR REPLACE FUNCTION check_ledger_day_open() RETURNS TRIGGER AS $$
+DECLARE
+  closed_through DATE;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    IF NOT OLD.livemode THEN
+      RETURN OLD;
+    END IF;
+  ELSIF NOT NEW.livemode THEN
+    RETURN NEW;
+  END IF;
+  IF TG_OP = 'UPDATE'
+    AND (OLD.reference IS NULL OR NEW.reference IS NOT DISTINCT FROM OLD.reference)
+    AND (OLD.amount_cents_i64 IS NULL OR NEW.amount_cents_i64 IS NOT DISTINCT FROM OLD.amount_cents_i64)
+    AND to_jsonb(NEW) - 'reference' - 'amount_cents_i64' = to_jsonb(OLD) - 'reference' - 'amount_cents_i64' THEN
+    RETURN NEW;
+  END IF;
+  SELECT MAX(ds) INTO closed_through FROM ledger_days;
+  IF closed_through IS NOT NULL THEN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND DATE(OLD.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(OLD.created_at);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND DATE(NEW.created_at) <= closed_through THEN
+      RAISE EXCEPTION 'ledger day % is closed', DATE(NEW.created_at);
+    END IF;
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
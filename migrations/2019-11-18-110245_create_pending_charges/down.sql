@@ -0,0 +1,5 @@
+DROP VIEW pending_charges;
+
+DROP TABLE pending_charges_all;
+
+DROP TYPE PENDING_CHARGE_STATE;
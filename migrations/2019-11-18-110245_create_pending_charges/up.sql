@@ -0,0 +1,39 @@
+CREATE TYPE PENDING_CHARGE_STATE AS ENUM (
+  'pending',
+  'succeeded',
+  'failed',
+  'expired'
+);
+
+-- Charges from delayed payment methods, i.e., bank debits, which Stripe
+-- returns as pending. The credit isn't added until the charge.succeeded
+-- webhook arrives, and a charge which hasn't succeeded by expires_at, if set,
+-- is refunded and expired by the cron.
+CREATE TABLE pending_charges_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  stripe_charge_id TEXT NOT NULL UNIQUE,
+  -- The credit to add, in USD after Stripe's fees
+  amount_cents INTEGER NOT NULL CHECK (amount_cents > 0),
+  -- As on the credit's transaction, for a charge in another currency
+  original_currency TEXT,
+  original_amount_cents INTEGER,
+  fx_rate DOUBLE PRECISION,
+  fx_rate_id BIGINT,
+  state PENDING_CHARGE_STATE NOT NULL DEFAULT 'pending',
+  expires_at TIMESTAMP,
+  -- The credit, once the charge has succeeded
+  transaction_id BIGINT,
+  resolved_at TIMESTAMP,
+  livemode BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX pending_charges_expires_at_idx ON pending_charges_all (expires_at)
+WHERE
+  state = 'pending';
+
+SELECT diesel_manage_updated_at('pending_charges_all');
+
+SELECT create_livemode_view('pending_charges');
@@ -0,0 +1,22 @@
+-- Clients barred from receiving payments or payouts, i.e., for fraud or
+-- sanctions. Entries are never deleted, so they're also the record of who
+-- denied a client, when, and why.
+CREATE TABLE client_denylist (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id UUID NOT NULL,
+  reason TEXT NOT NULL,
+  added_by TEXT NOT NULL,
+  removed_at TIMESTAMP,
+  removed_by TEXT,
+  caller TEXT,
+  request_id TEXT
+);
+
+-- A client has at most one active entry
+CREATE UNIQUE INDEX client_denylist_client_id_idx ON client_denylist (client_id)
+WHERE
+  removed_at IS NULL;
+
+SELECT diesel_manage_updated_at('client_denylist');
@@ -0,0 +1,5 @@
+DROP VIEW payment_holds;
+
+DROP TABLE payment_holds_all;
+
+DROP TYPE PAYMENT_HOLD_STATE;
@@ -0,0 +1,43 @@
+CREATE TYPE PAYMENT_HOLD_STATE AS ENUM (
+  'held',
+  'captured',
+  'released',
+  'expired'
+);
+
+-- Sends over the hold threshold, authorized but not yet made. The amount the
+-- sender pays is held from their balance until the send is captured, when
+-- the message is delivered, and made into a payment ('captured'), or until
+-- it's released, when the payment fails on capture ('released') or the hold
+-- isn't captured in time ('expired').
+CREATE TABLE payment_holds_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  client_id_from UUID NOT NULL,
+  client_id_to UUID NOT NULL,
+  message_hash TEXT NOT NULL,
+  payment_cents INTEGER NOT NULL CHECK (payment_cents > 0),
+  -- What's held: the payment and the send fee, unless a campaign pays it
+  amount_cents INTEGER NOT NULL CHECK (amount_cents > 0),
+  referrer_client_id UUID,
+  campaign_id TEXT,
+  state PAYMENT_HOLD_STATE NOT NULL DEFAULT 'held',
+  expires_at TIMESTAMP NOT NULL,
+  resolved_at TIMESTAMP,
+  livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  caller TEXT,
+  request_id TEXT
+);
+
+CREATE INDEX payment_holds_all_client_id_from_idx ON payment_holds_all (client_id_from, livemode)
+WHERE
+  state = 'held';
+
+CREATE INDEX payment_holds_all_expires_at_idx ON payment_holds_all (expires_at)
+WHERE
+  state = 'held';
+
+SELECT diesel_manage_updated_at('payment_holds_all');
+
+SELECT create_livemode_view('payment_holds');
@@ -0,0 +1,3 @@
+DROP VIEW transaction_imports;
+
+DROP TABLE transaction_imports_all;
@@ -0,0 +1,20 @@
+-- Batches of historical transactions loaded with ImportTransactions, i.e.,
+-- migrated from a legacy billing system. A batch ID is imported once:
+-- importing it again returns the first import, without writing anything.
+CREATE TABLE transaction_imports_all (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  batch_id TEXT NOT NULL,
+  -- Shared by every transaction in the batch
+  operation_id UUID NOT NULL,
+  tx_count INTEGER NOT NULL,
+  -- The range of the transactions' original timestamps
+  first_created_at TIMESTAMP NOT NULL,
+  last_created_at TIMESTAMP NOT NULL,
+  livemode BOOLEAN NOT NULL DEFAULT TRUE,
+  caller TEXT,
+  request_id TEXT,
+  UNIQUE (batch_id, livemode)
+);
+
+SELECT create_livemode_view('transaction_imports');
@@ -0,0 +1,6 @@
+DROP VIEW payout_holds;
+
+ALTER TABLE payout_holds_all
+  DROP COLUMN transferred_cents;
+
+SELECT create_livemode_view('payout_holds');
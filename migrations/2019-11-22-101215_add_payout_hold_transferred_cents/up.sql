@@ -0,0 +1,11 @@
+-- A payout split across destinations is recorded and debited a transfer at a
+-- time, so a transfer which was made stays debited should a later one fail.
+-- Each debit counts down the payout's hold, which then only holds what's yet
+-- to be transferred (and the tax withheld).
+DROP VIEW payout_holds;
+
+ALTER TABLE payout_holds_all
+  ADD COLUMN transferred_cents INTEGER NOT NULL DEFAULT 0 CHECK (transferred_cents >= 0
+    AND transferred_cents <= amount_cents);
+
+SELECT create_livemode_view('payout_holds');
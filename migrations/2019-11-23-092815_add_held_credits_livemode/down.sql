@@ -0,0 +1,12 @@
+-- Test data is dropped
+DROP VIEW held_credits;
+
+DELETE FROM held_credits_all WHERE NOT livemode;
+
+DROP INDEX held_credits_all_client_id_idx;
+
+CREATE INDEX held_credits_client_id_idx ON held_credits_all (client_id) WHERE state = 'held';
+
+ALTER TABLE held_credits_all DROP COLUMN livemode;
+
+ALTER TABLE held_credits_all RENAME TO held_credits;
@@ -0,0 +1,19 @@
+-- Credits are held in the ledger of the charge they're from, so a test
+-- charge's held credit doesn't come off the live balance. Each credit held
+-- before now takes the mode of the transaction it holds.
+ALTER TABLE held_credits RENAME TO held_credits_all;
+
+ALTER TABLE held_credits_all
+  ADD COLUMN livemode BOOLEAN NOT NULL DEFAULT TRUE;
+
+UPDATE held_credits_all
+SET livemode = FALSE
+FROM transactions_all
+WHERE transactions_all.id = held_credits_all.transaction_id
+  AND NOT transactions_all.livemode;
+
+DROP INDEX held_credits_client_id_idx;
+
+CREATE INDEX held_credits_all_client_id_idx ON held_credits_all (client_id, livemode) WHERE state = 'held';
+
+SELECT create_livemode_view('held_credits');
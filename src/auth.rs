@@ -0,0 +1,211 @@
+//! Per-RPC authorization. Callers present a bearer token in the
+//! `authorization` metadata, and are granted scopes by config. Each scope
+//! grants a list of RPCs.
+use beancounter_grpc::tower_grpc::metadata::MetadataMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::config;
+
+// Grants every RPC
+static ALL_RPCS: &str = "*";
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum AuthError {
+    #[fail(display = "missing or unknown credentials")]
+    Unauthenticated,
+    #[fail(display = "{} is not permitted to call {}", caller, rpc)]
+    PermissionDenied { caller: String, rpc: String },
+}
+
+#[derive(Debug)]
+struct Caller {
+    name: String,
+    rpcs: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Authorizer {
+    enabled: bool,
+    // Keyed by the SHA-256 of the caller's token
+    callers: HashMap<String, Caller>,
+}
+
+/// The hex encoded SHA-256 of a token, as used in config
+pub fn hash_token(token: &str) -> String {
+    use data_encoding::HEXLOWER;
+    use sha2::{Digest, Sha256};
+
+    HEXLOWER.encode(&Sha256::digest(token.as_bytes()))
+}
+
+impl Authorizer {
+    /// An authorizer which permits every call
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(auth: &config::Auth) -> Self {
+        let callers = auth
+            .callers
+            .iter()
+            .map(|caller| {
+                let rpcs = caller
+                    .scopes
+                    .iter()
+                    .flat_map(|scope| match auth.scopes.get(scope) {
+                        Some(rpcs) => rpcs.clone(),
+                        None => {
+                            warn!("Unknown scope {} for caller {}", scope, caller.name);
+                            vec![]
+                        }
+                    })
+                    .collect();
+                (
+                    caller.token_sha256.to_lowercase(),
+                    Caller {
+                        name: caller.name.clone(),
+                        rpcs,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            enabled: auth.enabled,
+            callers,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The caller identified by the request metadata, if any
+    fn caller(&self, metadata: &MetadataMap) -> Option<&Caller> {
+        metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                let mut parts = value.splitn(2, ' ');
+                match (parts.next(), parts.next()) {
+                    (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("bearer") => {
+                        Some(token.trim())
+                    }
+                    _ => None,
+                }
+            })
+            .and_then(|token| self.callers.get(&hash_token(token)))
+    }
+
+    /// The name of the caller identified by the request metadata, for logging.
+    /// Callers are named even while authorization is disabled.
+    pub fn caller_name(&self, metadata: &MetadataMap) -> Option<&str> {
+        self.caller(metadata).map(|caller| caller.name.as_str())
+    }
+
+    /// Check the caller identified by the request metadata may call the RPC,
+    /// which is named as in the proto (i.e., "GetBalance").
+    pub fn authorize(&self, metadata: &MetadataMap, rpc: &str) -> Result<(), AuthError> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let caller = self.caller(metadata).ok_or(AuthError::Unauthenticated)?;
+
+        if caller.rpcs.contains(rpc) || caller.rpcs.contains(ALL_RPCS) {
+            Ok(())
+        } else {
+            Err(AuthError::PermissionDenied {
+                caller: caller.name.clone(),
+                rpc: rpc.into(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(authorization: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("authorization", authorization.parse().unwrap());
+        metadata
+    }
+
+    #[test]
+    fn test_authorize() {
+        let mut scopes = HashMap::new();
+        scopes.insert("balances".to_string(), vec!["GetBalance".to_string()]);
+        scopes.insert(
+            "payments".to_string(),
+            vec!["AddPayment".to_string(), "SettlePayment".to_string()],
+        );
+        scopes.insert("admin".to_string(), vec!["*".to_string()]);
+
+        let authorizer = Authorizer::from_config(&config::Auth {
+            enabled: true,
+            scopes,
+            callers: vec![
+                config::AuthCaller {
+                    name: "rolodex".into(),
+                    token_sha256: hash_token("rolodex-token"),
+                    scopes: vec!["balances".into()],
+                },
+                config::AuthCaller {
+                    name: "messaging".into(),
+                    token_sha256: hash_token("messaging-token").to_uppercase(),
+                    scopes: vec!["balances".into(), "payments".into(), "unknown".into()],
+                },
+                config::AuthCaller {
+                    name: "admin".into(),
+                    token_sha256: hash_token("admin-token"),
+                    scopes: vec!["admin".into()],
+                },
+            ],
+        });
+
+        let rolodex = metadata("Bearer rolodex-token");
+        assert_eq!(Ok(()), authorizer.authorize(&rolodex, "GetBalance"));
+        assert_eq!(
+            Err(AuthError::PermissionDenied {
+                caller: "rolodex".into(),
+                rpc: "AddPayment".into()
+            }),
+            authorizer.authorize(&rolodex, "AddPayment")
+        );
+
+        let messaging = metadata("bearer messaging-token");
+        assert_eq!(Ok(()), authorizer.authorize(&messaging, "GetBalance"));
+        assert_eq!(Ok(()), authorizer.authorize(&messaging, "SettlePayment"));
+        assert!(authorizer.authorize(&messaging, "AddCredits").is_err());
+
+        let admin = metadata("Bearer admin-token");
+        assert_eq!(Ok(()), authorizer.authorize(&admin, "ReverseTransaction"));
+
+        assert_eq!(
+            Err(AuthError::Unauthenticated),
+            authorizer.authorize(&MetadataMap::new(), "GetBalance")
+        );
+        assert_eq!(
+            Err(AuthError::Unauthenticated),
+            authorizer.authorize(&metadata("Bearer wrong-token"), "GetBalance")
+        );
+        assert_eq!(
+            Err(AuthError::Unauthenticated),
+            authorizer.authorize(&metadata("rolodex-token"), "GetBalance")
+        );
+
+        assert_eq!(Some("rolodex"), authorizer.caller_name(&rolodex));
+        assert_eq!(
+            None,
+            authorizer.caller_name(&metadata("Bearer wrong-token"))
+        );
+
+        // Everything is permitted when disabled
+        assert_eq!(
+            Ok(()),
+            Authorizer::disabled().authorize(&MetadataMap::new(), "SetReadOnly")
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use diesel::sql_types::{BigInt, Text};
+use std::time::Duration;
+
+use crate::config;
+use crate::database::DbConnection;
+
+// Tables streamed to BigQuery. Settled and expired payments are deleted from
+// payments, so they're streamed from payment_outcomes instead. Rows are
+// streamed once, when they're new.
+static EXPORTED_TABLES: &[&str] = &["transactions", "payment_outcomes", "stripe_connect_payouts"];
+
+// Failed inserts are retried this many times, doubling the wait each time
+static MAX_INSERT_RETRIES: u32 = 3;
+static INSERT_RETRY_BACKOFF_MS: u64 = 1000;
+
+#[derive(Debug, Fail)]
+pub enum BigQueryError {
+    #[fail(display = "database error: {}", err)]
+    DatabaseError { err: String },
+    #[fail(display = "unable to get access token: {}", err)]
+    TokenError { err: String },
+    #[fail(display = "insert into {} failed: {}", table, err)]
+    InsertError { table: String, err: String },
+}
+
+impl From<diesel::result::Error> for BigQueryError {
+    fn from(err: diesel::result::Error) -> Self {
+        Self::DatabaseError {
+            err: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, QueryableByName)]
+struct ExportRow {
+    #[sql_type = "BigInt"]
+    id: i64,
+    #[sql_type = "Text"]
+    row: String,
+}
+
+pub struct BigQuery {
+    project_id: String,
+    dataset: String,
+    token_url: String,
+    batch_size: u32,
+    client: reqwest::Client,
+}
+
+impl BigQuery {
+    /// None when streaming to BigQuery is disabled
+    pub fn from_config(config: &config::BigQuery) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            project_id: config.project_id.clone(),
+            dataset: config.dataset.clone(),
+            token_url: config.token_url.clone(),
+            batch_size: config.batch_size,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    fn access_token(&self) -> Result<String, BigQueryError> {
+        use dotenv::{dotenv, var};
+
+        dotenv().ok();
+
+        if let Ok(token) = var("BIGQUERY_ACCESS_TOKEN") {
+            return Ok(token);
+        }
+
+        let response: serde_json::Value = self
+            .client
+            .get(&self.token_url)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .and_then(|response| response.error_for_status()?.json())
+            .map_err(|err| BigQueryError::TokenError {
+                err: err.to_string(),
+            })?;
+
+        response["access_token"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| BigQueryError::TokenError {
+                err: "no access_token in response".into(),
+            })
+    }
+
+    /// Stream rows into a table with the tabledata.insertAll API. Each row's
+    /// insert ID lets BigQuery drop duplicates when a retried request had
+    /// already succeeded.
+    fn insert_all(
+        &self,
+        token: &str,
+        table: &str,
+        rows: &[ExportRow],
+    ) -> Result<(), BigQueryError> {
+        let url = format!(
+            "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables/{}/insertAll",
+            self.project_id, self.dataset, table
+        );
+        let insert_error = |err: String| BigQueryError::InsertError {
+            table: table.into(),
+            err,
+        };
+
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let json: serde_json::Value =
+                    serde_json::from_str(&row.row).map_err(|err| insert_error(err.to_string()))?;
+                Ok(serde_json::json!({
+                    "insertId": format!("{}-{}", table, row.id),
+                    "json": json,
+                }))
+            })
+            .collect::<Result<Vec<_>, BigQueryError>>()?;
+        let body = serde_json::json!({ "rows": rows });
+
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .client
+                .post(&url)
+                .bearer_auth(token)
+                .json(&body)
+                .send()
+                .and_then(|response| response.error_for_status()?.json::<serde_json::Value>());
+
+            match result {
+                // Rows rejected by BigQuery won't be accepted on a retry
+                Ok(response) => match response["insertErrors"].as_array() {
+                    Some(errors) if !errors.is_empty() => {
+                        return Err(insert_error(serde_json::to_string(errors).unwrap()))
+                    }
+                    _ => return Ok(()),
+                },
+                Err(err) if attempt < MAX_INSERT_RETRIES => {
+                    let backoff = INSERT_RETRY_BACKOFF_MS << attempt;
+                    warn!(
+                        "Insert into {} failed, retrying in {}ms: {}",
+                        table, backoff, err
+                    );
+                    std::thread::sleep(Duration::from_millis(backoff));
+                    attempt += 1;
+                }
+                Err(err) => return Err(insert_error(err.to_string())),
+            }
+        }
+    }
+
+    /// Stream the rows added to a table since the last export, in batches.
+    /// Returns the number of rows streamed.
+    fn export_table(
+        &self,
+        token: &str,
+        table: &str,
+        conn: &DbConnection,
+    ) -> Result<usize, BigQueryError> {
+        use crate::schema::bigquery_exports::columns::*;
+        use crate::schema::bigquery_exports::table as bigquery_exports;
+        use diesel::prelude::*;
+        use diesel::sql_query;
+
+        let mut exported_id: i64 = bigquery_exports
+            .select(last_id)
+            .filter(table_name.eq(table))
+            .first(conn)
+            .optional()?
+            .unwrap_or(0);
+
+        let mut count = 0;
+        loop {
+            // IDs are taken before commit, so a transaction may commit a
+            // lower ID after a later one. Leaving out the newest rows keeps
+            // them from being skipped.
+            let rows: Vec<ExportRow> = sql_query(format!(
+                r#"
+                SELECT t.id, row_to_json(t)::TEXT AS row
+                FROM {} AS t
+                WHERE t.id > $1
+                    AND t.created_at < NOW() - INTERVAL '1 minute'
+                ORDER BY t.id
+                LIMIT $2
+                "#,
+                table
+            ))
+            .bind::<BigInt, _>(exported_id)
+            .bind::<BigInt, _>(i64::from(self.batch_size))
+            .load(conn)?;
+
+            if rows.is_empty() {
+                return Ok(count);
+            }
+
+            self.insert_all(token, table, &rows)?;
+
+            // Only move past rows BigQuery has accepted
+            exported_id = rows[rows.len() - 1].id;
+            diesel::insert_into(bigquery_exports)
+                .values((table_name.eq(table), last_id.eq(exported_id)))
+                .on_conflict(table_name)
+                .do_update()
+                .set(last_id.eq(exported_id))
+                .execute(conn)?;
+
+            count += rows.len();
+        }
+    }
+
+    /// Stream new rows from each exported table
+    pub fn export(&self, conn: &DbConnection) -> Result<(), BigQueryError> {
+        let token = self.access_token()?;
+
+        for table in EXPORTED_TABLES.iter() {
+            let count = self.export_table(&token, table, conn)?;
+            info!("Streamed {} rows from {} to BigQuery", count, table);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate beancounter;
+extern crate chrono;
+extern crate env_logger;
+
+use beancounter::config;
+use beancounter::database;
+use std::env;
+
+// Sealed transactions are verified this many at a time
+static VERIFY_BATCH_SIZE: i64 = 10000;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "database error: {}", err)]
+    DatabaseError { err: String },
+    #[fail(display = "bad arguments")]
+    BadArgs,
+    #[fail(display = "request error: {}", err)]
+    RequestError { err: String },
+    #[fail(display = "ledger chain has {} problems", problems)]
+    LedgerChainBroken { problems: usize },
+    #[fail(display = "import file error on line {}: {}", line, err)]
+    ImportFileError { line: usize, err: String },
+    #[fail(display = "import of batch {} failed: {}", batch_id, result)]
+    ImportFailed { batch_id: String, result: String },
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(err: diesel::result::Error) -> Self {
+        Self::DatabaseError {
+            err: err.to_string(),
+        }
+    }
+}
+
+impl From<beancounter::service::RequestError> for Error {
+    fn from(err: beancounter::service::RequestError) -> Self {
+        Self::RequestError {
+            err: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    ExportEarnings {
+        year: i32,
+        min_gross_cents: i64,
+        min_payment_count: i64,
+    },
+    ReplayStripeEvents {
+        start_at: chrono::DateTime<chrono::Utc>,
+        end_at: chrono::DateTime<chrono::Utc>,
+    },
+    VerifyLedgerChain,
+    ImportTransactions {
+        batch_id: String,
+        path: String,
+    },
+}
+
+fn parse_args() -> Result<Command, Error> {
+    let args: Vec<String> = env::args().collect();
+    let usage = || {
+        error!(
+            "Usage: {} export-earnings <year> [--min-gross-cents <cents>] [--min-payment-count <count>]",
+            args[0]
+        );
+        error!(
+            "       {} replay-stripe-events <start RFC 3339 time> <end RFC 3339 time>",
+            args[0]
+        );
+        error!("       {} verify-ledger-chain", args[0]);
+        error!(
+            "       {} import-transactions <batch id> <JSON lines file>",
+            args[0]
+        );
+        Error::BadArgs
+    };
+
+    let mut iter = args.iter().skip(1);
+    match iter.next().map(String::as_str) {
+        Some("export-earnings") => {
+            let year = iter
+                .next()
+                .and_then(|year| year.parse().ok())
+                .ok_or_else(usage)?;
+            let mut min_gross_cents = 0;
+            let mut min_payment_count = 0;
+            while let Some(arg) = iter.next() {
+                let value = iter
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(usage)?;
+                match arg.as_str() {
+                    "--min-gross-cents" => min_gross_cents = value,
+                    "--min-payment-count" => min_payment_count = value,
+                    _ => return Err(usage()),
+                }
+            }
+            Ok(Command::ExportEarnings {
+                year,
+                min_gross_cents,
+                min_payment_count,
+            })
+        }
+        Some("replay-stripe-events") => {
+            let mut time = || {
+                iter.next()
+                    .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| time.with_timezone(&chrono::Utc))
+                    .ok_or_else(usage)
+            };
+            let start_at = time()?;
+            let end_at = time()?;
+            Ok(Command::ReplayStripeEvents { start_at, end_at })
+        }
+        Some("verify-ledger-chain") => Ok(Command::VerifyLedgerChain),
+        Some("import-transactions") => {
+            let batch_id = iter.next().cloned().ok_or_else(usage)?;
+            let path = iter.next().cloned().ok_or_else(usage)?;
+            Ok(Command::ImportTransactions { batch_id, path })
+        }
+        _ => Err(usage()),
+    }
+}
+
+/// Write the year's earnings for each client as CSV to stdout, for 1099-K
+/// reporting. The earnings are as of the last cron run.
+fn export_earnings(year: i32, min_gross_cents: i64, min_payment_count: i64) -> Result<(), Error> {
+    use beancounter::models::AnnualEarnings;
+    use beancounter::schema::annual_earnings::columns;
+    use beancounter::schema::annual_earnings::table as annual_earnings;
+    use diesel::prelude::*;
+
+    let db_pool = database::get_db_pool(&config::get().database.reader);
+
+    let conn = db_pool.get().unwrap();
+
+    let earnings: Vec<AnnualEarnings> = annual_earnings
+        .filter(
+            columns::year
+                .eq(year)
+                .and(columns::gross_cents.ge(min_gross_cents))
+                .and(columns::payment_count.ge(min_payment_count)),
+        )
+        .order(columns::client_id)
+        .load(&conn)?;
+
+    let months = (1..=12)
+        .map(|month| format!("month_{}_gross_cents", month))
+        .collect::<Vec<_>>()
+        .join(",");
+    println!(
+        "client_id,year,gross_cents,fee_cents,net_cents,payment_count,{}",
+        months
+    );
+    for row in earnings.iter() {
+        let months = row
+            .monthly_gross_cents
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        println!(
+            "{},{},{},{},{},{},{}",
+            row.client_id,
+            row.year,
+            row.gross_cents,
+            row.fee_cents,
+            row.gross_cents - row.fee_cents,
+            row.payment_count,
+            months
+        );
+    }
+
+    info!(
+        "Exported earnings for {} clients in {}",
+        earnings.len(),
+        year
+    );
+
+    Ok(())
+}
+
+/// Re-fetch the Stripe events created in the window, and apply those which
+/// weren't already processed, i.e., because the webhook endpoint was down.
+fn replay_stripe_events(
+    start_at: chrono::DateTime<chrono::Utc>,
+    end_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), Error> {
+    use beancounter_grpc::proto::ReplayStripeEventsRequest;
+
+    let db_pool_reader = database::get_db_pool(&config::get().database.reader);
+    let db_pool_writer = database::get_db_pool(&config::get().database.writer);
+    let beancounter = beancounter::service::BeanCounter::new(db_pool_reader, db_pool_writer);
+    beancounter.apply_config(&config::get());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    let response = runtime.block_on(beancounter.handle_replay_stripe_events(
+        &ReplayStripeEventsRequest {
+            start_at: Some(start_at.into()),
+            end_at: Some(end_at.into()),
+        },
+    ))?;
+
+    println!(
+        "fetched={} replayed={} skipped={} handled={}",
+        response.fetched, response.replayed, response.skipped, response.handled
+    );
+
+    Ok(())
+}
+
+/// Recompute the ledger hash chain from the start, printing where it doesn't
+/// match the ledger or its anchors. Fails if it doesn't.
+fn verify_ledger_chain() -> Result<(), Error> {
+    use beancounter::ledger_chain;
+
+    let db_pool = database::get_db_pool(&config::get().database.reader);
+    let conn = db_pool.get().unwrap();
+
+    let verification = ledger_chain::verify(VERIFY_BATCH_SIZE, &conn)?;
+    for problem in verification.problems.iter() {
+        println!("{}", problem);
+    }
+    println!(
+        "sealed={} anchors={} problems={}",
+        verification.sealed,
+        verification.anchors,
+        verification.problems.len()
+    );
+
+    if verification.problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::LedgerChainBroken {
+            problems: verification.problems.len(),
+        })
+    }
+}
+
+/// A line of an import file, i.e.:
+///
+/// `{"created_at": "2019-06-01T12:00:00Z", "client_id": "...", "tx_type": "credit", "tx_reason": "correction", "amount_cents": 500}`
+#[derive(Debug, Deserialize)]
+struct ImportEntry {
+    // RFC 3339
+    created_at: String,
+    // Omitted for the system account
+    #[serde(default)]
+    client_id: String,
+    tx_type: beancounter::sql_types::TransactionType,
+    tx_reason: beancounter::sql_types::TransactionReason,
+    amount_cents: i32,
+    #[serde(default)]
+    reference: String,
+}
+
+/// Import the transactions in the file, one JSON object per line, from the
+/// legacy billing system as a single batch. Importing the same batch ID again
+/// writes nothing, so a failed import can simply be rerun.
+fn import_transactions(batch_id: &str, path: &str) -> Result<(), Error> {
+    use beancounter_grpc::proto::{
+        import_transactions_response, transaction, ImportTransactionsRequest, ImportedTransaction,
+        Mode,
+    };
+    use std::io::BufRead;
+
+    let file_error = |line: usize, err: &dyn std::fmt::Display| Error::ImportFileError {
+        line,
+        err: err.to_string(),
+    };
+    let file = std::fs::File::open(path).map_err(|err| file_error(0, &err))?;
+    let mut transactions = vec![];
+    for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| file_error(index + 1, &err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: ImportEntry =
+            serde_json::from_str(&line).map_err(|err| file_error(index + 1, &err))?;
+        let created_at = chrono::DateTime::parse_from_rfc3339(&entry.created_at)
+            .map_err(|err| file_error(index + 1, &err))?
+            .with_timezone(&chrono::Utc);
+        transactions.push(ImportedTransaction {
+            created_at: Some(created_at.into()),
+            tx_type: transaction::Type::from(entry.tx_type) as i32,
+            tx_reason: transaction::Reason::from(entry.tx_reason) as i32,
+            client_id: entry.client_id,
+            amount_cents: entry.amount_cents,
+            reference: entry.reference,
+        });
+    }
+
+    let db_pool_reader = database::get_db_pool(&config::get().database.reader);
+    let db_pool_writer = database::get_db_pool(&config::get().database.writer);
+    let beancounter = beancounter::service::BeanCounter::new(db_pool_reader, db_pool_writer);
+    beancounter.apply_config(&config::get());
+
+    let response = beancounter.handle_import_transactions(&ImportTransactionsRequest {
+        batch_id: batch_id.into(),
+        transactions,
+        mode: Mode::Live as i32,
+    })?;
+
+    let result = import_transactions_response::Result::from_i32(response.result)
+        .unwrap_or(import_transactions_response::Result::Success);
+    println!(
+        "result={:?} operation_id={} tx_count={} clients={}",
+        result,
+        response.operation_id,
+        response.tx_count,
+        response.balances.len()
+    );
+
+    match result {
+        import_transactions_response::Result::Success
+        | import_transactions_response::Result::AlreadyImported => Ok(()),
+        _ => Err(Error::ImportFailed {
+            batch_id: batch_id.into(),
+            result: format!("{:?}", result),
+        }),
+    }
+}
+
+pub fn main() -> Result<(), Error> {
+    ::env_logger::init();
+
+    let command = parse_args()?;
+
+    config::load_config();
+
+    match command {
+        Command::ExportEarnings {
+            year,
+            min_gross_cents,
+            min_payment_count,
+        } => export_earnings(year, min_gross_cents, min_payment_count),
+        Command::ReplayStripeEvents { start_at, end_at } => replay_stripe_events(start_at, end_at),
+        Command::VerifyLedgerChain => verify_ledger_chain(),
+        Command::ImportTransactions { batch_id, path } => import_transactions(&batch_id, &path),
+    }
+}
@@ -11,13 +11,52 @@ extern crate env_logger;
 
 use beancounter::config;
 use beancounter::database;
+use beancounter::dual_write;
+use beancounter::leader;
+use beancounter::models::ClientId;
+use beancounter::uuid_format;
 use diesel::sql_types::*;
 use uuid::Uuid;
 
+// Expired payments are refunded in chunks of this many, each in its own
+// transaction, so a backlog doesn't hold one transaction open for too long
+static CLEANUP_CHUNK_SIZE: i64 = 500;
+
+// Unsettled payments are refunded to the sender this many days after they're
+// sent
+static PAYMENT_EXPIRY_DAYS: i64 = 30;
+
+// Transactions partitions are created this many months ahead of the current
+// one, so a few missed cron runs don't cause inserts to fail
+static PARTITION_MONTHS_AHEAD: i32 = 3;
+
+// Transactions are sealed into the ledger hash chain once they're this old, by
+// which time whatever wrote them has committed
+static LEDGER_CHAIN_SETTLE_SECS: f64 = 600.0;
+
+// Transactions are sealed in batches of this many, each in its own transaction
+static LEDGER_CHAIN_BATCH_SIZE: i64 = 1000;
+
+// Dual-written columns are backfilled in chunks of this many rows, each in its
+// own transaction
+static DUAL_WRITE_CHUNK_SIZE: i64 = 5000;
+
 #[derive(Debug, Fail)]
 pub enum Error {
+    #[fail(display = "bad arguments")]
+    BadArgs,
     #[fail(display = "database error: {}", err)]
     DatabaseError { err: String },
+    #[fail(display = "invalid client ID: {}", err)]
+    InvalidClientId { err: String },
+}
+
+impl From<uuid::parser::ParseError> for Error {
+    fn from(err: uuid::parser::ParseError) -> Self {
+        Self::InvalidClientId {
+            err: err.to_string(),
+        }
+    }
 }
 
 impl From<diesel::result::Error> for Error {
@@ -28,128 +67,2068 @@ impl From<diesel::result::Error> for Error {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Job {
+    Cleanup,
+    Payouts,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    // Refund at most this many expired payments in this run
+    limit: Option<i64>,
+    // Only report what the cleanup would refund and what the payouts would pay
+    // out, and skip the other jobs
+    dry_run: bool,
+    // Only run this job
+    only: Option<Job>,
+    // Only refund payments to or from this client, and only pay them out
+    client_id: Option<ClientId>,
+    // Make at most this many payouts in this run
+    max_payouts: Option<i64>,
+}
+
+impl Args {
+    // Dry runs, and runs limited to a job or a client, only run the cleanup and
+    // payouts
+    fn targeted(&self) -> bool {
+        self.dry_run || self.only.is_some() || self.client_id.is_some()
+    }
+
+    fn runs(&self, job: Job) -> bool {
+        self.only.map_or(true, |only| only == job)
+    }
+}
+
+fn parse_args() -> Result<Args, Error> {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = || {
+        error!(
+            "Usage: {} [--limit <n>] [--dry-run] [--only-cleanup | --only-payouts] \
+             [--client-id <id>] [--max-payouts <n>]",
+            args[0]
+        );
+        Error::BadArgs
+    };
+
+    let mut parsed = Args::default();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--limit" | "--max-payouts" => {
+                let limit: i64 = iter
+                    .next()
+                    .ok_or_else(usage)?
+                    .parse()
+                    .map_err(|_| usage())?;
+                if limit <= 0 {
+                    return Err(usage());
+                }
+                if arg == "--limit" {
+                    parsed.limit = Some(limit);
+                } else {
+                    parsed.max_payouts = Some(limit);
+                }
+            }
+            "--dry-run" => parsed.dry_run = true,
+            "--only-cleanup" | "--only-payouts" => {
+                let job = if arg == "--only-cleanup" {
+                    Job::Cleanup
+                } else {
+                    Job::Payouts
+                };
+                if parsed.only.map_or(false, |only| only != job) {
+                    return Err(usage());
+                }
+                parsed.only = Some(job);
+            }
+            "--client-id" => {
+                parsed.client_id = Some(
+                    iter.next()
+                        .ok_or_else(usage)?
+                        .parse()
+                        .map_err(|_| usage())?,
+                );
+            }
+            _ => return Err(usage()),
+        }
+    }
+
+    Ok(parsed)
+}
+
 #[derive(Debug, QueryableByName)]
-pub struct ClientPayout {
+pub struct DormantAccount {
     #[sql_type = "diesel::pg::types::sql_types::Uuid"]
-    pub client_id: Uuid,
+    pub client_id: ClientId,
+    #[sql_type = "Timestamp"]
+    pub last_activity_at: chrono::NaiveDateTime,
+    #[sql_type = "Bool"]
+    pub warn_due: bool,
+    #[sql_type = "Bool"]
+    pub expire_promo_due: bool,
+    #[sql_type = "Bool"]
+    pub escheat_due: bool,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct TransactionsPartition {
+    #[sql_type = "Text"]
+    pub partition_name: String,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct FloatExposure {
+    #[sql_type = "BigInt"]
+    pub float_cents: i64,
     #[sql_type = "BigInt"]
     pub withdrawable_cents: i64,
-    #[sql_type = "Bool"]
-    pub enable_automatic_payouts: bool,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct PendingExposure {
+    #[sql_type = "Text"]
+    pub age: String,
     #[sql_type = "BigInt"]
-    pub automatic_payout_threshold_cents: i64,
-    #[sql_type = "Nullable<Text>"]
-    pub stripe_user_id: Option<String>,
+    pub payment_cents: i64,
+    #[sql_type = "BigInt"]
+    pub payment_count: i64,
+}
+
+fn make_intcounter(name: &str, description: &str) -> instrumented::prometheus::IntCounter {
+    let counter = instrumented::prometheus::IntCounter::new(name, description).unwrap();
+    instrumented::register(Box::new(counter.clone())).unwrap();
+    counter
 }
 
-fn do_cleanup() -> Result<(), Error> {
-    use beancounter::models::Payment;
+fn make_intgauge(name: &str, description: &str) -> instrumented::prometheus::IntGauge {
+    let gauge = instrumented::prometheus::IntGauge::new(name, description).unwrap();
+    instrumented::register(Box::new(gauge.clone())).unwrap();
+    gauge
+}
+
+fn make_intgauge_vec(
+    name: &str,
+    description: &str,
+    labels: &[&str],
+) -> instrumented::prometheus::IntGaugeVec {
+    let gauge = instrumented::prometheus::IntGaugeVec::new(
+        instrumented::prometheus::Opts::new(name, description),
+        labels,
+    )
+    .unwrap();
+    instrumented::register(Box::new(gauge.clone())).unwrap();
+    gauge
+}
+
+/// Refund an expired payment, and remove it. Returns false if it was settled
+/// or refunded in the meantime.
+fn refund_expired_payment(
+    expired_payment_id: i64,
+    system_client_id: ClientId,
+    internal_accounts: &beancounter::service::InternalAccounts,
+    config: &config::Config,
+    cron_run_id: Uuid,
+    conn: &diesel::r2d2::PooledConnection<
+        diesel::r2d2::ConnectionManager<beancounter::database::DbConnection>,
+    >,
+) -> Result<bool, Error> {
+    use beancounter::fees::{self, FeeChange, FeeSchedule};
+    use beancounter::models::{NewPaymentOutcome, NewPaymentRefund, Payment};
+    use beancounter::schema::payment_outcomes::table as payment_outcomes;
+    use beancounter::schema::payment_refunds::columns as refund_columns;
+    use beancounter::schema::payment_refunds::table as payment_refunds;
     use beancounter::schema::payments::dsl::*;
-    use beancounter::service::{add_promo_transaction, add_transaction};
-    use beancounter::sql_types::TransactionReason;
+    use beancounter::service::{
+        add_promo_transaction, add_transaction, client_account_tier, update_and_return_balance,
+    };
+    use beancounter::sql_types::{PaymentOutcome, RefundReason, TransactionReason};
+    use chrono::Utc;
+    use diesel::dsl::count_star;
+    use diesel::prelude::*;
+
+    // Lock the payment so it can't be settled while we're refunding it.
+    // If it's gone, it was settled or refunded in the meantime.
+    let payment: Payment = match payments
+        .filter(id.eq(expired_payment_id))
+        .for_update()
+        .first(conn)
+        .optional()?
+    {
+        Some(payment) => payment,
+        None => return Ok(false),
+    };
+
+    let already_refunded = payment_refunds
+        .filter(refund_columns::payment_id.eq(payment.id))
+        .select(count_star())
+        .first::<i64>(conn)?
+        > 0;
+
+    if !already_refunded {
+        // This payment was never settled. Refund (credit) the fee to the sender.
+        // But first, check if it was a promo.
+        let is_system_promo = payment.client_id_from == system_client_id;
+        let (tx_credit, _tx_debit) = if is_system_promo {
+            // This was a promo because it came from the system account
+            add_promo_transaction(
+                Some(payment.client_id_from),
+                internal_accounts.promo,
+                payment.payment_cents,
+                TransactionReason::MessageUnread,
+                conn,
+            )?
+        } else {
+            // Not a promo
+            add_transaction(
+                Some(payment.client_id_from),
+                internal_accounts.float,
+                payment.payment_cents,
+                TransactionReason::MessageUnread,
+                conn,
+            )?
+        };
+
+        // The send fee is refunded as well, or kept in whole or in part, by
+        // the policy. It's taken from the fee schedule for the sender's tier
+        // as of when the payment was sent, as the fee charged isn't kept with
+        // the payment. Promos from the system account had no fee.
+        let policy = config.expiry_refunds.policy;
+        let (fee_retained_cents, fee_refunded_cents) = if is_system_promo {
+            (0, 0)
+        } else {
+            let tier = client_account_tier(payment.client_id_from, conn)?;
+            let base = FeeSchedule::from_config(&config.fees);
+            let fees = fees::in_effect(
+                &base,
+                &FeeChange::from_config(&base, &config.fees),
+                payment.created_at,
+            )
+            .for_tier(config.tiers.get(tier));
+            let age_days = (Utc::now().naive_utc() - payment.created_at).num_days();
+            let fee_cents = fees.send_fee_cents(payment.payment_cents);
+            let fee_retained_cents = fees.expiry_fee_retained_cents(
+                payment.payment_cents,
+                age_days,
+                &config.expiry_refunds,
+            );
+            (fee_retained_cents, fee_cents - fee_retained_cents)
+        };
+        let fee_transaction_id = if fee_refunded_cents > 0 {
+            let (tx_credit, _tx_debit) = add_transaction(
+                Some(payment.client_id_from),
+                internal_accounts.fees,
+                fee_refunded_cents,
+                TransactionReason::MessageUnread,
+                conn,
+            )?;
+            Some(tx_credit.id)
+        } else {
+            None
+        };
+
+        // Record why the credit was issued
+        diesel::insert_into(payment_refunds)
+            .values(&NewPaymentRefund {
+                payment_id: payment.id,
+                payment_created_at: payment.created_at,
+                client_id_from: payment.client_id_from,
+                client_id_to: payment.client_id_to,
+                payment_cents: payment.payment_cents,
+                message_hash: payment.message_hash.clone(),
+                is_promo: payment.is_promo,
+                reason: RefundReason::Expired,
+                cron_run_id,
+                transaction_id: tx_credit.id,
+                policy,
+                fee_retained_cents,
+                fee_refunded_cents,
+                fee_transaction_id,
+            })
+            .execute(conn)?;
+
+        diesel::insert_into(payment_outcomes)
+            .values(&NewPaymentOutcome::from_payment(
+                &payment,
+                PaymentOutcome::Expired,
+            ))
+            .execute(conn)?;
+    } else {
+        warn!(
+            "Payment id={} was already refunded, removing it",
+            payment.id
+        );
+    }
+
+    // Delete the payment record from the DB
+    diesel::delete(payments)
+        .filter(id.eq(payment.id))
+        .execute(conn)?;
+
+    // The refund, and the payment no longer pending settlement
+    update_and_return_balance(payment.client_id_from, conn)?;
+
+    Ok(!already_refunded)
+}
+
+fn do_cleanup(cron_run_id: Uuid, args: &Args) -> Result<(), Error> {
+    use beancounter::schema::payments::dsl::*;
+    use beancounter::service::InternalAccounts;
     use chrono::{Duration, Utc};
     use diesel::connection::Connection;
     use diesel::prelude::*;
+    use std::time::Instant;
 
-    let db_pool = database::get_db_pool(&config::CONFIG.database.writer);
+    let config = config::get();
+    let system_client_id: ClientId = config.system_account.client_id.parse()?;
+    let internal_accounts = InternalAccounts::from_config(&config.internal_accounts);
+
+    let db_pool = database::get_db_pool(&config.database.writer);
 
     let conn = db_pool.get().unwrap();
 
+    let processed_counter = make_intcounter(
+        "cleanup_payments_processed",
+        "Expired payments processed by the cleanup",
+    );
+    let refunded_counter = make_intcounter(
+        "cleanup_payments_refunded",
+        "Expired payments refunded by the cleanup",
+    );
+
     let now = Utc::now().naive_utc();
-    let thirty_days_ago = now - Duration::days(30);
+    let expired_before = now - Duration::days(PAYMENT_EXPIRY_DAYS);
+
+    // Each chunk is refunded in its own transaction, so if we crash part way
+    // through, the payments which remain will be picked up by the next run.
+    let mut last_id = 0;
+    let mut processed_count = 0;
+    let mut refund_count = 0;
+    loop {
+        let chunk_size = match args.limit {
+            Some(limit) => std::cmp::min(CLEANUP_CHUNK_SIZE, limit - processed_count),
+            None => CLEANUP_CHUNK_SIZE,
+        };
+        if chunk_size <= 0 {
+            info!(
+                "Stopping cleanup at the limit of {} payments (cron_run_id={})",
+                processed_count, cron_run_id
+            );
+            break;
+        }
 
-    conn.transaction::<_, Error, _>(|| {
-        let expired_payments: Vec<Payment> = payments
-            .filter(created_at.lt(thirty_days_ago))
+        let mut query = payments
+            .filter(created_at.lt(expired_before).and(id.gt(last_id)))
+            .into_boxed();
+        if let Some(client) = args.client_id {
+            query = query.filter(client_id_from.eq(client).or(client_id_to.eq(client)));
+        }
+        let chunk: Vec<(i64, i32)> = query
+            .order(id.asc())
+            .select((id, payment_cents))
+            .limit(chunk_size)
             .get_results(&conn)?;
+        if chunk.is_empty() {
+            break;
+        }
+        last_id = chunk[chunk.len() - 1].0;
+        processed_count += chunk.len() as i64;
+
+        if args.dry_run {
+            info!(
+                "Would refund {} expired payments totalling {} cents, through id={} (cron_run_id={})",
+                chunk.len(),
+                chunk.iter().map(|(_, cents)| i64::from(*cents)).sum::<i64>(),
+                last_id,
+                cron_run_id
+            );
+            continue;
+        }
 
-        for payment in expired_payments.iter() {
-            // This payment was never settled. Refund (credit) the fee to the sender.
-            // But first, check if it was a promo.
-            if payment.client_id_from.to_simple().to_string()
-                == config::CONFIG.system_account.client_id
-            {
-                // This was a promo because it came from the system account
-                add_promo_transaction(
-                    Some(payment.client_id_from),
-                    None,
-                    payment.payment_cents,
-                    TransactionReason::MessageUnread,
+        let started = Instant::now();
+        let refunded = conn.transaction::<usize, Error, _>(|| {
+            let mut refunded = 0;
+            for (expired_payment_id, _) in chunk.iter() {
+                if refund_expired_payment(
+                    *expired_payment_id,
+                    system_client_id,
+                    &internal_accounts,
+                    &config,
+                    cron_run_id,
                     &conn,
-                )?;
+                )? {
+                    refunded += 1;
+                }
+            }
+            Ok(refunded)
+        })?;
+        refund_count += refunded;
+
+        processed_counter.inc_by(chunk.len() as i64);
+        refunded_counter.inc_by(refunded as i64);
+        info!(
+            "Cleanup progress: {} payments processed, {} refunded, chunk took {:?} (cron_run_id={})",
+            processed_count,
+            refund_count,
+            started.elapsed(),
+            cron_run_id
+        );
+    }
+
+    info!(
+        "{} expired payments processed, {} refunded (cron_run_id={})",
+        processed_count, refund_count, cron_run_id
+    );
+
+    Ok(())
+}
+
+fn do_dormancy(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::{Balance, NewDormancyEvent};
+    use beancounter::schema::balances::columns as balance_columns;
+    use beancounter::schema::balances::table as balances;
+    use beancounter::schema::dormancy_events::dsl::*;
+    use beancounter::service::{
+        add_transactions, update_and_return_balance, InternalAccounts, TransactionLeg,
+    };
+    use beancounter::sql_types::{DormancyAction, TransactionReason};
+    use diesel::connection::Connection;
+    use diesel::dsl::count_star;
+    use diesel::prelude::*;
+    use diesel::sql_query;
+
+    let config = config::get();
+    let rules = &config.dormancy;
+    if rules.warn_after_months == 0
+        && rules.expire_promo_after_months == 0
+        && rules.escheat_after_months == 0
+    {
+        info!("Dormancy rules are disabled");
+        return Ok(());
+    }
+    let internal_accounts = InternalAccounts::from_config(&config.internal_accounts);
+
+    let db_pool = database::get_db_pool(&config.database.writer);
+
+    let conn = db_pool.get().unwrap();
+
+    // An account's last activity is its most recent transaction, not counting
+    // the ones we make here.
+    let accounts: Vec<DormantAccount> = sql_query(
+        r#"
+        SELECT
+            *
+        FROM (
+            SELECT
+                b.client_id,
+                a.last_activity_at,
+                ($1 > 0
+                    AND a.last_activity_at < NOW() - $1 * interval '1 month') AS warn_due,
+                ($2 > 0
+                    AND b.promo_cents > 0
+                    AND a.last_activity_at < NOW() - $2 * interval '1 month') AS expire_promo_due,
+                ($3 > 0
+                    AND a.last_activity_at < NOW() - $3 * interval '1 month') AS escheat_due
+            FROM
+                balances AS b
+                INNER JOIN (
+                    SELECT
+                        client_id,
+                        MAX(created_at) AS last_activity_at
+                    FROM
+                        transactions
+                    WHERE
+                        client_id IS NOT NULL
+                        AND tx_reason NOT IN ('promo_expired', 'escheated')
+                    GROUP BY
+                        client_id) AS a ON a.client_id = b.client_id
+            WHERE
+                b.balance_cents > 0
+                OR b.promo_cents > 0) AS d
+        WHERE
+            warn_due
+            OR expire_promo_due
+            OR escheat_due;
+           "#,
+    )
+    .bind::<Integer, _>(rules.warn_after_months as i32)
+    .bind::<Integer, _>(rules.expire_promo_after_months as i32)
+    .bind::<Integer, _>(rules.escheat_after_months as i32)
+    .load(&conn)?;
+
+    info!(
+        "{} dormant accounts (cron_run_id={})",
+        accounts.len(),
+        cron_run_id
+    );
+
+    for account in accounts.iter() {
+        let actions = conn.transaction::<Vec<DormancyAction>, Error, _>(|| {
+            // Lock the balance so it can't change underneath us
+            balances
+                .filter(balance_columns::client_id.eq(account.client_id))
+                .for_update()
+                .first::<Balance>(&conn)?;
+            let balance = update_and_return_balance(account.client_id, &conn)?;
+
+            // Accounts are always warned before anything is taken from them,
+            // unless warnings are disabled.
+            let warned = rules.warn_after_months == 0
+                || dormancy_events
+                    .filter(
+                        client_id
+                            .eq(account.client_id)
+                            .and(action.eq(DormancyAction::Warned))
+                            .and(created_at.gt(account.last_activity_at)),
+                    )
+                    .select(count_star())
+                    .first::<i64>(&conn)?
+                    > 0;
+
+            let mut events = vec![];
+            if !warned {
+                if account.warn_due {
+                    events.push((
+                        DormancyAction::Warned,
+                        balance.balance_cents + balance.promo_cents,
+                        None,
+                    ));
+                }
             } else {
-                // Not a promo
-                add_transaction(
-                    Some(payment.client_id_from),
-                    None,
-                    payment.payment_cents,
-                    TransactionReason::MessageUnread,
-                    &conn,
-                )?;
+                let mut legs = vec![];
+                if (account.expire_promo_due || account.escheat_due) && balance.promo_cents > 0 {
+                    legs.push(TransactionLeg::promo(
+                        internal_accounts.promo,
+                        Some(account.client_id),
+                        balance.promo_cents as i32,
+                        TransactionReason::PromoExpired,
+                    ));
+                }
+                if account.escheat_due && balance.balance_cents > 0 {
+                    legs.push(TransactionLeg::new(
+                        internal_accounts.float,
+                        Some(account.client_id),
+                        balance.balance_cents as i32,
+                        TransactionReason::Escheated,
+                    ));
+                }
+
+                for (leg, (_tx_credit, tx_debit)) in
+                    legs.iter().zip(add_transactions(&legs, &conn)?)
+                {
+                    let dormancy_action = match leg.reason {
+                        TransactionReason::Escheated => DormancyAction::Escheated,
+                        _ => DormancyAction::PromoExpired,
+                    };
+                    events.push((
+                        dormancy_action,
+                        i64::from(leg.amount_cents),
+                        Some(tx_debit.id),
+                    ));
+                }
+
+                update_and_return_balance(account.client_id, &conn)?;
             }
 
-            // Delete the payment record from the DB
-            diesel::delete(payments)
-                .filter(id.eq(payment.id))
+            let new_events: Vec<NewDormancyEvent> = events
+                .iter()
+                .map(|(dormancy_action, amount, tx_id)| NewDormancyEvent {
+                    client_id: account.client_id,
+                    action: *dormancy_action,
+                    last_activity_at: account.last_activity_at,
+                    amount_cents: *amount,
+                    cron_run_id,
+                    transaction_id: *tx_id,
+                })
+                .collect();
+            diesel::insert_into(dormancy_events)
+                .values(&new_events)
                 .execute(&conn)?;
+
+            Ok(events
+                .iter()
+                .map(|(dormancy_action, _, _)| *dormancy_action)
+                .collect())
+        })?;
+
+        for dormancy_action in actions.iter() {
+            info!(
+                "Dormant account client_id={} last_activity_at={}: {:?} (cron_run_id={})",
+                account.client_id, account.last_activity_at, dormancy_action, cron_run_id
+            );
         }
+    }
 
-        Ok(())
-    })?;
+    Ok(())
+}
+
+fn do_payouts(cron_run_id: Uuid, args: &Args) -> Result<(), Error> {
+    use beancounter::models::NewPayoutRun;
+    use beancounter::reports::PayoutEligibility;
+    use beancounter_grpc::proto::{connect_payout_response, ConnectPayoutRequest, Mode};
+    use chrono::Utc;
+    use diesel::RunQueryDsl;
+    use std::collections::BTreeMap;
+
+    let started_at = Utc::now().naive_utc();
+
+    let succeeded_counter =
+        make_intcounter("payouts_succeeded", "Payouts made by the cron payout run");
+    let failed_counter = make_intcounter(
+        "payouts_failed",
+        "Payouts which failed in the cron payout run",
+    );
+    let skipped_counter = make_intcounter(
+        "payouts_skipped",
+        "Eligible accounts skipped by the cron payout run",
+    );
+    let cents_counter = make_intcounter(
+        "payouts_cents",
+        "Total amount paid out by the cron payout run",
+    );
+
+    let db_pool_reader = database::get_db_pool(&config::get().database.reader);
+    let db_pool_writer = database::get_db_pool(&config::get().database.writer);
+    let mut beancounter =
+        beancounter::service::BeanCounter::new(db_pool_reader.clone(), db_pool_writer.clone());
+    beancounter.set_internal_accounts(beancounter::service::InternalAccounts::from_config(
+        &config::get().internal_accounts,
+    ));
+    beancounter.set_withholding_rates(config::get().withholding.rates.clone());
+
+    let reader_conn = db_pool_reader.get().unwrap();
+
+    let payout_results = PayoutEligibility {
+        client_id: args.client_id,
+        limit: args.max_payouts,
+        ..PayoutEligibility::default()
+    }
+    .load(&reader_conn)?;
+
+    info!("{} payouts to process", payout_results.len());
+
+    if args.dry_run {
+        for payout in payout_results.iter() {
+            info!(
+                "Would pay out {} cents to {} (cron_run_id={})",
+                payout.withdrawable_cents, payout.client_id, cron_run_id
+            );
+        }
+        info!(
+            "Would pay out {} cents to {} accounts (cron_run_id={})",
+            payout_results
+                .iter()
+                .map(|payout| payout.withdrawable_cents)
+                .sum::<i64>(),
+            payout_results.len(),
+            cron_run_id
+        );
+        return Ok(());
+    }
+
+    let mut succeeded = 0;
+    let mut failed = 0;
+    let mut skipped_reasons: BTreeMap<&str, i32> = BTreeMap::new();
+    let mut total_cents: i64 = 0;
+
+    // Payouts are made one at a time, each waiting on its Stripe transfers
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    for payout in payout_results.iter() {
+        let response = runtime.block_on(beancounter.handle_connect_payout(&ConnectPayoutRequest {
+            client_id: payout.client_id.to_string(),
+            amount_cents: payout.withdrawable_cents as i32,
+            description: String::new(),
+            statement_descriptor: String::new(),
+            mode: Mode::Live as i32,
+        }));
+
+        let skipped_reason = match response {
+            Ok(response) => {
+                info!("Payout: {:?}", response);
+                match connect_payout_response::Result::from_i32(response.result) {
+                    Some(connect_payout_response::Result::Success) => None,
+                    Some(connect_payout_response::Result::InsufficientBalance) => {
+                        Some("insufficient_balance")
+                    }
+                    Some(connect_payout_response::Result::InvalidAmount) => Some("invalid_amount"),
+                    Some(connect_payout_response::Result::ConfirmationRequired) => {
+                        Some("confirmation_required")
+                    }
+                    Some(connect_payout_response::Result::NotFound) => Some("not_found"),
+                    Some(connect_payout_response::Result::AccountFrozen) => {
+                        info!("Payout skipped, ledger locked: {}", payout.client_id);
+                        Some("ledger_locked")
+                    }
+                    Some(connect_payout_response::Result::PayoutsDisabled) => {
+                        Some("payouts_disabled")
+                    }
+                    Some(connect_payout_response::Result::KycRequired) => Some("kyc_required"),
+                    // The transfer was attempted, and failed
+                    Some(connect_payout_response::Result::StripeUnavailable)
+                    | Some(connect_payout_response::Result::LimitExceeded) => {
+                        failed += 1;
+                        continue;
+                    }
+                    None => Some("unknown"),
+                }
+            }
+            Err(err) => {
+                error!("Payout error: {:?}", err);
+                failed += 1;
+                continue;
+            }
+        };
+
+        match skipped_reason {
+            Some(reason) => *skipped_reasons.entry(reason).or_insert(0) += 1,
+            None => {
+                succeeded += 1;
+                total_cents += payout.withdrawable_cents;
+            }
+        }
+    }
+
+    let skipped: i32 = skipped_reasons.values().sum();
+    succeeded_counter.inc_by(succeeded as i64);
+    failed_counter.inc_by(failed as i64);
+    skipped_counter.inc_by(skipped as i64);
+    cents_counter.inc_by(total_cents);
+
+    info!(
+        "Payout run: {} candidates, {} succeeded, {} failed, {} skipped {:?}, {} cents (cron_run_id={})",
+        payout_results.len(),
+        succeeded,
+        failed,
+        skipped,
+        skipped_reasons,
+        total_cents,
+        cron_run_id
+    );
+
+    let conn = db_pool_writer.get().unwrap();
+    diesel::insert_into(beancounter::schema::payout_runs::table)
+        .values(&NewPayoutRun {
+            cron_run_id,
+            started_at,
+            finished_at: Utc::now().naive_utc(),
+            candidates: payout_results.len() as i32,
+            succeeded,
+            failed,
+            skipped,
+            skipped_reasons: serde_json::json!(skipped_reasons),
+            total_cents,
+        })
+        .execute(&conn)?;
 
     Ok(())
 }
 
-fn do_payouts() -> Result<(), Error> {
-    use beancounter_grpc::proto::ConnectPayoutRequest;
+fn do_risk_flags(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::RiskFlag;
+    use diesel::sql_query;
+    use diesel::RunQueryDsl;
+
+    let config = config::get();
+    let rules = &config.risk;
+    if rules.baseline_days == 0 {
+        info!("Risk flags are disabled");
+        return Ok(());
+    }
+
+    let db_pool = database::get_db_pool(&config.database.writer);
+
+    let conn = db_pool.get().unwrap();
+
+    // Compare each account's credits added and payouts over the last day to
+    // its daily average over the preceding baseline days. Accounts are flagged
+    // at most once per kind per day. Reversed operations aren't counted.
+    let flags: Vec<RiskFlag> = sql_query(
+        r#"
+        INSERT INTO risk_flags (client_id, kind, ds, amount_cents, baseline_cents, cron_run_id)
+        SELECT
+            client_id,
+            kind,
+            CURRENT_DATE,
+            recent_cents,
+            baseline_cents,
+            $4
+        FROM (
+            SELECT
+                t.client_id,
+                (CASE WHEN t.tx_reason = 'credit_added' THEN
+                    'credit_velocity'
+                ELSE
+                    'payout_velocity'
+                END)::RISK_FLAG_KIND AS kind,
+                SUM(ABS(t.amount_cents))
+                    FILTER (WHERE t.created_at >= NOW() - interval '1 day') AS recent_cents,
+                COALESCE(SUM(ABS(t.amount_cents))
+                    FILTER (WHERE t.created_at < NOW() - interval '1 day'), 0)::FLOAT / $1
+                    AS baseline_cents
+            FROM
+                transactions AS t
+            WHERE
+                t.client_id IS NOT NULL
+                AND ((t.tx_reason = 'credit_added' AND t.tx_type = 'credit')
+                    OR (t.tx_reason = 'payout' AND t.tx_type = 'debit'))
+                AND t.created_at >= NOW() - ($1 + 1) * interval '1 day'
+                AND NOT EXISTS (
+                    SELECT
+                        *
+                    FROM
+                        transactions AS r
+                    WHERE
+                        r.reverses_operation_id = t.operation_id)
+            GROUP BY
+                1,
+                2) AS v
+        WHERE
+            recent_cents >= $3
+            AND recent_cents >= $2 * baseline_cents
+        ON CONFLICT (client_id, kind, ds)
+            DO NOTHING
+        RETURNING
+            *;
+           "#,
+    )
+    .bind::<Integer, _>(rules.baseline_days as i32)
+    .bind::<Double, _>(rules.velocity_multiplier)
+    .bind::<BigInt, _>(rules.min_amount_cents)
+    .bind::<diesel::pg::types::sql_types::Uuid, _>(cron_run_id)
+    .get_results(&conn)?;
+
+    info!(
+        "{} accounts flagged for review (cron_run_id={})",
+        flags.len(),
+        cron_run_id
+    );
+
+    // One event per flag, for alerting
+    for flag in flags.iter() {
+        warn!(
+            "risk_flag id={} client_id={} kind={:?} amount_cents={} baseline_cents={:.0} (cron_run_id={})",
+            flag.id, flag.client_id, flag.kind, flag.amount_cents, flag.baseline_cents, cron_run_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Fetch rates for credits in other currencies. The source quotes each
+/// currency per USD, and we store USD per unit of the currency.
+fn fetch_fx_rates(url: &str, currencies: &[String]) -> Result<Vec<(String, f64)>, String> {
+    let response: serde_json::Value = reqwest::get(url)
+        .and_then(|response| response.error_for_status()?.json())
+        .map_err(|err| err.to_string())?;
+
+    currencies
+        .iter()
+        .map(|currency| {
+            let currency = currency.to_uppercase();
+            match response["rates"][&currency].as_f64() {
+                Some(per_usd) if per_usd > 0.0 => Ok((currency, 1.0 / per_usd)),
+                _ => Err(format!("no rate for {} in response", currency)),
+            }
+        })
+        .collect()
+}
+
+fn do_fx_rates(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::NewFxRate;
+    use beancounter::schema::fx_rates::table as fx_rates;
+    use diesel::prelude::*;
+
+    let config = config::get();
+    let fx = &config.fx;
+    if fx.url.is_empty() || fx.currencies.is_empty() {
+        info!("FX rates are disabled");
+        return Ok(());
+    }
+
+    // Credits keep using the previous rates until they're too old, so a
+    // failed fetch shouldn't hold up the rest of the run.
+    let rates = match fetch_fx_rates(&fx.url, &fx.currencies) {
+        Ok(rates) => rates,
+        Err(err) => {
+            error!("Unable to fetch FX rates from {}: {}", fx.source, err);
+            return Ok(());
+        }
+    };
+
+    let db_pool = database::get_db_pool(&config.database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let new_rates: Vec<NewFxRate> = rates
+        .into_iter()
+        .map(|(currency, rate)| NewFxRate {
+            source: fx.source.clone(),
+            currency,
+            rate,
+            cron_run_id: Some(cron_run_id),
+        })
+        .collect();
+
+    diesel::insert_into(fx_rates)
+        .values(&new_rates)
+        .execute(&conn)?;
+
+    info!(
+        "Stored {} FX rates from {} (cron_run_id={})",
+        new_rates.len(),
+        fx.source,
+        cron_run_id
+    );
+
+    Ok(())
+}
+
+fn do_release_held_credits(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::schema::held_credits::dsl::*;
+    use beancounter::service::update_and_return_balance;
+    use beancounter::sql_types::HeldCreditState;
+    use diesel::connection::Connection;
     use diesel::prelude::*;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    // Release credits which are past their hold, and refresh the balances
+    // they're now spendable from. Each ledger's credits are released from
+    // its own balances.
+    for livemode in &["on", "off"] {
+        let released = conn.transaction::<Vec<ClientId>, Error, _>(|| {
+            diesel::sql_query(format!("SET LOCAL beancounter.livemode = {}", livemode))
+                .execute(&conn)?;
+            let mut clients: Vec<ClientId> = diesel::update(
+                held_credits.filter(
+                    state
+                        .eq(HeldCreditState::Held)
+                        .and(release_at.le(diesel::dsl::now)),
+                ),
+            )
+            .set((
+                state.eq(HeldCreditState::Released),
+                reviewed_at.eq(diesel::dsl::now),
+            ))
+            .returning(client_id)
+            .get_results(&conn)?;
+            clients.sort();
+            clients.dedup();
+
+            for client in clients.iter() {
+                update_and_return_balance(*client, &conn)?;
+            }
+
+            Ok(clients)
+        })?;
+
+        info!(
+            "Released held credits for {} clients livemode={} (cron_run_id={})",
+            released.len(),
+            livemode,
+            cron_run_id
+        );
+    }
+
+    Ok(())
+}
+
+fn do_payment_reminders(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::{NewOutboxEvent, Payment};
+    use beancounter::schema::outbox_events::table as outbox_events;
+    use beancounter::schema::payments::dsl::*;
+    use beancounter::sql_types::OutboxEventType;
+    use chrono::{Duration, Utc};
+    use diesel::connection::Connection;
+    use diesel::prelude::*;
+    use std::collections::BTreeMap;
+
+    let reminder_days = i64::from(config::get().reminders.payment_expiry_days);
+    if reminder_days == 0 {
+        return Ok(());
+    }
+
+    let reminded_counter = make_intcounter(
+        "payment_reminders_payments",
+        "Unread payments recipients were reminded of before they expire",
+    );
+    let events_counter = make_intcounter(
+        "payment_reminders_sent",
+        "Payment expiry reminders sent, one per recipient per run",
+    );
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let now = Utc::now().naive_utc();
+    // Payments already past expiry are left to the cleanup
+    let expired_before = now - Duration::days(PAYMENT_EXPIRY_DAYS);
+    let expiring_before = now - Duration::days(PAYMENT_EXPIRY_DAYS - reminder_days);
+
+    // Recipients are reminded in chunks, each in its own transaction, with
+    // all of their expiring payments in one event
+    let mut last_recipient = ClientId::from(Uuid::nil());
+    let mut reminded_count = 0;
+    let mut recipient_count = 0;
+    loop {
+        let recipients: Vec<ClientId> = payments
+            .filter(
+                created_at
+                    .ge(expired_before)
+                    .and(created_at.lt(expiring_before))
+                    .and(reminded_at.is_null())
+                    .and(client_id_to.gt(last_recipient)),
+            )
+            .select(client_id_to)
+            .distinct()
+            .order(client_id_to.asc())
+            .limit(CLEANUP_CHUNK_SIZE)
+            .load(&conn)?;
+        if recipients.is_empty() {
+            break;
+        }
+        last_recipient = recipients[recipients.len() - 1];
+
+        let reminded = conn.transaction::<usize, Error, _>(|| {
+            let expiring: Vec<Payment> = payments
+                .filter(
+                    created_at
+                        .ge(expired_before)
+                        .and(created_at.lt(expiring_before))
+                        .and(reminded_at.is_null())
+                        .and(client_id_to.eq_any(recipients.clone())),
+                )
+                .order(id.asc())
+                .for_update()
+                .load(&conn)?;
+            // They may have been settled since the recipients were loaded
+            if expiring.is_empty() {
+                return Ok(0);
+            }
+
+            let mut by_recipient: BTreeMap<ClientId, Vec<&Payment>> = BTreeMap::new();
+            for payment in expiring.iter() {
+                by_recipient
+                    .entry(payment.client_id_to)
+                    .or_insert_with(Vec::new)
+                    .push(payment);
+            }
+
+            let events: Vec<NewOutboxEvent> = by_recipient
+                .iter()
+                .map(|(recipient, reminders)| NewOutboxEvent {
+                    client_id: Some(*recipient),
+                    event_type: OutboxEventType::PaymentExpiring,
+                    payload: serde_json::json!({
+                        "payment_count": reminders.len(),
+                        "payment_cents": reminders
+                            .iter()
+                            .map(|payment| i64::from(payment.payment_cents))
+                            .sum::<i64>(),
+                        "payments": reminders
+                            .iter()
+                            .map(|payment| {
+                                let expires_at =
+                                    payment.created_at + Duration::days(PAYMENT_EXPIRY_DAYS);
+                                serde_json::json!({
+                                    "message_hash": payment.message_hash,
+                                    "payment_cents": payment.payment_cents,
+                                    "is_promo": payment.is_promo,
+                                    "expires_at": expires_at.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                                })
+                            })
+                            .collect::<Vec<_>>(),
+                    }),
+                })
+                .collect();
+            diesel::insert_into(outbox_events)
+                .values(&events)
+                .execute(&conn)?;
+
+            let reminded_ids: Vec<i64> = expiring.iter().map(|payment| payment.id).collect();
+            diesel::update(payments.filter(id.eq_any(reminded_ids)))
+                .set(reminded_at.eq(now))
+                .execute(&conn)?;
+
+            events_counter.inc_by(events.len() as i64);
+            recipient_count += events.len();
+            Ok(expiring.len())
+        })?;
+
+        reminded_count += reminded;
+        reminded_counter.inc_by(reminded as i64);
+    }
+
+    info!(
+        "Reminded {} recipients of {} expiring payments (cron_run_id={})",
+        recipient_count, reminded_count, cron_run_id
+    );
+
+    Ok(())
+}
+
+fn do_expire_payout_attempts(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::schema::payout_attempts::dsl::*;
+    use beancounter::sql_types::PayoutAttemptState;
+    use diesel::prelude::*;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    // Attempts which weren't confirmed in time are kept, so that abandoned
+    // payouts can be looked into
+    let expired = diesel::update(
+        payout_attempts.filter(
+            state
+                .eq(PayoutAttemptState::Pending)
+                .and(expires_at.le(diesel::dsl::now)),
+        ),
+    )
+    .set(state.eq(PayoutAttemptState::Expired))
+    .execute(&conn)?;
+
+    info!(
+        "Expired {} payout attempts (cron_run_id={})",
+        expired, cron_run_id
+    );
+
+    Ok(())
+}
+
+fn do_expire_pending_charges(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::PendingCharge;
+    use beancounter::schema::pending_charges::dsl::*;
+    use beancounter::sql_types::PendingChargeState;
+    use beancounter::stripe_client::Stripe;
+    use diesel::prelude::*;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let expired_counter = make_intcounter(
+        "pending_charges_expired",
+        "Pending charges refunded once they expired",
+    );
+
+    // Claim the charges first, so that a charge.succeeded event arriving while
+    // they're refunded doesn't credit them
+    let expired: Vec<PendingCharge> = diesel::update(
+        pending_charges.filter(
+            state
+                .eq(PendingChargeState::Pending)
+                .and(expires_at.le(diesel::dsl::now)),
+        ),
+    )
+    .set((
+        state.eq(PendingChargeState::Expired),
+        resolved_at.eq(diesel::dsl::now.nullable()),
+    ))
+    .get_results(&conn)?;
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    let stripe = Stripe::new();
+
+    for charge in expired.iter() {
+        match runtime.block_on(stripe.refund(&charge.stripe_charge_id)) {
+            Ok(refund) => {
+                expired_counter.inc();
+                info!(
+                    "Refunded expired pending charge id={} client_id={} stripe_charge_id={} refund_id={} (cron_run_id={})",
+                    charge.id, charge.client_id, charge.stripe_charge_id, refund.id, cron_run_id
+                );
+            }
+            Err(err) => {
+                // Left pending for the next run to retry
+                error!(
+                    "Error refunding expired pending charge id={} stripe_charge_id={}: {:?}",
+                    charge.id, charge.stripe_charge_id, err
+                );
+                diesel::update(
+                    pending_charges
+                        .filter(id.eq(charge.id).and(state.eq(PendingChargeState::Expired))),
+                )
+                .set((
+                    state.eq(PendingChargeState::Pending),
+                    resolved_at.eq(None::<chrono::NaiveDateTime>),
+                ))
+                .execute(&conn)?;
+            }
+        }
+    }
+
+    info!(
+        "Expired {} pending charges (cron_run_id={})",
+        expired.len(),
+        cron_run_id
+    );
+
+    Ok(())
+}
+
+fn do_expire_payment_holds(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::schema::payment_holds::dsl::*;
+    use beancounter::service::update_and_return_balance;
+    use beancounter::sql_types::PaymentHoldState;
+    use diesel::connection::Connection;
+    use diesel::prelude::*;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let expired_counter = make_intcounter(
+        "payment_holds_expired",
+        "Held payments released once they weren't captured in time",
+    );
+
+    // Release sends which weren't captured in time, and refresh the balances
+    // they were held from
+    let (expired, clients) = conn.transaction::<(usize, Vec<ClientId>), Error, _>(|| {
+        let mut clients: Vec<ClientId> = diesel::update(
+            payment_holds.filter(
+                state
+                    .eq(PaymentHoldState::Held)
+                    .and(expires_at.le(diesel::dsl::now)),
+            ),
+        )
+        .set((
+            state.eq(PaymentHoldState::Expired),
+            resolved_at.eq(diesel::dsl::now.nullable()),
+        ))
+        .returning(client_id_from)
+        .get_results(&conn)?;
+        let expired = clients.len();
+        clients.sort();
+        clients.dedup();
+
+        for client in clients.iter() {
+            update_and_return_balance(*client, &conn)?;
+        }
+
+        Ok((expired, clients))
+    })?;
+    expired_counter.inc_by(expired as i64);
+
+    info!(
+        "Expired {} payment holds for {} clients (cron_run_id={})",
+        expired,
+        clients.len(),
+        cron_run_id
+    );
+
+    Ok(())
+}
+
+fn do_transactions_partitions(cron_run_id: Uuid) -> Result<(), Error> {
     use diesel::sql_query;
+    use diesel::RunQueryDsl;
 
-    let db_pool_reader = database::get_db_pool(&config::CONFIG.database.reader);
-    let db_pool_writer = database::get_db_pool(&config::CONFIG.database.writer);
-    let beancounter =
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    // Existing partitions are skipped, so this only creates new months
+    let created: Vec<TransactionsPartition> = sql_query(
+        r#"
+        SELECT
+            partition_name
+        FROM (
+            SELECT
+                create_transactions_partition(month::DATE) AS partition_name
+            FROM
+                generate_series(
+                    date_trunc('month', NOW()),
+                    date_trunc('month', NOW()) + $1 * interval '1 month',
+                    interval '1 month') AS month) AS p
+        WHERE
+            partition_name IS NOT NULL;
+           "#,
+    )
+    .bind::<Integer, _>(PARTITION_MONTHS_AHEAD)
+    .get_results(&conn)?;
+
+    for partition in created.iter() {
+        info!(
+            "Created transactions partition {} (cron_run_id={})",
+            partition.partition_name, cron_run_id
+        );
+    }
+
+    Ok(())
+}
+
+fn do_auto_reloads() -> Result<(), Error> {
+    use beancounter::models::AutoReloadCharge;
+    use beancounter::schema::auto_reload_charges::dsl::*;
+    use beancounter::sql_types::AutoReloadState;
+    use diesel::prelude::*;
+
+    let db_pool_reader = database::get_db_pool(&config::get().database.reader);
+    let db_pool_writer = database::get_db_pool(&config::get().database.writer);
+    let mut beancounter =
         beancounter::service::BeanCounter::new(db_pool_reader.clone(), db_pool_writer.clone());
+    beancounter.set_internal_accounts(beancounter::service::InternalAccounts::from_config(
+        &config::get().internal_accounts,
+    ));
 
     let reader_conn = db_pool_reader.get().unwrap();
 
-    let payout_results: Vec<ClientPayout> = sql_query(
+    let reloads: Vec<AutoReloadCharge> = auto_reload_charges
+        .filter(state.eq(AutoReloadState::Pending))
+        .order(created_at.asc())
+        .get_results(&reader_conn)?;
+
+    info!("{} automatic reloads to process", reloads.len());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    for reload in reloads.iter() {
+        match runtime.block_on(beancounter.handle_auto_reload(reload)) {
+            Ok(result) => info!(
+                "Automatic reload id={} client_id={}: {:?}",
+                reload.id, reload.client_id, result
+            ),
+            Err(err) => error!(
+                "Automatic reload error id={} client_id={}: {:?}",
+                reload.id, reload.client_id, err
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn do_refresh_connect_accounts() -> Result<(), Error> {
+    use beancounter::models::StripeConnectAccount;
+    use beancounter::schema::stripe_connect_accounts::dsl::*;
+    use beancounter::service::refresh_connect_account_requirements;
+    use beancounter::stripe_client::Stripe;
+    use chrono::{Duration, Utc};
+    use diesel::prelude::*;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+
+    let conn = db_pool.get().unwrap();
+
+    let twelve_hours_ago = Utc::now().naive_utc() - Duration::hours(12);
+
+    let accounts: Vec<StripeConnectAccount> = stripe_connect_accounts
+        .filter(
+            stripe_user_id.is_not_null().and(
+                requirements_updated_at
+                    .is_null()
+                    .or(requirements_updated_at.lt(twelve_hours_ago)),
+            ),
+        )
+        .get_results(&conn)?;
+
+    info!("{} connect accounts to refresh", accounts.len());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    let stripe = Stripe::new();
+
+    for account in accounts.iter() {
+        let result = runtime.block_on(refresh_connect_account_requirements(
+            account.client_id,
+            account.stripe_user_id.as_ref().unwrap(),
+            &stripe,
+            &db_pool,
+        ));
+
+        match result {
+            Ok(account) => info!(
+                "Refreshed connect account client_id={} country={:?} currently_due={:?} disabled_reason={:?}",
+                account.client_id,
+                account.country,
+                account.requirements_currently_due,
+                account.requirements_disabled_reason
+            ),
+            Err(err) => error!(
+                "Error refreshing connect account client_id={}: {:?}",
+                account.client_id, err
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn do_settlement_stats() -> Result<(), Error> {
+    use diesel::prelude::*;
+    use diesel::sql_query;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+
+    let conn = db_pool.get().unwrap();
+
+    // Recompute the daily cohorts for the last 60 days. Payments expire after
+    // 30 days, so older cohorts won't change.
+    let cohorts = sql_query(
         r#"
+        INSERT INTO settlement_stats (
+            ds,
+            payment_count,
+            settled_count,
+            expired_count,
+            pending_count,
+            median_settlement_seconds)
         SELECT
-            b.client_id,
-            b.withdrawable_cents,
-            a.enable_automatic_payouts,
-            a.automatic_payout_threshold_cents,
-            a.stripe_user_id
+            c.ds,
+            COALESCE(o.settled_count, 0) + COALESCE(o.expired_count, 0) + COALESCE(p.pending_count, 0),
+            COALESCE(o.settled_count, 0),
+            COALESCE(o.expired_count, 0),
+            COALESCE(p.pending_count, 0),
+            o.median_settlement_seconds
         FROM
-            balances AS b
-            INNER JOIN stripe_connect_accounts AS a ON b.client_id = a.client_id
-        WHERE
-            withdrawable_cents >= a.automatic_payout_threshold_cents
-            AND a.enable_automatic_payouts = TRUE
-            AND NOT EXISTS (
+            (
                 SELECT
-                    *
+                    DATE(CURRENT_DATE - offs) AS ds
                 FROM
-                    stripe_connect_transfers AS t
+                    GENERATE_SERIES(0, 60, 1) AS offs) AS c
+            LEFT OUTER JOIN (
+                SELECT
+                    DATE(payment_created_at) AS ds,
+                    COUNT(1) FILTER (WHERE outcome = 'settled') AS settled_count,
+                    COUNT(1) FILTER (WHERE outcome = 'expired') AS expired_count,
+                    PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM created_at - payment_created_at))
+                        FILTER (WHERE outcome = 'settled') AS median_settlement_seconds
+                FROM
+                    payment_outcomes
+                WHERE
+                    payment_created_at >= CURRENT_DATE - interval '60 days'
+                GROUP BY
+                    DATE(payment_created_at)) AS o ON o.ds = c.ds
+            LEFT OUTER JOIN (
+                SELECT
+                    DATE(created_at) AS ds,
+                    COUNT(1) AS pending_count
+                FROM
+                    payments
                 WHERE
-                    t.created_at >= NOW() - interval '24 hours'
-                    AND b.client_id = t.client_id);
+                    created_at >= CURRENT_DATE - interval '60 days'
+                GROUP BY
+                    DATE(created_at)) AS p ON p.ds = c.ds
+        ON CONFLICT (ds)
+            DO UPDATE SET
+                payment_count = EXCLUDED.payment_count,
+                settled_count = EXCLUDED.settled_count,
+                expired_count = EXCLUDED.expired_count,
+                pending_count = EXCLUDED.pending_count,
+                median_settlement_seconds = EXCLUDED.median_settlement_seconds;
            "#,
     )
-    .load(&reader_conn)?;
+    .execute(&conn)?;
 
-    info!("{} payouts to process", payout_results.len());
+    info!("{} settlement stat cohorts updated", cohorts);
 
-    for payout in payout_results.iter() {
-        let payout = beancounter.handle_connect_payout(&ConnectPayoutRequest {
-            client_id: payout.client_id.to_simple().to_string(),
-            amount_cents: payout.withdrawable_cents as i32,
-        });
+    Ok(())
+}
+
+fn do_annual_earnings(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::service::update_annual_earnings;
+    use chrono::{Datelike, Utc};
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+
+    let conn = db_pool.get().unwrap();
+
+    // Last year is refreshed too, so it's complete once the year rolls over
+    let year = Utc::now().naive_utc().year();
+    for year in &[year - 1, year] {
+        let clients = update_annual_earnings(*year, cron_run_id, &conn)?;
+        info!("{} clients' earnings updated for {}", clients, year);
+    }
+
+    Ok(())
+}
+
+fn do_statements(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::{NewOutboxEvent, Statement};
+    use beancounter::schema::outbox_events::table as outbox_events;
+    use beancounter::sql_types::OutboxEventType;
+    use beancounter::statements::{sign_download_token, signing_secret};
+    use chrono::{Datelike, Duration, NaiveDate, Utc};
+    use diesel::connection::Connection;
+    use diesel::prelude::*;
+    use diesel::sql_query;
 
-        match payout {
-            Ok(payout) => info!("Payout: {:?}", payout),
-            Err(err) => error!("Payout error: {:?}", err),
+    let config = config::get();
+    let download_ttl_hours = i64::from(config.statements.download_ttl_hours);
+    if download_ttl_hours == 0 {
+        return Ok(());
+    }
+    let secret = match signing_secret() {
+        Some(secret) => secret,
+        None => {
+            warn!("STATEMENT_SIGNING_SECRET isn't set, skipping statements");
+            return Ok(());
         }
+    };
+
+    let statements_counter =
+        make_intcounter("statements_ready", "Monthly statements recorded and sent");
+
+    // The platform's own accounts don't get statements
+    let internal_accounts = config
+        .internal_accounts
+        .named()
+        .iter()
+        .map(|(_, id)| id.parse::<Uuid>())
+        .collect::<Result<Vec<Uuid>, _>>()?;
+
+    let db_pool = database::get_db_pool(&config.database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let today = Utc::now().naive_utc().date();
+    let this_month = NaiveDate::from_ymd(today.year(), today.month(), 1);
+    let last_month = (this_month - Duration::days(1)).with_day(1).unwrap();
+    let expires_at = (Utc::now() + Duration::hours(download_ttl_hours)).timestamp();
+
+    // Statements are recorded once per client and month, so later runs in the
+    // month find them done
+    let statements = conn.transaction::<Vec<Statement>, Error, _>(|| {
+        let statements: Vec<Statement> = sql_query(
+            r#"
+            INSERT INTO statements (client_id, period, gross_cents, fee_cents, withheld_cents, payment_count, cron_run_id)
+            SELECT
+                c.client_id,
+                $1,
+                COALESCE(e.gross_cents, 0),
+                COALESCE(e.fee_cents, 0),
+                COALESCE(w.withheld_cents, 0),
+                COALESCE(e.payment_count, 0),
+                $2
+            FROM (
+                SELECT DISTINCT
+                    client_id
+                FROM
+                    transactions
+                WHERE
+                    client_id IS NOT NULL
+                    AND NOT client_id = ANY ($3)
+                    AND created_at >= $1
+                    AND created_at < $1 + interval '1 month') AS c
+            LEFT JOIN (
+                SELECT
+                    client_id_to AS client_id,
+                    SUM(payment_cents)::BIGINT AS gross_cents,
+                    SUM(fee_cents)::BIGINT AS fee_cents,
+                    COUNT(1) AS payment_count
+                FROM
+                    payment_outcomes
+                WHERE
+                    outcome = 'settled'
+                    AND NOT is_promo
+                    AND created_at >= $1
+                    AND created_at < $1 + interval '1 month'
+                GROUP BY
+                    1) AS e USING (client_id)
+            LEFT JOIN (
+                SELECT
+                    client_id,
+                    SUM(
+                        CASE WHEN tx_type = 'debit' THEN
+                            amount_cents
+                        ELSE
+                            - amount_cents
+                        END)::BIGINT AS withheld_cents
+                FROM
+                    transactions
+                WHERE
+                    tx_reason = 'tax_withheld'
+                    AND created_at >= $1
+                    AND created_at < $1 + interval '1 month'
+                GROUP BY
+                    1) AS w USING (client_id)
+            ON CONFLICT (client_id, period)
+                DO NOTHING
+            RETURNING
+                *;
+               "#,
+        )
+        .bind::<Date, _>(last_month)
+        .bind::<diesel::pg::types::sql_types::Uuid, _>(cron_run_id)
+        .bind::<Array<diesel::pg::types::sql_types::Uuid>, _>(&internal_accounts)
+        .get_results(&conn)?;
+
+        let events: Vec<NewOutboxEvent> = statements
+            .iter()
+            .map(|statement| NewOutboxEvent {
+                client_id: Some(statement.client_id),
+                event_type: OutboxEventType::StatementReady,
+                payload: serde_json::json!({
+                    "statement_id": statement.id,
+                    "period": statement.period.format("%Y-%m").to_string(),
+                    "download_token": sign_download_token(&secret, statement.id, expires_at),
+                    "download_expires_at": expires_at,
+                }),
+            })
+            .collect();
+        diesel::insert_into(outbox_events)
+            .values(&events)
+            .execute(&conn)?;
+
+        Ok(statements)
+    })?;
+
+    statements_counter.inc_by(statements.len() as i64);
+    info!(
+        "{} statements ready for {} (cron_run_id={})",
+        statements.len(),
+        last_month.format("%Y-%m"),
+        cron_run_id
+    );
+
+    Ok(())
+}
+
+/// Invoice last month's usage to senders on a tier with a credit limit. Each
+/// day's sends, less what was refunded, is a line item. Senders with nothing
+/// to pay aren't invoiced, and invoices are made once per client and month.
+fn do_invoices(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::models::{Invoice, NewInvoice, NewInvoiceLineItem};
+    use beancounter::reports::InvoiceUsage;
+    use beancounter::schema::account_tiers::columns as account_tier_columns;
+    use beancounter::schema::account_tiers::table as account_tiers;
+    use beancounter::schema::invoice_line_items::table as invoice_line_items;
+    use beancounter::schema::invoices::columns as invoice_columns;
+    use beancounter::schema::invoices::table as invoices;
+    use beancounter::sql_types::AccountTier;
+    use chrono::{Datelike, Duration, NaiveDate, Utc};
+    use diesel::connection::Connection;
+    use diesel::prelude::*;
+
+    let config = config::get();
+    let credit_tiers: Vec<AccountTier> = [
+        AccountTier::Standard,
+        AccountTier::Pro,
+        AccountTier::Enterprise,
+    ]
+    .iter()
+    .cloned()
+    .filter(|tier| config.tiers.get(*tier).credit_limit_cents.unwrap_or(0) > 0)
+    .collect();
+    if credit_tiers.is_empty() {
+        return Ok(());
+    }
+
+    let invoices_counter = make_intcounter("invoices_created", "Monthly usage invoices created");
+
+    let db_pool = database::get_db_pool(&config.database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let today = Utc::now().naive_utc().date();
+    let this_month = NaiveDate::from_ymd(today.year(), today.month(), 1);
+    let last_month = (this_month - Duration::days(1)).with_day(1).unwrap();
+    let due_at = Utc::now().naive_utc() + Duration::days(i64::from(config.invoices.due_days));
+
+    let client_ids: Vec<ClientId> = account_tiers
+        .select(account_tier_columns::client_id)
+        .filter(account_tier_columns::tier.eq_any(credit_tiers))
+        .load(&conn)?;
+
+    let mut invoiced = 0;
+    for client_id in client_ids {
+        let invoiced_already: i64 = invoices
+            .select(diesel::dsl::count(invoice_columns::id))
+            .filter(invoice_columns::client_id.eq(client_id))
+            .filter(invoice_columns::period.eq(last_month))
+            .first(&conn)?;
+        if invoiced_already > 0 {
+            continue;
+        }
+
+        let usage = InvoiceUsage {
+            client_id,
+            start_at: last_month.and_hms(0, 0, 0),
+            end_at: this_month.and_hms(0, 0, 0),
+        }
+        .load(&conn)?;
+        let amount_cents: i64 = usage.iter().map(|day| day.amount_cents).sum();
+        if amount_cents <= 0 {
+            continue;
+        }
+
+        let invoice = conn.transaction::<Invoice, Error, _>(|| {
+            let invoice: Invoice = diesel::insert_into(invoices)
+                .values(&NewInvoice {
+                    client_id,
+                    period: last_month,
+                    amount_cents,
+                    due_at,
+                    cron_run_id,
+                })
+                .get_result(&conn)?;
+            let line_items: Vec<NewInvoiceLineItem> = usage
+                .iter()
+                .map(|day| NewInvoiceLineItem {
+                    invoice_id: invoice.id,
+                    ds: day.ds,
+                    sent_cents: day.sent_cents,
+                    refunded_cents: day.refunded_cents,
+                    amount_cents: day.amount_cents,
+                })
+                .collect();
+            diesel::insert_into(invoice_line_items)
+                .values(&line_items)
+                .execute(&conn)?;
+            Ok(invoice)
+        })?;
+
+        info!(
+            "Invoiced client_id={} invoice_id={} amount_cents={} period={} (cron_run_id={})",
+            client_id,
+            invoice.id,
+            invoice.amount_cents,
+            last_month.format("%Y-%m"),
+            cron_run_id
+        );
+        invoiced += 1;
+    }
+
+    invoices_counter.inc_by(invoiced);
+    info!(
+        "{} invoices created for {} (cron_run_id={})",
+        invoiced,
+        last_month.format("%Y-%m"),
+        cron_run_id
+    );
+
+    Ok(())
+}
+
+/// Gauges of what the platform owes its clients: the float (client balances),
+/// how much of it can be withdrawn, and the payments waiting to be settled or
+/// refunded, by how long they've been waiting. Only the live ledger is counted.
+fn do_float_exposure() -> Result<(), Error> {
+    use diesel::prelude::*;
+    use diesel::sql_query;
+
+    let float_gauge = make_intgauge(
+        "float_cents",
+        "Sum of client balances, including promo credit",
+    );
+    let withdrawable_gauge = make_intgauge(
+        "withdrawable_liability_cents",
+        "Sum of the amounts clients can withdraw",
+    );
+    let pending_cents_gauge = make_intgauge_vec(
+        "pending_payments_cents",
+        "Unsettled payments, by age",
+        &["age"],
+    );
+    let pending_count_gauge = make_intgauge_vec(
+        "pending_payments_count",
+        "Number of unsettled payments, by age",
+        &["age"],
+    );
+
+    let config = config::get();
+    let internal_accounts = config
+        .internal_accounts
+        .named()
+        .iter()
+        .map(|(_, id)| id.parse::<Uuid>())
+        .collect::<Result<Vec<Uuid>, _>>()?;
+
+    let db_pool = database::get_db_pool(&config.database.reader);
+    let conn = db_pool.get().unwrap();
+
+    // The platform's own accounts aren't owed to anyone
+    let exposure: FloatExposure = sql_query(
+        r#"
+        SELECT
+            COALESCE(SUM(balance_cents + promo_cents), 0)::BIGINT AS float_cents,
+            COALESCE(SUM(withdrawable_cents), 0)::BIGINT AS withdrawable_cents
+        FROM
+            balances
+        WHERE
+            client_id <> ALL ($1);
+           "#,
+    )
+    .bind::<Array<diesel::pg::types::sql_types::Uuid>, _>(&internal_accounts)
+    .get_result(&conn)?;
+
+    // Every bucket is reported, so an emptied one drops to 0. Payments are
+    // refunded once they're PAYMENT_EXPIRY_DAYS old, so any in the last bucket
+    // are overdue.
+    let pending: Vec<PendingExposure> = sql_query(
+        r#"
+        SELECT
+            b.age,
+            COALESCE(SUM(p.payment_cents), 0)::BIGINT AS payment_cents,
+            COUNT(p.id) AS payment_count
+        FROM (
+            VALUES
+                ('1d', interval '0 days', interval '1 day'),
+                ('7d', interval '1 day', interval '7 days'),
+                ('14d', interval '7 days', interval '14 days'),
+                ('30d', interval '14 days', $1 * interval '1 day'),
+                ('overdue', $1 * interval '1 day', NULL)) AS b (age, min_age, max_age)
+            LEFT JOIN payments AS p ON NOW() - p.created_at >= b.min_age
+                AND (b.max_age IS NULL
+                    OR NOW() - p.created_at < b.max_age)
+        GROUP BY
+            b.age;
+           "#,
+    )
+    .bind::<BigInt, _>(PAYMENT_EXPIRY_DAYS)
+    .get_results(&conn)?;
+
+    float_gauge.set(exposure.float_cents);
+    withdrawable_gauge.set(exposure.withdrawable_cents);
+    for bucket in pending.iter() {
+        pending_cents_gauge
+            .with_label_values(&[&bucket.age])
+            .set(bucket.payment_cents);
+        pending_count_gauge
+            .with_label_values(&[&bucket.age])
+            .set(bucket.payment_count);
+    }
+
+    info!(
+        "Float {} cents, withdrawable {} cents, pending payments {:?}",
+        exposure.float_cents, exposure.withdrawable_cents, pending
+    );
+
+    Ok(())
+}
+
+fn do_escrow_float() -> Result<(), Error> {
+    use beancounter::service::escrow_float;
+    use chrono::{Duration, Utc};
+
+    let cent_days_gauge = make_intgauge(
+        "escrow_float_cent_days",
+        "Cent-days of float provided by payments in escrow yesterday",
+    );
+    let hours_held_gauge = make_intgauge(
+        "escrow_mean_hours_held",
+        "Mean hours payments settled or expired yesterday spent in escrow",
+    );
+
+    let db_pool = database::get_db_pool(&config::get().database.reader);
+    let conn = db_pool.get().unwrap();
+
+    let today = Utc::now().naive_utc().date().and_hms(0, 0, 0);
+    let (total, _) = escrow_float(today - Duration::days(1), today, None, 0, &conn)?;
+
+    cent_days_gauge.set(total.float_cent_days.round() as i64);
+    hours_held_gauge.set(total.mean_hours_held.round() as i64);
+
+    info!(
+        "Escrow float yesterday {:.0} cent-days over {} payments, mean {:.1} hours held",
+        total.float_cent_days, total.payment_count, total.mean_hours_held
+    );
+
+    Ok(())
+}
+
+fn do_daily_close() -> Result<(), Error> {
+    use beancounter::models::LedgerDay;
+    use beancounter::schema::ledger_days::dsl::*;
+    use beancounter::schema::transactions::columns as tx_columns;
+    use beancounter::schema::transactions::table as transactions;
+    use chrono::{Duration, Utc};
+    use diesel::connection::Connection;
+    use diesel::dsl::{max, min};
+    use diesel::prelude::*;
+    use diesel::sql_query;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+
+    let conn = db_pool.get().unwrap();
+
+    let yesterday = Utc::now().naive_utc().date() - Duration::days(1);
+
+    // Close every day since the last closed one, up to and including yesterday
+    let first_open_day = match ledger_days
+        .select(max(ds))
+        .first::<Option<chrono::NaiveDate>>(&conn)?
+    {
+        Some(last_closed_day) => last_closed_day + Duration::days(1),
+        None => match transactions
+            .select(min(tx_columns::created_at))
+            .first::<Option<chrono::NaiveDateTime>>(&conn)?
+        {
+            Some(first_tx_at) => first_tx_at.date(),
+            None => {
+                info!("No transactions to close");
+                return Ok(());
+            }
+        },
+    };
+
+    let mut day = first_open_day;
+    while day <= yesterday {
+        let closed_day = conn.transaction::<LedgerDay, Error, _>(|| {
+            // Wait for in-flight writes to finish, and block new ones until the
+            // day is closed.
+            sql_query("LOCK TABLE transactions IN SHARE MODE").execute(&conn)?;
+
+            sql_query(
+                r#"
+                INSERT INTO ledger_days (ds, tx_count, credit_cents, debit_cents, balanced)
+                SELECT
+                    $1,
+                    COUNT(1),
+                    COALESCE(SUM(amount_cents) FILTER (WHERE amount_cents > 0), 0),
+                    COALESCE(SUM(amount_cents) FILTER (WHERE amount_cents < 0), 0),
+                    COALESCE(SUM(amount_cents), 0) = 0
+                FROM
+                    transactions
+                WHERE
+                    created_at >= $1
+                    AND created_at < $1 + interval '1 day';
+                   "#,
+            )
+            .bind::<Date, _>(day)
+            .execute(&conn)?;
+
+            sql_query(
+                r#"
+                INSERT INTO ledger_day_totals (ds, tx_type, tx_reason, tx_count, amount_cents)
+                SELECT
+                    $1,
+                    tx_type,
+                    tx_reason,
+                    COUNT(1),
+                    SUM(amount_cents)
+                FROM
+                    transactions
+                WHERE
+                    created_at >= $1
+                    AND created_at < $1 + interval '1 day'
+                GROUP BY
+                    tx_type,
+                    tx_reason;
+                   "#,
+            )
+            .bind::<Date, _>(day)
+            .execute(&conn)?;
+
+            Ok(ledger_days.filter(ds.eq(day)).first(&conn)?)
+        })?;
+
+        if closed_day.balanced {
+            info!(
+                "Closed ledger day {} with {} transactions",
+                closed_day.ds, closed_day.tx_count
+            );
+        } else {
+            error!(
+                "Closed ledger day {} does not balance: credits={} debits={}",
+                closed_day.ds, closed_day.credit_cents, closed_day.debit_cents
+            );
+        }
+
+        day = day.succ();
+    }
+
+    Ok(())
+}
+
+/// Seal the settled transactions into the ledger hash chain, and anchor its
+/// head. The anchor is logged as well, so there's a record of it outside of
+/// the database.
+fn do_ledger_chain(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::ledger_chain;
+
+    let db_pool = database::get_db_pool(&config::get().database.writer);
+    let conn = db_pool.get().unwrap();
+
+    let sealed = ledger_chain::seal(LEDGER_CHAIN_SETTLE_SECS, LEDGER_CHAIN_BATCH_SIZE, &conn)?;
+    match ledger_chain::anchor(&conn)? {
+        Some(head) => info!(
+            "Sealed {} transactions, anchored ledger chain at ledger_hash_id={} transaction_id={} chain_hash={} (cron_run_id={})",
+            sealed, head.id, head.transaction_id, head.chain_hash, cron_run_id
+        ),
+        None => info!("No transactions to seal (cron_run_id={})", cron_run_id),
+    }
+
+    Ok(())
+}
+
+/// Backfill each enabled dual write, and compare its new column with the old.
+/// Any difference is a bug in the dual write, so is logged as an error.
+fn do_dual_writes(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::dual_write::DUAL_WRITES;
+
+    let missing_gauge = make_intgauge_vec(
+        "dual_write_missing_rows",
+        "Rows without a dual-written column filled in",
+        &["dual_write"],
+    );
+    let divergent_gauge = make_intgauge_vec(
+        "dual_write_divergent_rows",
+        "Rows where a dual-written column doesn't match the one it replaces",
+        &["dual_write"],
+    );
+
+    let config = config::get();
+    let db_pool = database::get_db_pool(&config.database.writer);
+    let conn = db_pool.get().unwrap();
+
+    for dual_write in DUAL_WRITES
+        .iter()
+        .filter(|dual_write| dual_write.is_enabled())
+    {
+        let mut backfilled = 0;
+        loop {
+            let rows = dual_write.backfill(&conn, DUAL_WRITE_CHUNK_SIZE)?;
+            backfilled += rows;
+            if (rows as i64) < DUAL_WRITE_CHUNK_SIZE {
+                break;
+            }
+        }
+
+        let divergence = dual_write.compare(&conn)?;
+        missing_gauge
+            .with_label_values(&[dual_write.name])
+            .set(divergence.missing);
+        divergent_gauge
+            .with_label_values(&[dual_write.name])
+            .set(divergence.divergent);
+        if divergence.divergent > 0 {
+            error!(
+                "Dual write {} diverges in {} rows (cron_run_id={})",
+                dual_write.name, divergence.divergent, cron_run_id
+            );
+        }
+        info!(
+            "Backfilled {} rows for dual write {}, {} missing (cron_run_id={})",
+            backfilled, dual_write.name, divergence.missing, cron_run_id
+        );
+    }
+
+    Ok(())
+}
+
+fn do_bigquery_export(cron_run_id: Uuid) -> Result<(), Error> {
+    use beancounter::bigquery::BigQuery;
+
+    let config = config::get();
+    let bigquery = match BigQuery::from_config(&config.bigquery) {
+        Some(bigquery) => bigquery,
+        None => {
+            info!("BigQuery export is disabled");
+            return Ok(());
+        }
+    };
+
+    let db_pool = database::get_db_pool(&config.database.writer);
+    let conn = db_pool.get().unwrap();
+
+    // Rows not streamed this time are picked up by the next run, so a failure
+    // shouldn't hold up the rest of the run.
+    match bigquery.export(&conn) {
+        Ok(()) => info!(
+            "Streamed new rows to BigQuery (cron_run_id={})",
+            cron_run_id
+        ),
+        Err(err) => error!(
+            "Unable to stream rows to BigQuery: {} (cron_run_id={})",
+            err, cron_run_id
+        ),
     }
 
     Ok(())
@@ -160,15 +2139,85 @@ pub fn main() -> Result<(), Error> {
 
     ::env_logger::init();
 
+    let args = parse_args()?;
+
     config::load_config();
 
     // Allow disablement of metrics reporting for testing
     if env::var_os("DISABLE_INSTRUMENTED").is_none() {
-        instrumented::init(&config::CONFIG.metrics.bind_to_address);
+        instrumented::init(&config::get().metrics.bind_to_address);
+    }
+    dual_write::apply_config(&config::get().dual_write);
+    uuid_format::apply_config(config::get().service.uuid_format);
+
+    let cron_run_id = Uuid::new_v4();
+    info!("Starting cron run {}", cron_run_id);
+
+    // Only one instance runs at a time, across regions. Dry runs don't change
+    // anything, so don't need to lead.
+    let leader_gauge = make_intgauge("cron_leader", "1 while this instance leads the cron run");
+    let config = config::get();
+    let db_pool = database::get_db_pool(&config.database.writer);
+    let leader_conn = db_pool.get().unwrap();
+    if !args.dry_run {
+        if !leader::try_acquire(&leader_conn, &config.cron.region)? {
+            warn!(
+                "Another cron instance is leading, skipping cron run {}",
+                cron_run_id
+            );
+            return Ok(());
+        }
+        leader_gauge.set(1);
+        info!(
+            "Leading cron run {} as {}",
+            cron_run_id,
+            leader::application_name(&config.cron.region)
+        );
     }
 
-    do_cleanup()?;
-    do_payouts()?;
+    let result = run_jobs(cron_run_id, &args);
+
+    if !args.dry_run {
+        leader::release(&leader_conn)?;
+        leader_gauge.set(0);
+    }
+
+    result
+}
+
+fn run_jobs(cron_run_id: Uuid, args: &Args) -> Result<(), Error> {
+    if args.runs(Job::Cleanup) {
+        do_cleanup(cron_run_id, args)?;
+    }
+    if args.targeted() {
+        if args.runs(Job::Payouts) {
+            do_payouts(cron_run_id, args)?;
+        }
+        info!("Dry or targeted run, skipping the other jobs");
+        return Ok(());
+    }
+    do_transactions_partitions(cron_run_id)?;
+    do_dormancy(cron_run_id)?;
+    do_fx_rates(cron_run_id)?;
+    do_release_held_credits(cron_run_id)?;
+    do_payment_reminders(cron_run_id)?;
+    do_expire_payout_attempts(cron_run_id)?;
+    do_expire_pending_charges(cron_run_id)?;
+    do_expire_payment_holds(cron_run_id)?;
+    do_auto_reloads()?;
+    do_refresh_connect_accounts()?;
+    do_payouts(cron_run_id, args)?;
+    do_risk_flags(cron_run_id)?;
+    do_settlement_stats()?;
+    do_annual_earnings(cron_run_id)?;
+    do_statements(cron_run_id)?;
+    do_invoices(cron_run_id)?;
+    do_float_exposure()?;
+    do_escrow_float()?;
+    do_daily_close()?;
+    do_ledger_chain(cron_run_id)?;
+    do_dual_writes(cron_run_id)?;
+    do_bigquery_export(cron_run_id)?;
 
     Ok(())
 }
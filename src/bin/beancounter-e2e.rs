@@ -0,0 +1,666 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate failure;
+
+extern crate beancounter;
+extern crate beancounter_grpc;
+extern crate env_logger;
+extern crate futures;
+extern crate http;
+extern crate hyper;
+extern crate tokio;
+extern crate tower_hyper;
+extern crate tower_request_modifier;
+extern crate tower_service;
+extern crate tower_util;
+
+use beancounter::models::ClientId;
+use beancounter::testing::{DbPool, TestBeanCounter};
+use beancounter_grpc::proto;
+use beancounter_grpc::tower_grpc::{BoxBody, Request, Response, Status};
+use futures::{Future, Stream};
+use hyper::client::connect::{Destination, HttpConnector};
+use std::env;
+use std::time::Instant;
+use tower_hyper::{client, util};
+use tower_util::MakeService;
+
+type Client = proto::client::BeanCounter<
+    tower_request_modifier::RequestModifier<tower_hyper::client::Connection<BoxBody>, BoxBody>,
+>;
+
+// Charged to the sender's card, before Stripe's fee is taken
+static CHARGE_CENTS: i32 = 2000;
+
+// Accepted by stripe-mock as a transfer destination
+static DEFAULT_STRIPE_USER_ID: &str = "acct_1E2ETestAccount";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "bad arguments")]
+    BadArgs,
+    #[fail(display = "IO error: {}", err)]
+    IoError { err: String },
+    #[fail(display = "database error: {}", err)]
+    DatabaseError { err: String },
+    #[fail(display = "RPC error: {}", err)]
+    RpcError { err: String },
+    #[fail(display = "Stripe error: {}", err)]
+    StripeError { err: String },
+    #[fail(display = "{} failed: {}", step, err)]
+    StepFailed { step: &'static str, err: String },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::IoError {
+            err: format!("{}", err),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(err: diesel::result::Error) -> Error {
+        Error::DatabaseError {
+            err: err.to_string(),
+        }
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Error {
+        Error::RpcError {
+            err: status.to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Error {
+        Error::StripeError {
+            err: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    // Test a running service, rather than one started in-process
+    address: Option<String>,
+    // The running service's database, for the payout and ledger checks
+    database_url: Option<String>,
+    token: Option<String>,
+    stripe_user_id: Option<String>,
+    payment_cents: i32,
+}
+
+fn parse_args() -> Result<Args, Error> {
+    let args: Vec<String> = env::args().collect();
+    let usage = || {
+        error!(
+            "Usage: {} [--address <addr> [--database-url <url>]] [--token <token>] [--stripe-user-id <id>] [--payment-cents <n>]",
+            args[0]
+        );
+        Error::BadArgs
+    };
+
+    let mut parsed = Args {
+        payment_cents: 1000,
+        ..Args::default()
+    };
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(usage);
+        match arg.as_str() {
+            "--address" => parsed.address = Some(value()?),
+            "--database-url" => parsed.database_url = Some(value()?),
+            "--token" => parsed.token = Some(value()?),
+            "--stripe-user-id" => parsed.stripe_user_id = Some(value()?),
+            "--payment-cents" => parsed.payment_cents = value()?.parse().map_err(|_| usage())?,
+            _ => return Err(usage()),
+        }
+    }
+
+    // The payment and its fee have to be covered by the charge
+    if parsed.payment_cents <= 0
+        || parsed.payment_cents > CHARGE_CENTS / 2
+        || (parsed.database_url.is_some() && parsed.address.is_none())
+    {
+        return Err(usage());
+    }
+
+    Ok(parsed)
+}
+
+/// Serve a BeanCounter on the runtime, on a port picked by the OS, as the
+/// beancounter binary would. Authorization is disabled.
+fn serve(
+    runtime: &mut tokio::runtime::Runtime,
+    beancounter: &beancounter::service::BeanCounter,
+) -> Result<String, Error> {
+    use beancounter::auth::Authorizer;
+    use beancounter::config;
+    use beancounter_grpc::proto::server;
+    use tokio::net::TcpListener;
+    use tower_hyper::server::{Http, Server};
+
+    config::load_config();
+    let mut beancounter = beancounter.clone();
+    beancounter.apply_config(&config::get());
+    beancounter.set_authorizer(Authorizer::disabled());
+
+    let mut server = Server::new(server::BeanCounterServer::new(beancounter));
+    let http = Http::new().http2_only(true).clone();
+
+    let bind = TcpListener::bind(&"127.0.0.1:0".parse().unwrap())?;
+    let addr = bind.local_addr()?;
+
+    let serve = bind
+        .incoming()
+        .for_each(move |sock| {
+            let serve = server.serve_with(sock, http.clone());
+            tokio::spawn(serve.map_err(|e| error!("hyper error: {:?}", e)));
+
+            Ok(())
+        })
+        .map_err(|e| error!("accept error: {}", e));
+    runtime.spawn(serve);
+
+    Ok(format!("http://{}", addr))
+}
+
+/// A connection to the service under test
+struct Session {
+    runtime: tokio::runtime::Runtime,
+    client: Option<Client>,
+    token: Option<String>,
+}
+
+impl Session {
+    fn connect(mut runtime: tokio::runtime::Runtime, address: &str) -> Result<Self, Error> {
+        let uri: http::Uri = address.parse().map_err(|_| Error::BadArgs)?;
+        let dst = Destination::try_from_uri(uri.clone()).map_err(|_| Error::BadArgs)?;
+        let connector = util::Connector::new(HttpConnector::new(4));
+        let settings = client::Builder::new().http2_only(true).clone();
+        let mut make_client = client::Connect::with_builder(connector, settings);
+
+        let client = runtime.block_on(
+            make_client
+                .make_service(dst)
+                .map_err(|err| Error::IoError {
+                    err: format!("connect error: {:?}", err),
+                })
+                .map(move |conn| {
+                    let conn = tower_request_modifier::Builder::new()
+                        .set_origin(uri)
+                        .build(conn)
+                        .unwrap();
+                    proto::client::BeanCounter::new(conn)
+                }),
+        )?;
+
+        Ok(Self {
+            runtime,
+            client: Some(client),
+            token: None,
+        })
+    }
+
+    /// Make one call, with the caller's token if there is one
+    fn call<M, T, F, R>(&mut self, message: M, f: F) -> Result<T, Error>
+    where
+        M: Send + 'static,
+        T: Send + 'static,
+        F: FnOnce(&mut Client, Request<M>) -> R + Send + 'static,
+        R: Future<Item = Response<T>, Error = Status> + Send + 'static,
+    {
+        let mut request = Request::new(message);
+        if let Some(token) = &self.token {
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+        }
+
+        let client = self.client.take().ok_or_else(|| Error::IoError {
+            err: "connection lost".into(),
+        })?;
+        let (client, result) = self.runtime.block_on(
+            client
+                .ready()
+                .map_err(|err| Error::IoError {
+                    err: format!("{:?}", err),
+                })
+                .and_then(move |mut client| {
+                    f(&mut client, request).then(move |result| Ok((client, result)))
+                }),
+        )?;
+        self.client = Some(client);
+
+        Ok(result?.into_inner())
+    }
+
+    fn balance(&mut self, client_id: ClientId) -> Result<proto::Balance, Error> {
+        let response = self.call(
+            proto::GetBalanceRequest {
+                client_id: client_id.to_string(),
+                mode: proto::Mode::Test as i32,
+            },
+            |client, request| client.get_balance(request),
+        )?;
+        Ok(response.balance.unwrap_or_default())
+    }
+}
+
+/// Fails the step unless `ok`
+fn check(step: &'static str, ok: bool, err: impl FnOnce() -> String) -> Result<(), Error> {
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::StepFailed { step, err: err() })
+    }
+}
+
+/// A card token from the Stripe API, as a client would send it. stripe-mock
+/// returns the same token for any card.
+fn card_token() -> Result<String, Error> {
+    let api_base = env::var("STRIPE_API_BASE").unwrap_or_else(|_| "https://api.stripe.com".into());
+    let secret = env::var("STRIPE_API_SECRET").map_err(|_| Error::StripeError {
+        err: "STRIPE_API_SECRET must be set".into(),
+    })?;
+
+    let mut response = reqwest::Client::new()
+        .post(&format!("{}/v1/tokens", api_base))
+        .basic_auth(secret, None::<String>)
+        .form(&[
+            ("card[number]", "4242424242424242"),
+            ("card[exp_month]", "12"),
+            ("card[exp_year]", "2030"),
+            ("card[cvc]", "123"),
+        ])
+        .send()?
+        .error_for_status()?;
+    Ok(response.text()?)
+}
+
+/// Connect a Stripe account to the client, as CompleteConnectOauth would once
+/// the client authorized it. stripe-mock doesn't do OAuth.
+fn connect_account(
+    db_pool: &DbPool,
+    client_id: ClientId,
+    stripe_user_id: &str,
+) -> Result<(), Error> {
+    use beancounter::models::{NewStripeConnectAccount, UpdateStripeConnectAccount};
+    use beancounter::schema::stripe_connect_accounts::columns;
+    use beancounter::schema::stripe_connect_accounts::table as stripe_connect_accounts;
+    use diesel::prelude::*;
+
+    let conn = db_pool.get().unwrap();
+    diesel::insert_into(stripe_connect_accounts)
+        .values(&NewStripeConnectAccount { client_id })
+        .execute(&conn)?;
+    diesel::update(stripe_connect_accounts.filter(columns::client_id.eq(client_id)))
+        .set(&UpdateStripeConnectAccount {
+            stripe_user_id: Some(stripe_user_id.into()),
+            connect_account: None,
+            connect_credentials: None,
+        })
+        .execute(&conn)?;
+    Ok(())
+}
+
+/// The client's balance matches its transactions: the credits and debits are
+/// its balance and held credits, and the promo credits and debits its promo
+/// balance.
+fn reconcile_client(session: &mut Session, client_id: ClientId) -> Result<(), Error> {
+    use proto::transaction::Type;
+
+    let balance = session.balance(client_id)?;
+    let transactions = session
+        .call(
+            proto::GetTransactionsRequest {
+                client_id: client_id.to_string(),
+                limit: 0,
+                start_at: None,
+                end_at: None,
+                mode: proto::Mode::Test as i32,
+            },
+            |client, request| client.get_transactions(request),
+        )?
+        .transactions;
+
+    let sum = |types: &[Type]| -> i64 {
+        transactions
+            .iter()
+            .filter(|tx| types.iter().any(|t| *t as i32 == tx.tx_type))
+            .map(|tx| i64::from(tx.amount_cents))
+            .sum()
+    };
+    let cash_cents = sum(&[Type::Credit, Type::Debit]);
+    let promo_cents = sum(&[Type::PromoCredit, Type::PromoDebit]);
+
+    check(
+        "reconcile",
+        cash_cents == balance.balance_cents + balance.held_cents
+            && promo_cents == balance.promo_cents,
+        || {
+            format!(
+                "{} has transactions of {} cash and {} promo, but a balance of {} ({} held) and {} promo",
+                client_id,
+                cash_cents,
+                promo_cents,
+                balance.balance_cents,
+                balance.held_cents,
+                balance.promo_cents
+            )
+        },
+    )
+}
+
+/// Every operation involving the clients balances, and if the whole test
+/// ledger is ours, so does the ledger.
+fn check_ledger(db_pool: &DbPool, clients: &[ClientId], whole_ledger: bool) -> Result<(), Error> {
+    use beancounter::schema::transactions::columns::*;
+    use beancounter::schema::transactions::table as transactions;
+    use diesel::dsl::sum;
+    use diesel::prelude::*;
+    use std::collections::HashMap;
+
+    let conn = db_pool.get().unwrap();
+    diesel::sql_query("SET beancounter.livemode = off").execute(&conn)?;
+
+    let legs: Vec<(Option<uuid::Uuid>, i32)> = transactions
+        .select((operation_id, amount_cents))
+        .filter(
+            operation_id.eq_any(
+                transactions
+                    .select(operation_id)
+                    .filter(client_id.eq_any(clients)),
+            ),
+        )
+        .load(&conn)?;
+    let mut operations: HashMap<Option<uuid::Uuid>, i64> = HashMap::new();
+    for (operation, amount) in legs {
+        *operations.entry(operation).or_default() += i64::from(amount);
+    }
+    if let Some((operation, total)) = operations.iter().find(|(_, total)| **total != 0) {
+        return Err(Error::StepFailed {
+            step: "ledger",
+            err: format!("operation {:?} sums to {}", operation, total),
+        });
+    }
+
+    if whole_ledger {
+        let total = transactions
+            .select(sum(amount_cents))
+            .first::<Option<i64>>(&conn)?
+            .unwrap_or(0);
+        check("ledger", total == 0, || {
+            format!("the test ledger sums to {}", total)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Charge the sender's card, pay the recipient, settle the payment as read, pay
+/// the recipient out, then reconcile both clients' balances with their
+/// transactions. Everything is on the test ledger.
+fn run(
+    session: &mut Session,
+    db_pool: Option<&DbPool>,
+    whole_ledger: bool,
+    args: &Args,
+) -> Result<(), Error> {
+    use rand::RngCore;
+
+    let sender = ClientId::from(uuid::Uuid::new_v4());
+    let recipient = ClientId::from(uuid::Uuid::new_v4());
+    info!("Sender is {}, recipient is {}", sender, recipient);
+
+    let started = Instant::now();
+    let health = session.call(
+        proto::HealthCheckRequest {
+            service: String::new(),
+        },
+        |client, request| client.check(request),
+    )?;
+    check(
+        "check",
+        health.status == proto::health_check_response::ServingStatus::Serving as i32,
+        || format!("status is {}", health.status),
+    )?;
+    info!("check passed in {:?}", started.elapsed());
+
+    let started = Instant::now();
+    let token = card_token()?;
+    let charge = session.call(
+        proto::StripeChargeRequest {
+            client_id: sender.to_string(),
+            amount_cents: CHARGE_CENTS,
+            token,
+            currency: String::new(),
+            mode: proto::Mode::Test as i32,
+            amount: None,
+            statement_descriptor_suffix: String::new(),
+        },
+        |client, request| client.stripe_charge(request),
+    )?;
+    check(
+        "charge",
+        charge.result == proto::stripe_charge_response::Result::Success as i32
+            && charge.held_credit_id == 0,
+        || format!("{} {}", charge.message, charge.api_response),
+    )?;
+    let sender_balance = session.balance(sender)?;
+    check(
+        "charge",
+        sender_balance.balance_cents > 0 && sender_balance.balance_cents < i64::from(CHARGE_CENTS),
+        || {
+            format!(
+                "balance is {} after a charge of {}, less Stripe's fee",
+                sender_balance.balance_cents, CHARGE_CENTS
+            )
+        },
+    )?;
+    info!("charge passed in {:?}", started.elapsed());
+
+    let started = Instant::now();
+    let quote = session.call(
+        proto::QuoteFeesRequest {
+            client_id_from: sender.to_string(),
+            payment_cents: args.payment_cents,
+            mode: proto::Mode::Test as i32,
+        },
+        |client, request| client.quote_fees(request),
+    )?;
+    let mut message_hash = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut message_hash);
+    let payment = session.call(
+        proto::AddPaymentRequest {
+            client_id_from: sender.to_string(),
+            client_id_to: recipient.to_string(),
+            message_hash: message_hash.clone(),
+            payment_cents: args.payment_cents,
+            is_promo: false,
+            referrer_client_id: String::new(),
+            mode: proto::Mode::Test as i32,
+            campaign_id: String::new(),
+        },
+        |client, request| client.add_payment(request),
+    )?;
+    check(
+        "pay",
+        payment.result == proto::add_payment_response::Result::Success as i32
+            && payment.fee_cents == quote.send_fee_cents,
+        || {
+            format!(
+                "result {} with a fee of {}, quoted {}",
+                payment.result, payment.fee_cents, quote.send_fee_cents
+            )
+        },
+    )?;
+    let balance_cents = session.balance(sender)?.balance_cents;
+    check(
+        "pay",
+        balance_cents == sender_balance.balance_cents - i64::from(quote.total_cents),
+        || {
+            format!(
+                "sender balance is {}, expected {} less {}",
+                balance_cents, sender_balance.balance_cents, quote.total_cents
+            )
+        },
+    )?;
+    info!("pay passed in {:?}", started.elapsed());
+
+    let started = Instant::now();
+    let settled = session.call(
+        proto::SettlePaymentRequest {
+            client_id: recipient.to_string(),
+            message_hash,
+            action: proto::settle_payment_request::Action::Read as i32,
+            tip_cents: 0,
+            mode: proto::Mode::Test as i32,
+        },
+        |client, request| client.settle_payment(request),
+    )?;
+    let recipient_balance = session.balance(recipient)?;
+    check(
+        "settle",
+        settled.result == proto::settle_payment_response::Result::Success as i32
+            && settled.fee_cents == quote.read_fee_cents
+            && settled.payment_cents == args.payment_cents - quote.read_fee_cents
+            && recipient_balance.balance_cents == i64::from(settled.payment_cents),
+        || {
+            format!(
+                "result {}, paid {} with a fee of {}, and the recipient's balance is {}",
+                settled.result,
+                settled.payment_cents,
+                settled.fee_cents,
+                recipient_balance.balance_cents
+            )
+        },
+    )?;
+    info!("settle passed in {:?}", started.elapsed());
+
+    match db_pool {
+        Some(db_pool) => {
+            let started = Instant::now();
+            connect_account(
+                db_pool,
+                recipient,
+                args.stripe_user_id
+                    .as_ref()
+                    .map_or(DEFAULT_STRIPE_USER_ID, String::as_str),
+            )?;
+            let amount_cents = recipient_balance.withdrawable_cents as i32;
+            let payout = session.call(
+                proto::ConnectPayoutRequest {
+                    client_id: recipient.to_string(),
+                    amount_cents,
+                    description: "beancounter-e2e".into(),
+                    statement_descriptor: String::new(),
+                    mode: proto::Mode::Test as i32,
+                },
+                |client, request| client.connect_payout(request),
+            )?;
+            let balance_cents = session.balance(recipient)?.balance_cents;
+            check(
+                "payout",
+                payout.result == proto::connect_payout_response::Result::Success as i32
+                    && amount_cents > 0
+                    && balance_cents == recipient_balance.balance_cents - i64::from(amount_cents),
+                || {
+                    format!(
+                        "result {} paying out {}, and the recipient's balance is {}",
+                        payout.result, amount_cents, balance_cents
+                    )
+                },
+            )?;
+            // The payout's ledger entry references the transfer it was made by
+            let references: Vec<String> = session
+                .call(
+                    proto::GetTransactionsRequest {
+                        client_id: recipient.to_string(),
+                        limit: 0,
+                        start_at: None,
+                        end_at: None,
+                        mode: proto::Mode::Test as i32,
+                    },
+                    |client, request| client.get_transactions(request),
+                )?
+                .transactions
+                .into_iter()
+                .filter(|tx| tx.tx_reason == proto::transaction::Reason::Payout as i32)
+                .map(|tx| tx.reference)
+                .collect();
+            check(
+                "payout",
+                !references.is_empty() && references.iter().all(|id| id.starts_with("tr_")),
+                || format!("payout transactions have references {:?}", references),
+            )?;
+            info!("payout passed in {:?}", started.elapsed());
+        }
+        None => warn!("Skipping payout, which needs --database-url"),
+    }
+
+    let started = Instant::now();
+    reconcile_client(session, sender)?;
+    reconcile_client(session, recipient)?;
+    info!("reconcile passed in {:?}", started.elapsed());
+
+    if let Some(db_pool) = db_pool {
+        let started = Instant::now();
+        check_ledger(db_pool, &[sender, recipient], whole_ledger)?;
+        info!("ledger passed in {:?}", started.elapsed());
+    }
+
+    Ok(())
+}
+
+pub fn main() -> Result<(), Error> {
+    use diesel::r2d2::{ConnectionManager, Pool};
+
+    ::env_logger::init();
+
+    let args = parse_args()?;
+
+    let mut runtime = tokio::runtime::Runtime::new().expect("Unable to build tokio runtime");
+
+    // Without an address, the service is started in-process on a temporary
+    // schema of BEANCOUNTER_TEST_DATABASE_URL, which is dropped afterwards
+    let test_beancounter;
+    let (address, db_pool, whole_ledger) = match &args.address {
+        Some(address) => {
+            warn!(
+                "Running against {}. Only point this at a test environment, it charges cards and pays out.",
+                address
+            );
+            let db_pool = args.database_url.as_ref().map(|database_url| {
+                Pool::builder()
+                    .max_size(2)
+                    .build(ConnectionManager::new(database_url.as_str()))
+                    .expect("Unable to create DB connection pool")
+            });
+            (address.clone(), db_pool, false)
+        }
+        None => {
+            test_beancounter = TestBeanCounter::new();
+            let address = serve(&mut runtime, &test_beancounter.beancounter)?;
+            info!("Started in-process service on {}", address);
+            (address, Some(test_beancounter.db_pool().clone()), true)
+        }
+    };
+
+    let mut session = Session::connect(runtime, &address)?;
+    session.token = args.token.clone();
+
+    run(&mut session, db_pool.as_ref(), whole_ledger, &args).map_err(|err| {
+        error!("{}", err);
+        err
+    })?;
+    info!("All steps passed");
+
+    Ok(())
+}
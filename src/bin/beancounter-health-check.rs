@@ -2,6 +2,8 @@
 extern crate log;
 #[macro_use]
 extern crate failure;
+#[macro_use]
+extern crate serde_derive;
 
 extern crate beancounter_grpc;
 extern crate env_logger;
@@ -19,6 +21,7 @@ use beancounter_grpc::tower_grpc::Request;
 use futures::Future;
 use hyper::client::connect::{Destination, HttpConnector};
 use std::env;
+use std::time::Instant;
 use tower_hyper::{client, util};
 use tower_util::MakeService;
 
@@ -68,62 +71,235 @@ impl From<std::io::Error> for Error {
     }
 }
 
-pub fn main() -> Result<(), Error> {
-    ::env_logger::init();
+#[derive(Debug, Serialize)]
+struct DependencyReport {
+    name: String,
+    healthy: bool,
+    latency_ms: f64,
+    error: Option<String>,
+}
 
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    healthy: bool,
+    dependencies: Vec<DependencyReport>,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    address: String,
+    deep: bool,
+    metrics_url: Option<String>,
+    json: bool,
+}
+
+fn parse_args() -> Result<Args, Error> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        error!("Usage: {} <addr>", args[0]);
-        return Err(Error::BadArgs);
+    let usage = || {
+        error!(
+            "Usage: {} <addr> [--deep] [--metrics-url <url>] [--json]",
+            args[0]
+        );
+        Error::BadArgs
+    };
+
+    let mut parsed = Args::default();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--deep" => parsed.deep = true,
+            "--json" => parsed.json = true,
+            "--metrics-url" => parsed.metrics_url = Some(iter.next().ok_or_else(usage)?.clone()),
+            _ if parsed.address.is_empty() && !arg.starts_with("--") => {
+                parsed.address = arg.clone()
+            }
+            _ => return Err(usage()),
+        }
     }
 
-    let address = &args[1];
+    if parsed.address.is_empty() {
+        return Err(usage());
+    }
 
-    let uri: http::Uri = address.parse()?;
+    Ok(parsed)
+}
 
-    let dst = Destination::try_from_uri(uri.clone())?;
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn check_service(args: &Args, runtime: &mut tokio::runtime::Runtime) -> Vec<DependencyReport> {
+    use beancounter_grpc::proto::client::BeanCounter;
+
+    let uri: http::Uri = match args.address.parse() {
+        Ok(uri) => uri,
+        Err(err) => {
+            return vec![DependencyReport {
+                name: "beancounter".into(),
+                healthy: false,
+                latency_ms: 0.0,
+                error: Some(Error::from(err).to_string()),
+            }]
+        }
+    };
+
+    let start = Instant::now();
+    let dst = match Destination::try_from_uri(uri.clone()) {
+        Ok(dst) => dst,
+        Err(err) => {
+            return vec![DependencyReport {
+                name: "beancounter".into(),
+                healthy: false,
+                latency_ms: elapsed_ms(start),
+                error: Some(err.to_string()),
+            }]
+        }
+    };
     let connector = util::Connector::new(HttpConnector::new(4));
     let settings = client::Builder::new().http2_only(true).clone();
     let mut make_client = client::Connect::with_builder(connector, settings);
 
-    let mut runtime = tokio::runtime::Runtime::new()?;
-
     let result = runtime.block_on(
         make_client
             .make_service(dst)
-            .map_err(|e| panic!("connect error: {:?}", e))
+            .map_err(|err| format!("connect error: {:?}", err))
             .and_then(move |conn| {
-                use beancounter_grpc::proto::client::BeanCounter;
-
                 let conn = tower_request_modifier::Builder::new()
                     .set_origin(uri)
                     .build(conn)
                     .unwrap();
 
                 // Wait until the client is ready...
-                BeanCounter::new(conn).ready()
+                BeanCounter::new(conn)
+                    .ready()
+                    .map_err(|err| format!("{:?}", err))
             })
             .and_then(|mut client| {
-                client.check(Request::new(proto::HealthCheckRequest {
-                    service: "beancounter".into(),
-                }))
-            })
-            .map(|response| response.get_ref().clone())
-            .map_err(|e| {
-                error!("ERR = {:?}", e);
-                panic!("health check failed");
+                client
+                    .check(Request::new(proto::HealthCheckRequest {
+                        service: "beancounter".into(),
+                    }))
+                    .map(|response| (client, response.get_ref().clone()))
+                    .map_err(|err| format!("{:?}", err))
             }),
     );
 
-    info!("{:?}", result);
+    let client = match result {
+        Ok((client, response)) => {
+            if !response.is_serving() {
+                return vec![DependencyReport {
+                    name: "beancounter".into(),
+                    healthy: false,
+                    latency_ms: elapsed_ms(start),
+                    error: Some(Error::NotServing.to_string()),
+                }];
+            }
+            client
+        }
+        Err(err) => {
+            return vec![DependencyReport {
+                name: "beancounter".into(),
+                healthy: false,
+                latency_ms: elapsed_ms(start),
+                error: Some(err),
+            }]
+        }
+    };
 
-    if let Ok(response) = result {
-        if response.status == proto::health_check_response::ServingStatus::Serving as i32 {
-            Ok(())
-        } else {
-            Err(Error::NotServing)
+    let mut reports = vec![DependencyReport {
+        name: "beancounter".into(),
+        healthy: true,
+        latency_ms: elapsed_ms(start),
+        error: None,
+    }];
+
+    if args.deep {
+        let start = Instant::now();
+        let result = runtime.block_on(
+            client
+                .ready()
+                .and_then(|mut client| client.deep_check(Request::new(proto::DeepCheckRequest {})))
+                .map(|response| response.get_ref().clone()),
+        );
+
+        match result {
+            Ok(response) => {
+                match &response.cron_leader {
+                    Some(leader) if leader.running => info!(
+                        "Cron run in progress, led by {} from {}",
+                        leader.name, leader.client_addr
+                    ),
+                    Some(_) => info!("No cron run in progress"),
+                    None => (),
+                }
+                reports.extend(response.dependencies.into_iter().map(|dependency| {
+                    DependencyReport {
+                        name: dependency.name,
+                        healthy: dependency.healthy,
+                        latency_ms: dependency.latency_ms,
+                        error: if dependency.error.is_empty() {
+                            None
+                        } else {
+                            Some(dependency.error)
+                        },
+                    }
+                }))
+            }
+            Err(err) => reports.push(DependencyReport {
+                name: "deep_check".into(),
+                healthy: false,
+                latency_ms: elapsed_ms(start),
+                error: Some(format!("{}: {:?}", Error::BadResponse, err)),
+            }),
         }
+    }
+
+    reports
+}
+
+fn check_metrics(metrics_url: &str) -> DependencyReport {
+    let start = Instant::now();
+    let result = reqwest::get(metrics_url).map_err(|err| err.to_string());
+    let error = match result {
+        Ok(ref response) if response.status().is_success() => None,
+        Ok(response) => Some(format!("unexpected status {}", response.status())),
+        Err(err) => Some(err),
+    };
+
+    DependencyReport {
+        name: "metrics".into(),
+        healthy: error.is_none(),
+        latency_ms: elapsed_ms(start),
+        error,
+    }
+}
+
+pub fn main() -> Result<(), Error> {
+    ::env_logger::init();
+
+    let args = parse_args()?;
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+
+    let mut dependencies = check_service(&args, &mut runtime);
+    if let Some(metrics_url) = &args.metrics_url {
+        dependencies.push(check_metrics(metrics_url));
+    }
+
+    let report = HealthReport {
+        healthy: dependencies.iter().all(|d| d.healthy),
+        dependencies,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string(&report).unwrap());
+    } else {
+        info!("{:?}", report);
+    }
+
+    if report.healthy {
+        Ok(())
     } else {
-        Err(Error::BadResponse)
+        Err(Error::NotServing)
     }
 }
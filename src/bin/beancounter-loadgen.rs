@@ -0,0 +1,457 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate beancounter_grpc;
+extern crate env_logger;
+extern crate futures;
+extern crate http;
+extern crate hyper;
+extern crate tokio;
+extern crate tower_hyper;
+extern crate tower_request_modifier;
+extern crate tower_service;
+extern crate tower_util;
+
+use beancounter_grpc::proto;
+use beancounter_grpc::tower_grpc::{BoxBody, Request, Response, Status};
+use futures::Future;
+use hyper::client::connect::{Destination, HttpConnector};
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, Instant};
+use tower_hyper::{client, util};
+use tower_util::MakeService;
+
+type Client = proto::client::BeanCounter<
+    tower_request_modifier::RequestModifier<tower_hyper::client::Connection<BoxBody>, BoxBody>,
+>;
+
+// Credits added to each worker's sender before it starts making payments
+static SENDER_CREDITS_CENTS: i32 = 10_000_000;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "bad arguments")]
+    BadArgs,
+    #[fail(display = "IO error: {}", err)]
+    IoError { err: String },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::IoError {
+            err: format!("{}", err),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    GetBalance,
+    AddPayment,
+    SettlePayment,
+}
+
+impl Op {
+    fn name(self) -> &'static str {
+        match self {
+            Op::GetBalance => "get_balance",
+            Op::AddPayment => "add_payment",
+            Op::SettlePayment => "settle_payment",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Args {
+    address: String,
+    concurrency: usize,
+    duration: Duration,
+    // Relative weight of each op
+    mix: Vec<(Op, u32)>,
+    payment_cents: i32,
+    token: Option<String>,
+    json: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            address: String::new(),
+            concurrency: 4,
+            duration: Duration::from_secs(30),
+            mix: vec![
+                (Op::GetBalance, 50),
+                (Op::AddPayment, 25),
+                (Op::SettlePayment, 25),
+            ],
+            payment_cents: 100,
+            token: None,
+            json: false,
+        }
+    }
+}
+
+fn parse_mix(mix: &str) -> Option<Vec<(Op, u32)>> {
+    mix.split(',')
+        .map(|part| {
+            let mut parts = part.splitn(2, '=');
+            let op = match parts.next()? {
+                "get_balance" => Op::GetBalance,
+                "add_payment" => Op::AddPayment,
+                "settle_payment" => Op::SettlePayment,
+                _ => return None,
+            };
+            let weight = parts.next()?.parse().ok()?;
+            Some((op, weight))
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|mix| mix.iter().any(|(_, weight)| *weight > 0))
+}
+
+fn parse_args() -> Result<Args, Error> {
+    let args: Vec<String> = env::args().collect();
+    let usage = || {
+        error!(
+            "Usage: {} <addr> [--concurrency <n>] [--duration-secs <n>] [--mix get_balance=<n>,add_payment=<n>,settle_payment=<n>] [--payment-cents <n>] [--token <token>] [--json]",
+            args[0]
+        );
+        Error::BadArgs
+    };
+
+    let mut parsed = Args::default();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(usage);
+        match arg.as_str() {
+            "--concurrency" => parsed.concurrency = value()?.parse().map_err(|_| usage())?,
+            "--duration-secs" => {
+                parsed.duration = Duration::from_secs(value()?.parse().map_err(|_| usage())?)
+            }
+            "--mix" => parsed.mix = parse_mix(value()?).ok_or_else(usage)?,
+            "--payment-cents" => parsed.payment_cents = value()?.parse().map_err(|_| usage())?,
+            "--token" => parsed.token = Some(value()?.clone()),
+            "--json" => parsed.json = true,
+            _ if parsed.address.is_empty() && !arg.starts_with("--") => {
+                parsed.address = arg.clone()
+            }
+            _ => return Err(usage()),
+        }
+    }
+
+    if parsed.address.is_empty() || parsed.concurrency == 0 {
+        return Err(usage());
+    }
+
+    Ok(parsed)
+}
+
+fn connect(address: &str, runtime: &mut tokio::runtime::Runtime) -> Result<Client, String> {
+    let uri: http::Uri = address.parse().map_err(|err| format!("{}", err))?;
+    let dst = Destination::try_from_uri(uri.clone()).map_err(|err| err.to_string())?;
+    let connector = util::Connector::new(HttpConnector::new(4));
+    let settings = client::Builder::new().http2_only(true).clone();
+    let mut make_client = client::Connect::with_builder(connector, settings);
+
+    runtime.block_on(
+        make_client
+            .make_service(dst)
+            .map_err(|err| format!("connect error: {:?}", err))
+            .map(move |conn| {
+                let conn = tower_request_modifier::Builder::new()
+                    .set_origin(uri)
+                    .build(conn)
+                    .unwrap();
+                proto::client::BeanCounter::new(conn)
+            }),
+    )
+}
+
+/// Make one call, handing back the client unless its connection failed
+fn call<T, F, R>(
+    runtime: &mut tokio::runtime::Runtime,
+    client: Client,
+    f: F,
+) -> (Option<Client>, Result<T, String>)
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Client) -> R + Send + 'static,
+    R: Future<Item = Response<T>, Error = Status> + Send + 'static,
+{
+    let result = runtime.block_on(client.ready().map_err(|err| format!("{:?}", err)).and_then(
+        move |mut client| {
+            f(&mut client).then(move |result| {
+                Ok((
+                    client,
+                    result
+                        .map(Response::into_inner)
+                        .map_err(|status| status.to_string()),
+                ))
+            })
+        },
+    ));
+
+    match result {
+        Ok((client, result)) => (Some(client), result),
+        Err(err) => (None, Err(err)),
+    }
+}
+
+fn request<T>(message: T, token: &Option<String>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(token) = token {
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+    }
+    request
+}
+
+#[derive(Debug, Default)]
+struct OpStats {
+    latencies_ms: Vec<f64>,
+    errors: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct OpReport {
+    op: String,
+    requests: usize,
+    errors: u64,
+    requests_per_sec: f64,
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((p / 100.0) * (sorted.len() - 1) as f64).round() as usize]
+}
+
+/// Run one worker's share of the load. Each worker pays between its own pair
+/// of clients, and settles the payments it made.
+fn run_worker(args: Args) -> HashMap<&'static str, OpStats> {
+    use rand::{Rng, RngCore};
+    use uuid::Uuid;
+
+    let mut stats: HashMap<&'static str, OpStats> = HashMap::new();
+    let mut runtime = tokio::runtime::Runtime::new().expect("Unable to build tokio runtime");
+    let mut client = None;
+
+    let client_id_from = Uuid::new_v4().to_simple().to_string();
+    let client_id_to = Uuid::new_v4().to_simple().to_string();
+    let mut unsettled: Vec<Vec<u8>> = vec![];
+    let mut funded = false;
+
+    let total_weight: u32 = args.mix.iter().map(|(_, weight)| weight).sum();
+    let started = Instant::now();
+
+    while started.elapsed() < args.duration {
+        let conn = match client.take() {
+            Some(conn) => conn,
+            None => match connect(&args.address, &mut runtime) {
+                Ok(conn) => conn,
+                Err(err) => {
+                    error!("{}", err);
+                    std::thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            },
+        };
+
+        // All load is on the test ledger, so it never touches live balances
+        if !funded {
+            let message = request(
+                proto::AddCreditsRequest {
+                    client_id: client_id_from.clone(),
+                    amount_cents: SENDER_CREDITS_CENTS,
+                    currency: String::new(),
+                    mode: proto::Mode::Test as i32,
+                    amount: None,
+                },
+                &args.token,
+            );
+            let (conn, result) = call(&mut runtime, conn, move |client| {
+                client.add_credits(message)
+            });
+            client = conn;
+            match result {
+                Ok(_) => funded = true,
+                Err(err) => {
+                    error!("Unable to add credits for {}: {}", client_id_from, err);
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            }
+            continue;
+        }
+
+        let mut pick = rand::thread_rng().gen_range(0, total_weight);
+        let mut op = Op::GetBalance;
+        for (candidate, weight) in args.mix.iter() {
+            if pick < *weight {
+                op = *candidate;
+                break;
+            }
+            pick -= weight;
+        }
+        // There has to be a payment to settle
+        if op == Op::SettlePayment && unsettled.is_empty() {
+            op = Op::AddPayment;
+        }
+
+        let start = Instant::now();
+        let (conn, result) = match op {
+            Op::GetBalance => {
+                let message = request(
+                    proto::GetBalanceRequest {
+                        client_id: client_id_from.clone(),
+                        mode: proto::Mode::Test as i32,
+                    },
+                    &args.token,
+                );
+                let (conn, result) = call(&mut runtime, conn, move |client| {
+                    client.get_balance(message)
+                });
+                (conn, result.map(|_| ()))
+            }
+            Op::AddPayment => {
+                let mut message_hash = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut message_hash);
+                let message = request(
+                    proto::AddPaymentRequest {
+                        client_id_from: client_id_from.clone(),
+                        client_id_to: client_id_to.clone(),
+                        message_hash: message_hash.clone(),
+                        payment_cents: args.payment_cents,
+                        is_promo: false,
+                        referrer_client_id: String::new(),
+                        mode: proto::Mode::Test as i32,
+                        campaign_id: String::new(),
+                    },
+                    &args.token,
+                );
+                let (conn, result) = call(&mut runtime, conn, move |client| {
+                    client.add_payment(message)
+                });
+                let result = result.and_then(|response| {
+                    if response.result == proto::add_payment_response::Result::Success as i32 {
+                        unsettled.push(message_hash);
+                        Ok(())
+                    } else {
+                        Err(format!("payment not added: {}", response.result))
+                    }
+                });
+                (conn, result)
+            }
+            Op::SettlePayment => {
+                let message = request(
+                    proto::SettlePaymentRequest {
+                        client_id: client_id_to.clone(),
+                        message_hash: unsettled.pop().unwrap(),
+                        action: proto::settle_payment_request::Action::Read as i32,
+                        tip_cents: 0,
+                        mode: proto::Mode::Test as i32,
+                    },
+                    &args.token,
+                );
+                let (conn, result) = call(&mut runtime, conn, move |client| {
+                    client.settle_payment(message)
+                });
+                (conn, result.map(|_| ()))
+            }
+        };
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+        client = conn;
+
+        let op_stats = stats.entry(op.name()).or_default();
+        match result {
+            Ok(()) => op_stats.latencies_ms.push(latency_ms),
+            Err(err) => {
+                debug!("{} failed: {}", op.name(), err);
+                op_stats.errors += 1;
+            }
+        }
+    }
+
+    stats
+}
+
+pub fn main() -> Result<(), Error> {
+    ::env_logger::init();
+
+    let args = parse_args()?;
+
+    warn!(
+        "Sending load to {} with {} workers for {}s. Only point this at a test environment, it adds credits and payments.",
+        args.address,
+        args.concurrency,
+        args.duration.as_secs()
+    );
+
+    let started = Instant::now();
+    let workers: Vec<_> = (0..args.concurrency)
+        .map(|_| {
+            let args = args.clone();
+            std::thread::spawn(move || run_worker(args))
+        })
+        .collect();
+
+    let mut stats: HashMap<&'static str, OpStats> = HashMap::new();
+    for worker in workers.into_iter() {
+        for (op, worker_stats) in worker.join().expect("worker panicked").into_iter() {
+            let op_stats = stats.entry(op).or_default();
+            op_stats.latencies_ms.extend(worker_stats.latencies_ms);
+            op_stats.errors += worker_stats.errors;
+        }
+    }
+    let elapsed_secs = started.elapsed().as_secs_f64();
+
+    let mut reports: Vec<OpReport> = stats
+        .into_iter()
+        .map(|(op, mut op_stats)| {
+            op_stats
+                .latencies_ms
+                .sort_by(|a, b| a.partial_cmp(b).unwrap());
+            OpReport {
+                op: op.into(),
+                requests: op_stats.latencies_ms.len(),
+                errors: op_stats.errors,
+                requests_per_sec: op_stats.latencies_ms.len() as f64 / elapsed_secs,
+                p50_ms: percentile(&op_stats.latencies_ms, 50.0),
+                p90_ms: percentile(&op_stats.latencies_ms, 90.0),
+                p99_ms: percentile(&op_stats.latencies_ms, 99.0),
+                max_ms: op_stats.latencies_ms.last().cloned().unwrap_or(0.0),
+            }
+        })
+        .collect();
+    reports.sort_by(|a, b| a.op.cmp(&b.op));
+
+    if args.json {
+        println!("{}", serde_json::to_string(&reports).unwrap());
+    } else {
+        for report in reports.iter() {
+            info!(
+                "{}: {} ok, {} errors, {:.1}/s, p50={:.1}ms p90={:.1}ms p99={:.1}ms max={:.1}ms",
+                report.op,
+                report.requests,
+                report.errors,
+                report.requests_per_sec,
+                report.p50_ms,
+                report.p90_ms,
+                report.p99_ms,
+                report.max_ms
+            );
+        }
+    }
+
+    Ok(())
+}
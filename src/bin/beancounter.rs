@@ -3,46 +3,144 @@ extern crate log;
 
 extern crate beancounter;
 extern crate beancounter_grpc;
+extern crate signal_hook;
 extern crate tokio;
 extern crate tower_hyper;
 
+use beancounter::client_locks::ClientLocks;
 use beancounter::config;
 use beancounter::database::get_db_pool;
+use beancounter::ledger_queue::LedgerQueue;
+use beancounter::login_links::LoginLinkCache;
+use beancounter::response_cache::ResponseCache;
+use beancounter::self_check;
 use beancounter::service;
+use beancounter::slo;
+use beancounter::stripe_client::Stripe;
 use beancounter_grpc::proto::server;
 use futures::{Future, Stream};
 use tokio::net::TcpListener;
 use tower_hyper::server::{Http, Server};
 
+/// Reload the config whenever we get a SIGHUP, applying it to the running
+/// service. A config which fails to load is logged and ignored.
+fn reload_on_sighup(beancounter: service::BeanCounter) {
+    use signal_hook::iterator::Signals;
+
+    let signals = Signals::new(&[signal_hook::SIGHUP]).expect("Unable to register SIGHUP handler");
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            match config::reload() {
+                Ok(config) => {
+                    beancounter.apply_config(&config);
+                    info!("CONFIG => {:#?}", config);
+                }
+                Err(err) => error!(
+                    "Rejected config reload, keeping the current config: {}",
+                    err
+                ),
+            }
+        }
+    });
+}
+
+/// Write the ledger queue every `flush_interval_ms`, and once more before
+/// exiting on SIGTERM or SIGINT, so that a clean shutdown loses nothing.
+fn flush_ledger_queue(beancounter: service::BeanCounter, flush_interval_ms: u64) {
+    use signal_hook::iterator::Signals;
+    use std::time::Duration;
+
+    let flusher = beancounter.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_millis(flush_interval_ms));
+        // Failures are logged by the queue, and retried next time
+        let _ = flusher.flush_ledger_queue();
+    });
+
+    let signals = Signals::new(&[signal_hook::SIGTERM, signal_hook::SIGINT])
+        .expect("Unable to register SIGTERM handler");
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            // Stop taking writes, so nothing is queued after the last flush
+            beancounter.set_read_only(true);
+            match beancounter.flush_ledger_queue() {
+                Ok(written) => info!("Wrote {} queued ledger rows before exiting", written),
+                Err(err) => error!("Exiting with unwritten ledger rows: {}", err),
+            }
+            std::process::exit(128 + signal);
+        }
+    });
+}
+
+/// Update the SLO gauges every `interval_secs`, so burn rates fall back as
+/// windows pass without failures.
+fn update_slo_gauges(interval_secs: u64) {
+    use std::time::Duration;
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(interval_secs));
+        slo::update_gauges();
+    });
+}
+
 pub fn main() {
     use std::env;
 
     ::env_logger::init();
 
     config::load_config();
+    let config = config::get();
 
     // Allow disablement of metrics reporting for testing
     if env::var_os("DISABLE_INSTRUMENTED").is_none() {
-        instrumented::init(&config::CONFIG.metrics.bind_to_address);
+        instrumented::init(&config.metrics.bind_to_address);
+    }
+
+    let db_pool_reader = get_db_pool(&config.database.reader);
+    let db_pool_writer = get_db_pool(&config.database.writer);
+
+    if config.service.skip_startup_checks {
+        warn!("Skipping startup checks");
+    } else if let Err(err) = self_check::run(&db_pool_writer.get().unwrap(), &Stripe::new()) {
+        error!("Startup check failed, refusing to start: {}", err);
+        std::process::exit(1);
+    }
+
+    let mut beancounter = service::BeanCounter::new(db_pool_reader, db_pool_writer);
+    if config.service.read_only {
+        warn!("Starting in read-only mode");
+    }
+    // Read-only mode is only taken from the config at startup, reloads leave
+    // it as set with SetReadOnly
+    beancounter.set_read_only(config.service.read_only);
+    beancounter.apply_config(&config);
+    // Like read-only mode, the client locks, login link cache, ledger queue and
+    // response cache are only set up at startup
+    beancounter.set_client_locks(ClientLocks::from_config(&config.client_locks));
+    beancounter.set_login_links(LoginLinkCache::from_config(&config.stripe));
+    beancounter.set_ledger_queue(LedgerQueue::from_config(&config.ledger_queue));
+    beancounter.set_response_cache(ResponseCache::from_config(&config.response_cache));
+
+    reload_on_sighup(beancounter.clone());
+    update_slo_gauges(config.slo.update_interval_secs);
+    if config.ledger_queue.enabled {
+        flush_ledger_queue(beancounter.clone(), config.ledger_queue.flush_interval_ms);
     }
 
-    let new_service = server::BeanCounterServer::new(service::BeanCounter::new(
-        get_db_pool(&config::CONFIG.database.reader),
-        get_db_pool(&config::CONFIG.database.writer),
-    ));
+    let new_service = server::BeanCounterServer::new(beancounter);
 
     let mut server = Server::new(new_service);
 
     let http = Http::new().http2_only(true).clone();
 
-    let addr = config::CONFIG.service.bind_to_address.parse().unwrap();
+    let addr = config.service.bind_to_address.parse().unwrap();
     let bind = TcpListener::bind(&addr).expect("bind");
 
     let serve = bind
         .incoming()
         .for_each(move |sock| {
             let addr = sock.peer_addr().ok();
-            info!("New connection from addr={:?}", addr);
+            debug!("New connection from addr={:?}", addr);
 
             let serve = server.serve_with(sock, http.clone());
             tokio::spawn(serve.map_err(|e| error!("hyper error: {:?}", e)));
@@ -52,15 +150,14 @@ pub fn main() {
         .map_err(|e| error!("accept error: {}", e));
 
     let mut rt = tokio::runtime::Builder::new()
-        .core_threads(config::CONFIG.service.worker_threads)
+        .core_threads(config.service.worker_threads)
         .build()
         .expect("Unable to build tokio runtime");
 
     rt.spawn(serve);
     info!(
         "Started server with {} threads, listening on {}",
-        config::CONFIG.service.worker_threads,
-        addr
+        config.service.worker_threads, addr
     );
     rt.shutdown_on_idle().wait().expect("Error in main loop");
 }
@@ -0,0 +1,136 @@
+//! In-process serialization of writes to hot accounts. Every write to a
+//! client's balance updates its balances row, so concurrent writes to a very
+//! active account contend for the row lock in Postgres, and the serializable
+//! ones abort and retry. Queueing them here instead means only one reaches the
+//! database at a time.
+//!
+//! Clients share a lock with every other client hashing to the same shard.
+//! The locks only reduce contention: the database still serializes writes
+//! from other processes, or which skip the lock, so correctness never depends
+//! on them.
+use instrumented::{prometheus, register};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::models::ClientId;
+
+fn make_intcounter(name: &str, description: &str) -> prometheus::IntCounter {
+    let counter = prometheus::IntCounter::new(name, description).unwrap();
+    register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+lazy_static! {
+    static ref SERIALIZED_WRITES: prometheus::IntCounter = make_intcounter(
+        "client_lock_serialized_writes_total",
+        "Writes which took a client lock"
+    );
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+    lock: Mutex<()>,
+    // The current second, and the number of writes seen in it
+    writes: Mutex<(u64, u32)>,
+}
+
+impl Shard {
+    /// Count a write, returning whether the shard is hot
+    fn is_hot(&self, hot_writes_per_second: u32) -> bool {
+        let second = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+        let mut writes = self.writes.lock().unwrap_or_else(PoisonError::into_inner);
+        if writes.0 != second {
+            *writes = (second, 0);
+        }
+        writes.1 += 1;
+        writes.1 > hot_writes_per_second
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClientLocks {
+    shards: Vec<Shard>,
+    hot_writes_per_second: u32,
+}
+
+impl ClientLocks {
+    /// No locks, so that every write goes straight to the database
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(shards: usize, hot_writes_per_second: u32) -> Self {
+        Self {
+            shards: (0..shards).map(|_| Shard::default()).collect(),
+            hot_writes_per_second,
+        }
+    }
+
+    pub fn from_config(config: &config::ClientLocks) -> Self {
+        Self::new(config.shards, config.hot_writes_per_second)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.shards.is_empty()
+    }
+
+    /// Wait for the client's lock, if its shard is hot. The write should be
+    /// made while the returned guard is held.
+    pub fn serialize(&self, client_id: ClientId) -> Option<MutexGuard<()>> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        client_id.hash(&mut hasher);
+        let shard = &self.shards[(hasher.finish() % self.shards.len() as u64) as usize];
+        if !shard.is_hot(self.hot_writes_per_second) {
+            return None;
+        }
+
+        SERIALIZED_WRITES.inc();
+        // Nothing is guarded, so a panic while holding the lock is harmless
+        Some(shard.lock.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_client_locks() {
+        let client_id: ClientId = Uuid::new_v4().into();
+
+        assert!(ClientLocks::disabled().serialize(client_id).is_none());
+
+        // Every write is serialized without a threshold
+        let locks = ClientLocks::new(4, 0);
+        assert!(locks.serialize(client_id).is_some());
+        assert!(locks.serialize(client_id).is_some());
+
+        // Writes are only serialized once the shard is hot, unless the second
+        // ticks over part way through
+        let second = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        let started = second();
+        let locks = ClientLocks::new(1, 2);
+        assert!(locks.serialize(client_id).is_none());
+        assert!(locks.serialize(client_id).is_none());
+        let hot = locks.serialize(client_id).is_some();
+        if second() == started {
+            assert!(hot);
+        }
+    }
+}
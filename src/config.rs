@@ -1,10 +1,23 @@
+use arc_swap::ArcSwap;
 use log::info;
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::Arc;
 use toml;
 use yansi::Paint;
 
+#[derive(Debug, Fail)]
+pub enum ConfigError {
+    #[fail(display = "unable to read {}: {}", path, err)]
+    Io { path: String, err: String },
+    #[fail(display = "unable to parse {}: {}", path, err)]
+    Parse { path: String, err: String },
+    #[fail(display = "invalid config: {}", err)]
+    Invalid { err: String },
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub service: Service,
@@ -12,6 +25,56 @@ pub struct Config {
     pub metrics: Metrics,
     pub stripe: Stripe,
     pub system_account: Account,
+    #[serde(default)]
+    pub referral: Referral,
+    #[serde(default)]
+    pub dormancy: Dormancy,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub risk: Risk,
+    #[serde(default)]
+    pub fx: Fx,
+    #[serde(default)]
+    pub bigquery: BigQuery,
+    #[serde(default)]
+    pub internal_accounts: InternalAccounts,
+    #[serde(default)]
+    pub withholding: Withholding,
+    #[serde(default)]
+    pub client_locks: ClientLocks,
+    #[serde(default)]
+    pub ledger_queue: LedgerQueue,
+    #[serde(default)]
+    pub response_cache: ResponseCache,
+    #[serde(default)]
+    pub fees: Fees,
+    #[serde(default)]
+    pub tiers: Tiers,
+    #[serde(default)]
+    pub expiry_refunds: ExpiryRefunds,
+    #[serde(default)]
+    pub payouts: Payouts,
+    #[serde(default)]
+    pub payment_holds: PaymentHolds,
+    #[serde(default)]
+    pub reminders: Reminders,
+    #[serde(default)]
+    pub request_log: RequestLog,
+    #[serde(default)]
+    pub statements: Statements,
+    #[serde(default)]
+    pub payment_links: PaymentLinks,
+    #[serde(default)]
+    pub invoices: Invoices,
+    #[serde(default)]
+    pub cron: Cron,
+    #[serde(default)]
+    pub ledger_stream: LedgerStream,
+    #[serde(default)]
+    pub dual_write: DualWrite,
+    #[serde(default)]
+    pub slo: Slo,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,10 +83,511 @@ pub struct Account {
     pub welcome_promo_amount: i32,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct Referral {
+    // Fraction of the read fee credited to a payment's referrer. Referral
+    // bonuses are disabled when this is 0.
+    pub read_fee_share: f64,
+}
+
+// Rules for accounts with no ledger activity. Each threshold is a number of
+// months, and 0 disables that rule.
+#[derive(Debug, Default, Deserialize)]
+pub struct Dormancy {
+    // Record a warning for the account
+    pub warn_after_months: u32,
+    // Expire any remaining promo balance
+    pub expire_promo_after_months: u32,
+    // Escheat the remaining cash balance. Only enable where legally required.
+    pub escheat_after_months: u32,
+}
+
+// Flags accounts whose credits added or payouts over the last day jump above
+// their daily average. Accounts with less history than the baseline are
+// compared to a lower average, so min_amount_cents keeps new accounts quiet.
+#[derive(Debug, Default, Deserialize)]
+pub struct Risk {
+    // Days of history the daily average is taken over. 0 disables detection.
+    pub baseline_days: u32,
+    // Flag when the last day's amount is at least this multiple of the average
+    pub velocity_multiplier: f64,
+    // The last day's amount must also be at least this much to be flagged
+    pub min_amount_cents: i64,
+}
+
+// Credits may be added in these currencies besides USD, and are converted to
+// USD at the latest rate fetched by the cron from the FX source.
+#[derive(Debug, Default, Deserialize)]
+pub struct Fx {
+    // Recorded against each rate snapshot
+    pub source: String,
+    // Returns JSON like {"rates": {"EUR": 0.9}}, in units of each currency
+    // per USD. Rates aren't fetched when empty.
+    pub url: String,
+    // ISO 4217 codes, i.e., "EUR"
+    pub currencies: Vec<String>,
+    // Credits in a currency are refused when its latest rate is older
+    pub max_rate_age_hours: u32,
+}
+
+// New ledger rows are streamed by the cron into BigQuery tables of the same
+// name, in the project's dataset. The access token is taken from
+// BIGQUERY_ACCESS_TOKEN when set, otherwise from token_url (i.e., the GCE
+// metadata server).
+#[derive(Debug, Default, Deserialize)]
+pub struct BigQuery {
+    pub enabled: bool,
+    pub project_id: String,
+    pub dataset: String,
+    pub token_url: String,
+    // Rows per streaming insert request
+    pub batch_size: u32,
+}
+
+// Well-known client IDs for the platform side of ledger entries, so that fee
+// revenue, the float held for clients and promo expense each have their own
+// sub-ledger. Entries for an account which isn't set are made against the
+// shared cash account (a NULL client_id), as before.
+#[derive(Debug, Default, Deserialize)]
+pub struct InternalAccounts {
+    // Read fees, less referral bonuses
+    pub fees: Option<String>,
+    // Cash added by clients which hasn't been paid out
+    pub float: Option<String>,
+    // Promo credits issued, less promo spent or expired
+    pub promo: Option<String>,
+    // Tax withheld from payouts, until it's remitted
+    pub withholding: Option<String>,
+    // Growth campaign budgets, which pay the send fee of campaign-funded
+    // payments
+    pub campaigns: Option<String>,
+}
+
+impl InternalAccounts {
+    /// The accounts which are set, by name
+    pub fn named(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("fees", &self.fees),
+            ("float", &self.float),
+            ("promo", &self.promo),
+            ("withholding", &self.withholding),
+            ("campaigns", &self.campaigns),
+        ]
+        .iter()
+        .filter_map(|(name, id)| id.as_ref().map(|id| (*name, id.as_str())))
+        .collect()
+    }
+}
+
+// Tax withheld from Connect payouts, by the country of the client's connected
+// account. Payouts to countries which aren't listed have nothing withheld.
+#[derive(Debug, Default, Deserialize)]
+pub struct Withholding {
+    // ISO 3166-1 alpha-2 codes (i.e., "DE") to the fraction of each payout
+    // withheld
+    #[serde(default)]
+    pub rates: HashMap<String, f64>,
+}
+
+// Writes to each client's balance are queued in-process, behind a lock shared
+// by every client hashing to the same shard, so that hot accounts don't
+// contend for their balances row. Taken from the config at startup only.
+#[derive(Debug, Default, Deserialize)]
+pub struct ClientLocks {
+    // 0 disables the locks
+    pub shards: usize,
+    // Writes to a shard are only queued once it's seen more than this many in
+    // the current second. 0 queues every write.
+    pub hot_writes_per_second: u32,
+}
+
+// Credits to the internal accounts made by payments are queued in-process and
+// written in batches, rather than in each payment's own database transaction.
+// Off by default; see the ledger_queue module for what it trades away. Taken
+// from the config at startup only.
+#[derive(Debug, Default, Deserialize)]
+pub struct LedgerQueue {
+    pub enabled: bool,
+    // The most rows held at once. Once it's full, payments write their credits
+    // themselves until the next flush makes room.
+    pub capacity: usize,
+    // How often the queue is written
+    pub flush_interval_ms: u64,
+}
+
+// Responses to GetConnectAccount and the first page of GetTransactions are
+// cached in-process, to absorb bursts of dashboard loads. Off by default; see
+// the response_cache module for how stale a response can be. Cached
+// GetConnectAccount responses include a login link, so keep the TTL well under
+// the links' own. Taken from the config at startup only.
+#[derive(Debug, Default, Deserialize)]
+pub struct ResponseCache {
+    // How long responses are cached for. 0 disables the cache.
+    pub ttl_ms: u64,
+}
+
+// The fees on each payment, in basis points (1/100th of a percent) of the
+// payment, and how fractions of a cent are rounded.
+#[derive(Debug, Deserialize)]
+pub struct Fees {
+    // Paid by the sender, on top of the payment
+    pub send_fee_bps: u32,
+    // Withheld from the recipient when the payment settles
+    pub read_fee_bps: u32,
+    #[serde(default)]
+    pub rounding: crate::fees::Rounding,
+    // Changes to the fees above, announced ahead of time, in the order they
+    // take effect
+    #[serde(default)]
+    pub changes: Vec<FeeChange>,
+}
+
+impl Default for Fees {
+    fn default() -> Self {
+        let schedule = crate::fees::FeeSchedule::default();
+        Self {
+            send_fee_bps: schedule.send_fee_bps,
+            read_fee_bps: schedule.read_fee_bps,
+            rounding: schedule.rounding,
+            changes: vec![],
+        }
+    }
+}
+
+// A change to the fees from the start (UTC) of effective_on, a date as
+// YYYY-MM-DD. Fees it doesn't set stay as they were. Tiers which override a
+// fee keep their own.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FeeChange {
+    pub effective_on: String,
+    pub send_fee_bps: Option<u32>,
+    pub read_fee_bps: Option<u32>,
+}
+
+impl FeeChange {
+    /// When the change takes effect, if effective_on is a valid date
+    pub fn effective_at(&self) -> Option<chrono::NaiveDateTime> {
+        chrono::NaiveDate::parse_from_str(&self.effective_on, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_hms(0, 0, 0))
+    }
+}
+
+// Each client is on an account tier, standard unless set with SetAccountTier.
+// A tier can override the fees and limits set above; anything it doesn't set
+// is the same as everywhere else.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Tiers {
+    #[serde(default)]
+    pub standard: Tier,
+    #[serde(default)]
+    pub pro: Tier,
+    #[serde(default)]
+    pub enterprise: Tier,
+}
+
+impl Tiers {
+    pub fn get(&self, tier: crate::sql_types::AccountTier) -> &Tier {
+        use crate::sql_types::AccountTier;
+
+        match tier {
+            AccountTier::Standard => &self.standard,
+            AccountTier::Pro => &self.pro,
+            AccountTier::Enterprise => &self.enterprise,
+        }
+    }
+
+    fn all(&self) -> [&Tier; 3] {
+        [&self.standard, &self.pro, &self.enterprise]
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Tier {
+    pub send_fee_bps: Option<u32>,
+    pub read_fee_bps: Option<u32>,
+    // The most a payment can debit the sender, fee included
+    pub max_payment_cents: Option<i32>,
+    // Overrides payouts.confirmation_threshold_cents
+    pub payout_confirmation_threshold_cents: Option<i32>,
+    // How far below zero senders on the tier can take their balance. They're
+    // invoiced monthly for what they send.
+    pub credit_limit_cents: Option<i64>,
+}
+
+// Payments which expire unread are refunded to the sender by the cron. The
+// send fee is kept ("fee_retained"), refunded too ("full_refund"), or kept in
+// part by the payment's age ("sliding_scale"). Promo payments have no fee.
+#[derive(Debug, Default, Deserialize)]
+pub struct ExpiryRefunds {
+    #[serde(default)]
+    pub policy: crate::sql_types::ExpiryRefundPolicy,
+    // For sliding_scale, in ascending order of age. Payments younger than the
+    // first step have their fee refunded in full.
+    #[serde(default)]
+    pub sliding_scale: Vec<ExpiryRefundStep>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpiryRefundStep {
+    // Days since the payment was sent
+    pub min_age_days: u32,
+    // Basis points of the send fee kept
+    pub fee_retained_bps: u32,
+}
+
+// Manual payouts over the threshold are made in two steps: they're initiated,
+// which quotes the payout, then confirmed before the quote expires.
+#[derive(Debug, Default, Deserialize)]
+pub struct Payouts {
+    // 0 never asks for confirmation
+    pub confirmation_threshold_cents: i32,
+    pub confirmation_ttl_minutes: u32,
+    #[serde(default)]
+    pub automatic_thresholds: AutomaticPayoutThresholds,
+    // Once a client has earned more than this, they can't be paid out until
+    // Stripe has verified their identity. 0 never asks for verification.
+    #[serde(default)]
+    pub kyc_threshold_cents: i64,
+}
+
+// The automatic payout thresholds clients can set with
+// UpdateConnectAccountPrefs: from min_cents to max_cents, in multiples of
+// step_cents.
+#[derive(Debug, Deserialize)]
+pub struct AutomaticPayoutThresholds {
+    pub min_cents: i64,
+    pub max_cents: i64,
+    pub step_cents: i64,
+}
+
+impl Default for AutomaticPayoutThresholds {
+    fn default() -> Self {
+        let thresholds = crate::connect_prefs::PayoutThresholds::default();
+        Self {
+            min_cents: thresholds.min_cents,
+            max_cents: thresholds.max_cents,
+            step_cents: thresholds.step_cents,
+        }
+    }
+}
+
+// Sends of at least the threshold are held from the sender's balance, rather
+// than paid, until they're captured with CapturePayment when the message is
+// delivered. Holds which aren't captured in time are released by the cron.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PaymentHolds {
+    // 0 never holds a send
+    pub threshold_cents: i32,
+    pub capture_window_minutes: u32,
+}
+
+// Recipients are sent a reminder event for their unread payments shortly
+// before they expire, and are refunded to the sender.
+#[derive(Debug, Default, Deserialize)]
+pub struct Reminders {
+    // How many days before expiry. 0 disables reminders.
+    pub payment_expiry_days: u32,
+}
+
+// Each client's statement for the previous month is recorded by the cron once
+// the month is over, and a statement ready event sent with a download token.
+// Tokens are signed with STATEMENT_SIGNING_SECRET, and statements aren't
+// recorded without it.
+#[derive(Debug, Default, Deserialize)]
+pub struct Statements {
+    // How long the token in each event can be used for. 0 disables statements.
+    pub download_ttl_hours: u32,
+}
+
+// Clients can request payment off-platform with a link, which pays them an
+// amount once redeemed. Links are signed with PAYMENT_LINK_SIGNING_SECRET, and
+// can't be created without it.
+#[derive(Debug, Default, Deserialize)]
+pub struct PaymentLinks {
+    // How long a link can be redeemed for. 0 disables payment links.
+    pub ttl_hours: u32,
+}
+
+// Senders on a tier with a credit limit are invoiced by the cron for each
+// month's usage once it's over, and their payments of the invoice recorded
+// with RecordInvoicePayment.
+#[derive(Debug, Deserialize)]
+pub struct Invoices {
+    // How long after the month's invoice is recorded it's due
+    pub due_days: u32,
+}
+
+impl Default for Invoices {
+    fn default() -> Self {
+        Self { due_days: 30 }
+    }
+}
+
+// Cron instances in more than one region elect a leader for each run, and only
+// the leader runs the jobs, see `leader`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Cron {
+    // Where this instance runs, to tell the leader apart in the deep check
+    pub region: String,
+}
+
+// StreamLedger sends each client's balance movements, summed over an interval,
+// rather than every transaction.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LedgerStream {
+    // How often a batch is sent, unless the caller asks otherwise
+    pub interval_secs: u32,
+    // Transactions are only sent once they're this old, by which time any
+    // transaction with an earlier ID has committed too, so the cursor never
+    // skips one
+    pub settle_secs: u32,
+    // At most this many transactions are summed into a batch
+    pub batch_size: i64,
+}
+
+impl Default for LedgerStream {
+    fn default() -> Self {
+        Self {
+            interval_secs: 10,
+            settle_secs: 30,
+            batch_size: 5000,
+        }
+    }
+}
+
+// Schema changes in progress, which write their new columns alongside the old
+// ones. See `dual_write`.
+#[derive(Debug, Default, Deserialize)]
+pub struct DualWrite {
+    // The dual writes turned on, by name
+    pub enabled: Vec<String>,
+}
+
+// Objectives for each RPC's availability and latency, which the service
+// measures its calls against, exporting how fast the error budget is being
+// spent. See `slo`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Slo {
+    // Fraction of calls which shouldn't fail with a server error, i.e., 0.999
+    pub availability_target: f64,
+    // Fraction of calls which should finish within latency_threshold_ms
+    pub latency_target: f64,
+    pub latency_threshold_ms: u64,
+    // The windows burn rates are computed over, in minutes
+    pub windows_minutes: Vec<u32>,
+    // How often the gauges are updated. Only read at startup.
+    pub update_interval_secs: u64,
+    // Targets for particular RPCs, named as in the proto (i.e., "GetBalance")
+    #[serde(default)]
+    pub rpcs: HashMap<String, SloTarget>,
+}
+
+impl Default for Slo {
+    fn default() -> Self {
+        Self {
+            availability_target: 0.999,
+            latency_target: 0.99,
+            latency_threshold_ms: 500,
+            windows_minutes: vec![5, 60, 360],
+            update_interval_secs: 15,
+            rpcs: HashMap::new(),
+        }
+    }
+}
+
+// Unset fields are taken from [slo]
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SloTarget {
+    pub availability_target: Option<f64>,
+    pub latency_target: Option<f64>,
+    pub latency_threshold_ms: Option<u64>,
+}
+
+// A summary of each RPC handled (the caller, client, latency and outcome) is
+// logged for a sample of successful calls, and for every failed call. Request
+// bodies are never logged.
+#[derive(Debug, Default, Deserialize)]
+pub struct RequestLog {
+    // Fraction of successful calls logged, for RPCs not in sample_rates
+    pub sample_rate: f64,
+    // By RPC, named as in the proto (i.e., "GetBalance")
+    #[serde(default)]
+    pub sample_rates: HashMap<String, f64>,
+    // Include the client each call is for
+    #[serde(default)]
+    pub log_client_ids: bool,
+}
+
+// Per-RPC authorization. When enabled, callers must present a bearer token
+// belonging to a caller with a scope granting the RPC.
+#[derive(Debug, Default, Deserialize)]
+pub struct Auth {
+    pub enabled: bool,
+    // The RPCs granted by each scope, named as in the proto (i.e.,
+    // "GetBalance"). "*" grants every RPC.
+    #[serde(default)]
+    pub scopes: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub callers: Vec<AuthCaller>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthCaller {
+    pub name: String,
+    // Hex encoded SHA-256 of the caller's token
+    pub token_sha256: String,
+    pub scopes: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Stripe {
     pub redirect_uri: String,
     pub connect_client_id: String,
+    // Log the method, path, latency and outcome of each Stripe API call
+    #[serde(default)]
+    pub log_api_calls: bool,
+    // Also log redacted request and response bodies, at debug level
+    #[serde(default)]
+    pub log_api_bodies: bool,
+    // Pay out each Connect transfer to the account's bank right away, rather
+    // than waiting for the account's payout schedule
+    #[serde(default)]
+    pub trigger_payouts: bool,
+    // Request instant payouts, falling back to standard payouts for accounts
+    // which aren't eligible
+    #[serde(default)]
+    pub instant_payouts: bool,
+    // Keep the token and charge for each card charge in stripe_charges, with
+    // card details scrubbed
+    #[serde(default)]
+    pub persist_charges: bool,
+    // Fields removed before persisting, in addition to the card number,
+    // expiry, fingerprint and so on, which are always removed
+    #[serde(default)]
+    pub scrub_fields: Vec<String>,
+    // Credits from charges Stripe evaluates at these risk levels (i.e.,
+    // "elevated") are held, and can't be spent until released
+    #[serde(default)]
+    pub hold_risk_levels: Vec<String>,
+    // Held credits are released after this long. 0 leaves them held until
+    // they're reviewed.
+    #[serde(default)]
+    pub hold_release_hours: u32,
+    // Charges which Stripe returns as pending, i.e., bank debits, are credited
+    // when they succeed, or refunded if they haven't after this long. 0 waits
+    // for Stripe to resolve them however long it takes.
+    #[serde(default)]
+    pub pending_charge_expiry_hours: u32,
+    // How long Express dashboard login links are valid for once created, so
+    // that each is reused until shortly before then. 0 creates a new link
+    // every time.
+    #[serde(default)]
+    pub login_link_ttl_secs: u32,
+    // The account's statement descriptor prefix, as set in the Stripe
+    // dashboard. A charge's statement descriptor suffix must fit after it.
+    #[serde(default)]
+    pub statement_descriptor_prefix: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +597,14 @@ pub struct Service {
     pub tls_cert_path: String,
     pub tls_key_path: String,
     pub bind_to_address: String,
+    #[serde(default)]
+    pub read_only: bool,
+    // Skips checking the schema version, enums and Stripe key at startup
+    #[serde(default)]
+    pub skip_startup_checks: bool,
+    // The format IDs are returned in
+    #[serde(default)]
+    pub uuid_format: crate::uuid_format::UuidFormat,
 }
 
 #[derive(Debug, Deserialize)]
@@ -61,19 +633,236 @@ fn get_beancounter_toml_path() -> String {
 }
 
 lazy_static! {
-    pub static ref CONFIG: Config = {
-        let beancounter_toml_path = get_beancounter_toml_path();
-        let config: Config = toml::from_str(&read_file_to_string(&beancounter_toml_path)).unwrap();
-        config
+    // Swapped out as a whole when the config is reloaded
+    static ref CONFIG: ArcSwap<Config> = {
+        let config =
+            read_config(&get_beancounter_toml_path()).unwrap_or_else(|err| panic!("{}", err));
+        ArcSwap::from_pointee(config)
     };
 }
 
-fn read_file_to_string(filename: &str) -> String {
-    let mut file = File::open(filename).expect("Unable to open the file");
+fn read_file_to_string(filename: &str) -> Result<String, std::io::Error> {
+    let mut file = File::open(filename)?;
     let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .expect("Unable to read the file");
-    contents
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn read_config(path: &str) -> Result<Config, ConfigError> {
+    let contents = read_file_to_string(path).map_err(|err| ConfigError::Io {
+        path: path.into(),
+        err: err.to_string(),
+    })?;
+    let config: Config = toml::from_str(&contents).map_err(|err| ConfigError::Parse {
+        path: path.into(),
+        err: err.to_string(),
+    })?;
+    config.validate()?;
+    Ok(config)
+}
+
+impl Config {
+    /// Check values which parse, but don't make sense
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |err: &str| Err(ConfigError::Invalid { err: err.into() });
+
+        if self.referral.read_fee_share < 0.0 || self.referral.read_fee_share > 1.0 {
+            return invalid("referral.read_fee_share must be between 0 and 1");
+        }
+        if self.fees.send_fee_bps > 10_000 || self.fees.read_fee_bps > 10_000 {
+            return invalid("fees can't be more than 10000 basis points");
+        }
+        let changes = &self.fees.changes;
+        if changes.iter().any(|change| {
+            change.effective_at().is_none()
+                || change.send_fee_bps.map_or(false, |bps| bps > 10_000)
+                || change.read_fee_bps.map_or(false, |bps| bps > 10_000)
+        }) || changes
+            .windows(2)
+            .any(|changes| changes[0].effective_at() >= changes[1].effective_at())
+        {
+            return invalid(
+                "fees.changes must have effective_on dates as YYYY-MM-DD in ascending order, and fees of at most 10000 basis points",
+            );
+        }
+        if self.tiers.all().iter().any(|tier| {
+            tier.send_fee_bps.map_or(false, |bps| bps > 10_000)
+                || tier.read_fee_bps.map_or(false, |bps| bps > 10_000)
+                || tier.max_payment_cents.map_or(false, |cents| cents <= 0)
+                || tier
+                    .payout_confirmation_threshold_cents
+                    .map_or(false, |cents| cents < 0)
+                || tier.credit_limit_cents.map_or(false, |cents| cents < 0)
+        }) {
+            return invalid(
+                "tiers can't have fees over 10000 basis points, a max_payment_cents under 1, or a negative payout_confirmation_threshold_cents or credit_limit_cents",
+            );
+        }
+        let sliding_scale = &self.expiry_refunds.sliding_scale;
+        if sliding_scale
+            .iter()
+            .any(|step| step.fee_retained_bps > 10_000)
+            || sliding_scale
+                .windows(2)
+                .any(|steps| steps[0].min_age_days >= steps[1].min_age_days)
+        {
+            return invalid(
+                "expiry_refunds.sliding_scale must be in ascending order of age, keeping at most 10000 basis points",
+            );
+        }
+        if self.expiry_refunds.policy == crate::sql_types::ExpiryRefundPolicy::SlidingScale
+            && sliding_scale.is_empty()
+        {
+            return invalid(
+                "expiry_refunds.sliding_scale must be set for the sliding_scale policy",
+            );
+        }
+        if self.payouts.confirmation_threshold_cents < 0 {
+            return invalid("payouts.confirmation_threshold_cents can't be negative");
+        }
+        if (self.payouts.confirmation_threshold_cents > 0
+            || self
+                .tiers
+                .all()
+                .iter()
+                .any(|tier| tier.payout_confirmation_threshold_cents.unwrap_or(0) > 0))
+            && self.payouts.confirmation_ttl_minutes == 0
+        {
+            return invalid("payouts.confirmation_ttl_minutes must be set with a threshold");
+        }
+        if self.payment_holds.threshold_cents < 0 {
+            return invalid("payment_holds.threshold_cents can't be negative");
+        }
+        if self.payment_holds.threshold_cents > 0 && self.payment_holds.capture_window_minutes == 0
+        {
+            return invalid("payment_holds.capture_window_minutes must be set with a threshold");
+        }
+        let thresholds = &self.payouts.automatic_thresholds;
+        if thresholds.min_cents < 0
+            || thresholds.min_cents > thresholds.max_cents
+            || thresholds.step_cents < 1
+        {
+            return invalid("payouts.automatic_thresholds must have 0 <= min_cents <= max_cents, and a step_cents of at least 1");
+        }
+        if std::iter::once(&self.request_log.sample_rate)
+            .chain(self.request_log.sample_rates.values())
+            .any(|rate| *rate < 0.0 || *rate > 1.0)
+        {
+            return invalid("request_log sample rates must be between 0 and 1");
+        }
+        if let Some(name) = self
+            .dual_write
+            .enabled
+            .iter()
+            .find(|name| crate::dual_write::find(name).is_none())
+        {
+            return Err(ConfigError::Invalid {
+                err: format!("dual_write.enabled has an unknown dual write {}", name),
+            });
+        }
+        if self.ledger_stream.interval_secs == 0 || self.ledger_stream.batch_size < 1 {
+            return invalid("ledger_stream needs an interval_secs and batch_size of at least 1");
+        }
+        // A target of 1 leaves no error budget to burn
+        if std::iter::once(self.slo.availability_target)
+            .chain(std::iter::once(self.slo.latency_target))
+            .chain(self.slo.rpcs.values().flat_map(|target| {
+                target
+                    .availability_target
+                    .into_iter()
+                    .chain(target.latency_target)
+            }))
+            .any(|target| target <= 0.0 || target >= 1.0)
+        {
+            return invalid("slo targets must be between 0 and 1, exclusive");
+        }
+        if self.slo.windows_minutes.is_empty()
+            || self
+                .slo
+                .windows_minutes
+                .iter()
+                .any(|window| *window == 0 || *window > 7 * 24 * 60)
+            || self.slo.update_interval_secs == 0
+        {
+            return invalid(
+                "slo needs windows_minutes of 1 minute to 7 days, and an update_interval_secs",
+            );
+        }
+        if self.risk.velocity_multiplier < 0.0 || self.risk.min_amount_cents < 0 {
+            return invalid("risk thresholds can't be negative");
+        }
+        if self
+            .fx
+            .currencies
+            .iter()
+            .any(|currency| currency.len() != 3)
+        {
+            return invalid("fx.currencies must be ISO 4217 codes");
+        }
+        if self.bigquery.enabled
+            && (self.bigquery.project_id.is_empty()
+                || self.bigquery.dataset.is_empty()
+                || self.bigquery.batch_size == 0)
+        {
+            return invalid("bigquery needs a project_id, dataset and batch_size when enabled");
+        }
+        let internal_accounts = self.internal_accounts.named();
+        if let Some((name, _)) = internal_accounts
+            .iter()
+            .find(|(_, id)| uuid::Uuid::parse_str(id).is_err())
+        {
+            return Err(ConfigError::Invalid {
+                err: format!("internal_accounts.{} must be a UUID", name),
+            });
+        }
+        if internal_accounts.iter().enumerate().any(|(i, (_, a))| {
+            internal_accounts[i + 1..]
+                .iter()
+                .any(|(_, b)| uuid::Uuid::parse_str(a).ok() == uuid::Uuid::parse_str(b).ok())
+        }) {
+            return invalid("internal_accounts must be distinct");
+        }
+        if let Some((country, _)) = self.withholding.rates.iter().find(|(country, rate)| {
+            country.len() != 2
+                || !country.chars().all(|c| c.is_ascii_uppercase())
+                || **rate < 0.0
+                || **rate >= 1.0
+        }) {
+            return Err(ConfigError::Invalid {
+                err: format!(
+                    "withholding.rates.{} must be at least 0 and less than 1, for an ISO 3166-1 alpha-2 code",
+                    country
+                ),
+            });
+        }
+        if let Some(caller) = self.auth.callers.iter().find(|caller| {
+            caller.token_sha256.len() != 64
+                || !caller.token_sha256.chars().all(|c| c.is_ascii_hexdigit())
+        }) {
+            return Err(ConfigError::Invalid {
+                err: format!("auth caller {} has an invalid token_sha256", caller.name),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The current config. Hold on to it for the length of a request or job, so
+/// that a reload part way through isn't seen half applied. Settings used at
+/// startup (addresses, databases and threads) need a restart to change.
+pub fn get() -> Arc<Config> {
+    CONFIG.load_full()
+}
+
+/// Read the config file again, replacing the current config if it's valid.
+/// An invalid file is rejected, and the current config is kept.
+pub fn reload() -> Result<Arc<Config>, ConfigError> {
+    let path = get_beancounter_toml_path();
+    let config = Arc::new(read_config(&path)?);
+    CONFIG.store(config.clone());
+    info!("Reloaded BeanCounter configuration values from {}", path);
+    Ok(config)
 }
 
 pub fn load_config() {
@@ -81,5 +870,5 @@ pub fn load_config() {
         "Loaded BeanCounter configuration values from {}",
         get_beancounter_toml_path()
     );
-    info!("CONFIG => {:#?}", Paint::red(&*CONFIG));
+    info!("CONFIG => {:#?}", Paint::red(&*get()));
 }
@@ -0,0 +1,112 @@
+//! Validation of the preferences clients set on their connected accounts.
+//! Invalid preferences are rejected field by field, with the bounds they were
+//! checked against, so that a UI can show the limits without hardcoding them.
+use crate::config;
+
+/// The automatic payout thresholds clients can choose: from the minimum to the
+/// maximum, in whole steps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PayoutThresholds {
+    pub min_cents: i64,
+    pub max_cents: i64,
+    pub step_cents: i64,
+}
+
+impl Default for PayoutThresholds {
+    /// Whole dollars from $100 to $100,000
+    fn default() -> Self {
+        Self {
+            min_cents: 100 * 100,
+            max_cents: 100_000 * 100,
+            step_cents: 100,
+        }
+    }
+}
+
+/// Why a preference was rejected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldErrorReason {
+    BelowMinimum,
+    AboveMaximum,
+    NotAWholeStep,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldError {
+    /// As named in ConnectAccountPrefs
+    pub field: &'static str,
+    pub reason: FieldErrorReason,
+    pub message: String,
+}
+
+impl PayoutThresholds {
+    pub fn from_config(config: &config::AutomaticPayoutThresholds) -> Self {
+        Self {
+            min_cents: config.min_cents,
+            max_cents: config.max_cents,
+            step_cents: config.step_cents,
+        }
+    }
+
+    /// Check an automatic payout threshold, returning what's wrong with it if
+    /// anything. The threshold is checked whether or not automatic payouts are
+    /// enabled, as it's kept for when they are.
+    pub fn check(&self, threshold_cents: i64) -> Option<FieldError> {
+        let error = |reason, message| {
+            Some(FieldError {
+                field: "automatic_payout_threshold_cents",
+                reason,
+                message,
+            })
+        };
+        if threshold_cents < self.min_cents {
+            error(
+                FieldErrorReason::BelowMinimum,
+                format!("must be at least {} cents", self.min_cents),
+            )
+        } else if threshold_cents > self.max_cents {
+            error(
+                FieldErrorReason::AboveMaximum,
+                format!("must be at most {} cents", self.max_cents),
+            )
+        } else if threshold_cents % self.step_cents != 0 {
+            error(
+                FieldErrorReason::NotAWholeStep,
+                format!("must be a multiple of {} cents", self.step_cents),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payout_thresholds() {
+        let thresholds = PayoutThresholds::default();
+        assert_eq!(thresholds.check(100 * 100), None);
+        assert_eq!(thresholds.check(100_000 * 100), None);
+        assert_eq!(thresholds.check(250 * 100), None);
+
+        let reason = |threshold_cents| {
+            thresholds
+                .check(threshold_cents)
+                .map(|error| (error.field, error.reason))
+        };
+        let field = "automatic_payout_threshold_cents";
+        // No longer raised to the minimum
+        assert_eq!(reason(0), Some((field, FieldErrorReason::BelowMinimum)));
+        assert_eq!(reason(-100), Some((field, FieldErrorReason::BelowMinimum)));
+        assert_eq!(
+            reason(100_000 * 100 + 100),
+            Some((field, FieldErrorReason::AboveMaximum))
+        );
+        assert_eq!(
+            reason(100 * 100 + 50),
+            Some((field, FieldErrorReason::NotAWholeStep))
+        );
+    }
+}
@@ -1,12 +1,270 @@
 use crate::config;
+use crate::timing;
+use diesel::connection::{
+    AnsiTransactionManager, Connection, SimpleConnection, TransactionManager,
+};
+use diesel::deserialize::{Queryable, QueryableByName};
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel::result::{ConnectionResult, QueryResult};
+use diesel::sql_types::HasSqlType;
+use std::ops::Deref;
+
+/// A PgConnection which times every statement it runs, so each RPC's DB time
+/// and query count can be recorded (see `timing`).
+pub struct DbConnection(PgConnection);
+
+impl DbConnection {
+    /// The underlying connection, for Postgres specific APIs such as
+    /// `build_transaction()`.
+    pub fn pg(&self) -> &PgConnection {
+        &self.0
+    }
+}
+
+impl SimpleConnection for DbConnection {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        timing::query(|| self.0.batch_execute(query))
+    }
+}
+
+impl Connection for DbConnection {
+    type Backend = Pg;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(database_url: &str) -> ConnectionResult<Self> {
+        PgConnection::establish(database_url).map(DbConnection)
+    }
+
+    fn execute(&self, query: &str) -> QueryResult<usize> {
+        timing::query(|| self.0.execute(query))
+    }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Pg> + QueryId,
+        Pg: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Pg>,
+    {
+        timing::query(|| self.0.query_by_index(source))
+    }
+
+    fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Pg> + QueryId,
+        U: QueryableByName<Pg>,
+    {
+        timing::query(|| self.0.query_by_name(source))
+    }
+
+    fn execute_returning_count<T>(&self, source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Pg> + QueryId,
+    {
+        timing::query(|| self.0.execute_returning_count(source))
+    }
+
+    fn transaction_manager(&self) -> &Self::TransactionManager {
+        self.0.transaction_manager()
+    }
+}
+
+/// A transaction which is kept open while the request it's for waits on
+/// Stripe. The connection is moved into the future's continuation, rather than
+/// held by a thread blocked on the call. It's rolled back if it's dropped
+/// without being committed, such as when the call fails.
+pub struct OpenTransaction {
+    conn: Option<PooledConnection<ConnectionManager<DbConnection>>>,
+}
+
+impl OpenTransaction {
+    pub fn begin(conn: PooledConnection<ConnectionManager<DbConnection>>) -> QueryResult<Self> {
+        conn.transaction_manager().begin_transaction(&*conn)?;
+        Ok(Self { conn: Some(conn) })
+    }
+
+    /// Commit, handing back the connection
+    pub fn commit(mut self) -> QueryResult<PooledConnection<ConnectionManager<DbConnection>>> {
+        let conn = self.conn.take().unwrap();
+        conn.transaction_manager().commit_transaction(&*conn)?;
+        Ok(conn)
+    }
+
+    /// Roll back, handing back the connection for what's written instead
+    pub fn rollback(mut self) -> QueryResult<PooledConnection<ConnectionManager<DbConnection>>> {
+        let conn = self.conn.take().unwrap();
+        conn.transaction_manager().rollback_transaction(&*conn)?;
+        Ok(conn)
+    }
+}
+
+impl Deref for OpenTransaction {
+    type Target = PooledConnection<ConnectionManager<DbConnection>>;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl Drop for OpenTransaction {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if let Err(err) = conn.transaction_manager().rollback_transaction(&*conn) {
+                error!("Unable to roll back open transaction: {}", err);
+            }
+        }
+    }
+}
+
+/// What a read is for, which decides where it's routed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadIntent {
+    /// Reporting to the caller. Lagging the primary by a little is fine, so
+    /// it's read from the replica.
+    Report,
+    /// Deciding what to write, such as checking a balance covers a payment.
+    /// It's read from the primary, so it sees every committed write.
+    Decide,
+}
+
+/// Hands out connections by what they're for: reads go to the replica or the
+/// primary depending on their `ReadIntent`, and writes to the primary.
+///
+/// Reads get a `ReadConnection`, which can't be passed to anything expecting a
+/// pooled connection, and so to anything which writes. Its session is also
+/// read only, so Postgres rejects a write made through it any other way.
+#[derive(Clone)]
+pub struct DbRouter {
+    reader: Pool<ConnectionManager<DbConnection>>,
+    writer: Pool<ConnectionManager<DbConnection>>,
+}
+
+impl DbRouter {
+    pub fn new(
+        reader: Pool<ConnectionManager<DbConnection>>,
+        writer: Pool<ConnectionManager<DbConnection>>,
+    ) -> Self {
+        Self { reader, writer }
+    }
+
+    /// A connection for reads, bound to the given ledger
+    pub fn read(&self, intent: ReadIntent, livemode: bool) -> ReadConnection {
+        let pool = match intent {
+            ReadIntent::Report => &self.reader,
+            ReadIntent::Decide => &self.writer,
+        };
+        ReadConnection(checkout(pool, livemode, true))
+    }
+
+    /// A connection for writes, bound to the given ledger
+    pub fn write(&self, livemode: bool) -> PooledConnection<ConnectionManager<DbConnection>> {
+        checkout(&self.writer, livemode, false)
+    }
+
+    pub fn reader_pool(&self) -> &Pool<ConnectionManager<DbConnection>> {
+        &self.reader
+    }
+
+    pub fn writer_pool(&self) -> &Pool<ConnectionManager<DbConnection>> {
+        &self.writer
+    }
+}
+
+/// Transactions, payments and balances are views which only show rows for the
+/// ledger in the connection's `beancounter.livemode` setting, and fill it in on
+/// insert. Pooled connections keep whatever settings they were last used with,
+/// so both it and whether the session is read only are set on every checkout.
+fn checkout(
+    pool: &Pool<ConnectionManager<DbConnection>>,
+    livemode: bool,
+    read_only: bool,
+) -> PooledConnection<ConnectionManager<DbConnection>> {
+    let on_off = |setting| if setting { "on" } else { "off" };
+
+    let conn = pool.get().unwrap();
+    conn.batch_execute(&format!(
+        "SET beancounter.livemode = {}; SET default_transaction_read_only = {}",
+        on_off(livemode),
+        on_off(read_only)
+    ))
+    .unwrap();
+    conn
+}
+
+/// A connection which can only read. Statements which report a count of rows
+/// affected, which is how diesel runs inserts, updates and deletes, are
+/// rejected without being sent.
+pub struct ReadConnection(PooledConnection<ConnectionManager<DbConnection>>);
+
+#[derive(Debug)]
+struct WriteOnReadConnection;
+
+impl std::fmt::Display for WriteOnReadConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "write attempted on a read connection")
+    }
+}
+
+impl std::error::Error for WriteOnReadConnection {}
+
+impl SimpleConnection for ReadConnection {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        self.0.batch_execute(query)
+    }
+}
+
+impl Connection for ReadConnection {
+    type Backend = Pg;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(_database_url: &str) -> ConnectionResult<Self> {
+        panic!("read connections are checked out from a DbRouter")
+    }
+
+    fn execute(&self, _query: &str) -> QueryResult<usize> {
+        Err(diesel::result::Error::QueryBuilderError(Box::new(
+            WriteOnReadConnection,
+        )))
+    }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Pg> + QueryId,
+        Pg: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Pg>,
+    {
+        self.0.query_by_index(source)
+    }
+
+    fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Pg> + QueryId,
+        U: QueryableByName<Pg>,
+    {
+        self.0.query_by_name(source)
+    }
+
+    fn execute_returning_count<T>(&self, _source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Pg> + QueryId,
+    {
+        Err(diesel::result::Error::QueryBuilderError(Box::new(
+            WriteOnReadConnection,
+        )))
+    }
+
+    fn transaction_manager(&self) -> &Self::TransactionManager {
+        self.0.transaction_manager()
+    }
+}
 
 pub fn get_db_pool(
     database: &config::Database,
-) -> diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<diesel::pg::PgConnection>> {
-    use diesel::pg::PgConnection;
-    use diesel::r2d2::{ConnectionManager, Pool};
-
-    let manager = ConnectionManager::<PgConnection>::new(format!(
+) -> diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<DbConnection>> {
+    let manager = ConnectionManager::<DbConnection>::new(format!(
         "postgres://{}:{}@{}:{}/{}",
         database.username, database.password, database.host, database.port, database.name,
     ));
@@ -0,0 +1,154 @@
+//! Dual writes, for large schema changes without a maintenance window. Rather
+//! than rewriting a table in a migration, a column's replacement is added,
+//! nullable, and filled in alongside the old column:
+//!
+//! 1. A migration adds the new column, without touching existing rows.
+//! 2. Once it's run, the dual write is enabled in `[dual_write]`, and every
+//!    new row is written with both columns.
+//! 3. The cron backfills the rows written before, in chunks, then compares
+//!    the two columns. Rows still missing the new column, and rows where the
+//!    two disagree, are reported in the `dual_write_missing_rows` and
+//!    `dual_write_divergent_rows` gauges.
+//! 4. When nothing is missing or divergent, reads move to the new column,
+//!    and a later migration drops the old one.
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::database::DbConnection;
+
+pub struct DualWrite {
+    pub name: &'static str,
+    // The table the rows are in, rather than a livemode view, so both ledgers
+    // are backfilled and compared
+    pub table: &'static str,
+    pub column: &'static str,
+    // What the new column should hold, computed from the old
+    pub expected: &'static str,
+    enabled: AtomicBool,
+}
+
+/// transactions.amount_cents, to a BIGINT
+pub static TRANSACTIONS_AMOUNT_I64: DualWrite = DualWrite {
+    name: "transactions_amount_i64",
+    table: "transactions_all",
+    column: "amount_cents_i64",
+    expected: "amount_cents::BIGINT",
+    enabled: AtomicBool::new(false),
+};
+
+pub static DUAL_WRITES: [&DualWrite; 1] = [&TRANSACTIONS_AMOUNT_I64];
+
+pub fn find(name: &str) -> Option<&'static DualWrite> {
+    DUAL_WRITES
+        .iter()
+        .find(|dual_write| dual_write.name == name)
+        .cloned()
+}
+
+/// Enable the dual writes named in the config, and disable the rest
+pub fn apply_config(config: &crate::config::DualWrite) {
+    for dual_write in DUAL_WRITES.iter() {
+        let enabled = config.enabled.iter().any(|name| name == dual_write.name);
+        if enabled != dual_write.is_enabled() {
+            info!(
+                "{} dual write {}",
+                if enabled { "Enabling" } else { "Disabling" },
+                dual_write.name
+            );
+        }
+        dual_write.set_enabled(enabled);
+    }
+}
+
+/// How far the new column is from matching the old
+#[derive(Debug, Default, PartialEq, QueryableByName)]
+pub struct Divergence {
+    // Rows the new column hasn't been filled in for
+    #[sql_type = "BigInt"]
+    pub missing: i64,
+    // Rows where the new column doesn't hold what's expected
+    #[sql_type = "BigInt"]
+    pub divergent: i64,
+}
+
+impl DualWrite {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed)
+    }
+
+    /// The value to write to the new column, while the dual write is enabled
+    pub fn value<T>(&self, value: T) -> Option<T> {
+        if self.is_enabled() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn backfill_sql(&self) -> String {
+        format!(
+            "UPDATE {table} SET {column} = {expected} \
+             WHERE id IN (SELECT id FROM {table} WHERE {column} IS NULL LIMIT $1)",
+            table = self.table,
+            column = self.column,
+            expected = self.expected,
+        )
+    }
+
+    fn compare_sql(&self) -> String {
+        format!(
+            "SELECT \
+             COUNT(1) FILTER (WHERE {column} IS NULL) AS missing, \
+             COUNT(1) FILTER (WHERE {column} <> {expected}) AS divergent \
+             FROM {table}",
+            table = self.table,
+            column = self.column,
+            expected = self.expected,
+        )
+    }
+
+    /// Fill in the new column for up to `limit` rows written before the dual
+    /// write was enabled. Returns how many were.
+    pub fn backfill(&self, conn: &DbConnection, limit: i64) -> QueryResult<usize> {
+        sql_query(self.backfill_sql())
+            .bind::<BigInt, _>(limit)
+            .execute(conn)
+    }
+
+    pub fn compare(&self, conn: &DbConnection) -> QueryResult<Divergence> {
+        sql_query(self.compare_sql()).get_result(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sql() {
+        assert_eq!(
+            TRANSACTIONS_AMOUNT_I64.backfill_sql(),
+            "UPDATE transactions_all SET amount_cents_i64 = amount_cents::BIGINT \
+             WHERE id IN (SELECT id FROM transactions_all WHERE amount_cents_i64 IS NULL LIMIT $1)"
+        );
+        assert_eq!(
+            TRANSACTIONS_AMOUNT_I64.compare_sql(),
+            "SELECT \
+             COUNT(1) FILTER (WHERE amount_cents_i64 IS NULL) AS missing, \
+             COUNT(1) FILTER (WHERE amount_cents_i64 <> amount_cents::BIGINT) AS divergent \
+             FROM transactions_all"
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        assert!(find("transactions_amount_i64").is_some());
+        assert!(find("nope").is_none());
+    }
+}
@@ -0,0 +1,348 @@
+//! Fee math. Fees are set in basis points (hundredths of a percent), and
+//! computed in integer cents with an explicit rounding policy, so a payment's
+//! fee is exact and the same wherever it's computed.
+use chrono::NaiveDateTime;
+
+use crate::config;
+use crate::sql_types::ExpiryRefundPolicy;
+
+/// How a fee which isn't a whole number of cents is rounded.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Towards zero, i.e., 2.9¢ is 2¢
+    Down,
+    /// To the nearest cent, with halves rounded up
+    HalfUp,
+    /// To the nearest cent, with halves rounded to the even cent (banker's
+    /// rounding)
+    HalfEven,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding::Down
+    }
+}
+
+const BPS_PER_UNIT: i64 = 10_000;
+
+/// `amount_cents * bps / 10000`, rounded. Negative amounts are rounded
+/// symmetrically, so that a fee on a reversal mirrors the original.
+pub fn apply_bps(amount_cents: i64, bps: u32, rounding: Rounding) -> i64 {
+    if amount_cents < 0 {
+        return -apply_bps(-amount_cents, bps, rounding);
+    }
+
+    let product = amount_cents * i64::from(bps);
+    let quotient = product / BPS_PER_UNIT;
+    let remainder = product % BPS_PER_UNIT;
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::HalfUp => remainder * 2 >= BPS_PER_UNIT,
+        Rounding::HalfEven => {
+            remainder * 2 > BPS_PER_UNIT || (remainder * 2 == BPS_PER_UNIT && quotient % 2 == 1)
+        }
+    };
+
+    if round_up {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+/// The fees charged on payments: the send fee is paid by the sender on top of
+/// the payment, and the read fee is withheld from the recipient.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeSchedule {
+    pub send_fee_bps: u32,
+    pub read_fee_bps: u32,
+    pub rounding: Rounding,
+}
+
+impl Default for FeeSchedule {
+    /// 3% to send and 7% to read, rounded down
+    fn default() -> Self {
+        Self {
+            send_fee_bps: 300,
+            read_fee_bps: 700,
+            rounding: Rounding::Down,
+        }
+    }
+}
+
+impl FeeSchedule {
+    pub fn from_config(config: &config::Fees) -> Self {
+        Self {
+            send_fee_bps: config.send_fee_bps,
+            read_fee_bps: config.read_fee_bps,
+            rounding: config.rounding,
+        }
+    }
+
+    /// The schedule for clients on a tier, with its fee overrides applied.
+    /// The rounding is the same for every tier.
+    pub fn for_tier(&self, tier: &config::Tier) -> Self {
+        Self {
+            send_fee_bps: tier.send_fee_bps.unwrap_or(self.send_fee_bps),
+            read_fee_bps: tier.read_fee_bps.unwrap_or(self.read_fee_bps),
+            rounding: self.rounding,
+        }
+    }
+
+    /// The non-refundable fee charged to the sender of a payment.
+    pub fn send_fee_cents(&self, payment_cents: i32) -> i32 {
+        apply_bps(i64::from(payment_cents), self.send_fee_bps, self.rounding) as i32
+    }
+
+    /// What the sender is debited for a payment: exactly the payment plus the
+    /// send fee.
+    pub fn send_total_cents(&self, payment_cents: i32) -> i32 {
+        payment_cents + self.send_fee_cents(payment_cents)
+    }
+
+    /// The fee withheld from the recipient when a payment settles.
+    pub fn read_fee_cents(&self, payment_cents: i32) -> i32 {
+        apply_bps(i64::from(payment_cents), self.read_fee_bps, self.rounding) as i32
+    }
+
+    /// The part of a payment's send fee which is kept when the payment expires
+    /// unread, `age_days` after it was sent. The rest is refunded with it.
+    pub fn expiry_fee_retained_cents(
+        &self,
+        payment_cents: i32,
+        age_days: i64,
+        refunds: &config::ExpiryRefunds,
+    ) -> i32 {
+        let fee_cents = self.send_fee_cents(payment_cents);
+        match refunds.policy {
+            ExpiryRefundPolicy::FullRefund => 0,
+            ExpiryRefundPolicy::FeeRetained => fee_cents,
+            ExpiryRefundPolicy::SlidingScale => refunds
+                .sliding_scale
+                .iter()
+                .take_while(|step| i64::from(step.min_age_days) <= age_days)
+                .last()
+                .map_or(0, |step| {
+                    apply_bps(i64::from(fee_cents), step.fee_retained_bps, self.rounding) as i32
+                }),
+        }
+    }
+
+    /// The read fee as a fraction of the payment
+    pub fn read_fee_rate(&self) -> f64 {
+        f64::from(self.read_fee_bps) / BPS_PER_UNIT as f64
+    }
+
+    /// The send fee as a fraction of the payment
+    pub fn send_fee_rate(&self) -> f64 {
+        f64::from(self.send_fee_bps) / BPS_PER_UNIT as f64
+    }
+}
+
+/// A scheduled change to the fees, and the schedule from when it takes effect
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeChange {
+    pub effective_at: NaiveDateTime,
+    pub schedule: FeeSchedule,
+}
+
+impl FeeChange {
+    /// The config's changes to `base`, each applied over the one before it.
+    /// Changes without a valid date are left out, which the config's
+    /// validation rules out.
+    pub fn from_config(base: &FeeSchedule, config: &config::Fees) -> Vec<Self> {
+        let mut schedule = *base;
+        config
+            .changes
+            .iter()
+            .filter_map(|change| {
+                let effective_at = change.effective_at()?;
+                schedule = FeeSchedule {
+                    send_fee_bps: change.send_fee_bps.unwrap_or(schedule.send_fee_bps),
+                    read_fee_bps: change.read_fee_bps.unwrap_or(schedule.read_fee_bps),
+                    rounding: schedule.rounding,
+                };
+                Some(Self {
+                    effective_at,
+                    schedule,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The schedule in effect at `at`: `base`, as changed by the last of the
+/// `changes` to have taken effect by then
+pub fn in_effect(base: &FeeSchedule, changes: &[FeeChange], at: NaiveDateTime) -> FeeSchedule {
+    changes
+        .iter()
+        .take_while(|change| change.effective_at <= at)
+        .last()
+        .map_or(*base, |change| change.schedule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUNDINGS: &[Rounding] = &[Rounding::Down, Rounding::HalfUp, Rounding::HalfEven];
+
+    #[test]
+    fn test_apply_bps() {
+        // 2.5¢
+        assert_eq!(apply_bps(250, 100, Rounding::Down), 2);
+        assert_eq!(apply_bps(250, 100, Rounding::HalfUp), 3);
+        assert_eq!(apply_bps(250, 100, Rounding::HalfEven), 2);
+        // 3.5¢
+        assert_eq!(apply_bps(350, 100, Rounding::Down), 3);
+        assert_eq!(apply_bps(350, 100, Rounding::HalfUp), 4);
+        assert_eq!(apply_bps(350, 100, Rounding::HalfEven), 4);
+        // 2.51¢ and 2.49¢
+        assert_eq!(apply_bps(251, 100, Rounding::HalfEven), 3);
+        assert_eq!(apply_bps(249, 100, Rounding::HalfUp), 2);
+
+        assert_eq!(apply_bps(-250, 100, Rounding::HalfUp), -3);
+        assert_eq!(apply_bps(1000, 0, Rounding::HalfUp), 0);
+        assert_eq!(apply_bps(1000, 10_000, Rounding::Down), 1000);
+    }
+
+    #[test]
+    fn test_every_amount_to_ten_dollars() {
+        for &rounding in ROUNDINGS {
+            for &(send_fee_bps, read_fee_bps) in &[(300, 700), (0, 0), (250, 1250), (1, 9999)] {
+                let fees = FeeSchedule {
+                    send_fee_bps,
+                    read_fee_bps,
+                    rounding,
+                };
+
+                let mut last_send_fee = 0;
+                for payment_cents in 1..=1000 {
+                    let send_fee = fees.send_fee_cents(payment_cents);
+                    let read_fee = fees.read_fee_cents(payment_cents);
+
+                    // Within half a cent of the exact fee, or under a cent
+                    // below it when rounding down
+                    for &(fee, bps) in &[(send_fee, send_fee_bps), (read_fee, read_fee_bps)] {
+                        let exact = i64::from(payment_cents) * i64::from(bps);
+                        let error = i64::from(fee) * BPS_PER_UNIT - exact;
+                        match rounding {
+                            Rounding::Down => assert!(error <= 0 && error > -BPS_PER_UNIT),
+                            Rounding::HalfUp => {
+                                assert!(error * 2 >= -BPS_PER_UNIT && error * 2 < BPS_PER_UNIT)
+                            }
+                            Rounding::HalfEven => {
+                                assert!(error.abs() * 2 <= BPS_PER_UNIT);
+                                if error.abs() * 2 == BPS_PER_UNIT {
+                                    assert_eq!(fee % 2, 0);
+                                }
+                            }
+                        }
+                    }
+
+                    assert!(send_fee >= 0 && send_fee <= payment_cents);
+                    assert!(read_fee >= 0 && read_fee <= payment_cents);
+                    assert!(send_fee >= last_send_fee);
+                    assert_eq!(
+                        fees.send_total_cents(payment_cents),
+                        payment_cents + send_fee
+                    );
+                    last_send_fee = send_fee;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_schedule() {
+        // Matches the original 3% and 7%, rounded down
+        let fees = FeeSchedule::default();
+        for payment_cents in 1..=1000 {
+            assert_eq!(fees.send_fee_cents(payment_cents), payment_cents * 3 / 100);
+            assert_eq!(fees.read_fee_cents(payment_cents), payment_cents * 7 / 100);
+        }
+    }
+
+    #[test]
+    fn test_expiry_fee_retained() {
+        let fees = FeeSchedule::default();
+        let mut refunds = config::ExpiryRefunds::default();
+        // A 3% fee on $10
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 30, &refunds), 30);
+
+        refunds.policy = ExpiryRefundPolicy::FullRefund;
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 30, &refunds), 0);
+
+        refunds.policy = ExpiryRefundPolicy::SlidingScale;
+        refunds.sliding_scale = vec![
+            config::ExpiryRefundStep {
+                min_age_days: 30,
+                fee_retained_bps: 2500,
+            },
+            config::ExpiryRefundStep {
+                min_age_days: 60,
+                fee_retained_bps: 10_000,
+            },
+        ];
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 29, &refunds), 0);
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 30, &refunds), 7);
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 59, &refunds), 7);
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 60, &refunds), 30);
+        assert_eq!(fees.expiry_fee_retained_cents(1000, 365, &refunds), 30);
+        // No fee, nothing kept
+        assert_eq!(fees.expiry_fee_retained_cents(10, 60, &refunds), 0);
+    }
+
+    #[test]
+    fn test_for_tier() {
+        let fees = FeeSchedule::default();
+        assert_eq!(fees.for_tier(&config::Tier::default()), fees);
+
+        let tier = config::Tier {
+            send_fee_bps: Some(100),
+            ..config::Tier::default()
+        };
+        let tier_fees = fees.for_tier(&tier);
+        assert_eq!(tier_fees.send_fee_bps, 100);
+        assert_eq!(tier_fees.read_fee_bps, fees.read_fee_bps);
+        assert_eq!(tier_fees.send_fee_cents(1000), 10);
+        assert_eq!(tier_fees.read_fee_cents(1000), 70);
+    }
+
+    #[test]
+    fn test_fee_changes() {
+        let base = FeeSchedule::default();
+        let change = |effective_on: &str, send_fee_bps, read_fee_bps| config::FeeChange {
+            effective_on: effective_on.into(),
+            send_fee_bps,
+            read_fee_bps,
+        };
+        let config = config::Fees {
+            changes: vec![
+                change("2020-01-01", Some(400), None),
+                change("2020-06-01", None, Some(800)),
+            ],
+            ..config::Fees::default()
+        };
+        let changes = FeeChange::from_config(&base, &config);
+        assert_eq!(changes.len(), 2);
+        // Each change keeps what the ones before it set
+        assert_eq!(changes[1].schedule.send_fee_bps, 400);
+        assert_eq!(changes[1].schedule.read_fee_bps, 800);
+
+        let at = |date: &str| {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .unwrap()
+                .and_hms(0, 0, 0)
+        };
+        assert_eq!(in_effect(&base, &changes, at("2019-12-31")), base);
+        let schedule = in_effect(&base, &changes, at("2020-01-01"));
+        assert_eq!(schedule.send_fee_bps, 400);
+        assert_eq!(schedule.read_fee_bps, 700);
+        let schedule = in_effect(&base, &changes, at("2021-01-01"));
+        assert_eq!(schedule, changes[1].schedule);
+        assert_eq!(in_effect(&base, &[], at("2021-01-01")), base);
+    }
+}
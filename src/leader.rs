@@ -0,0 +1,108 @@
+//! Leader election for the cron. Instances deployed in more than one region
+//! share the writer database, and only one of them may run at a time, so two
+//! never make the same payouts. A run first takes a session-level Postgres
+//! advisory lock on its own connection, and holds it until the connection
+//! closes at the end of the run. A run which can't take the lock is passive,
+//! and leaves the jobs to the leader.
+//!
+//! The lock shows up in `pg_locks`, so the service can report which instance
+//! is leading, from the `application_name` the cron sets on its connection.
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::*;
+
+use crate::database::DbConnection;
+
+// The advisory lock key, "bean". It fits in 32 bits, so the lock's classid is
+// 0 and its objid the key.
+pub static CRON_LOCK_KEY: i64 = 0x6265_616e;
+
+/// The cron's application_name, with the region it runs in, if set
+pub fn application_name(region: &str) -> String {
+    if region.is_empty() {
+        "beancounter-cron".into()
+    } else {
+        format!("beancounter-cron ({})", region)
+    }
+}
+
+#[derive(QueryableByName)]
+struct Acquired {
+    #[sql_type = "Bool"]
+    acquired: bool,
+}
+
+/// Try to become the leader. The lock is held for as long as `conn` stays
+/// open, so keep it for the length of the run.
+pub fn try_acquire(conn: &DbConnection, region: &str) -> QueryResult<bool> {
+    sql_query("SELECT set_config('application_name', $1, false)")
+        .bind::<Text, _>(application_name(region))
+        .execute(conn)?;
+    let acquired: Acquired = sql_query("SELECT pg_try_advisory_lock($1) AS acquired")
+        .bind::<BigInt, _>(CRON_LOCK_KEY)
+        .get_result(conn)?;
+    Ok(acquired.acquired)
+}
+
+/// Step down at the end of a run, so the next can start without waiting for
+/// the connection to close
+pub fn release(conn: &DbConnection) -> QueryResult<()> {
+    sql_query("SELECT pg_advisory_unlock($1)")
+        .bind::<BigInt, _>(CRON_LOCK_KEY)
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// The connection holding the lock
+#[derive(Debug, QueryableByName)]
+pub struct Leader {
+    #[sql_type = "Text"]
+    pub application_name: String,
+    #[sql_type = "Nullable<Text>"]
+    pub client_addr: Option<String>,
+    // When the connection was opened, about when the run started
+    #[sql_type = "Timestamp"]
+    pub since: chrono::NaiveDateTime,
+}
+
+/// The current leader, if a cron run is in progress
+pub fn current(conn: &DbConnection) -> QueryResult<Option<Leader>> {
+    sql_query(
+        r#"
+        SELECT
+            a.application_name,
+            HOST(a.client_addr) AS client_addr,
+            a.backend_start AT TIME ZONE 'UTC' AS since
+        FROM
+            pg_locks l
+            JOIN pg_stat_activity a USING (pid)
+        WHERE
+            l.locktype = 'advisory'
+            AND l.granted
+            AND l.classid = 0
+            AND l.objid::BIGINT = $1
+            AND l.objsubid = 1
+            AND l.database = (
+                SELECT
+                    oid
+                FROM
+                    pg_database
+                WHERE
+                    datname = current_database())
+        "#,
+    )
+    .bind::<BigInt, _>(CRON_LOCK_KEY)
+    .get_results(conn)
+    .map(|mut leaders: Vec<Leader>| leaders.pop())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_application_name() {
+        assert_eq!(application_name(""), "beancounter-cron");
+        assert_eq!(application_name("us-east1"), "beancounter-cron (us-east1)");
+    }
+}
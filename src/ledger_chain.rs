@@ -0,0 +1,678 @@
+//! A hash chain over the live ledger, for tamper evidence. Each transaction
+//! is hashed, and the hash chained to the previous transaction's, both across
+//! the whole ledger and within the transaction's client, so that modifying,
+//! removing or inserting a transaction after the fact breaks the chain from
+//! that point on.
+//!
+//! Transactions are written by many requests at once, and a chain needs a
+//! single order, so rather than hashing each row as it's written, the cron
+//! seals the rows which have settled, in order of (created_at, id). A row is
+//! settled once it's older than the settle delay, by which time the request
+//! or ledger queue flush which wrote it has committed. Each cron run then
+//! anchors the head of the chain, both in the ledger_hash_anchors table and in
+//! the cron's log, which is kept outside of the database.
+//!
+//! `beancounter-admin verify-ledger-chain` recomputes the chain and reports
+//! where it doesn't match the ledger.
+use data_encoding::HEXLOWER;
+use diesel::sql_types::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::database::DbConnection;
+use crate::models::{ClientId, LedgerHash, NewLedgerHash};
+
+/// A transaction, as it's hashed
+#[derive(Debug, Clone, QueryableByName)]
+pub struct LedgerEntry {
+    #[sql_type = "BigInt"]
+    pub id: i64,
+    #[sql_type = "Timestamp"]
+    pub created_at: chrono::NaiveDateTime,
+    #[sql_type = "Nullable<diesel::pg::types::sql_types::Uuid>"]
+    pub client_id: Option<ClientId>,
+    #[sql_type = "Text"]
+    pub tx_type: String,
+    #[sql_type = "Text"]
+    pub tx_reason: String,
+    #[sql_type = "Integer"]
+    pub amount_cents: i32,
+    #[sql_type = "Nullable<diesel::pg::types::sql_types::Uuid>"]
+    pub operation_id: Option<uuid::Uuid>,
+    #[sql_type = "Nullable<diesel::pg::types::sql_types::Uuid>"]
+    pub reverses_operation_id: Option<uuid::Uuid>,
+    #[sql_type = "Nullable<Text>"]
+    pub original_currency: Option<String>,
+    #[sql_type = "Nullable<Integer>"]
+    pub original_amount_cents: Option<i32>,
+    #[sql_type = "Nullable<Double>"]
+    pub fx_rate: Option<f64>,
+    #[sql_type = "Nullable<BigInt>"]
+    pub fx_rate_id: Option<i64>,
+    #[sql_type = "Nullable<Text>"]
+    pub reference: Option<String>,
+}
+
+// Every column of the live ledger, with the enums as their database names.
+// The `transactions` view only shows the ledger of the connection's
+// `beancounter.livemode`, which is live unless set otherwise.
+static LEDGER_ENTRY_COLUMNS: &str = r#"
+    id,
+    created_at,
+    client_id,
+    tx_type::TEXT AS tx_type,
+    tx_reason::TEXT AS tx_reason,
+    amount_cents,
+    operation_id,
+    reverses_operation_id,
+    original_currency,
+    original_amount_cents,
+    fx_rate,
+    fx_rate_id,
+    reference
+"#;
+
+#[derive(QueryableByName)]
+struct ClientHead {
+    #[sql_type = "diesel::pg::types::sql_types::Uuid"]
+    client_id: ClientId,
+    #[sql_type = "Text"]
+    client_hash: String,
+}
+
+#[derive(QueryableByName)]
+struct UnsealedId {
+    #[sql_type = "BigInt"]
+    id: i64,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    HEXLOWER.encode(&Sha256::digest(data))
+}
+
+/// Hash a transaction's contents. Every column is included, in a fixed order,
+/// so the hash changes if any of them do.
+pub fn content_hash(entry: &LedgerEntry) -> String {
+    let contents = serde_json::json!([
+        entry.id,
+        entry.created_at.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+        // In the simple format, whichever IDs are returned in
+        entry
+            .client_id
+            .map(|client_id| client_id.as_uuid().to_simple().to_string()),
+        entry.tx_type,
+        entry.tx_reason,
+        entry.amount_cents,
+        entry.operation_id.map(|id| id.to_simple().to_string()),
+        entry
+            .reverses_operation_id
+            .map(|id| id.to_simple().to_string()),
+        entry.original_currency,
+        entry.original_amount_cents,
+        entry.fx_rate,
+        entry.fx_rate_id,
+        entry.reference,
+    ]);
+    sha256_hex(contents.to_string().as_bytes())
+}
+
+/// Chain a content hash to the hash before it, if any
+fn link_hash(previous: Option<&String>, content_hash: &str) -> String {
+    sha256_hex(
+        format!(
+            "{}:{}",
+            previous.map(String::as_str).unwrap_or_default(),
+            content_hash
+        )
+        .as_bytes(),
+    )
+}
+
+/// The heads of the global chain and of each client's chain. Transactions
+/// without a client are chained together like a client's.
+#[derive(Debug, Default)]
+pub struct Chain {
+    head: Option<String>,
+    client_heads: HashMap<Option<ClientId>, String>,
+}
+
+impl Chain {
+    /// Hash the next transaction onto the chain
+    pub fn link(&mut self, entry: &LedgerEntry) -> NewLedgerHash {
+        let content_hash = content_hash(entry);
+        let chain_hash = link_hash(self.head.as_ref(), &content_hash);
+        let client_hash = link_hash(self.client_heads.get(&entry.client_id), &content_hash);
+        self.head = Some(chain_hash.clone());
+        self.client_heads
+            .insert(entry.client_id, client_hash.clone());
+        NewLedgerHash {
+            transaction_id: entry.id,
+            transaction_created_at: entry.created_at,
+            client_id: entry.client_id,
+            content_hash,
+            client_hash,
+            chain_hash,
+        }
+    }
+
+    /// Move on to a row sealed earlier, returning whether its hashes follow
+    /// from the ones before it. The chain continues from the row's hashes
+    /// either way, so one broken link is reported once.
+    fn follow(&mut self, sealed: &LedgerHash) -> bool {
+        let follows = sealed.chain_hash == link_hash(self.head.as_ref(), &sealed.content_hash)
+            && sealed.client_hash
+                == link_hash(
+                    self.client_heads.get(&sealed.client_id),
+                    &sealed.content_hash,
+                );
+        self.head = Some(sealed.chain_hash.clone());
+        self.client_heads
+            .insert(sealed.client_id, sealed.client_hash.clone());
+        follows
+    }
+}
+
+/// The head of the chain, and of the chains of the given clients
+fn load_chain(
+    clients: &[Option<ClientId>],
+    conn: &DbConnection,
+) -> Result<(Chain, Option<LedgerHash>), diesel::result::Error> {
+    use crate::schema::ledger_hashes::columns::*;
+    use crate::schema::ledger_hashes::table as ledger_hashes;
+    use diesel::prelude::*;
+
+    let last: Option<LedgerHash> = ledger_hashes.order(id.desc()).first(conn).optional()?;
+    let mut chain = Chain {
+        head: last.as_ref().map(|last| last.chain_hash.clone()),
+        client_heads: HashMap::new(),
+    };
+
+    let with_client: Vec<ClientId> = clients.iter().filter_map(|client| *client).collect();
+    if !with_client.is_empty() {
+        let heads: Vec<ClientHead> = diesel::sql_query(
+            r#"
+            SELECT DISTINCT ON (client_id)
+                client_id,
+                client_hash
+            FROM
+                ledger_hashes
+            WHERE
+                client_id = ANY($1)
+            ORDER BY
+                client_id,
+                id DESC
+            "#,
+        )
+        .bind::<Array<diesel::pg::types::sql_types::Uuid>, _>(with_client)
+        .load(conn)?;
+        for head in heads.into_iter() {
+            chain
+                .client_heads
+                .insert(Some(head.client_id), head.client_hash);
+        }
+    }
+    if clients.contains(&None) {
+        let head: Option<String> = ledger_hashes
+            .filter(client_id.is_null())
+            .order(id.desc())
+            .select(client_hash)
+            .first(conn)
+            .optional()?;
+        if let Some(head) = head {
+            chain.client_heads.insert(None, head);
+        }
+    }
+
+    Ok((chain, last))
+}
+
+/// When the last sealed transaction was made. The chain continues from it, so
+/// a transaction written dated before it would never be sealed. Writers of
+/// backdated transactions take `LOCK TABLE ledger_hashes IN SHARE MODE` before
+/// checking, so the chain can't move past them until they commit.
+pub fn sealed_through(
+    conn: &DbConnection,
+) -> Result<Option<chrono::NaiveDateTime>, diesel::result::Error> {
+    let (_, last) = load_chain(&[], conn)?;
+    Ok(last.map(|last| last.transaction_created_at))
+}
+
+/// Seal the settled transactions which aren't in the chain yet, a batch per
+/// database transaction, returning how many were sealed
+pub fn seal(
+    settle_delay_secs: f64,
+    batch_size: i64,
+    conn: &DbConnection,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::ledger_hashes::table as ledger_hashes;
+    use diesel::connection::Connection;
+    use diesel::prelude::*;
+
+    let mut sealed = 0;
+    loop {
+        let batch = conn.transaction::<usize, diesel::result::Error, _>(|| {
+            // One sealer at a time, since each batch continues from the last
+            diesel::sql_query("LOCK TABLE ledger_hashes IN EXCLUSIVE MODE").execute(conn)?;
+
+            let (_, last) = load_chain(&[], conn)?;
+            let entries: Vec<LedgerEntry> = diesel::sql_query(format!(
+                r#"
+                SELECT
+                    {}
+                FROM
+                    transactions
+                WHERE
+                    ($1 IS NULL OR (created_at, id) > ($1, $2))
+                    AND created_at < NOW() - make_interval(secs => $3)
+                ORDER BY
+                    created_at,
+                    id
+                LIMIT $4
+                "#,
+                LEDGER_ENTRY_COLUMNS
+            ))
+            .bind::<Nullable<Timestamp>, _>(last.as_ref().map(|last| last.transaction_created_at))
+            .bind::<BigInt, _>(last.as_ref().map_or(0, |last| last.transaction_id))
+            .bind::<Double, _>(settle_delay_secs)
+            .bind::<BigInt, _>(batch_size)
+            .load(conn)?;
+            if entries.is_empty() {
+                return Ok(0);
+            }
+
+            let mut clients: Vec<Option<ClientId>> =
+                entries.iter().map(|entry| entry.client_id).collect();
+            clients.sort();
+            clients.dedup();
+            let (mut chain, _) = load_chain(&clients, conn)?;
+
+            let hashes: Vec<NewLedgerHash> =
+                entries.iter().map(|entry| chain.link(entry)).collect();
+            diesel::insert_into(ledger_hashes)
+                .values(&hashes)
+                .execute(conn)
+        })?;
+
+        sealed += batch;
+        if (batch as i64) < batch_size {
+            return Ok(sealed);
+        }
+    }
+}
+
+/// Record the head of the chain as an anchor, returning it, or None if
+/// nothing has been sealed yet
+pub fn anchor(conn: &DbConnection) -> Result<Option<LedgerHash>, diesel::result::Error> {
+    use crate::models::NewLedgerHashAnchor;
+    use crate::schema::ledger_hash_anchors::table as ledger_hash_anchors;
+    use diesel::prelude::*;
+
+    let (_, last) = load_chain(&[], conn)?;
+    if let Some(last) = &last {
+        diesel::insert_into(ledger_hash_anchors)
+            .values(&NewLedgerHashAnchor {
+                ledger_hash_id: last.id,
+                chain_hash: last.chain_hash.clone(),
+            })
+            .execute(conn)?;
+    }
+    Ok(last)
+}
+
+/// Where the chain and the ledger disagree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The transaction no longer matches the hash it was sealed with
+    Modified { transaction_id: i64 },
+    /// The transaction was sealed, but is no longer in the ledger
+    Removed { transaction_id: i64 },
+    /// The transaction is older than the head of the chain, but was never
+    /// sealed, i.e., it was inserted after the rows around it were
+    Unsealed { transaction_id: i64 },
+    /// The sealed row's hashes don't follow from the rows before it
+    BrokenLink { ledger_hash_id: i64 },
+    /// The sealed row is out of order with the row before it
+    OutOfOrder { ledger_hash_id: i64 },
+    /// The chain no longer has the hash it was anchored with
+    AnchorMismatch { anchor_id: i64 },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Problem::Modified { transaction_id } => {
+                write!(f, "transaction id={} was modified", transaction_id)
+            }
+            Problem::Removed { transaction_id } => {
+                write!(f, "transaction id={} was removed", transaction_id)
+            }
+            Problem::Unsealed { transaction_id } => {
+                write!(
+                    f,
+                    "transaction id={} is missing from the chain",
+                    transaction_id
+                )
+            }
+            Problem::BrokenLink { ledger_hash_id } => {
+                write!(
+                    f,
+                    "ledger hash id={} doesn't follow the chain",
+                    ledger_hash_id
+                )
+            }
+            Problem::OutOfOrder { ledger_hash_id } => {
+                write!(f, "ledger hash id={} is out of order", ledger_hash_id)
+            }
+            Problem::AnchorMismatch { anchor_id } => {
+                write!(f, "anchor id={} doesn't match the chain", anchor_id)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Verification {
+    // Sealed rows and anchors checked
+    pub sealed: usize,
+    pub anchors: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Check each sealed row against the row before it, the transaction it
+/// sealed, and any anchor recorded for it
+fn check_sealed(
+    chain: &mut Chain,
+    previous: Option<&LedgerHash>,
+    sealed: &LedgerHash,
+    entry: Option<&LedgerEntry>,
+    anchored: &[(i64, String)],
+) -> Vec<Problem> {
+    let mut problems = vec![];
+    if let Some(previous) = previous {
+        if (sealed.transaction_created_at, sealed.transaction_id)
+            <= (previous.transaction_created_at, previous.transaction_id)
+        {
+            problems.push(Problem::OutOfOrder {
+                ledger_hash_id: sealed.id,
+            });
+        }
+    }
+    if !chain.follow(sealed) {
+        problems.push(Problem::BrokenLink {
+            ledger_hash_id: sealed.id,
+        });
+    }
+    match entry {
+        Some(entry) => {
+            if content_hash(entry) != sealed.content_hash {
+                problems.push(Problem::Modified {
+                    transaction_id: sealed.transaction_id,
+                });
+            }
+        }
+        None => problems.push(Problem::Removed {
+            transaction_id: sealed.transaction_id,
+        }),
+    }
+    for (anchor_id, chain_hash) in anchored.iter() {
+        if *chain_hash != sealed.chain_hash {
+            problems.push(Problem::AnchorMismatch {
+                anchor_id: *anchor_id,
+            });
+        }
+    }
+    problems
+}
+
+/// Recompute the chain from the start, in batches, against the ledger and the
+/// anchors
+pub fn verify(batch_size: i64, conn: &DbConnection) -> Result<Verification, diesel::result::Error> {
+    use crate::models::LedgerHashAnchor;
+    use crate::schema::ledger_hash_anchors::columns as anchor_columns;
+    use crate::schema::ledger_hash_anchors::table as ledger_hash_anchors;
+    use crate::schema::ledger_hashes::columns::*;
+    use crate::schema::ledger_hashes::table as ledger_hashes;
+    use diesel::prelude::*;
+
+    let mut verification = Verification::default();
+
+    let mut anchors: HashMap<i64, Vec<(i64, String)>> = HashMap::new();
+    for anchor in ledger_hash_anchors
+        .order(anchor_columns::id)
+        .load::<LedgerHashAnchor>(conn)?
+        .into_iter()
+    {
+        anchors
+            .entry(anchor.ledger_hash_id)
+            .or_default()
+            .push((anchor.id, anchor.chain_hash));
+        verification.anchors += 1;
+    }
+
+    let mut chain = Chain::default();
+    let mut previous: Option<LedgerHash> = None;
+    loop {
+        let batch: Vec<LedgerHash> = ledger_hashes
+            .filter(id.gt(previous.as_ref().map_or(0, |previous| previous.id)))
+            .order(id)
+            .limit(batch_size)
+            .load(conn)?;
+        if batch.is_empty() {
+            break;
+        }
+
+        let transaction_ids: Vec<i64> = batch.iter().map(|sealed| sealed.transaction_id).collect();
+        let entries: HashMap<i64, LedgerEntry> = diesel::sql_query(format!(
+            "SELECT {} FROM transactions WHERE id = ANY($1)",
+            LEDGER_ENTRY_COLUMNS
+        ))
+        .bind::<Array<BigInt>, _>(transaction_ids)
+        .load::<LedgerEntry>(conn)?
+        .into_iter()
+        .map(|entry| (entry.id, entry))
+        .collect();
+
+        for sealed in batch.into_iter() {
+            verification.problems.extend(check_sealed(
+                &mut chain,
+                previous.as_ref(),
+                &sealed,
+                entries.get(&sealed.transaction_id),
+                anchors.get(&sealed.id).map(Vec::as_slice).unwrap_or(&[]),
+            ));
+            verification.sealed += 1;
+            previous = Some(sealed);
+        }
+    }
+
+    // Anything up to the head of the chain should have been sealed
+    if let Some(head) = &previous {
+        let unsealed: Vec<UnsealedId> = diesel::sql_query(
+            r#"
+            SELECT
+                t.id
+            FROM
+                transactions AS t
+            WHERE
+                (t.created_at, t.id) <= ($1, $2)
+                AND NOT EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        ledger_hashes AS h
+                    WHERE
+                        h.transaction_id = t.id)
+            ORDER BY
+                t.created_at,
+                t.id
+            "#,
+        )
+        .bind::<Timestamp, _>(head.transaction_created_at)
+        .bind::<BigInt, _>(head.transaction_id)
+        .load(conn)?;
+        verification
+            .problems
+            .extend(unsealed.into_iter().map(|unsealed| Problem::Unsealed {
+                transaction_id: unsealed.id,
+            }));
+    }
+
+    // Anchors of rows past the end of the chain, i.e., rows removed from it
+    let last_id = previous.as_ref().map_or(0, |previous| previous.id);
+    for (ledger_hash_id, anchored) in anchors.iter() {
+        if *ledger_hash_id > last_id {
+            verification
+                .problems
+                .extend(
+                    anchored
+                        .iter()
+                        .map(|(anchor_id, _)| Problem::AnchorMismatch {
+                            anchor_id: *anchor_id,
+                        }),
+                );
+        }
+    }
+
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i64, client_id: Option<ClientId>, amount_cents: i32) -> LedgerEntry {
+        LedgerEntry {
+            id,
+            created_at: chrono::NaiveDate::from_ymd(2019, 11, 12)
+                .and_hms_micro(9, 30, 0, id as u32),
+            client_id,
+            tx_type: "credit".into(),
+            tx_reason: "message_read".into(),
+            amount_cents,
+            operation_id: None,
+            reverses_operation_id: None,
+            original_currency: None,
+            original_amount_cents: None,
+            fx_rate: None,
+            fx_rate_id: None,
+            reference: None,
+        }
+    }
+
+    fn sealed(id: i64, hash: NewLedgerHash) -> LedgerHash {
+        LedgerHash {
+            id,
+            created_at: hash.transaction_created_at,
+            transaction_id: hash.transaction_id,
+            transaction_created_at: hash.transaction_created_at,
+            client_id: hash.client_id,
+            content_hash: hash.content_hash,
+            client_hash: hash.client_hash,
+            chain_hash: hash.chain_hash,
+        }
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let client_id = Some(ClientId::from(uuid::Uuid::new_v4()));
+        let original = entry(1, client_id, 100);
+        assert_eq!(content_hash(&original), content_hash(&original.clone()));
+
+        let mut modified = original.clone();
+        modified.amount_cents = 101;
+        assert_ne!(content_hash(&original), content_hash(&modified));
+        let mut modified = original.clone();
+        modified.client_id = None;
+        assert_ne!(content_hash(&original), content_hash(&modified));
+        let mut modified = original.clone();
+        modified.reference = Some("tr_123".into());
+        assert_ne!(content_hash(&original), content_hash(&modified));
+    }
+
+    #[test]
+    fn test_chain() {
+        let client_a = Some(ClientId::from(uuid::Uuid::new_v4()));
+        let client_b = Some(ClientId::from(uuid::Uuid::new_v4()));
+        let entries = vec![
+            entry(1, client_a, 100),
+            entry(2, client_b, -100),
+            entry(3, client_a, 50),
+            entry(4, None, -50),
+        ];
+
+        let mut chain = Chain::default();
+        let hashes: Vec<LedgerHash> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| sealed(i as i64 + 1, chain.link(entry)))
+            .collect();
+        // The first row of each chain starts it
+        assert_eq!(hashes[0].chain_hash, hashes[0].client_hash);
+        assert_ne!(hashes[1].chain_hash, hashes[1].client_hash);
+        assert_eq!(
+            hashes[2].client_hash,
+            link_hash(Some(&hashes[0].client_hash), &hashes[2].content_hash)
+        );
+        assert_eq!(
+            hashes[3].chain_hash,
+            link_hash(Some(&hashes[2].chain_hash), &hashes[3].content_hash)
+        );
+
+        // Anchors are given by the ledger hash they're of, and have ID 1
+        let check = |hashes: &[LedgerHash], entries: &[LedgerEntry], anchors: &[(i64, String)]| {
+            let mut chain = Chain::default();
+            let mut problems = vec![];
+            for (i, (hash, entry)) in hashes.iter().zip(entries.iter()).enumerate() {
+                let previous = if i > 0 { hashes.get(i - 1) } else { None };
+                let anchored: Vec<(i64, String)> = anchors
+                    .iter()
+                    .filter(|(ledger_hash_id, _)| *ledger_hash_id == hash.id)
+                    .map(|(_, chain_hash)| (1, chain_hash.clone()))
+                    .collect();
+                problems.extend(check_sealed(
+                    &mut chain,
+                    previous,
+                    hash,
+                    Some(entry),
+                    &anchored,
+                ));
+            }
+            problems
+        };
+        assert!(check(&hashes, &entries, &[(4, hashes[3].chain_hash.clone())]).is_empty());
+
+        // A modified transaction is reported once
+        let mut modified = entries.clone();
+        modified[1].amount_cents = -1000;
+        assert_eq!(
+            check(&hashes, &modified, &[]),
+            vec![Problem::Modified { transaction_id: 2 }]
+        );
+
+        // Rehashing it breaks the link, and the anchor
+        let mut rehashed = hashes.clone();
+        rehashed[1].content_hash = content_hash(&modified[1]);
+        assert_eq!(
+            check(&rehashed, &modified, &[]),
+            vec![Problem::BrokenLink { ledger_hash_id: 2 }]
+        );
+        let mut rechained = Chain::default();
+        let rechained: Vec<LedgerHash> = modified
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| sealed(i as i64 + 1, rechained.link(entry)))
+            .collect();
+        assert_eq!(
+            check(&rechained, &modified, &[(4, hashes[3].chain_hash.clone())]),
+            vec![Problem::AnchorMismatch { anchor_id: 1 }]
+        );
+
+        // Rows sealed out of order
+        let mut reordered = hashes.clone();
+        reordered.swap(1, 2);
+        let mut reordered_entries = entries.clone();
+        reordered_entries.swap(1, 2);
+        assert!(check(&reordered, &reordered_entries, &[])
+            .contains(&Problem::OutOfOrder { ledger_hash_id: 2 }));
+    }
+}
@@ -0,0 +1,281 @@
+//! An in-process write-ahead queue for the ledger, to absorb bursts of
+//! payments. Each payment writes a credit and a debit for its amount and for
+//! its fee, and under a burst of sends the writer spends its time on those
+//! rows. The credits all go to the internal accounts (the float, fees and
+//! promo accounts), whose balances are never checked before a write, so they
+//! can be queued and written in batches instead. The sender's debits, the
+//! payment, and the check and update of the sender's balance stay in the
+//! request's own transaction, so a payment still can't overdraw its sender.
+//!
+//! Queued rows are only handed over once the request's transaction has
+//! committed. Room is reserved for them beforehand, and released if the
+//! transaction rolls back or is retried. When the queue is full, the request
+//! writes its credits itself, so the queue never blocks a payment or drops a
+//! row.
+//!
+//! What it gives up, while rows are queued:
+//!
+//! - The ledger doesn't sum to zero, and the internal accounts' balances lag
+//!   by up to the flush interval. Anything which depends on either, such as
+//!   reversing an operation, flushes first.
+//! - A queued row is dated when it was queued, not when it's written, so an
+//!   operation never straddles a day. If the day has been closed by the time
+//!   a flush is retried, the rows can't be written, and are logged and kept.
+//! - If the process dies, the queued rows are lost: at most `capacity` rows,
+//!   covering at most the flush interval. The service flushes on SIGTERM and
+//!   SIGINT, so only a crash or SIGKILL loses anything. The loss shows up as
+//!   an unbalanced day in the daily close, and each lost row is the credit
+//!   half of an operation whose debits were written, so the operations with
+//!   no matching credits identify exactly what to rebuild:
+//!
+//!   ```sql
+//!   SELECT operation_id, SUM(amount_cents) FROM transactions
+//!   GROUP BY operation_id HAVING SUM(amount_cents) <> 0;
+//!   ```
+use chrono::NaiveDateTime;
+use instrumented::{prometheus, register};
+use std::sync::{Mutex, PoisonError};
+
+use crate::config;
+use crate::database::DbRouter;
+use crate::models::{NewTransaction, QueuedTransaction};
+
+fn make_intcounter(name: &str, description: &str) -> prometheus::IntCounter {
+    let counter = prometheus::IntCounter::new(name, description).unwrap();
+    register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+fn make_intgauge(name: &str, description: &str) -> prometheus::IntGauge {
+    let gauge = prometheus::IntGauge::new(name, description).unwrap();
+    register(Box::new(gauge.clone())).unwrap();
+    gauge
+}
+
+lazy_static! {
+    static ref QUEUED_ROWS: prometheus::IntGauge =
+        make_intgauge("ledger_queue_rows", "Transactions waiting to be written");
+    static ref FLUSHED_ROWS: prometheus::IntCounter = make_intcounter(
+        "ledger_queue_flushed_rows_total",
+        "Transactions written by the ledger queue"
+    );
+    static ref OVERFLOWS: prometheus::IntCounter = make_intcounter(
+        "ledger_queue_overflows_total",
+        "Operations which wrote their own credits because the queue was full"
+    );
+    static ref FLUSH_FAILURES: prometheus::IntCounter = make_intcounter(
+        "ledger_queue_flush_failures_total",
+        "Flushes which failed, leaving their rows queued"
+    );
+}
+
+#[derive(Default)]
+struct State {
+    rows: Vec<QueuedTransaction>,
+    // Room held for requests whose transactions haven't committed yet
+    reserved: usize,
+}
+
+#[derive(Default)]
+pub struct LedgerQueue {
+    state: Mutex<State>,
+    capacity: usize,
+}
+
+impl LedgerQueue {
+    /// No queue, so that every row is written by the request which makes it
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            capacity,
+        }
+    }
+
+    pub fn from_config(config: &config::LedgerQueue) -> Self {
+        if config.enabled {
+            Self::new(config.capacity)
+        } else {
+            Self::disabled()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The number of rows waiting to be written
+    pub fn len(&self) -> usize {
+        self.lock().rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reserve room for rows, dated `queued_at`, which are only queued once
+    /// the caller commits the rest of their operation. The rows are handed
+    /// back when there isn't room, for the caller to write itself.
+    pub fn defer(
+        &self,
+        livemode: bool,
+        queued_at: NaiveDateTime,
+        rows: Vec<NewTransaction>,
+    ) -> Result<Deferred, Vec<NewTransaction>> {
+        if !self.is_enabled() {
+            return Err(rows);
+        }
+
+        let mut state = self.lock();
+        if state.rows.len() + state.reserved + rows.len() > self.capacity {
+            OVERFLOWS.inc();
+            return Err(rows);
+        }
+        state.reserved += rows.len();
+
+        Ok(Deferred {
+            queue: self,
+            rows: rows
+                .into_iter()
+                .map(|transaction| QueuedTransaction {
+                    created_at: queued_at,
+                    livemode,
+                    transaction,
+                })
+                .collect(),
+        })
+    }
+
+    /// Write every queued row, a ledger at a time, returning how many were
+    /// written. Rows which fail to write are put back to be retried.
+    pub fn flush(&self, db: &DbRouter) -> Result<usize, diesel::result::Error> {
+        use crate::schema::transactions::table as transactions;
+        use diesel::prelude::*;
+
+        let rows = std::mem::replace(&mut self.lock().rows, vec![]);
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let (live, test): (Vec<_>, Vec<_>) = rows.into_iter().partition(|row| row.livemode);
+        let mut written = 0;
+        let mut failed = vec![];
+        let mut result = Ok(());
+        for (livemode, rows) in vec![(true, live), (false, test)] {
+            if rows.is_empty() {
+                continue;
+            }
+            let conn = db.write(livemode);
+            match diesel::insert_into(transactions)
+                .values(&rows)
+                .execute(&conn)
+            {
+                Ok(count) => written += count,
+                Err(err) => {
+                    FLUSH_FAILURES.inc();
+                    error!(
+                        "Unable to write {} queued ledger rows, keeping them: {}",
+                        rows.len(),
+                        err
+                    );
+                    failed.extend(rows);
+                    result = Err(err);
+                }
+            }
+        }
+
+        // Failed rows go back ahead of any queued since
+        let mut state = self.lock();
+        if !failed.is_empty() {
+            let newer = std::mem::replace(&mut state.rows, failed);
+            state.rows.extend(newer);
+        }
+        QUEUED_ROWS.set(state.rows.len() as i64);
+        FLUSHED_ROWS.inc_by(written as i64);
+        result.map(|_| written)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<State> {
+        // Rows are only moved in and out whole, so a panic can't leave the
+        // state half updated
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Rows with room reserved in the queue, waiting for the rest of their
+/// operation to commit. Dropping them releases the room.
+pub struct Deferred<'a> {
+    queue: &'a LedgerQueue,
+    rows: Vec<QueuedTransaction>,
+}
+
+impl<'a> Deferred<'a> {
+    /// Queue the rows, once the rest of their operation has committed
+    pub fn commit(mut self) {
+        let rows = std::mem::replace(&mut self.rows, vec![]);
+        let mut state = self.queue.lock();
+        state.reserved -= rows.len();
+        state.rows.extend(rows);
+        QUEUED_ROWS.set(state.rows.len() as i64);
+    }
+}
+
+impl<'a> Drop for Deferred<'a> {
+    fn drop(&mut self) {
+        if !self.rows.is_empty() {
+            self.queue.lock().reserved -= self.rows.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_types::{TransactionReason, TransactionType};
+
+    fn rows(count: usize) -> Vec<NewTransaction> {
+        (0..count)
+            .map(|_| NewTransaction {
+                client_id: None,
+                tx_type: TransactionType::Credit,
+                tx_reason: TransactionReason::MessageSent,
+                amount_cents: 100,
+                operation_id: None,
+                reverses_operation_id: None,
+                original_currency: None,
+                original_amount_cents: None,
+                fx_rate: None,
+                fx_rate_id: None,
+                reference: None,
+                amount_cents_i64: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ledger_queue() {
+        let now = chrono::Utc::now().naive_utc();
+
+        let queue = LedgerQueue::disabled();
+        assert_eq!(queue.defer(true, now, rows(1)).err().unwrap().len(), 1);
+
+        let queue = LedgerQueue::new(4);
+        let deferred = queue.defer(true, now, rows(2)).ok().unwrap();
+        // Reserved room counts against the capacity before it's committed
+        assert_eq!(queue.defer(true, now, rows(3)).err().unwrap().len(), 3);
+        assert!(queue.is_empty());
+        deferred.commit();
+        assert_eq!(queue.len(), 2);
+
+        // Rows from a transaction which rolled back release their room
+        let deferred = queue.defer(false, now, rows(2)).ok().unwrap();
+        drop(deferred);
+        assert_eq!(queue.len(), 2);
+        queue.defer(false, now, rows(2)).ok().unwrap().commit();
+        assert_eq!(queue.len(), 4);
+        assert!(queue.defer(true, now, rows(1)).is_err());
+    }
+}
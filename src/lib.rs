@@ -11,15 +11,18 @@ extern crate lazy_static;
 #[macro_use]
 extern crate serde_derive;
 
+extern crate arc_swap;
 extern crate beancounter_grpc;
 extern crate chrono;
 extern crate data_encoding;
 extern crate dotenv;
 extern crate env_logger;
 extern crate futures;
+extern crate hmac;
 extern crate instrumented;
 extern crate regex;
 extern crate serde_qs;
+extern crate sha2;
 extern crate stripe;
 extern crate tokio;
 extern crate toml;
@@ -27,10 +30,32 @@ extern crate tower_hyper;
 extern crate url;
 extern crate yansi;
 
+pub mod auth;
+pub mod bigquery;
+pub mod client_locks;
 pub mod config;
+pub mod connect_prefs;
 pub mod database;
+pub mod dual_write;
+pub mod fees;
+pub mod leader;
+pub mod ledger_chain;
+pub mod ledger_queue;
+pub mod login_links;
 pub mod models;
+pub mod payment_links;
+pub mod reports;
+pub mod request_context;
+pub mod request_log;
+pub mod response_cache;
 pub mod schema;
+pub mod self_check;
 pub mod service;
+pub mod slo;
 pub mod sql_types;
+pub mod statements;
 pub mod stripe_client;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timing;
+pub mod uuid_format;
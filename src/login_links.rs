@@ -0,0 +1,160 @@
+//! In-process cache of Express dashboard login links. Creating one is a Stripe
+//! API call, which adds a few hundred milliseconds to every GetConnectAccount
+//! and counts towards the rate limit, so each link is reused until shortly
+//! before it expires, and only then replaced.
+//!
+//! Each process has its own cache, so a client may be given a different link
+//! by another process. Any unexpired link works.
+use futures::{future, Future, IntoFuture};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::stripe_client::LoginLink;
+
+// Links aren't handed out when they're this close to expiring, so the client
+// has time to follow them
+const EXPIRY_MARGIN_SECS: i64 = 30;
+
+#[derive(Debug, Default)]
+pub struct LoginLinkCache {
+    // How long Stripe's links are valid for, from when they're created. 0
+    // disables the cache.
+    ttl_secs: i64,
+    // By Stripe account ID
+    links: Mutex<HashMap<String, LoginLink>>,
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+impl LoginLinkCache {
+    /// No cache, so that every link is created fresh
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(ttl_secs: u32) -> Self {
+        Self {
+            ttl_secs: i64::from(ttl_secs),
+            links: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(stripe: &config::Stripe) -> Self {
+        Self::new(stripe.login_link_ttl_secs)
+    }
+
+    fn is_fresh(&self, link: &LoginLink, now: i64) -> bool {
+        link.created + self.ttl_secs - EXPIRY_MARGIN_SECS > now
+    }
+
+    /// The account's cached link, or a new one from `create` if there's no
+    /// cached link or it's about to expire
+    pub fn get_or_create<F, R>(
+        cache: &Arc<Self>,
+        stripe_user_id: &str,
+        create: F,
+    ) -> Box<dyn Future<Item = String, Error = R::Error> + Send>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture<Item = LoginLink>,
+        R::Future: Send + 'static,
+    {
+        Self::get_or_create_at(cache, stripe_user_id, now_secs(), create)
+    }
+
+    fn get_or_create_at<F, R>(
+        cache: &Arc<Self>,
+        stripe_user_id: &str,
+        now: i64,
+        create: F,
+    ) -> Box<dyn Future<Item = String, Error = R::Error> + Send>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture<Item = LoginLink>,
+        R::Future: Send + 'static,
+    {
+        if cache.ttl_secs <= EXPIRY_MARGIN_SECS {
+            return Box::new(create().into_future().map(|link| link.url));
+        }
+
+        // The lock only guards the map, so it's still consistent if poisoned
+        if let Some(link) = cache
+            .links
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(stripe_user_id)
+            .filter(|link| cache.is_fresh(link, now))
+        {
+            return Box::new(future::ok(link.url.clone()));
+        }
+
+        // The lock isn't held while the link is created, so concurrent misses
+        // may each create one. The last one created is kept.
+        let cache = cache.clone();
+        let stripe_user_id = stripe_user_id.to_string();
+        Box::new(create().into_future().map(move |link| {
+            let url = link.url.clone();
+            let mut links = cache.links.lock().unwrap_or_else(PoisonError::into_inner);
+            links.retain(|_, link| cache.is_fresh(link, now));
+            links.insert(stripe_user_id, link);
+            url
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, created: i64) -> LoginLink {
+        LoginLink {
+            object: "login_link".into(),
+            created,
+            url: url.into(),
+        }
+    }
+
+    #[test]
+    fn test_login_link_cache() {
+        let cache = Arc::new(LoginLinkCache::new(300));
+        let created = 1_572_000_000;
+        let get = |now: i64, url: &str| {
+            LoginLinkCache::get_or_create_at(&cache, "acct_1", now, || Ok::<_, ()>(link(url, now)))
+                .wait()
+                .unwrap()
+        };
+
+        assert_eq!(get(created, "first"), "first");
+        assert_eq!(get(created + 60, "second"), "first");
+        // Replaced once it's about to expire
+        assert_eq!(get(created + 300 - EXPIRY_MARGIN_SECS, "third"), "third");
+
+        // Errors aren't cached
+        assert_eq!(
+            LoginLinkCache::get_or_create_at(&cache, "acct_2", created, || Err("rate limited"))
+                .wait(),
+            Err("rate limited")
+        );
+        assert_eq!(get(created + 400, "fourth"), "third");
+
+        // Every link is created when disabled
+        let cache = Arc::new(LoginLinkCache::disabled());
+        for url in ["first", "second"].iter() {
+            assert_eq!(
+                LoginLinkCache::get_or_create_at(&cache, "acct_1", created, || {
+                    Ok::<_, ()>(link(url, created))
+                })
+                .wait()
+                .unwrap(),
+                *url
+            );
+        }
+    }
+}
@@ -1,28 +1,320 @@
 extern crate uuid;
 
 use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use std::io::Write;
 use uuid::Uuid;
 
 use crate::schema::*;
 use crate::sql_types::*;
 
+/// Identifies a client. Client IDs are parsed from either the simple or
+/// hyphenated UUID format, and are displayed in the one configured, see
+/// `uuid_format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[sql_type = "diesel::sql_types::Uuid"]
+pub struct ClientId(Uuid);
+
+impl ClientId {
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl From<Uuid> for ClientId {
+    fn from(uuid: Uuid) -> Self {
+        ClientId(uuid)
+    }
+}
+
+impl From<ClientId> for Uuid {
+    fn from(client_id: ClientId) -> Self {
+        client_id.0
+    }
+}
+
+impl std::str::FromStr for ClientId {
+    type Err = uuid::parser::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ClientId(Uuid::parse_str(s)?))
+    }
+}
+
+impl std::fmt::Display for ClientId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", crate::uuid_format::format(&self.0))
+    }
+}
+
+impl ToSql<diesel::sql_types::Uuid, Pg> for ClientId {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<diesel::sql_types::Uuid, Pg>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<diesel::sql_types::Uuid, Pg> for ClientId {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        Ok(ClientId(FromSql::<diesel::sql_types::Uuid, Pg>::from_sql(
+            bytes,
+        )?))
+    }
+}
+
+impl serde::Serialize for ClientId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ClientId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Queryable, Identifiable)]
+#[primary_key(id, created_at)]
 pub struct Transaction {
     pub id: i64,
     pub created_at: NaiveDateTime,
-    pub client_id: Option<Uuid>,
+    pub client_id: Option<ClientId>,
     pub tx_type: TransactionType,
     pub tx_reason: TransactionReason,
     pub amount_cents: i32,
+    pub operation_id: Option<Uuid>,
+    pub reverses_operation_id: Option<Uuid>,
+    pub original_currency: Option<String>,
+    pub original_amount_cents: Option<i32>,
+    pub fx_rate: Option<f64>,
+    pub fx_rate_id: Option<i64>,
+    pub livemode: bool,
+    pub reference: Option<String>,
+    pub amount_cents_i64: Option<i64>,
 }
 
-#[derive(Insertable)]
+#[derive(Clone, Insertable)]
 #[table_name = "transactions"]
 pub struct NewTransaction {
-    pub client_id: Option<Uuid>,
+    pub client_id: Option<ClientId>,
     pub tx_type: TransactionType,
     pub tx_reason: TransactionReason,
     pub amount_cents: i32,
+    pub operation_id: Option<Uuid>,
+    pub reverses_operation_id: Option<Uuid>,
+    pub original_currency: Option<String>,
+    pub original_amount_cents: Option<i32>,
+    pub fx_rate: Option<f64>,
+    pub fx_rate_id: Option<i64>,
+    pub reference: Option<String>,
+    // Dual-written while the amounts move to a BIGINT, see `dual_write`
+    pub amount_cents_i64: Option<i64>,
+}
+
+/// A transaction written by the ledger queue, some time after the operation
+/// it's part of. It's dated when it was queued, and carries the ledger it was
+/// queued from, rather than taking the connection's.
+#[derive(Clone, Insertable)]
+#[table_name = "transactions"]
+pub struct QueuedTransaction {
+    pub created_at: NaiveDateTime,
+    pub livemode: bool,
+    #[diesel(embed)]
+    pub transaction: NewTransaction,
+}
+
+/// A historical transaction loaded with ImportTransactions, dated when it was
+/// originally made
+#[derive(Clone, Insertable)]
+#[table_name = "transactions"]
+pub struct ImportedTransaction {
+    pub created_at: NaiveDateTime,
+    #[diesel(embed)]
+    pub transaction: NewTransaction,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct FxRate {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub source: String,
+    pub currency: String,
+    pub rate: f64,
+    pub cron_run_id: Option<Uuid>,
+}
+
+#[derive(Insertable)]
+#[table_name = "fx_rates"]
+pub struct NewFxRate {
+    pub source: String,
+    pub currency: String,
+    pub rate: f64,
+    pub cron_run_id: Option<Uuid>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct AutoReloadPrefs {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub enabled: bool,
+    pub threshold_cents: i64,
+    pub reload_amount_cents: i32,
+    pub daily_cap_cents: i64,
+    pub stripe_customer_id: Option<String>,
+    pub consecutive_failures: i32,
+    pub next_attempt_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "auto_reload_prefs"]
+pub struct NewAutoReloadPrefs {
+    pub client_id: ClientId,
+    pub enabled: bool,
+    pub threshold_cents: i64,
+    pub reload_amount_cents: i32,
+    pub daily_cap_cents: i64,
+    pub stripe_customer_id: Option<String>,
+}
+
+#[derive(Debug, AsChangeset)]
+#[table_name = "auto_reload_prefs"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct UpdateAutoReloadAttempt {
+    pub enabled: bool,
+    pub consecutive_failures: i32,
+    pub next_attempt_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct BalanceAlertPrefs {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub low_balance_cents: Option<i64>,
+    pub withdrawable_cents: Option<i64>,
+    pub low_balance_alerted: bool,
+    pub withdrawable_alerted: bool,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "balance_alert_prefs"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewBalanceAlertPrefs {
+    pub client_id: ClientId,
+    pub low_balance_cents: Option<i64>,
+    pub withdrawable_cents: Option<i64>,
+    pub low_balance_alerted: bool,
+    pub withdrawable_alerted: bool,
+}
+
+#[derive(Debug, AsChangeset)]
+#[table_name = "balance_alert_prefs"]
+pub struct UpdateBalanceAlertState {
+    pub low_balance_alerted: bool,
+    pub withdrawable_alerted: bool,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "payment_prefs"]
+pub struct PaymentPrefs {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub min_payment_cents: i32,
+    pub blocked_client_ids: Vec<ClientId>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "payment_prefs"]
+pub struct NewPaymentPrefs {
+    pub client_id: ClientId,
+    pub min_payment_cents: i32,
+    pub blocked_client_ids: Vec<ClientId>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "fee_schedules"]
+pub struct ClientFeePlan {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub plan: FeePlan,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "fee_schedules"]
+pub struct NewClientFeePlan {
+    pub client_id: ClientId,
+    pub plan: FeePlan,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "account_tiers"]
+pub struct ClientAccountTier {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub tier: AccountTier,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "account_tiers"]
+pub struct NewClientAccountTier {
+    pub client_id: ClientId,
+    pub tier: AccountTier,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub client_id: Option<ClientId>,
+    pub event_type: OutboxEventType,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "outbox_events"]
+pub struct NewOutboxEvent {
+    pub client_id: Option<ClientId>,
+    pub event_type: OutboxEventType,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct AutoReloadCharge {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub amount_cents: i32,
+    pub state: AutoReloadState,
+    pub charge: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "auto_reload_charges"]
+pub struct NewAutoReloadCharge {
+    pub client_id: ClientId,
+    pub amount_cents: i32,
+}
+
+#[derive(Debug, AsChangeset)]
+#[table_name = "auto_reload_charges"]
+pub struct UpdateAutoReloadCharge {
+    pub state: AutoReloadState,
+    pub charge: Option<serde_json::Value>,
+    pub error: Option<String>,
 }
 
 #[derive(Queryable, Identifiable, Debug)]
@@ -30,25 +322,52 @@ pub struct Balance {
     pub id: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
-    pub client_id: Uuid,
+    pub client_id: ClientId,
     pub balance_cents: i64,
     pub promo_cents: i64,
     pub withdrawable_cents: i64,
+    pub referral_cents: i64,
+    pub held_cents: i64,
+    pub pending_settlement_cents: i64,
+    pub lifetime_earned_cents: i64,
+    pub promo_earned_cents: i64,
+    pub livemode: bool,
+}
+
+/// The balance as of a change to it
+#[derive(Queryable, Identifiable, Debug)]
+#[table_name = "balance_history"]
+pub struct BalanceSnapshot {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub balance_cents: i64,
+    pub promo_cents: i64,
+    pub withdrawable_cents: i64,
+    pub held_cents: i64,
+    pub lifetime_earned_cents: i64,
+    pub promo_earned_cents: i64,
+    pub livemode: bool,
 }
 
 #[derive(Insertable)]
 #[table_name = "balances"]
 pub struct NewBalance {
-    pub client_id: Uuid,
+    pub client_id: ClientId,
     pub balance_cents: i64,
     pub promo_cents: i64,
     pub withdrawable_cents: i64,
+    pub referral_cents: i64,
+    pub held_cents: i64,
+    pub pending_settlement_cents: i64,
+    pub lifetime_earned_cents: i64,
+    pub promo_earned_cents: i64,
 }
 
 #[derive(Insertable)]
 #[table_name = "balances"]
 pub struct NewZeroBalance {
-    pub client_id: Uuid,
+    pub client_id: ClientId,
 }
 
 #[derive(AsChangeset)]
@@ -57,6 +376,11 @@ pub struct UpdatedBalance {
     pub balance_cents: i64,
     pub promo_cents: i64,
     pub withdrawable_cents: i64,
+    pub referral_cents: i64,
+    pub held_cents: i64,
+    pub pending_settlement_cents: i64,
+    pub lifetime_earned_cents: i64,
+    pub promo_earned_cents: i64,
 }
 
 #[derive(Queryable, Identifiable)]
@@ -64,21 +388,294 @@ pub struct Payment {
     pub id: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
-    pub client_id_from: Uuid,
-    pub client_id_to: Uuid,
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
     pub payment_cents: i32,
     pub message_hash: String,
     pub is_promo: bool,
+    pub referrer_client_id: Option<ClientId>,
+    pub payment_split_id: Option<i64>,
+    pub livemode: bool,
+    pub reminded_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
 #[table_name = "payments"]
 pub struct NewPayment {
-    pub client_id_from: Uuid,
-    pub client_id_to: Uuid,
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
     pub payment_cents: i32,
     pub message_hash: String,
     pub is_promo: bool,
+    pub referrer_client_id: Option<ClientId>,
+    pub payment_split_id: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "payment_outcomes"]
+pub struct NewPaymentOutcome {
+    pub payment_created_at: NaiveDateTime,
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
+    pub payment_cents: i32,
+    pub is_promo: bool,
+    pub outcome: PaymentOutcome,
+    pub payment_split_id: Option<i64>,
+    pub fee_cents: i32,
+    pub message_hash: Option<String>,
+    pub payout_cents: i32,
+    pub referral_cents: i32,
+    pub read_fee_paid_by_sender: bool,
+    pub refund_cents: i32,
+    pub tip_cents: i32,
+}
+
+impl NewPaymentOutcome {
+    pub fn from_payment(payment: &Payment, outcome: PaymentOutcome) -> Self {
+        Self {
+            payment_created_at: payment.created_at,
+            client_id_from: payment.client_id_from,
+            client_id_to: payment.client_id_to,
+            payment_cents: payment.payment_cents,
+            is_promo: payment.is_promo,
+            outcome,
+            payment_split_id: payment.payment_split_id,
+            fee_cents: 0,
+            message_hash: Some(payment.message_hash.clone()),
+            payout_cents: 0,
+            referral_cents: 0,
+            read_fee_paid_by_sender: false,
+            refund_cents: 0,
+            tip_cents: 0,
+        }
+    }
+
+    /// With the amounts paid out when settled: the read fee, the payout to
+    /// the recipient, and the referrer's share of the fee
+    pub fn with_settlement(
+        self,
+        fee_cents: i32,
+        payout_cents: i32,
+        referral_cents: i32,
+        read_fee_paid_by_sender: bool,
+    ) -> Self {
+        Self {
+            fee_cents,
+            payout_cents,
+            referral_cents,
+            read_fee_paid_by_sender,
+            ..self
+        }
+    }
+
+    /// With the amounts paid back to the sender when declined
+    pub fn with_refund(self, refund_cents: i32, tip_cents: i32) -> Self {
+        Self {
+            refund_cents,
+            tip_cents,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PaymentSplit {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub client_id_from: ClientId,
+    pub message_hash: String,
+    pub payment_cents: i32,
+    pub fee_cents: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "payment_splits"]
+pub struct NewPaymentSplit {
+    pub client_id_from: ClientId,
+    pub message_hash: String,
+    pub payment_cents: i32,
+    pub fee_cents: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PaymentSplitShare {
+    pub id: i64,
+    pub payment_split_id: i64,
+    pub client_id_to: ClientId,
+    pub share: i32,
+    pub payment_cents: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "payment_split_shares"]
+pub struct NewPaymentSplitShare {
+    pub payment_split_id: i64,
+    pub client_id_to: ClientId,
+    pub share: i32,
+    pub payment_cents: i32,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PaymentRefund {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub payment_id: i64,
+    pub payment_created_at: NaiveDateTime,
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
+    pub payment_cents: i32,
+    pub message_hash: String,
+    pub is_promo: bool,
+    pub reason: RefundReason,
+    pub cron_run_id: Uuid,
+    pub transaction_id: i64,
+    pub policy: ExpiryRefundPolicy,
+    pub fee_retained_cents: i32,
+    pub fee_refunded_cents: i32,
+    pub fee_transaction_id: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "payment_refunds"]
+pub struct NewPaymentRefund {
+    pub payment_id: i64,
+    pub payment_created_at: NaiveDateTime,
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
+    pub payment_cents: i32,
+    pub message_hash: String,
+    pub is_promo: bool,
+    pub reason: RefundReason,
+    pub cron_run_id: Uuid,
+    pub transaction_id: i64,
+    pub policy: ExpiryRefundPolicy,
+    pub fee_retained_cents: i32,
+    pub fee_refunded_cents: i32,
+    pub fee_transaction_id: Option<i64>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct DormancyEvent {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub action: DormancyAction,
+    pub last_activity_at: NaiveDateTime,
+    pub amount_cents: i64,
+    pub cron_run_id: Uuid,
+    pub transaction_id: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "dormancy_events"]
+pub struct NewDormancyEvent {
+    pub client_id: ClientId,
+    pub action: DormancyAction,
+    pub last_activity_at: NaiveDateTime,
+    pub amount_cents: i64,
+    pub cron_run_id: Uuid,
+    pub transaction_id: Option<i64>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct LedgerDay {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub ds: chrono::NaiveDate,
+    pub tx_count: i64,
+    pub credit_cents: i64,
+    pub debit_cents: i64,
+    pub balanced: bool,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct LedgerDayTotal {
+    pub id: i64,
+    pub ds: chrono::NaiveDate,
+    pub tx_type: TransactionType,
+    pub tx_reason: TransactionReason,
+    pub tx_count: i64,
+    pub amount_cents: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct LedgerHash {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub transaction_id: i64,
+    pub transaction_created_at: NaiveDateTime,
+    pub client_id: Option<ClientId>,
+    pub content_hash: String,
+    pub client_hash: String,
+    pub chain_hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "ledger_hashes"]
+pub struct NewLedgerHash {
+    pub transaction_id: i64,
+    pub transaction_created_at: NaiveDateTime,
+    pub client_id: Option<ClientId>,
+    pub content_hash: String,
+    pub client_hash: String,
+    pub chain_hash: String,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct LedgerHashAnchor {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub ledger_hash_id: i64,
+    pub chain_hash: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "ledger_hash_anchors"]
+pub struct NewLedgerHashAnchor {
+    pub ledger_hash_id: i64,
+    pub chain_hash: String,
+}
+
+#[derive(Debug, Queryable, QueryableByName, Identifiable)]
+#[table_name = "risk_flags"]
+pub struct RiskFlag {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub kind: RiskFlagKind,
+    pub ds: chrono::NaiveDate,
+    pub amount_cents: i64,
+    pub baseline_cents: f64,
+    pub cron_run_id: Uuid,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "annual_earnings"]
+pub struct AnnualEarnings {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub year: i32,
+    pub gross_cents: i64,
+    pub fee_cents: i64,
+    pub payment_count: i64,
+    pub monthly_gross_cents: Vec<i64>,
+    pub monthly_payment_counts: Vec<i64>,
+    pub cron_run_id: Uuid,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct SettlementStat {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub ds: chrono::NaiveDate,
+    pub payment_count: i64,
+    pub settled_count: i64,
+    pub expired_count: i64,
+    pub pending_count: i64,
+    pub median_settlement_seconds: Option<f64>,
 }
 
 #[derive(Queryable, Identifiable)]
@@ -86,15 +683,24 @@ pub struct StripeCharge {
     pub id: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
-    pub client_id: Uuid,
+    pub client_id: ClientId,
+    pub token: serde_json::Value,
     pub charge: serde_json::Value,
+    pub transaction_id: Option<i64>,
+    pub risk_level: Option<String>,
+    pub seller_message: Option<String>,
 }
 
 #[derive(Insertable)]
 #[table_name = "stripe_charges"]
 pub struct NewStripeCharge {
-    pub client_id: Uuid,
+    pub client_id: ClientId,
+    pub token: serde_json::Value,
     pub charge: serde_json::Value,
+    pub transaction_id: Option<i64>,
+    pub risk_level: Option<String>,
+    pub seller_message: Option<String>,
+    pub statement_descriptor_suffix: Option<String>,
 }
 
 #[derive(Debug, Queryable, Identifiable)]
@@ -103,18 +709,24 @@ pub struct StripeConnectAccount {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub oauth_state: Uuid,
-    pub client_id: Uuid,
+    pub client_id: ClientId,
     pub stripe_user_id: Option<String>,
     pub connect_account: Option<serde_json::Value>,
     pub connect_credentials: Option<serde_json::Value>,
     pub enable_automatic_payouts: bool,
     pub automatic_payout_threshold_cents: i64,
+    pub requirements_currently_due: Vec<String>,
+    pub requirements_disabled_reason: Option<String>,
+    pub requirements_updated_at: Option<NaiveDateTime>,
+    pub email: Option<String>,
+    pub country: Option<String>,
+    pub identity_verified: bool,
 }
 
 #[derive(Insertable)]
 #[table_name = "stripe_connect_accounts"]
 pub struct NewStripeConnectAccount {
-    pub client_id: Uuid,
+    pub client_id: ClientId,
 }
 
 #[derive(Debug, AsChangeset)]
@@ -132,22 +744,536 @@ pub struct UpdateStripeConnectAccount {
     pub connect_credentials: Option<serde_json::Value>,
 }
 
+#[derive(Debug, AsChangeset)]
+#[table_name = "stripe_connect_accounts"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct UpdateStripeConnectAccountRequirements {
+    pub connect_account: Option<serde_json::Value>,
+    pub requirements_currently_due: Vec<String>,
+    pub requirements_disabled_reason: Option<String>,
+    pub requirements_updated_at: Option<NaiveDateTime>,
+    pub email: Option<String>,
+    pub country: Option<String>,
+    pub identity_verified: bool,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct StripeConnectDestination {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub stripe_user_id: String,
+    pub is_primary: bool,
+    pub split_percent: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "stripe_connect_destinations"]
+pub struct NewStripeConnectDestination {
+    pub client_id: ClientId,
+    pub stripe_user_id: String,
+    pub is_primary: bool,
+    pub split_percent: i32,
+}
+
+#[derive(Debug, AsChangeset)]
+#[table_name = "stripe_connect_destinations"]
+pub struct UpdateStripeConnectDestination {
+    pub is_primary: bool,
+    pub split_percent: i32,
+}
+
 #[derive(Debug, Queryable, Identifiable)]
 pub struct StripeConnectTransfer {
     pub id: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
-    pub client_id: Uuid,
+    pub client_id: ClientId,
     pub stripe_user_id: String,
     pub connect_transfer: serde_json::Value,
     pub amount_cents: i32,
+    pub description: Option<String>,
+    pub statement_descriptor: Option<String>,
 }
 
 #[derive(Insertable)]
 #[table_name = "stripe_connect_transfers"]
 pub struct NewStripeConnectTransfer {
-    pub client_id: Uuid,
+    pub client_id: ClientId,
     pub stripe_user_id: String,
     pub connect_transfer: serde_json::Value,
     pub amount_cents: i32,
+    pub description: Option<String>,
+    pub statement_descriptor: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct StripeConnectPayout {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub stripe_user_id: String,
+    pub transfer_id: i64,
+    pub stripe_payout_id: Option<String>,
+    pub amount_cents: i32,
+    pub method: String,
+    pub status: String,
+    pub failure_code: Option<String>,
+    pub failure_message: Option<String>,
+    pub connect_payout: Option<serde_json::Value>,
+}
+
+#[derive(Insertable)]
+#[table_name = "stripe_connect_payouts"]
+pub struct NewStripeConnectPayout {
+    pub client_id: ClientId,
+    pub stripe_user_id: String,
+    pub transfer_id: i64,
+    pub stripe_payout_id: Option<String>,
+    pub amount_cents: i32,
+    pub method: String,
+    pub status: String,
+    pub failure_code: Option<String>,
+    pub failure_message: Option<String>,
+    pub connect_payout: Option<serde_json::Value>,
+}
+
+#[derive(Debug, AsChangeset)]
+#[table_name = "stripe_connect_payouts"]
+pub struct UpdateStripeConnectPayout {
+    pub status: String,
+    pub failure_code: Option<String>,
+    pub failure_message: Option<String>,
+    pub connect_payout: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct StripeEvent {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub event_id: String,
+    pub event_type: String,
+    pub stripe_account: Option<String>,
+    pub replayed: bool,
+    pub handled: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "stripe_events"]
+pub struct NewStripeEvent {
+    pub event_id: String,
+    pub event_type: String,
+    pub stripe_account: Option<String>,
+    pub replayed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_id_formats() {
+        let simple: ClientId = "6f5ed1a9e4e14b7b9f7ea4b1f3c58d2c".parse().unwrap();
+        let hyphenated: ClientId = "6f5ed1a9-e4e1-4b7b-9f7e-a4b1f3c58d2c".parse().unwrap();
+
+        assert_eq!(simple, hyphenated);
+        assert_eq!(hyphenated.to_string(), "6f5ed1a9e4e14b7b9f7ea4b1f3c58d2c");
+        assert!("not-a-client-id".parse::<ClientId>().is_err());
+
+        let json = serde_json::to_string(&hyphenated).unwrap();
+        assert_eq!(json, "\"6f5ed1a9e4e14b7b9f7ea4b1f3c58d2c\"");
+        assert_eq!(serde_json::from_str::<ClientId>(&json).unwrap(), simple);
+    }
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct TransactionNote {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub transaction_id: i64,
+    pub author: String,
+    pub note: String,
+    pub tags: Vec<String>,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "transaction_notes"]
+pub struct NewTransactionNote {
+    pub transaction_id: i64,
+    pub author: String,
+    pub note: String,
+    pub tags: Vec<String>,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct TransactionImport {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub batch_id: String,
+    pub operation_id: Uuid,
+    pub tx_count: i32,
+    pub first_created_at: NaiveDateTime,
+    pub last_created_at: NaiveDateTime,
+    pub livemode: bool,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "transaction_imports"]
+pub struct NewTransactionImport {
+    pub batch_id: String,
+    pub operation_id: Uuid,
+    pub tx_count: i32,
+    pub first_created_at: NaiveDateTime,
+    pub last_created_at: NaiveDateTime,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct HeldCredit {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub transaction_id: i64,
+    pub stripe_charge_id: String,
+    pub amount_cents: i32,
+    pub risk_level: String,
+    pub release_at: Option<NaiveDateTime>,
+    pub state: HeldCreditState,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<NaiveDateTime>,
+    pub livemode: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "held_credits"]
+pub struct NewHeldCredit {
+    pub client_id: ClientId,
+    pub transaction_id: i64,
+    pub stripe_charge_id: String,
+    pub amount_cents: i32,
+    pub risk_level: String,
+    pub release_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PayoutAttempt {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub confirmation_token: Uuid,
+    pub amount_cents: i32,
+    pub withheld_cents: i32,
+    pub description: Option<String>,
+    pub statement_descriptor: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub state: PayoutAttemptState,
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub failure_reason: Option<String>,
+    pub failure_code: Option<String>,
+    pub decline_code: Option<String>,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "payout_attempts"]
+pub struct NewPayoutAttempt {
+    pub client_id: ClientId,
+    pub confirmation_token: Uuid,
+    pub amount_cents: i32,
+    pub withheld_cents: i32,
+    pub description: Option<String>,
+    pub statement_descriptor: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PayoutHold {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub amount_cents: i32,
+    pub state: PayoutHoldState,
+    pub livemode: bool,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+    // How much of the hold has been transferred and debited
+    pub transferred_cents: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "payout_holds"]
+pub struct NewPayoutHold {
+    pub client_id: ClientId,
+    pub amount_cents: i32,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PaymentHold {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
+    pub message_hash: String,
+    pub payment_cents: i32,
+    pub amount_cents: i32,
+    pub referrer_client_id: Option<ClientId>,
+    pub campaign_id: Option<String>,
+    pub state: PaymentHoldState,
+    pub expires_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub livemode: bool,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "payment_holds"]
+pub struct NewPaymentHold {
+    pub client_id_from: ClientId,
+    pub client_id_to: ClientId,
+    pub message_hash: String,
+    pub payment_cents: i32,
+    pub amount_cents: i32,
+    pub referrer_client_id: Option<ClientId>,
+    pub campaign_id: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PaymentLink {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub payment_cents: i32,
+    pub description: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub redeemed_at: Option<NaiveDateTime>,
+    pub redeemed_by: Option<ClientId>,
+    pub message_hash: Option<String>,
+    pub livemode: bool,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "payment_links"]
+pub struct NewPaymentLink {
+    pub client_id: ClientId,
+    pub payment_cents: i32,
+    pub description: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct ClientLedgerLock {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub reason: String,
+    pub locked_by: String,
+    pub expires_at: NaiveDateTime,
+    pub unlocked_at: Option<NaiveDateTime>,
+    pub unlocked_by: Option<String>,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "client_ledger_locks"]
+pub struct NewClientLedgerLock {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub locked_by: String,
+    pub expires_at: NaiveDateTime,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "client_denylist"]
+pub struct DenylistEntry {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub reason: String,
+    pub added_by: String,
+    pub removed_at: Option<NaiveDateTime>,
+    pub removed_by: Option<String>,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "client_denylist"]
+pub struct NewDenylistEntry {
+    pub client_id: ClientId,
+    pub reason: String,
+    pub added_by: String,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// A payout made without confirmation, recorded once its transfer failed
+#[derive(Insertable)]
+#[table_name = "payout_attempts"]
+pub struct NewFailedPayoutAttempt {
+    pub client_id: ClientId,
+    pub confirmation_token: Uuid,
+    pub amount_cents: i32,
+    pub withheld_cents: i32,
+    pub description: Option<String>,
+    pub statement_descriptor: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub state: PayoutAttemptState,
+    pub failure_reason: Option<String>,
+    pub failure_code: Option<String>,
+    pub decline_code: Option<String>,
+    pub caller: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PayoutRun {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub cron_run_id: Uuid,
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    pub candidates: i32,
+    pub succeeded: i32,
+    pub failed: i32,
+    pub skipped: i32,
+    pub skipped_reasons: serde_json::Value,
+    pub total_cents: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "payout_runs"]
+pub struct NewPayoutRun {
+    pub cron_run_id: Uuid,
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    pub candidates: i32,
+    pub succeeded: i32,
+    pub failed: i32,
+    pub skipped: i32,
+    pub skipped_reasons: serde_json::Value,
+    pub total_cents: i64,
+}
+
+#[derive(Debug, Queryable, QueryableByName, Identifiable)]
+#[table_name = "statements"]
+pub struct Statement {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub period: chrono::NaiveDate,
+    pub gross_cents: i64,
+    pub fee_cents: i64,
+    pub withheld_cents: i64,
+    pub payment_count: i64,
+    pub cron_run_id: Uuid,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct Invoice {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub period: chrono::NaiveDate,
+    pub amount_cents: i64,
+    pub paid_cents: i64,
+    pub state: InvoiceState,
+    pub due_at: NaiveDateTime,
+    pub livemode: bool,
+    pub cron_run_id: Uuid,
+}
+
+#[derive(Insertable)]
+#[table_name = "invoices"]
+pub struct NewInvoice {
+    pub client_id: ClientId,
+    pub period: chrono::NaiveDate,
+    pub amount_cents: i64,
+    pub due_at: NaiveDateTime,
+    pub cron_run_id: Uuid,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct InvoiceLineItem {
+    pub id: i64,
+    pub invoice_id: i64,
+    pub ds: chrono::NaiveDate,
+    pub sent_cents: i64,
+    pub refunded_cents: i64,
+    pub amount_cents: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "invoice_line_items"]
+pub struct NewInvoiceLineItem {
+    pub invoice_id: i64,
+    pub ds: chrono::NaiveDate,
+    pub sent_cents: i64,
+    pub refunded_cents: i64,
+    pub amount_cents: i64,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct PendingCharge {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub client_id: ClientId,
+    pub stripe_charge_id: String,
+    pub amount_cents: i32,
+    pub original_currency: Option<String>,
+    pub original_amount_cents: Option<i32>,
+    pub fx_rate: Option<f64>,
+    pub fx_rate_id: Option<i64>,
+    pub state: PendingChargeState,
+    pub expires_at: Option<NaiveDateTime>,
+    pub transaction_id: Option<i64>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub livemode: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "pending_charges"]
+pub struct NewPendingCharge {
+    pub client_id: ClientId,
+    pub stripe_charge_id: String,
+    pub amount_cents: i32,
+    pub original_currency: Option<String>,
+    pub original_amount_cents: Option<i32>,
+    pub fx_rate: Option<f64>,
+    pub fx_rate_id: Option<i64>,
+    pub expires_at: Option<NaiveDateTime>,
 }
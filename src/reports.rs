@@ -0,0 +1,550 @@
+//! Reports over the ledger, built up from SQL fragments. Fragments are
+//! `&'static str`, so they can only come from the code, and every value a
+//! report is run with (thresholds, intervals and so on) is sent as a bound
+//! parameter. A report's SQL and parameters can be checked without a database.
+use crate::database::DbConnection;
+use crate::models::ClientId;
+use diesel::pg::data_types::PgInterval;
+use diesel::pg::Pg;
+use diesel::query_builder::BoxedSqlQuery;
+use diesel::sql_types::*;
+use diesel::{QueryResult, QueryableByName, RunQueryDsl};
+
+// Marks where a fragment's parameters go
+const PLACEHOLDER: &str = "{}";
+
+/// A value bound to a report query
+#[derive(Clone, Debug, PartialEq)]
+pub enum Param {
+    Int(i32),
+    BigInt(i64),
+    Double(f64),
+    Bool(bool),
+    Text(String),
+    ClientId(ClientId),
+    Interval(chrono::Duration),
+    Timestamp(chrono::NaiveDateTime),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReportQuery {
+    columns: Vec<&'static str>,
+    from: &'static str,
+    joins: Vec<&'static str>,
+    conditions: Vec<&'static str>,
+    group_by: Vec<&'static str>,
+    order_by: Vec<&'static str>,
+    limit: Option<i64>,
+    // In the order their placeholders appear in the SQL
+    params: Vec<Param>,
+}
+
+impl ReportQuery {
+    /// Select from a table, i.e., "balances AS b"
+    pub fn from(table: &'static str) -> Self {
+        Self {
+            from: table,
+            ..Self::default()
+        }
+    }
+
+    pub fn select(mut self, column: &'static str) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    /// Add a join, i.e., "INNER JOIN stripe_connect_accounts AS a ON ..."
+    pub fn join(mut self, join: &'static str) -> Self {
+        self.joins.push(join);
+        self
+    }
+
+    /// Add a condition, ANDed with the others. Each {} in the condition is
+    /// replaced with the next of `params`.
+    pub fn filter(mut self, condition: &'static str, params: Vec<Param>) -> Self {
+        assert_eq!(
+            condition.matches(PLACEHOLDER).count(),
+            params.len(),
+            "wrong number of parameters for {:?}",
+            condition
+        );
+        self.conditions.push(condition);
+        self.params.extend(params);
+        self
+    }
+
+    pub fn group_by(mut self, expression: &'static str) -> Self {
+        self.group_by.push(expression);
+        self
+    }
+
+    pub fn order_by(mut self, expression: &'static str) -> Self {
+        self.order_by.push(expression);
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn params(&self) -> Vec<Param> {
+        let mut params = self.params.clone();
+        if let Some(limit) = self.limit {
+            params.push(Param::BigInt(limit));
+        }
+        params
+    }
+
+    /// The query's SQL, with numbered placeholders for its parameters
+    pub fn to_sql(&self) -> String {
+        let mut sql = format!(
+            "SELECT {} FROM {}",
+            if self.columns.is_empty() {
+                "*".to_string()
+            } else {
+                self.columns.join(", ")
+            },
+            self.from
+        );
+        for join in self.joins.iter() {
+            sql.push(' ');
+            sql.push_str(join);
+        }
+        if !self.conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(
+                &self
+                    .conditions
+                    .iter()
+                    .map(|condition| format!("({})", condition))
+                    .collect::<Vec<_>>()
+                    .join(" AND "),
+            );
+        }
+        if !self.group_by.is_empty() {
+            sql.push_str(" GROUP BY ");
+            sql.push_str(&self.group_by.join(", "));
+        }
+        if !self.order_by.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&self.order_by.join(", "));
+        }
+        if self.limit.is_some() {
+            sql.push_str(" LIMIT {}");
+        }
+
+        // Number the placeholders
+        let mut numbered = String::with_capacity(sql.len());
+        for (i, part) in sql.split(PLACEHOLDER).enumerate() {
+            if i > 0 {
+                numbered.push_str(&format!("${}", i));
+            }
+            numbered.push_str(part);
+        }
+        numbered
+    }
+
+    pub fn load<T: QueryableByName<Pg>>(
+        &self,
+        conn: &impl diesel::connection::Connection<Backend = Pg>,
+    ) -> QueryResult<Vec<T>> {
+        self.params()
+            .into_iter()
+            .fold(
+                diesel::sql_query(self.to_sql()).into_boxed::<Pg>(),
+                bind_param,
+            )
+            .load(conn)
+    }
+}
+
+fn bind_param(
+    query: BoxedSqlQuery<'static, Pg, diesel::query_builder::SqlQuery>,
+    param: Param,
+) -> BoxedSqlQuery<'static, Pg, diesel::query_builder::SqlQuery> {
+    match param {
+        Param::Int(value) => query.bind::<Integer, _>(value),
+        Param::BigInt(value) => query.bind::<BigInt, _>(value),
+        Param::Double(value) => query.bind::<Double, _>(value),
+        Param::Bool(value) => query.bind::<Bool, _>(value),
+        Param::Text(value) => query.bind::<Text, _>(value),
+        Param::ClientId(value) => query.bind::<diesel::sql_types::Uuid, _>(value),
+        Param::Interval(value) => query.bind::<Interval, _>(PgInterval::from_microseconds(
+            value.num_microseconds().unwrap_or(std::i64::MAX),
+        )),
+        Param::Timestamp(value) => query.bind::<Timestamp, _>(value),
+    }
+}
+
+/// Clients due an automatic payout of their withdrawable balance
+#[derive(Clone, Debug)]
+pub struct PayoutEligibility {
+    /// Only clients who've enabled automatic payouts
+    pub automatic_only: bool,
+    /// Skip clients with less than this withdrawable, on top of their own
+    /// automatic payout threshold
+    pub min_withdrawable_cents: i64,
+    /// Skip clients paid out more recently than this
+    pub min_interval: chrono::Duration,
+    /// Only this client, if set
+    pub client_id: Option<ClientId>,
+    pub limit: Option<i64>,
+}
+
+impl Default for PayoutEligibility {
+    fn default() -> Self {
+        Self {
+            automatic_only: true,
+            min_withdrawable_cents: 1,
+            min_interval: chrono::Duration::hours(24),
+            client_id: None,
+            limit: None,
+        }
+    }
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct EligiblePayout {
+    #[sql_type = "diesel::sql_types::Uuid"]
+    pub client_id: ClientId,
+    #[sql_type = "BigInt"]
+    pub withdrawable_cents: i64,
+    #[sql_type = "Bool"]
+    pub enable_automatic_payouts: bool,
+    #[sql_type = "BigInt"]
+    pub automatic_payout_threshold_cents: i64,
+    #[sql_type = "Nullable<Text>"]
+    pub stripe_user_id: Option<String>,
+}
+
+impl PayoutEligibility {
+    pub fn query(&self) -> ReportQuery {
+        let mut query = ReportQuery::from("balances AS b")
+            .select("b.client_id")
+            .select("b.withdrawable_cents")
+            .select("a.enable_automatic_payouts")
+            .select("a.automatic_payout_threshold_cents")
+            .select("a.stripe_user_id")
+            .join("INNER JOIN stripe_connect_accounts AS a ON b.client_id = a.client_id")
+            .filter(
+                "b.withdrawable_cents >= a.automatic_payout_threshold_cents",
+                vec![],
+            )
+            .filter(
+                "b.withdrawable_cents >= {}",
+                vec![Param::BigInt(self.min_withdrawable_cents)],
+            )
+            .filter(
+                "NOT EXISTS (SELECT * FROM stripe_connect_transfers AS t \
+                 WHERE t.client_id = b.client_id AND t.created_at >= NOW() - {})",
+                vec![Param::Interval(self.min_interval)],
+            )
+            .order_by("b.client_id");
+        if self.automatic_only {
+            query = query.filter("a.enable_automatic_payouts = TRUE", vec![]);
+        }
+        if let Some(client_id) = self.client_id {
+            query = query.filter("b.client_id = {}", vec![Param::ClientId(client_id)]);
+        }
+        if let Some(limit) = self.limit {
+            query = query.limit(limit);
+        }
+        query
+    }
+
+    pub fn load(&self, conn: &DbConnection) -> QueryResult<Vec<EligiblePayout>> {
+        self.query().load(conn)
+    }
+}
+
+/// The send fees paid from the campaigns account for campaign-funded payments,
+/// by campaign. Each fee's entries carry its campaign ID as their reference.
+#[derive(Clone, Debug)]
+pub struct CampaignSpend {
+    pub campaigns_account: ClientId,
+    /// Only this campaign, if set
+    pub campaign_id: Option<String>,
+    pub start_at: chrono::NaiveDateTime,
+    pub end_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct CampaignSpendRow {
+    #[sql_type = "Text"]
+    pub campaign_id: String,
+    #[sql_type = "BigInt"]
+    pub payment_count: i64,
+    #[sql_type = "BigInt"]
+    pub spent_cents: i64,
+}
+
+impl CampaignSpend {
+    pub fn query(&self) -> ReportQuery {
+        // Reversals are netted off the spend, but still count the payment
+        let mut query = ReportQuery::from("transactions")
+            .select("reference AS campaign_id")
+            .select("COUNT(*) FILTER (WHERE reverses_operation_id IS NULL) AS payment_count")
+            .select("-SUM(amount_cents)::BIGINT AS spent_cents")
+            .filter(
+                "client_id = {}",
+                vec![Param::ClientId(self.campaigns_account)],
+            )
+            .filter("reference IS NOT NULL", vec![])
+            .filter(
+                "created_at >= {} AND created_at < {}",
+                vec![
+                    Param::Timestamp(self.start_at),
+                    Param::Timestamp(self.end_at),
+                ],
+            )
+            .group_by("reference")
+            .order_by("reference");
+        if let Some(campaign_id) = &self.campaign_id {
+            query = query.filter("reference = {}", vec![Param::Text(campaign_id.clone())]);
+        }
+        query
+    }
+
+    pub fn load(
+        &self,
+        conn: &impl diesel::connection::Connection<Backend = Pg>,
+    ) -> QueryResult<Vec<CampaignSpendRow>> {
+        self.query().load(conn)
+    }
+}
+
+/// A sender's usage each day, as invoiced: cash sent, fees included, less what
+/// was refunded to them when payments went unread or were declined. Promo
+/// payments aren't invoiced.
+#[derive(Clone, Debug)]
+pub struct InvoiceUsage {
+    pub client_id: ClientId,
+    pub start_at: chrono::NaiveDateTime,
+    pub end_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct InvoiceUsageDay {
+    #[sql_type = "Date"]
+    pub ds: chrono::NaiveDate,
+    #[sql_type = "BigInt"]
+    pub sent_cents: i64,
+    #[sql_type = "BigInt"]
+    pub refunded_cents: i64,
+    #[sql_type = "BigInt"]
+    pub amount_cents: i64,
+}
+
+impl InvoiceUsage {
+    pub fn query(&self) -> ReportQuery {
+        ReportQuery::from("transactions")
+            .select("DATE(created_at) AS ds")
+            .select("COALESCE(-SUM(amount_cents) FILTER (WHERE tx_type = 'debit'), 0)::BIGINT AS sent_cents")
+            .select("COALESCE(SUM(amount_cents) FILTER (WHERE tx_type = 'credit'), 0)::BIGINT AS refunded_cents")
+            .select("-SUM(amount_cents)::BIGINT AS amount_cents")
+            .filter("client_id = {}", vec![Param::ClientId(self.client_id)])
+            .filter(
+                "(tx_type = 'debit' AND tx_reason = 'message_sent') \
+                 OR (tx_type = 'credit' AND tx_reason IN ('message_unread', 'message_declined'))",
+                vec![],
+            )
+            .filter(
+                "created_at >= {} AND created_at < {}",
+                vec![
+                    Param::Timestamp(self.start_at),
+                    Param::Timestamp(self.end_at),
+                ],
+            )
+            .group_by("DATE(created_at)")
+            .order_by("ds")
+    }
+
+    pub fn load(
+        &self,
+        conn: &impl diesel::connection::Connection<Backend = Pg>,
+    ) -> QueryResult<Vec<InvoiceUsageDay>> {
+        self.query().load(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_sql() {
+        let query = ReportQuery::from("transactions")
+            .select("client_id")
+            .select("SUM(amount_cents)")
+            .filter("amount_cents > {}", vec![Param::Int(100)])
+            .filter(
+                "created_at >= NOW() - {} AND tx_reason::text = {}",
+                vec![
+                    Param::Interval(chrono::Duration::days(7)),
+                    Param::Text("'; DROP TABLE transactions; --".into()),
+                ],
+            )
+            .group_by("client_id")
+            .order_by("2 DESC")
+            .limit(10);
+
+        assert_eq!(
+            query.to_sql(),
+            "SELECT client_id, SUM(amount_cents) FROM transactions \
+             WHERE (amount_cents > $1) AND (created_at >= NOW() - $2 AND tx_reason::text = $3) \
+             GROUP BY client_id ORDER BY 2 DESC LIMIT $4"
+        );
+        assert_eq!(
+            query.params(),
+            vec![
+                Param::Int(100),
+                Param::Interval(chrono::Duration::days(7)),
+                Param::Text("'; DROP TABLE transactions; --".into()),
+                Param::BigInt(10),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong number of parameters")]
+    fn test_filter_params_mismatch() {
+        ReportQuery::from("balances").filter("balance_cents > {}", vec![]);
+    }
+
+    #[test]
+    fn test_payout_eligibility() {
+        let query = PayoutEligibility::default().query();
+        assert_eq!(
+            query.to_sql(),
+            "SELECT b.client_id, b.withdrawable_cents, a.enable_automatic_payouts, \
+             a.automatic_payout_threshold_cents, a.stripe_user_id \
+             FROM balances AS b \
+             INNER JOIN stripe_connect_accounts AS a ON b.client_id = a.client_id \
+             WHERE (b.withdrawable_cents >= a.automatic_payout_threshold_cents) \
+             AND (b.withdrawable_cents >= $1) \
+             AND (NOT EXISTS (SELECT * FROM stripe_connect_transfers AS t \
+             WHERE t.client_id = b.client_id AND t.created_at >= NOW() - $2)) \
+             AND (a.enable_automatic_payouts = TRUE) \
+             ORDER BY b.client_id"
+        );
+        assert_eq!(
+            query.params(),
+            vec![
+                Param::BigInt(1),
+                Param::Interval(chrono::Duration::hours(24)),
+            ]
+        );
+
+        let query = PayoutEligibility {
+            automatic_only: false,
+            min_withdrawable_cents: 10_000,
+            min_interval: chrono::Duration::days(7),
+            client_id: None,
+            limit: Some(50),
+        }
+        .query();
+        assert!(!query.to_sql().contains("enable_automatic_payouts = TRUE"));
+        assert!(query.to_sql().ends_with("ORDER BY b.client_id LIMIT $3"));
+        assert_eq!(
+            query.params(),
+            vec![
+                Param::BigInt(10_000),
+                Param::Interval(chrono::Duration::days(7)),
+                Param::BigInt(50),
+            ]
+        );
+
+        let client_id = ClientId::from(uuid::Uuid::nil());
+        let query = PayoutEligibility {
+            client_id: Some(client_id),
+            limit: Some(1),
+            ..PayoutEligibility::default()
+        }
+        .query();
+        assert!(query
+            .to_sql()
+            .ends_with("AND (b.client_id = $3) ORDER BY b.client_id LIMIT $4"));
+        assert_eq!(query.params()[2], Param::ClientId(client_id));
+    }
+
+    #[test]
+    fn test_campaign_spend() {
+        let campaigns_account = ClientId::from(uuid::Uuid::nil());
+        let start_at = chrono::NaiveDate::from_ymd(2019, 11, 1).and_hms(0, 0, 0);
+        let end_at = chrono::NaiveDate::from_ymd(2019, 12, 1).and_hms(0, 0, 0);
+
+        let query = CampaignSpend {
+            campaigns_account,
+            campaign_id: None,
+            start_at,
+            end_at,
+        }
+        .query();
+        assert_eq!(
+            query.to_sql(),
+            "SELECT reference AS campaign_id, \
+             COUNT(*) FILTER (WHERE reverses_operation_id IS NULL) AS payment_count, \
+             -SUM(amount_cents)::BIGINT AS spent_cents \
+             FROM transactions \
+             WHERE (client_id = $1) AND (reference IS NOT NULL) \
+             AND (created_at >= $2 AND created_at < $3) \
+             GROUP BY reference ORDER BY reference"
+        );
+        assert_eq!(
+            query.params(),
+            vec![
+                Param::ClientId(campaigns_account),
+                Param::Timestamp(start_at),
+                Param::Timestamp(end_at),
+            ]
+        );
+
+        let query = CampaignSpend {
+            campaigns_account,
+            campaign_id: Some("launch".into()),
+            start_at,
+            end_at,
+        }
+        .query();
+        assert!(query
+            .to_sql()
+            .contains("AND (reference = $4) GROUP BY reference"));
+        assert_eq!(query.params()[3], Param::Text("launch".into()));
+    }
+
+    #[test]
+    fn test_invoice_usage() {
+        let client_id = ClientId::from(uuid::Uuid::nil());
+        let start_at = chrono::NaiveDate::from_ymd(2019, 11, 1).and_hms(0, 0, 0);
+        let end_at = chrono::NaiveDate::from_ymd(2019, 12, 1).and_hms(0, 0, 0);
+
+        let query = InvoiceUsage {
+            client_id,
+            start_at,
+            end_at,
+        }
+        .query();
+        assert_eq!(
+            query.to_sql(),
+            "SELECT DATE(created_at) AS ds, \
+             COALESCE(-SUM(amount_cents) FILTER (WHERE tx_type = 'debit'), 0)::BIGINT AS sent_cents, \
+             COALESCE(SUM(amount_cents) FILTER (WHERE tx_type = 'credit'), 0)::BIGINT AS refunded_cents, \
+             -SUM(amount_cents)::BIGINT AS amount_cents \
+             FROM transactions \
+             WHERE (client_id = $1) \
+             AND ((tx_type = 'debit' AND tx_reason = 'message_sent') \
+             OR (tx_type = 'credit' AND tx_reason IN ('message_unread', 'message_declined'))) \
+             AND (created_at >= $2 AND created_at < $3) \
+             GROUP BY DATE(created_at) ORDER BY ds"
+        );
+        assert_eq!(
+            query.params(),
+            vec![
+                Param::ClientId(client_id),
+                Param::Timestamp(start_at),
+                Param::Timestamp(end_at),
+            ]
+        );
+    }
+}
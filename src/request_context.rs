@@ -0,0 +1,165 @@
+//! What's known about the call a request came in on: who made it, the ID it's
+//! logged and audited under, when the caller gives up on it, and the trace
+//! it's part of. It's read from the gRPC metadata once per call, and carried
+//! by the service handle bound to the call.
+use beancounter_grpc::tower_grpc::metadata::MetadataMap;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Longest request ID accepted from a caller. Longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    // From the caller's x-request-id header if it's usable, otherwise
+    // generated. Unset outside of gRPC calls, i.e., in the cron.
+    pub request_id: Option<String>,
+    // As named in the auth config, if the caller's token is known
+    pub caller: Option<String>,
+    // When the caller will give up on the request, if they told us
+    pub deadline: Option<Instant>,
+    // The caller's W3C traceparent header, passed on as is
+    pub traceparent: Option<String>,
+}
+
+/// Parse a `grpc-timeout` header value, which is up to 8 digits followed by a
+/// unit: H, M, S, m (millis), u (micros) or n (nanos).
+pub fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount = amount.parse::<u64>().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// Request IDs end up in logs, audit rows and Stripe metadata, so only short
+/// IDs of letters, digits, '.', '_' and '-' are taken from callers.
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LEN
+        && request_id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_' || b == b'-')
+}
+
+/// A traceparent is "version-trace_id-parent_id-flags", in lowercase hex of
+/// 2, 32, 16 and 2 digits.
+fn is_valid_traceparent(traceparent: &str) -> bool {
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    parts.len() == 4
+        && parts.iter().zip([2, 32, 16, 2].iter()).all(|(part, len)| {
+            part.len() == *len
+                && part
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        })
+}
+
+impl RequestContext {
+    /// The context of a gRPC call, from its metadata, by the caller the
+    /// authorizer identified
+    pub fn from_metadata(metadata: &MetadataMap, caller: Option<&str>) -> Self {
+        let header = |name| metadata.get(name).and_then(|value| value.to_str().ok());
+        let request_id = header("x-request-id")
+            .filter(|request_id| is_valid_request_id(request_id))
+            .map(String::from)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_simple().to_string());
+        Self {
+            request_id: Some(request_id),
+            caller: caller.map(String::from),
+            deadline: header("grpc-timeout")
+                .and_then(parse_grpc_timeout)
+                .map(|timeout| Instant::now() + timeout),
+            traceparent: header("traceparent")
+                .filter(|traceparent| is_valid_traceparent(traceparent))
+                .map(String::from),
+        }
+    }
+
+    /// Added to the metadata of objects created in Stripe, so they can be
+    /// traced back to the call which created them
+    pub fn stripe_metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        if let Some(request_id) = &self.request_id {
+            metadata.insert("request_id".to_string(), request_id.clone());
+        }
+        if let Some(caller) = &self.caller {
+            metadata.insert("caller".to_string(), caller.clone());
+        }
+        metadata
+    }
+
+    /// The ID of the trace the request is part of, from its traceparent
+    pub fn trace_id(&self) -> Option<&str> {
+        self.traceparent
+            .as_ref()
+            .and_then(|traceparent| traceparent.split('-').nth(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_context_from_metadata() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("x-request-id", "req-1234_abc.5".parse().unwrap());
+        metadata.insert("grpc-timeout", "500m".parse().unwrap());
+        metadata.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                .parse()
+                .unwrap(),
+        );
+
+        let context = RequestContext::from_metadata(&metadata, Some("messaging"));
+        assert_eq!(context.request_id.as_ref().unwrap(), "req-1234_abc.5");
+        assert_eq!(context.caller.as_ref().unwrap(), "messaging");
+        assert!(context.deadline.unwrap() <= Instant::now() + Duration::from_millis(500));
+        assert_eq!(
+            context.traceparent.as_ref().unwrap(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(context.trace_id(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+        let stripe_metadata = context.stripe_metadata();
+        assert_eq!(stripe_metadata["request_id"], "req-1234_abc.5");
+        assert_eq!(stripe_metadata["caller"], "messaging");
+
+        // Unusable headers are replaced or left out
+        let mut metadata = MetadataMap::new();
+        metadata.insert("x-request-id", "not allowed!".parse().unwrap());
+        metadata.insert("traceparent", "00-xyz-00f067aa0ba902b7-01".parse().unwrap());
+        let context = RequestContext::from_metadata(&metadata, None);
+        let request_id = context.request_id.clone().unwrap();
+        assert_eq!(request_id.len(), 32);
+        assert_eq!(context.caller, None);
+        assert_eq!(context.deadline, None);
+        assert_eq!(context.traceparent, None);
+        assert_eq!(context.trace_id(), None);
+        assert!(!context.stripe_metadata().contains_key("caller"));
+
+        // Each call without an ID gets its own
+        let other = RequestContext::from_metadata(&MetadataMap::new(), None);
+        assert_ne!(other.request_id.unwrap(), request_id);
+
+        let id = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        assert!(!is_valid_request_id(&id));
+        assert!(is_valid_request_id(&id[1..]));
+
+        // Nothing is known outside of a call
+        assert!(RequestContext::default().stripe_metadata().is_empty());
+    }
+}
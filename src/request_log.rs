@@ -0,0 +1,178 @@
+//! One-line summaries of the RPCs handled: the RPC, the caller, the request
+//! and trace IDs, the client the call is for, latency and outcome. Successful
+//! calls are sampled, at a rate set per RPC, and failed calls are always
+//! logged.
+//!
+//! Each call is also counted towards its RPC's SLOs, see `slo`.
+//!
+//! Request and response bodies are never logged, so tokens, message hashes and
+//! the like can't leak into the logs. The client ID is the only field taken
+//! from the request, and it's left out unless enabled.
+use beancounter_grpc::tower_grpc::{Code, Status};
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::config;
+use crate::request_context::RequestContext;
+
+#[derive(Debug, Default)]
+pub struct RequestLogger {
+    // Fraction of successful calls logged, for RPCs not in sample_rates
+    sample_rate: f64,
+    // By RPC, named as in the proto (i.e., "GetBalance")
+    sample_rates: HashMap<String, f64>,
+    log_client_ids: bool,
+}
+
+impl RequestLogger {
+    /// A logger which only logs failed calls
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(request_log: &config::RequestLog) -> Self {
+        Self {
+            sample_rate: request_log.sample_rate,
+            sample_rates: request_log.sample_rates.clone(),
+            log_client_ids: request_log.log_client_ids,
+        }
+    }
+
+    fn sample_rate(&self, rpc: &str) -> f64 {
+        self.sample_rates
+            .get(rpc)
+            .cloned()
+            .unwrap_or(self.sample_rate)
+    }
+
+    /// Start the log entry for a call, which is written when it's dropped if
+    /// the call was sampled or failed.
+    pub fn start(
+        &self,
+        rpc: &'static str,
+        context: &RequestContext,
+        client_id: Option<&str>,
+    ) -> RequestLogEntry {
+        let sample_rate = self.sample_rate(rpc);
+        RequestLogEntry {
+            rpc,
+            caller: context.caller.clone().unwrap_or_else(|| "unknown".into()),
+            request_id: context.request_id.clone(),
+            trace_id: context.trace_id().map(String::from),
+            client_id: client_id
+                .filter(|client_id| self.log_client_ids && !client_id.is_empty())
+                .map(String::from),
+            started: Instant::now(),
+            sampled: sample_rate >= 1.0
+                || (sample_rate > 0.0 && rand::thread_rng().gen::<f64>() < sample_rate),
+            failed_with: None,
+        }
+    }
+}
+
+pub struct RequestLogEntry {
+    rpc: &'static str,
+    caller: String,
+    request_id: Option<String>,
+    trace_id: Option<String>,
+    client_id: Option<String>,
+    started: Instant,
+    sampled: bool,
+    failed_with: Option<Code>,
+}
+
+impl RequestLogEntry {
+    /// Record that the call failed, passing the status through
+    pub fn failed(&mut self, status: Status) -> Status {
+        self.failed_with = Some(status.code());
+        status
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!("rpc name={} caller={}", self.rpc, self.caller);
+        if let Some(request_id) = &self.request_id {
+            summary.push_str(&format!(" request_id={}", request_id));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            summary.push_str(&format!(" trace_id={}", trace_id));
+        }
+        if let Some(client_id) = &self.client_id {
+            summary.push_str(&format!(" client_id={}", client_id));
+        }
+        summary.push_str(&format!(
+            " latency_ms={}",
+            self.started.elapsed().as_millis()
+        ));
+        match self.failed_with {
+            None => summary.push_str(" outcome=ok"),
+            Some(code) => summary.push_str(&format!(" outcome=error code={:?}", code)),
+        }
+        summary
+    }
+}
+
+impl Drop for RequestLogEntry {
+    fn drop(&mut self) {
+        crate::slo::observe(self.rpc, self.started.elapsed(), self.failed_with);
+        match self.failed_with {
+            None if self.sampled => info!("{}", self.summary()),
+            None => (),
+            Some(_) => warn!("{}", self.summary()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_logger() {
+        let mut sample_rates = HashMap::new();
+        sample_rates.insert("GetBalance".to_string(), 0.0);
+        let logger = RequestLogger::from_config(&config::RequestLog {
+            sample_rate: 1.0,
+            sample_rates,
+            log_client_ids: false,
+        });
+
+        let context = RequestContext {
+            request_id: Some("req-1".into()),
+            caller: Some("messaging".into()),
+            deadline: None,
+            traceparent: Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".into()),
+        };
+        let entry = logger.start("AddPayment", &context, Some("abc"));
+        assert!(entry.sampled);
+        assert_eq!(entry.client_id, None);
+        assert!(entry.summary().starts_with(
+            "rpc name=AddPayment caller=messaging request_id=req-1 \
+             trace_id=4bf92f3577b34da6a3ce929d0e0e4736 latency_ms="
+        ));
+        assert!(entry.summary().ends_with(" outcome=ok"));
+
+        let mut entry = logger.start("GetBalance", &RequestContext::default(), Some("abc"));
+        assert!(!entry.sampled);
+        entry.failed(Status::new(Code::NotFound, "client not found"));
+        assert!(entry
+            .summary()
+            .starts_with("rpc name=GetBalance caller=unknown"));
+        assert!(entry.summary().ends_with(" outcome=error code=NotFound"));
+
+        let logger = RequestLogger::from_config(&config::RequestLog {
+            sample_rate: 0.0,
+            sample_rates: HashMap::new(),
+            log_client_ids: true,
+        });
+        let entry = logger.start("GetBalance", &RequestContext::default(), Some("abc"));
+        assert!(!entry.sampled);
+        assert!(entry.summary().contains(" client_id=abc "));
+        assert_eq!(
+            logger
+                .start("GetStats", &RequestContext::default(), Some(""))
+                .client_id,
+            None
+        );
+    }
+}
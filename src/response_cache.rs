@@ -0,0 +1,332 @@
+//! In-process cache of the reads a client's dashboard makes each time it's
+//! loaded: GetConnectAccount and the first page of GetTransactions. When many
+//! clients load their dashboards at once, as after payout emails go out, most
+//! of those reads are answered from the cache instead of the database.
+//!
+//! Responses are cached for a short TTL, and dropped as soon as this process
+//! writes anything for the client. Each process has its own cache, so writes
+//! made by other processes, such as the cron jobs, are only seen once the TTL
+//! has passed.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use beancounter_grpc::proto::{GetConnectAccountResponse, GetTransactionsResponse};
+
+use crate::config;
+use crate::models::ClientId;
+
+struct Cached<T> {
+    cached_at: Instant,
+    response: T,
+}
+
+// Responses by client, ledger and whatever else the request is keyed on
+type Responses<K, T> = HashMap<(ClientId, bool, K), Cached<T>>;
+
+#[derive(Default)]
+struct Entries {
+    connect_accounts: Responses<(), GetConnectAccountResponse>,
+    // By page size
+    transactions: Responses<i64, GetTransactionsResponse>,
+    // When each client's responses were last dropped. A response read before
+    // then isn't cached, as it may be from before the write.
+    invalidated_at: HashMap<ClientId, Instant>,
+}
+
+#[derive(Default)]
+pub struct ResponseCache {
+    // Zero disables the cache
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+fn get<K: Eq + Hash, T: Clone>(
+    responses: &Responses<K, T>,
+    key: &(ClientId, bool, K),
+    ttl: Duration,
+    now: Instant,
+) -> Option<T> {
+    responses
+        .get(key)
+        .filter(|cached| now.duration_since(cached.cached_at) < ttl)
+        .map(|cached| cached.response.clone())
+}
+
+impl Entries {
+    fn is_invalidated_since(&self, client_id: ClientId, read_at: Instant) -> bool {
+        self.invalidated_at
+            .get(&client_id)
+            .map_or(false, |invalidated_at| *invalidated_at >= read_at)
+    }
+
+    // Responses read longer than the TTL ago are never cached, so nothing
+    // older than that is needed to decide what to cache
+    fn expire(&mut self, ttl: Duration, now: Instant) {
+        let fresh = |at: Instant| now.duration_since(at) < ttl;
+        self.connect_accounts
+            .retain(|_, cached| fresh(cached.cached_at));
+        self.transactions
+            .retain(|_, cached| fresh(cached.cached_at));
+        self.invalidated_at.retain(|_, at| fresh(*at));
+    }
+}
+
+impl ResponseCache {
+    /// No cache, so that every read goes to the database
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn from_config(response_cache: &config::ResponseCache) -> Self {
+        Self::new(Duration::from_millis(response_cache.ttl_ms))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > Duration::from_millis(0)
+    }
+
+    // The lock only guards the maps, so they're still consistent if poisoned
+    fn with_entries<R>(&self, f: impl FnOnce(&mut Entries) -> R) -> R {
+        f(&mut self.entries.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// The client's cached GetConnectAccount response, if it's fresh
+    pub fn connect_account(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+    ) -> Option<GetConnectAccountResponse> {
+        self.connect_account_at(client_id, livemode, Instant::now())
+    }
+
+    fn connect_account_at(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        now: Instant,
+    ) -> Option<GetConnectAccountResponse> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.with_entries(|entries| {
+            get(
+                &entries.connect_accounts,
+                &(client_id, livemode, ()),
+                self.ttl,
+                now,
+            )
+        })
+    }
+
+    /// Cache a GetConnectAccount response, read from the database at
+    /// `read_at`, unless the client has been written since
+    pub fn put_connect_account(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        read_at: Instant,
+        response: &GetConnectAccountResponse,
+    ) {
+        self.put_connect_account_at(client_id, livemode, read_at, response, Instant::now())
+    }
+
+    fn put_connect_account_at(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        read_at: Instant,
+        response: &GetConnectAccountResponse,
+        now: Instant,
+    ) {
+        if !self.is_enabled() || now.duration_since(read_at) >= self.ttl {
+            return;
+        }
+        self.with_entries(|entries| {
+            entries.expire(self.ttl, now);
+            if !entries.is_invalidated_since(client_id, read_at) {
+                entries.connect_accounts.insert(
+                    (client_id, livemode, ()),
+                    Cached {
+                        cached_at: read_at,
+                        response: response.clone(),
+                    },
+                );
+            }
+        })
+    }
+
+    /// The client's cached first page of GetTransactions with this limit, if
+    /// it's fresh
+    pub fn transactions(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        limit: i64,
+    ) -> Option<GetTransactionsResponse> {
+        self.transactions_at(client_id, livemode, limit, Instant::now())
+    }
+
+    fn transactions_at(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        limit: i64,
+        now: Instant,
+    ) -> Option<GetTransactionsResponse> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.with_entries(|entries| {
+            get(
+                &entries.transactions,
+                &(client_id, livemode, limit),
+                self.ttl,
+                now,
+            )
+        })
+    }
+
+    /// Cache the first page of GetTransactions with this limit, read from the
+    /// database at `read_at`, unless the client has been written since
+    pub fn put_transactions(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        limit: i64,
+        read_at: Instant,
+        response: &GetTransactionsResponse,
+    ) {
+        self.put_transactions_at(
+            client_id,
+            livemode,
+            limit,
+            read_at,
+            response,
+            Instant::now(),
+        )
+    }
+
+    fn put_transactions_at(
+        &self,
+        client_id: ClientId,
+        livemode: bool,
+        limit: i64,
+        read_at: Instant,
+        response: &GetTransactionsResponse,
+        now: Instant,
+    ) {
+        if !self.is_enabled() || now.duration_since(read_at) >= self.ttl {
+            return;
+        }
+        self.with_entries(|entries| {
+            entries.expire(self.ttl, now);
+            if !entries.is_invalidated_since(client_id, read_at) {
+                entries.transactions.insert(
+                    (client_id, livemode, limit),
+                    Cached {
+                        cached_at: read_at,
+                        response: response.clone(),
+                    },
+                );
+            }
+        })
+    }
+
+    /// Drop the clients' cached responses, on both ledgers, once something
+    /// has been written for them
+    pub fn invalidate(&self, clients: &[ClientId]) {
+        self.invalidate_at(clients, Instant::now())
+    }
+
+    fn invalidate_at(&self, clients: &[ClientId], now: Instant) {
+        if !self.is_enabled() || clients.is_empty() {
+            return;
+        }
+        self.with_entries(|entries| {
+            entries
+                .connect_accounts
+                .retain(|(client_id, _, _), _| !clients.contains(client_id));
+            entries
+                .transactions
+                .retain(|(client_id, _, _), _| !clients.contains(client_id));
+            for client_id in clients {
+                entries.invalidated_at.insert(*client_id, now);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transactions(count: usize) -> GetTransactionsResponse {
+        GetTransactionsResponse {
+            transactions: vec![Default::default(); count],
+        }
+    }
+
+    #[test]
+    fn test_response_cache() {
+        let cache = ResponseCache::new(Duration::from_secs(5));
+        let client_id = ClientId::from(uuid::Uuid::new_v4());
+        let other_client_id = ClientId::from(uuid::Uuid::new_v4());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let cached = |limit, now| {
+            cache
+                .transactions_at(client_id, true, limit, now)
+                .map(|response| response.transactions.len())
+        };
+
+        assert_eq!(cached(10, start), None);
+        cache.put_transactions_at(client_id, true, 10, start, &transactions(1), start);
+        assert_eq!(cached(10, at(1)), Some(1));
+        // Keyed by the limit and ledger
+        assert_eq!(cached(20, at(1)), None);
+        assert_eq!(cache.transactions_at(client_id, false, 10, at(1)), None);
+        // Expired after the TTL
+        assert_eq!(cached(10, at(5)), None);
+
+        // Dropped when the client is written
+        cache.put_transactions_at(client_id, true, 10, at(5), &transactions(2), at(5));
+        let response = GetConnectAccountResponse {
+            client_id: client_id.to_string(),
+            ..Default::default()
+        };
+        cache.put_connect_account_at(client_id, true, at(5), &response, at(5));
+        cache.invalidate_at(&[other_client_id], at(6));
+        assert_eq!(cached(10, at(6)), Some(2));
+        cache.invalidate_at(&[client_id], at(6));
+        assert_eq!(cached(10, at(6)), None);
+        assert_eq!(cache.connect_account_at(client_id, true, at(6)), None);
+
+        // A response read before the write isn't cached after it
+        cache.put_transactions_at(client_id, true, 10, at(6), &transactions(3), at(7));
+        assert_eq!(cached(10, at(7)), None);
+        cache.put_transactions_at(client_id, true, 10, at(7), &transactions(4), at(7));
+        assert_eq!(cached(10, at(8)), Some(4));
+
+        // Nothing is cached when disabled
+        let cache = ResponseCache::disabled();
+        cache.put_connect_account_at(client_id, true, start, &response, start);
+        assert_eq!(cache.connect_account_at(client_id, true, start), None);
+    }
+}
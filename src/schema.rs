@@ -1,3 +1,104 @@
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    account_tiers (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        tier -> Account_tier,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    annual_earnings (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        year -> Int4,
+        gross_cents -> Int8,
+        fee_cents -> Int8,
+        payment_count -> Int8,
+        monthly_gross_cents -> Array<Int8>,
+        monthly_payment_counts -> Array<Int8>,
+        cron_run_id -> Uuid,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    auto_reload_charges (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        amount_cents -> Int4,
+        state -> Auto_reload_state,
+        charge -> Nullable<Json>,
+        error -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    auto_reload_prefs (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        enabled -> Bool,
+        threshold_cents -> Int8,
+        reload_amount_cents -> Int4,
+        daily_cap_cents -> Int8,
+        stripe_customer_id -> Nullable<Text>,
+        consecutive_failures -> Int4,
+        next_attempt_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    balance_alert_prefs (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        low_balance_cents -> Nullable<Int8>,
+        withdrawable_cents -> Nullable<Int8>,
+        low_balance_alerted -> Bool,
+        withdrawable_alerted -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    balance_history (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        client_id -> Uuid,
+        balance_cents -> Int8,
+        promo_cents -> Int8,
+        withdrawable_cents -> Int8,
+        held_cents -> Int8,
+        lifetime_earned_cents -> Int8,
+        promo_earned_cents -> Int8,
+        livemode -> Bool,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::sql_types::*;
@@ -10,6 +111,364 @@ table! {
         balance_cents -> Int8,
         promo_cents -> Int8,
         withdrawable_cents -> Int8,
+        referral_cents -> Int8,
+        held_cents -> Int8,
+        pending_settlement_cents -> Int8,
+        lifetime_earned_cents -> Int8,
+        promo_earned_cents -> Int8,
+        livemode -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    bigquery_exports (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        table_name -> Text,
+        last_id -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    client_ledger_locks (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        reason -> Text,
+        locked_by -> Text,
+        expires_at -> Timestamp,
+        unlocked_at -> Nullable<Timestamp>,
+        unlocked_by -> Nullable<Text>,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    client_denylist (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        reason -> Text,
+        added_by -> Text,
+        removed_at -> Nullable<Timestamp>,
+        removed_by -> Nullable<Text>,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    dormancy_events (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        client_id -> Uuid,
+        action -> Dormancy_action,
+        last_activity_at -> Timestamp,
+        amount_cents -> Int8,
+        cron_run_id -> Uuid,
+        transaction_id -> Nullable<Int8>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    fee_schedules (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        plan -> Fee_plan,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    fx_rates (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        source -> Text,
+        currency -> Text,
+        rate -> Float8,
+        cron_run_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    held_credits (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        transaction_id -> Int8,
+        stripe_charge_id -> Text,
+        amount_cents -> Int4,
+        risk_level -> Text,
+        release_at -> Nullable<Timestamp>,
+        state -> Held_credit_state,
+        reviewed_by -> Nullable<Text>,
+        reviewed_at -> Nullable<Timestamp>,
+        livemode -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    invoice_line_items (id) {
+        id -> Int8,
+        invoice_id -> Int8,
+        ds -> Date,
+        sent_cents -> Int8,
+        refunded_cents -> Int8,
+        amount_cents -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    invoices (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        period -> Date,
+        amount_cents -> Int8,
+        paid_cents -> Int8,
+        state -> Invoice_state,
+        due_at -> Timestamp,
+        livemode -> Bool,
+        cron_run_id -> Uuid,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    ledger_day_totals (id) {
+        id -> Int8,
+        ds -> Date,
+        tx_type -> Transaction_type,
+        tx_reason -> Transaction_reason,
+        tx_count -> Int8,
+        amount_cents -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    ledger_days (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        ds -> Date,
+        tx_count -> Int8,
+        credit_cents -> Int8,
+        debit_cents -> Int8,
+        balanced -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    ledger_hash_anchors (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        ledger_hash_id -> Int8,
+        chain_hash -> Text,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    ledger_hashes (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        transaction_id -> Int8,
+        transaction_created_at -> Timestamp,
+        client_id -> Nullable<Uuid>,
+        content_hash -> Text,
+        client_hash -> Text,
+        chain_hash -> Text,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    outbox_events (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        client_id -> Nullable<Uuid>,
+        event_type -> Outbox_event_type,
+        payload -> Json,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_holds (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id_from -> Uuid,
+        client_id_to -> Uuid,
+        message_hash -> Text,
+        payment_cents -> Int4,
+        amount_cents -> Int4,
+        referrer_client_id -> Nullable<Uuid>,
+        campaign_id -> Nullable<Text>,
+        state -> Payment_hold_state,
+        expires_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
+        livemode -> Bool,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_links (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        payment_cents -> Int4,
+        description -> Nullable<Text>,
+        expires_at -> Timestamp,
+        redeemed_at -> Nullable<Timestamp>,
+        redeemed_by -> Nullable<Uuid>,
+        message_hash -> Nullable<Text>,
+        livemode -> Bool,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_outcomes (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        payment_created_at -> Timestamp,
+        client_id_from -> Uuid,
+        client_id_to -> Uuid,
+        payment_cents -> Int4,
+        is_promo -> Bool,
+        outcome -> Payment_outcome,
+        payment_split_id -> Nullable<Int8>,
+        fee_cents -> Int4,
+        message_hash -> Nullable<Text>,
+        livemode -> Bool,
+        payout_cents -> Int4,
+        referral_cents -> Int4,
+        read_fee_paid_by_sender -> Bool,
+        refund_cents -> Int4,
+        tip_cents -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_prefs (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        min_payment_cents -> Int4,
+        blocked_client_ids -> Array<Uuid>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_refunds (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        payment_id -> Int8,
+        payment_created_at -> Timestamp,
+        client_id_from -> Uuid,
+        client_id_to -> Uuid,
+        payment_cents -> Int4,
+        message_hash -> Text,
+        is_promo -> Bool,
+        reason -> Refund_reason,
+        cron_run_id -> Uuid,
+        transaction_id -> Int8,
+        policy -> Expiry_refund_policy,
+        fee_retained_cents -> Int4,
+        fee_refunded_cents -> Int4,
+        fee_transaction_id -> Nullable<Int8>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_split_shares (id) {
+        id -> Int8,
+        payment_split_id -> Int8,
+        client_id_to -> Uuid,
+        share -> Int4,
+        payment_cents -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payment_splits (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        client_id_from -> Uuid,
+        message_hash -> Text,
+        payment_cents -> Int4,
+        fee_cents -> Int4,
     }
 }
 
@@ -26,6 +485,145 @@ table! {
         payment_cents -> Int4,
         message_hash -> Text,
         is_promo -> Bool,
+        referrer_client_id -> Nullable<Uuid>,
+        payment_split_id -> Nullable<Int8>,
+        livemode -> Bool,
+        reminded_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payout_attempts (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        confirmation_token -> Uuid,
+        amount_cents -> Int4,
+        withheld_cents -> Int4,
+        description -> Nullable<Text>,
+        statement_descriptor -> Nullable<Text>,
+        expires_at -> Timestamp,
+        state -> Payout_attempt_state,
+        confirmed_at -> Nullable<Timestamp>,
+        failure_reason -> Nullable<Text>,
+        failure_code -> Nullable<Text>,
+        decline_code -> Nullable<Text>,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payout_holds (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        amount_cents -> Int4,
+        state -> Payout_hold_state,
+        livemode -> Bool,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+        transferred_cents -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    payout_runs (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        cron_run_id -> Uuid,
+        started_at -> Timestamp,
+        finished_at -> Timestamp,
+        candidates -> Int4,
+        succeeded -> Int4,
+        failed -> Int4,
+        skipped -> Int4,
+        skipped_reasons -> Json,
+        total_cents -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    pending_charges (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        stripe_charge_id -> Text,
+        amount_cents -> Int4,
+        original_currency -> Nullable<Text>,
+        original_amount_cents -> Nullable<Int4>,
+        fx_rate -> Nullable<Float8>,
+        fx_rate_id -> Nullable<Int8>,
+        state -> Pending_charge_state,
+        expires_at -> Nullable<Timestamp>,
+        transaction_id -> Nullable<Int8>,
+        resolved_at -> Nullable<Timestamp>,
+        livemode -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    risk_flags (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        client_id -> Uuid,
+        kind -> Risk_flag_kind,
+        ds -> Date,
+        amount_cents -> Int8,
+        baseline_cents -> Float8,
+        cron_run_id -> Uuid,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    settlement_stats (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        ds -> Date,
+        payment_count -> Int8,
+        settled_count -> Int8,
+        expired_count -> Int8,
+        pending_count -> Int8,
+        median_settlement_seconds -> Nullable<Float8>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    statements (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        client_id -> Uuid,
+        period -> Date,
+        gross_cents -> Int8,
+        fee_cents -> Int8,
+        withheld_cents -> Int8,
+        payment_count -> Int8,
+        cron_run_id -> Uuid,
     }
 }
 
@@ -40,6 +638,10 @@ table! {
         client_id -> Uuid,
         token -> Json,
         charge -> Json,
+        transaction_id -> Nullable<Int8>,
+        risk_level -> Nullable<Text>,
+        seller_message -> Nullable<Text>,
+        statement_descriptor_suffix -> Nullable<Text>,
     }
 }
 
@@ -58,6 +660,48 @@ table! {
         connect_credentials -> Nullable<Json>,
         enable_automatic_payouts -> Bool,
         automatic_payout_threshold_cents -> Int8,
+        requirements_currently_due -> Array<Text>,
+        requirements_disabled_reason -> Nullable<Text>,
+        requirements_updated_at -> Nullable<Timestamp>,
+        email -> Nullable<Text>,
+        country -> Nullable<Text>,
+        identity_verified -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    stripe_connect_destinations (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        stripe_user_id -> Text,
+        is_primary -> Bool,
+        split_percent -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    stripe_connect_payouts (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        client_id -> Uuid,
+        stripe_user_id -> Text,
+        transfer_id -> Int8,
+        stripe_payout_id -> Nullable<Text>,
+        amount_cents -> Int4,
+        method -> Text,
+        status -> Text,
+        failure_code -> Nullable<Text>,
+        failure_message -> Nullable<Text>,
+        connect_payout -> Nullable<Json>,
     }
 }
 
@@ -73,6 +717,8 @@ table! {
         stripe_user_id -> Text,
         connect_transfer -> Json,
         amount_cents -> Int4,
+        description -> Nullable<Text>,
+        statement_descriptor -> Nullable<Text>,
     }
 }
 
@@ -80,21 +726,126 @@ table! {
     use diesel::sql_types::*;
     use crate::sql_types::*;
 
-    transactions (id) {
+    stripe_events (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        event_id -> Text,
+        event_type -> Text,
+        stripe_account -> Nullable<Text>,
+        replayed -> Bool,
+        handled -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    transaction_imports (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        batch_id -> Text,
+        operation_id -> Uuid,
+        tx_count -> Int4,
+        first_created_at -> Timestamp,
+        last_created_at -> Timestamp,
+        livemode -> Bool,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    transaction_notes (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        transaction_id -> Int8,
+        author -> Text,
+        note -> Text,
+        tags -> Array<Text>,
+        caller -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::*;
+
+    transactions (id, created_at) {
         id -> Int8,
         created_at -> Timestamp,
         client_id -> Nullable<Uuid>,
         tx_type -> Transaction_type,
         tx_reason -> Transaction_reason,
         amount_cents -> Int4,
+        operation_id -> Nullable<Uuid>,
+        reverses_operation_id -> Nullable<Uuid>,
+        original_currency -> Nullable<Text>,
+        original_amount_cents -> Nullable<Int4>,
+        fx_rate -> Nullable<Float8>,
+        fx_rate_id -> Nullable<Int8>,
+        livemode -> Bool,
+        reference -> Nullable<Text>,
+        amount_cents_i64 -> Nullable<Int8>,
     }
 }
 
+joinable!(invoice_line_items -> invoices (invoice_id));
+joinable!(ledger_hash_anchors -> ledger_hashes (ledger_hash_id));
+joinable!(payment_outcomes -> payment_splits (payment_split_id));
+joinable!(payment_split_shares -> payment_splits (payment_split_id));
+joinable!(payments -> payment_splits (payment_split_id));
+joinable!(transactions -> fx_rates (fx_rate_id));
+
 allow_tables_to_appear_in_same_query!(
+    account_tiers,
+    annual_earnings,
+    auto_reload_charges,
+    auto_reload_prefs,
+    balance_alert_prefs,
+    balance_history,
     balances,
+    bigquery_exports,
+    client_denylist,
+    client_ledger_locks,
+    dormancy_events,
+    fee_schedules,
+    fx_rates,
+    held_credits,
+    invoice_line_items,
+    invoices,
+    ledger_day_totals,
+    ledger_days,
+    ledger_hash_anchors,
+    ledger_hashes,
+    outbox_events,
+    payment_holds,
+    payment_links,
+    payment_outcomes,
+    payment_prefs,
+    payment_refunds,
+    payment_split_shares,
+    payment_splits,
     payments,
+    payout_attempts,
+    payout_holds,
+    payout_runs,
+    pending_charges,
+    risk_flags,
+    settlement_stats,
+    statements,
     stripe_charges,
     stripe_connect_accounts,
+    stripe_connect_destinations,
+    stripe_connect_payouts,
     stripe_connect_transfers,
+    stripe_events,
+    transaction_imports,
+    transaction_notes,
     transactions,
 );
@@ -0,0 +1,161 @@
+//! Checks run at startup, before the service accepts any requests. A binary
+//! deployed against a database which hasn't been migrated yet, or with a
+//! revoked Stripe key, would otherwise start serving and fail request by
+//! request, so the service refuses to start instead.
+//!
+//! A database which is ahead of the binary is allowed, with a warning, since
+//! migrations run before a deploy and the old binaries keep serving until
+//! they're replaced.
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use std::time::Duration;
+
+use crate::database::DbConnection;
+use crate::sql_types::{TransactionReason, TransactionType};
+use crate::stripe_client::Stripe;
+
+/// The version of the newest migration, which this binary expects the
+/// database to have run. Update it with each new migration.
+pub const EXPECTED_SCHEMA_VERSION: &str = "20191123092815";
+
+// How long to wait for Stripe to answer
+static STRIPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Fail)]
+pub enum SelfCheckError {
+    #[fail(display = "database error: {}", err)]
+    DatabaseError { err: String },
+    #[fail(
+        display = "database schema is at version {}, expected {}; run the migrations",
+        found, expected
+    )]
+    SchemaBehind { found: String, expected: String },
+    #[fail(display = "database enum {} is missing a variant: {}", enum_name, err)]
+    MissingEnumVariant { enum_name: String, err: String },
+    #[fail(display = "stripe check failed: {}", err)]
+    StripeError { err: String },
+}
+
+impl From<diesel::result::Error> for SelfCheckError {
+    fn from(err: diesel::result::Error) -> Self {
+        SelfCheckError::DatabaseError {
+            err: err.to_string(),
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct SchemaVersion {
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    version: Option<String>,
+}
+
+/// Check the database has run every migration this binary knows about
+pub fn check_schema_version(
+    conn: &PooledConnection<ConnectionManager<DbConnection>>,
+) -> Result<(), SelfCheckError> {
+    use diesel::prelude::*;
+
+    let found = diesel::sql_query("SELECT MAX(version) AS version FROM __diesel_schema_migrations")
+        .get_result::<SchemaVersion>(conn)?
+        .version
+        .unwrap_or_default();
+
+    // Versions are timestamps of the same length, so they compare as strings
+    if found.as_str() < EXPECTED_SCHEMA_VERSION {
+        return Err(SelfCheckError::SchemaBehind {
+            found,
+            expected: EXPECTED_SCHEMA_VERSION.into(),
+        });
+    }
+    if found.as_str() > EXPECTED_SCHEMA_VERSION {
+        warn!(
+            "Database schema is at version {}, ahead of this binary's {}",
+            found, EXPECTED_SCHEMA_VERSION
+        );
+    }
+
+    Ok(())
+}
+
+/// Check the database's ledger enums have every variant the code may write,
+/// by round tripping each one. Postgres rejects a value its enum lacks.
+pub fn check_enums(
+    conn: &PooledConnection<ConnectionManager<DbConnection>>,
+) -> Result<(), SelfCheckError> {
+    use crate::sql_types::{Transaction_reason, Transaction_type};
+    use diesel::prelude::*;
+
+    let missing = |enum_name: &str, err: diesel::result::Error| match err {
+        diesel::result::Error::DatabaseError(_, info) => SelfCheckError::MissingEnumVariant {
+            enum_name: enum_name.into(),
+            err: info.message().into(),
+        },
+        err => SelfCheckError::from(err),
+    };
+
+    for tx_type in TransactionType::ALL {
+        diesel::select(tx_type.into_sql::<Transaction_type>())
+            .get_result::<TransactionType>(conn)
+            .map_err(|err| missing("transaction_type", err))?;
+    }
+    for tx_reason in TransactionReason::ALL {
+        diesel::select(tx_reason.into_sql::<Transaction_reason>())
+            .get_result::<TransactionReason>(conn)
+            .map_err(|err| missing("transaction_reason", err))?;
+    }
+
+    Ok(())
+}
+
+/// Check Stripe accepts the API key
+pub fn check_stripe(stripe: &Stripe) -> Result<(), SelfCheckError> {
+    use tokio::timer::Timeout;
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime
+        .block_on(Timeout::new(stripe.ping(), STRIPE_TIMEOUT))
+        .map(|_| ())
+        .map_err(|err| SelfCheckError::StripeError {
+            err: match err.into_inner() {
+                Some(err) => err.to_string(),
+                None => "timed out".into(),
+            },
+        })
+}
+
+/// Run every check, returning the first to fail
+pub fn run(
+    conn: &PooledConnection<ConnectionManager<DbConnection>>,
+    stripe: &Stripe,
+) -> Result<(), SelfCheckError> {
+    check_schema_version(conn)?;
+    check_enums(conn)?;
+    check_stripe(stripe)?;
+    info!("Startup checks passed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_schema_version() {
+        let migrations = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let newest = std::fs::read_dir(migrations)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with('.') && !name.starts_with("00000000000000"))
+            .max()
+            .unwrap();
+        let version: String = newest
+            .split('_')
+            .next()
+            .unwrap()
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        assert_eq!(version, EXPECTED_SCHEMA_VERSION);
+    }
+}
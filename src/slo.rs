@@ -0,0 +1,331 @@
+//! Service level indicators for each RPC, measured against the objectives in
+//! `[slo]`, so that alerts can be set on the error budget directly rather
+//! than derived from the raw histograms. Each call is counted when its request
+//! log entry is written: as available unless it failed with a server error,
+//! and as fast if it finished within the RPC's latency threshold. Calls are
+//! kept in one-minute buckets, and `update_gauges` sets, for each RPC and
+//! window:
+//!
+//! - `slo_availability_sli` and `slo_latency_sli`, the fraction of good calls
+//! - `slo_availability_burn_rate` and `slo_latency_burn_rate`, how fast the
+//!   error budget is being spent. At 1 the budget lasts exactly the SLO's
+//!   period. Alerts usually page on a high burn rate over both a short and a
+//!   long window, i.e., 14.4 over 5 minutes and an hour.
+//!
+//! A window without calls has an SLI of 1, and burns nothing.
+use beancounter_grpc::tower_grpc::Code;
+use instrumented::{prometheus, register};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+
+fn make_gauge_vec(name: &str, description: &str) -> prometheus::GaugeVec {
+    let gauge =
+        prometheus::GaugeVec::new(prometheus::Opts::new(name, description), &["rpc", "window"])
+            .unwrap();
+
+    register(Box::new(gauge.clone())).unwrap();
+
+    gauge
+}
+
+lazy_static! {
+    static ref AVAILABILITY_SLI: prometheus::GaugeVec = make_gauge_vec(
+        "slo_availability_sli",
+        "Fraction of calls which didn't fail with a server error, by RPC and window"
+    );
+    static ref AVAILABILITY_BURN_RATE: prometheus::GaugeVec = make_gauge_vec(
+        "slo_availability_burn_rate",
+        "Rate the availability error budget is being spent at, by RPC and window"
+    );
+    static ref LATENCY_SLI: prometheus::GaugeVec = make_gauge_vec(
+        "slo_latency_sli",
+        "Fraction of calls which finished within the latency threshold, by RPC and window"
+    );
+    static ref LATENCY_BURN_RATE: prometheus::GaugeVec = make_gauge_vec(
+        "slo_latency_burn_rate",
+        "Rate the latency error budget is being spent at, by RPC and window"
+    );
+    static ref TRACKER: Mutex<Tracker> = Mutex::new(Tracker::new(config::Slo::default()));
+}
+
+/// Whether a call which failed with the code counts against availability.
+/// Failures caused by the request, such as bad arguments or a client which
+/// doesn't exist, don't.
+pub fn is_server_error(code: Code) -> bool {
+    match code {
+        Code::Unknown
+        | Code::DeadlineExceeded
+        | Code::Aborted
+        | Code::Internal
+        | Code::Unavailable
+        | Code::DataLoss => true,
+        _ => false,
+    }
+}
+
+/// An RPC's objectives
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Target {
+    pub availability: f64,
+    pub latency: f64,
+    pub latency_threshold: Duration,
+}
+
+impl Target {
+    fn for_rpc(config: &config::Slo, rpc: &str) -> Self {
+        let target = config.rpcs.get(rpc).cloned().unwrap_or_default();
+        Self {
+            availability: target
+                .availability_target
+                .unwrap_or(config.availability_target),
+            latency: target.latency_target.unwrap_or(config.latency_target),
+            latency_threshold: Duration::from_millis(
+                target
+                    .latency_threshold_ms
+                    .unwrap_or(config.latency_threshold_ms),
+            ),
+        }
+    }
+}
+
+/// The fraction of good calls, and the rate the error budget is spent at
+fn sli_and_burn_rate(calls: u64, bad: u64, target: f64) -> (f64, f64) {
+    if calls == 0 {
+        return (1.0, 0.0);
+    }
+    let bad_fraction = bad as f64 / calls as f64;
+    (1.0 - bad_fraction, bad_fraction / (1.0 - target))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Counts {
+    calls: u64,
+    errors: u64,
+    slow: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Bucket {
+    // Minutes since the epoch
+    minute: u64,
+    counts: Counts,
+}
+
+struct Tracker {
+    config: config::Slo,
+    // By RPC, a ring of one-minute buckets covering the longest window
+    buckets: HashMap<&'static str, Vec<Bucket>>,
+}
+
+impl Tracker {
+    fn new(config: config::Slo) -> Self {
+        Self {
+            config,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn ring_len(&self) -> u64 {
+        self.config
+            .windows_minutes
+            .iter()
+            .cloned()
+            .max()
+            .map_or(1, u64::from)
+    }
+
+    fn apply_config(&mut self, config: &config::Slo) {
+        let windows_changed = config.windows_minutes != self.config.windows_minutes;
+        self.config = config.clone();
+        // The rings are sized for the old windows
+        if windows_changed {
+            self.buckets.clear();
+        }
+    }
+
+    fn observe(
+        &mut self,
+        rpc: &'static str,
+        minute: u64,
+        latency: Duration,
+        failed_with: Option<Code>,
+    ) {
+        let target = Target::for_rpc(&self.config, rpc);
+        let ring_len = self.ring_len();
+        let ring = self
+            .buckets
+            .entry(rpc)
+            .or_insert_with(|| vec![Bucket::default(); ring_len as usize]);
+
+        let bucket = &mut ring[(minute % ring_len) as usize];
+        if bucket.minute != minute {
+            *bucket = Bucket {
+                minute,
+                counts: Counts::default(),
+            };
+        }
+        bucket.counts.calls += 1;
+        if failed_with.map_or(false, is_server_error) {
+            bucket.counts.errors += 1;
+        }
+        if latency > target.latency_threshold {
+            bucket.counts.slow += 1;
+        }
+    }
+
+    /// The calls to the RPC in the window of minutes up to and including
+    /// `minute`
+    fn counts(&self, rpc: &str, minute: u64, window_minutes: u32) -> Counts {
+        self.buckets
+            .get(rpc)
+            .map(|ring| {
+                ring.iter()
+                    .filter(|bucket| {
+                        bucket.minute <= minute
+                            && bucket.minute + u64::from(window_minutes) > minute
+                    })
+                    .fold(Counts::default(), |total, bucket| Counts {
+                        calls: total.calls + bucket.counts.calls,
+                        errors: total.errors + bucket.counts.errors,
+                        slow: total.slow + bucket.counts.slow,
+                    })
+            })
+            .unwrap_or_default()
+    }
+
+    fn update_gauges(&self, minute: u64) {
+        for rpc in self.buckets.keys() {
+            let target = Target::for_rpc(&self.config, rpc);
+            for window_minutes in self.config.windows_minutes.iter() {
+                let counts = self.counts(rpc, minute, *window_minutes);
+                let window = format!("{}m", window_minutes);
+                let labels = &[*rpc, window.as_str()];
+
+                let (sli, burn_rate) =
+                    sli_and_burn_rate(counts.calls, counts.errors, target.availability);
+                AVAILABILITY_SLI.with_label_values(labels).set(sli);
+                AVAILABILITY_BURN_RATE
+                    .with_label_values(labels)
+                    .set(burn_rate);
+
+                let (sli, burn_rate) = sli_and_burn_rate(counts.calls, counts.slow, target.latency);
+                LATENCY_SLI.with_label_values(labels).set(sli);
+                LATENCY_BURN_RATE.with_label_values(labels).set(burn_rate);
+            }
+        }
+    }
+}
+
+fn current_minute() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() / 60)
+        .unwrap_or_default()
+}
+
+/// Measure calls against the objectives in the config
+pub fn apply_config(config: &config::Slo) {
+    TRACKER.lock().unwrap().apply_config(config);
+}
+
+/// Count a call to the RPC, which took `latency`, and failed with the code if
+/// it failed
+pub fn observe(rpc: &'static str, latency: Duration, failed_with: Option<Code>) {
+    TRACKER
+        .lock()
+        .unwrap()
+        .observe(rpc, current_minute(), latency, failed_with);
+}
+
+/// Set the SLI and burn rate gauges from the calls in each window
+pub fn update_gauges() {
+    TRACKER.lock().unwrap().update_gauges(current_minute());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sli_and_burn_rate() {
+        assert_eq!(sli_and_burn_rate(0, 0, 0.999), (1.0, 0.0));
+        assert_eq!(sli_and_burn_rate(1000, 0, 0.999), (1.0, 0.0));
+        let (sli, burn_rate) = sli_and_burn_rate(1000, 1, 0.999);
+        assert!((sli - 0.999).abs() < 1e-9);
+        assert!((burn_rate - 1.0).abs() < 1e-9);
+        let (_, burn_rate) = sli_and_burn_rate(100, 10, 0.99);
+        assert!((burn_rate - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut config = config::Slo::default();
+        config.windows_minutes = vec![5, 60];
+        config.rpcs.insert(
+            "GetBalance".into(),
+            config::SloTarget {
+                latency_threshold_ms: Some(100),
+                ..Default::default()
+            },
+        );
+        let mut tracker = Tracker::new(config);
+
+        let fast = Duration::from_millis(50);
+        let slow = Duration::from_millis(200);
+        tracker.observe("GetBalance", 1000, fast, None);
+        tracker.observe("GetBalance", 1000, slow, None);
+        tracker.observe("GetBalance", 1000, fast, Some(Code::Unavailable));
+        // Failures caused by the request don't count against availability
+        tracker.observe("GetBalance", 1000, fast, Some(Code::InvalidArgument));
+        // The default threshold is 500ms
+        tracker.observe("AddPayment", 1000, slow, None);
+        tracker.observe("GetBalance", 1010, slow, Some(Code::Internal));
+
+        assert_eq!(
+            tracker.counts("GetBalance", 1010, 5),
+            Counts {
+                calls: 1,
+                errors: 1,
+                slow: 1,
+            }
+        );
+        assert_eq!(
+            tracker.counts("GetBalance", 1010, 60),
+            Counts {
+                calls: 5,
+                errors: 2,
+                slow: 2,
+            }
+        );
+        assert_eq!(
+            tracker.counts("AddPayment", 1010, 60),
+            Counts {
+                calls: 1,
+                errors: 0,
+                slow: 0,
+            }
+        );
+        assert_eq!(tracker.counts("GetStats", 1010, 60), Counts::default());
+
+        // Buckets are reused once they're older than the longest window
+        tracker.observe("GetBalance", 1060, fast, None);
+        assert_eq!(tracker.counts("GetBalance", 1060, 60).calls, 2);
+
+        tracker.update_gauges(1060);
+        assert_eq!(
+            AVAILABILITY_SLI
+                .with_label_values(&["GetBalance", "60m"])
+                .get(),
+            0.5
+        );
+        assert_eq!(
+            LATENCY_BURN_RATE
+                .with_label_values(&["GetBalance", "5m"])
+                .get(),
+            0.0
+        );
+    }
+}
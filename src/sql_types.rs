@@ -1,6 +1,7 @@
-#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum, Deserialize)]
 #[PgType = "transaction_type"]
 #[DieselType = "Transaction_type"]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionType {
     #[db_rename = "debit"]
     Debit,
@@ -12,9 +13,20 @@ pub enum TransactionType {
     PromoDebit,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+impl TransactionType {
+    /// Every variant, for checking the database's enum at startup
+    pub const ALL: &'static [TransactionType] = &[
+        TransactionType::Debit,
+        TransactionType::Credit,
+        TransactionType::PromoCredit,
+        TransactionType::PromoDebit,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum, Deserialize)]
 #[PgType = "transaction_reason"]
 #[DieselType = "Transaction_reason"]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionReason {
     #[db_rename = "message_read"]
     MessageRead,
@@ -26,4 +38,242 @@ pub enum TransactionReason {
     CreditAdded,
     #[db_rename = "payout"]
     Payout,
+    #[db_rename = "referral_bonus"]
+    ReferralBonus,
+    #[db_rename = "promo_expired"]
+    PromoExpired,
+    #[db_rename = "escheated"]
+    Escheated,
+    #[db_rename = "message_declined"]
+    MessageDeclined,
+    #[db_rename = "tip"]
+    Tip,
+    #[db_rename = "internal_transfer"]
+    InternalTransfer,
+    #[db_rename = "tax_withheld"]
+    TaxWithheld,
+    #[db_rename = "promo_message_read"]
+    PromoMessageRead,
+    #[db_rename = "correction"]
+    Correction,
+    #[db_rename = "invoice_payment"]
+    InvoicePayment,
+}
+
+impl TransactionReason {
+    /// Every variant, for checking the database's enum at startup
+    pub const ALL: &'static [TransactionReason] = &[
+        TransactionReason::MessageRead,
+        TransactionReason::MessageUnread,
+        TransactionReason::MessageSent,
+        TransactionReason::CreditAdded,
+        TransactionReason::Payout,
+        TransactionReason::ReferralBonus,
+        TransactionReason::PromoExpired,
+        TransactionReason::Escheated,
+        TransactionReason::MessageDeclined,
+        TransactionReason::Tip,
+        TransactionReason::InternalTransfer,
+        TransactionReason::TaxWithheld,
+        TransactionReason::PromoMessageRead,
+        TransactionReason::Correction,
+        TransactionReason::InvoicePayment,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "payment_outcome"]
+#[DieselType = "Payment_outcome"]
+pub enum PaymentOutcome {
+    #[db_rename = "settled"]
+    Settled,
+    #[db_rename = "expired"]
+    Expired,
+    #[db_rename = "declined"]
+    Declined,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "refund_reason"]
+#[DieselType = "Refund_reason"]
+pub enum RefundReason {
+    #[db_rename = "expired"]
+    Expired,
+}
+
+/// What's refunded of a payment's send fee when it expires unread. The
+/// payment itself is always refunded.
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum, Deserialize)]
+#[PgType = "expiry_refund_policy"]
+#[DieselType = "Expiry_refund_policy"]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryRefundPolicy {
+    /// The fee is refunded too
+    #[db_rename = "full_refund"]
+    FullRefund,
+    /// The fee is kept
+    #[db_rename = "fee_retained"]
+    FeeRetained,
+    /// Part of the fee is kept, more the older the payment
+    #[db_rename = "sliding_scale"]
+    SlidingScale,
+}
+
+impl Default for ExpiryRefundPolicy {
+    fn default() -> Self {
+        ExpiryRefundPolicy::FeeRetained
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "auto_reload_state"]
+#[DieselType = "Auto_reload_state"]
+pub enum AutoReloadState {
+    #[db_rename = "pending"]
+    Pending,
+    #[db_rename = "succeeded"]
+    Succeeded,
+    #[db_rename = "failed"]
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "dormancy_action"]
+#[DieselType = "Dormancy_action"]
+pub enum DormancyAction {
+    #[db_rename = "warned"]
+    Warned,
+    #[db_rename = "promo_expired"]
+    PromoExpired,
+    #[db_rename = "escheated"]
+    Escheated,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "risk_flag_kind"]
+#[DieselType = "Risk_flag_kind"]
+pub enum RiskFlagKind {
+    #[db_rename = "credit_velocity"]
+    CreditVelocity,
+    #[db_rename = "payout_velocity"]
+    PayoutVelocity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "held_credit_state"]
+#[DieselType = "Held_credit_state"]
+pub enum HeldCreditState {
+    #[db_rename = "held"]
+    Held,
+    #[db_rename = "released"]
+    Released,
+    #[db_rename = "rejected"]
+    Rejected,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "payout_attempt_state"]
+#[DieselType = "Payout_attempt_state"]
+pub enum PayoutAttemptState {
+    #[db_rename = "pending"]
+    Pending,
+    #[db_rename = "confirmed"]
+    Confirmed,
+    #[db_rename = "expired"]
+    Expired,
+    #[db_rename = "failed"]
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "payout_hold_state"]
+#[DieselType = "Payout_hold_state"]
+pub enum PayoutHoldState {
+    #[db_rename = "held"]
+    Held,
+    #[db_rename = "paid"]
+    Paid,
+    #[db_rename = "released"]
+    Released,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "payment_hold_state"]
+#[DieselType = "Payment_hold_state"]
+pub enum PaymentHoldState {
+    #[db_rename = "held"]
+    Held,
+    #[db_rename = "captured"]
+    Captured,
+    #[db_rename = "released"]
+    Released,
+    #[db_rename = "expired"]
+    Expired,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "invoice_state"]
+#[DieselType = "Invoice_state"]
+pub enum InvoiceState {
+    #[db_rename = "open"]
+    Open,
+    #[db_rename = "paid"]
+    Paid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "pending_charge_state"]
+#[DieselType = "Pending_charge_state"]
+pub enum PendingChargeState {
+    #[db_rename = "pending"]
+    Pending,
+    #[db_rename = "succeeded"]
+    Succeeded,
+    #[db_rename = "failed"]
+    Failed,
+    #[db_rename = "expired"]
+    Expired,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "outbox_event_type"]
+#[DieselType = "Outbox_event_type"]
+pub enum OutboxEventType {
+    #[db_rename = "low_balance"]
+    LowBalance,
+    #[db_rename = "withdrawable_above_threshold"]
+    WithdrawableAboveThreshold,
+    #[db_rename = "payment_expiring"]
+    PaymentExpiring,
+    #[db_rename = "statement_ready"]
+    StatementReady,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "fee_plan"]
+#[DieselType = "Fee_plan"]
+pub enum FeePlan {
+    #[db_rename = "standard"]
+    Standard,
+    #[db_rename = "sender_pays_read_fee"]
+    SenderPaysReadFee,
+}
+
+/// A client's account tier, which sets the fees and limits they're on
+#[derive(Clone, Copy, Debug, PartialEq, DbEnum)]
+#[PgType = "account_tier"]
+#[DieselType = "Account_tier"]
+pub enum AccountTier {
+    #[db_rename = "standard"]
+    Standard,
+    #[db_rename = "pro"]
+    Pro,
+    #[db_rename = "enterprise"]
+    Enterprise,
+}
+
+impl Default for AccountTier {
+    fn default() -> Self {
+        AccountTier::Standard
+    }
 }
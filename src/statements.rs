@@ -0,0 +1,351 @@
+//! Monthly statements. The cron records each client's totals for the month
+//! once it's over, and sends a statement ready event with a signed download
+//! token. The document is rendered when it's downloaded, from the recorded
+//! totals and the month's ledger entries, in the format asked for.
+use chrono::NaiveDateTime;
+
+use crate::models;
+
+/// A ledger entry listed on a statement
+#[derive(Debug, Clone, QueryableByName)]
+pub struct StatementLine {
+    #[sql_type = "diesel::sql_types::Timestamp"]
+    pub created_at: NaiveDateTime,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub tx_type: String,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub tx_reason: String,
+    #[sql_type = "diesel::sql_types::Integer"]
+    pub amount_cents: i32,
+}
+
+// A client's ledger entries over the month starting at $2, oldest first
+pub static STATEMENT_LINES_QUERY: &str = r#"
+    SELECT
+        created_at,
+        tx_type::TEXT AS tx_type,
+        tx_reason::TEXT AS tx_reason,
+        amount_cents
+    FROM
+        transactions
+    WHERE
+        client_id = $1
+        AND created_at >= $2
+        AND created_at < $2 + interval '1 month'
+    ORDER BY
+        created_at,
+        id
+"#;
+
+/// Renders a statement into a downloadable document
+pub trait Renderer {
+    fn content_type(&self) -> &'static str;
+    fn render(&self, statement: &models::Statement, lines: &[StatementLine]) -> Vec<u8>;
+}
+
+fn dollars(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+pub struct CsvRenderer;
+
+impl Renderer for CsvRenderer {
+    fn content_type(&self) -> &'static str {
+        "text/csv"
+    }
+
+    fn render(&self, statement: &models::Statement, lines: &[StatementLine]) -> Vec<u8> {
+        let mut csv = String::from("date,type,reason,amount\n");
+        for line in lines {
+            csv.push_str(&format!(
+                "{},{},{},{}\n",
+                line.created_at.format("%Y-%m-%d %H:%M:%S"),
+                line.tx_type,
+                line.tx_reason,
+                dollars(i64::from(line.amount_cents))
+            ));
+        }
+        let net_cents = statement.gross_cents - statement.fee_cents;
+        for (label, cents) in &[
+            ("gross", statement.gross_cents),
+            ("fees", statement.fee_cents),
+            ("net", net_cents),
+            ("withheld", statement.withheld_cents),
+        ] {
+            csv.push_str(&format!(
+                "{},total,{},{}\n",
+                statement.period.format("%Y-%m"),
+                label,
+                dollars(*cents)
+            ));
+        }
+        csv.into_bytes()
+    }
+}
+
+/// Plain text PDFs, in a fixed width font so the columns line up
+pub struct PdfRenderer;
+
+// US letter, in points
+const PDF_PAGE_WIDTH: u32 = 612;
+const PDF_PAGE_HEIGHT: u32 = 792;
+const PDF_MARGIN: u32 = 50;
+const PDF_LINE_HEIGHT: u32 = 14;
+const PDF_LINES_PER_PAGE: usize = 50;
+
+fn pdf_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+impl PdfRenderer {
+    fn text_lines(statement: &models::Statement, lines: &[StatementLine]) -> Vec<String> {
+        let mut text = vec![
+            format!("Statement for {}", statement.period.format("%B %Y")),
+            format!("Client {}", statement.client_id),
+            String::new(),
+            format!("{:<20}{:>14}", "Payments received", statement.payment_count),
+            format!("{:<20}{:>14}", "Gross", dollars(statement.gross_cents)),
+            format!("{:<20}{:>14}", "Fees", dollars(statement.fee_cents)),
+            format!(
+                "{:<20}{:>14}",
+                "Net",
+                dollars(statement.gross_cents - statement.fee_cents)
+            ),
+            format!(
+                "{:<20}{:>14}",
+                "Tax withheld",
+                dollars(statement.withheld_cents)
+            ),
+            String::new(),
+            format!(
+                "{:<21}{:<14}{:<20}{:>12}",
+                "Date", "Type", "Reason", "Amount"
+            ),
+        ];
+        text.extend(lines.iter().map(|line| {
+            format!(
+                "{:<21}{:<14}{:<20}{:>12}",
+                line.created_at.format("%Y-%m-%d %H:%M:%S"),
+                line.tx_type,
+                line.tx_reason,
+                dollars(i64::from(line.amount_cents))
+            )
+        }));
+        text
+    }
+}
+
+impl Renderer for PdfRenderer {
+    fn content_type(&self) -> &'static str {
+        "application/pdf"
+    }
+
+    fn render(&self, statement: &models::Statement, lines: &[StatementLine]) -> Vec<u8> {
+        let text = Self::text_lines(statement, lines);
+        let pages: Vec<&[String]> = text.chunks(PDF_LINES_PER_PAGE).collect();
+
+        // Objects are numbered from 1: the catalog, the page tree, the font,
+        // then a page and its contents for each page
+        let page_id = |page: usize| 4 + 2 * page;
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                (0..pages.len())
+                    .map(|page| format!("{} 0 R", page_id(page)))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                pages.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_string(),
+        ];
+        for (page, page_text) in pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PDF_PAGE_WIDTH,
+                PDF_PAGE_HEIGHT,
+                page_id(page) + 1
+            ));
+            let mut content = format!(
+                "BT /F1 10 Tf {} TL {} {} Td",
+                PDF_LINE_HEIGHT,
+                PDF_MARGIN,
+                PDF_PAGE_HEIGHT - PDF_MARGIN
+            );
+            for line in page_text.iter() {
+                content.push_str(&format!(" ({}) Tj T*", pdf_escape(line)));
+            }
+            content.push_str(" ET");
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            ));
+        }
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+        }
+        let xref_offset = pdf.len();
+        pdf.push_str(&format!(
+            "xref\n0 {}\n0000000000 65535 f \n",
+            objects.len() + 1
+        ));
+        for offset in offsets {
+            pdf.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        pdf.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        ));
+        pdf.into_bytes()
+    }
+}
+
+/// The secret download tokens are signed with, from STATEMENT_SIGNING_SECRET
+pub fn signing_secret() -> Option<String> {
+    use dotenv::{dotenv, var};
+
+    dotenv().ok();
+    var("STATEMENT_SIGNING_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+fn token_mac(secret: &str, statement_id: i64, expires_at: i64) -> hmac::Hmac<sha2::Sha256> {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+    mac.input(format!("{}.{}", statement_id, expires_at).as_bytes());
+    mac
+}
+
+/// A token for downloading the statement until expires_at, a Unix timestamp.
+/// It has the form `<statement_id>.<expires_at>.<signature>`, where the
+/// signature is an HMAC-SHA256 of `<statement_id>.<expires_at>`.
+pub fn sign_download_token(secret: &str, statement_id: i64, expires_at: i64) -> String {
+    use data_encoding::HEXLOWER;
+    use hmac::Mac;
+
+    format!(
+        "{}.{}.{}",
+        statement_id,
+        expires_at,
+        HEXLOWER.encode(&token_mac(secret, statement_id, expires_at).result().code())
+    )
+}
+
+/// The statement a download token is for, if it's signed with the secret and
+/// hasn't expired by now
+pub fn verify_download_token(secret: &str, token: &str, now: i64) -> Option<i64> {
+    use data_encoding::HEXLOWER_PERMISSIVE;
+    use hmac::Mac;
+
+    if secret.is_empty() {
+        return None;
+    }
+    let mut parts = token.splitn(3, '.');
+    let statement_id = parts.next()?.parse::<i64>().ok()?;
+    let expires_at = parts.next()?.parse::<i64>().ok()?;
+    let signature = HEXLOWER_PERMISSIVE.decode(parts.next()?.as_bytes()).ok()?;
+    if expires_at < now {
+        return None;
+    }
+    token_mac(secret, statement_id, expires_at)
+        .verify(&signature)
+        .ok()
+        .map(|_| statement_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn statement() -> models::Statement {
+        models::Statement {
+            id: 1,
+            created_at: NaiveDate::from_ymd(2019, 11, 1).and_hms(0, 0, 0),
+            client_id: uuid::Uuid::nil().into(),
+            period: NaiveDate::from_ymd(2019, 10, 1),
+            gross_cents: 1000,
+            fee_cents: 70,
+            withheld_cents: 0,
+            payment_count: 2,
+            cron_run_id: uuid::Uuid::nil(),
+        }
+    }
+
+    fn lines() -> Vec<StatementLine> {
+        vec![StatementLine {
+            created_at: NaiveDate::from_ymd(2019, 10, 3).and_hms(12, 0, 0),
+            tx_type: "credit".into(),
+            tx_reason: "message_read".into(),
+            amount_cents: 465,
+        }]
+    }
+
+    #[test]
+    fn test_csv_renderer() {
+        let csv = String::from_utf8(CsvRenderer.render(&statement(), &lines())).unwrap();
+        assert_eq!(
+            csv,
+            "date,type,reason,amount\n\
+             2019-10-03 12:00:00,credit,message_read,4.65\n\
+             2019-10,total,gross,10.00\n\
+             2019-10,total,fees,0.70\n\
+             2019-10,total,net,9.30\n\
+             2019-10,total,withheld,0.00\n"
+        );
+    }
+
+    #[test]
+    fn test_pdf_renderer() {
+        let mut lines = lines();
+        lines[0].tx_reason = "(test)".into();
+        let many_lines: Vec<StatementLine> = std::iter::repeat(lines[0].clone()).take(60).collect();
+
+        for lines in &[lines, many_lines] {
+            let pdf = String::from_utf8(PdfRenderer.render(&statement(), lines)).unwrap();
+            assert!(pdf.starts_with("%PDF-1.4\n"));
+            assert!(pdf.ends_with("%%EOF\n"));
+            assert!(pdf.contains("\\(test\\)"));
+
+            // The cross reference table points at each object
+            let xref_offset: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+            assert!(pdf[xref_offset..].starts_with("xref\n"));
+            for (i, entry) in pdf[xref_offset..].lines().skip(3).enumerate() {
+                if !entry.ends_with(" n ") {
+                    break;
+                }
+                let offset: usize = entry[..10].parse().unwrap();
+                assert!(pdf[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_download_token() {
+        let token = sign_download_token("secret", 42, 1000);
+        assert_eq!(verify_download_token("secret", &token, 999), Some(42));
+        assert_eq!(verify_download_token("secret", &token, 1000), Some(42));
+        // Expired
+        assert_eq!(verify_download_token("secret", &token, 1001), None);
+        // Wrong secret
+        assert_eq!(verify_download_token("other", &token, 999), None);
+        assert_eq!(verify_download_token("", &token, 999), None);
+        // Tampered with
+        let tampered = token.replacen("42.", "43.", 1);
+        assert_eq!(verify_download_token("secret", &tampered, 999), None);
+        assert_eq!(verify_download_token("secret", "42.1000", 999), None);
+        assert_eq!(verify_download_token("secret", "garbage", 999), None);
+    }
+}
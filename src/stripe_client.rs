@@ -1,12 +1,173 @@
-use instrumented::instrument;
+use futures::{future, Future};
 use regex::Regex;
+use std::time::Instant;
 
 use crate::config;
+use crate::request_context::RequestContext;
 
 // Stripe fees
 static STRIPE_BASE_FEE: i64 = 30; // 30 cents
 static STRIPE_PCT_FEE: f64 = 0.029; // 2.9%
 
+// Stripe's limit on the length of a statement descriptor, including a
+// charge's prefix and suffix
+static MAX_STATEMENT_DESCRIPTOR_LENGTH: usize = 22;
+
+// Webhook events signed longer ago than this are rejected, to limit replays
+static WEBHOOK_TOLERANCE_SECONDS: i64 = 300;
+
+// Fields which are never logged
+static REDACTED_FIELDS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "number",
+    "cvc",
+    "exp_month",
+    "exp_year",
+    "fingerprint",
+    "last4",
+    "dynamic_last4",
+    "routing_number",
+    "account_number",
+];
+
+// Fields which are never persisted. These are the card details which could
+// identify or be combined with a card number, and credentials.
+static SCRUBBED_FIELDS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "number",
+    "cvc",
+    "exp_month",
+    "exp_year",
+    "fingerprint",
+    "last4",
+    "dynamic_last4",
+    "iin",
+    "name",
+    "routing_number",
+    "account_number",
+];
+
+lazy_static! {
+    // Matches API keys and card/bank tokens wherever they appear
+    static ref SECRET_VALUE: Regex = Regex::new(r"^(sk|rk|tok|btok|src)_").unwrap();
+}
+
+/// Returns a copy of an API request or response body which is safe to log,
+/// with secrets and card data replaced.
+pub fn redact(value: &serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    if REDACTED_FIELDS.contains(&key.as_str()) && !value.is_null() {
+                        (key.clone(), Value::String("[REDACTED]".into()))
+                    } else {
+                        (key.clone(), redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(redact).collect()),
+        Value::String(string) if SECRET_VALUE.is_match(string) => {
+            Value::String("[REDACTED]".into())
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Returns a copy of a token or charge which is safe to persist, with card
+/// details and any of the extra fields removed. Unlike `redact`, the fields
+/// are dropped entirely, and IDs are kept for looking things up in Stripe.
+pub fn scrub(value: &serde_json::Value, extra_fields: &[String]) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| {
+                    !SCRUBBED_FIELDS.contains(&key.as_str())
+                        && !extra_fields.iter().any(|field| field == *key)
+                })
+                .map(|(key, value)| (key.clone(), scrub(value, extra_fields)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| scrub(value, extra_fields))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Check a statement descriptor is one Stripe will accept for a payout
+pub fn is_valid_statement_descriptor(descriptor: &str) -> bool {
+    descriptor.len() <= MAX_STATEMENT_DESCRIPTOR_LENGTH
+        && descriptor
+            .chars()
+            .all(|c| c.is_ascii() && !c.is_ascii_control() && !"<>\"'".contains(c))
+}
+
+/// Check a charge's statement descriptor suffix is one Stripe will accept
+/// after `prefix`. Stripe joins them as "PREFIX* SUFFIX", which must fit in
+/// a statement descriptor and contain a letter, and reserves the `*`.
+pub fn is_valid_statement_descriptor_suffix(prefix: &str, suffix: &str) -> bool {
+    let descriptor = charge_statement_descriptor(prefix, suffix);
+    !suffix.trim().is_empty()
+        && !suffix.contains('*')
+        && is_valid_statement_descriptor(&descriptor)
+        && descriptor.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// How a charge with a statement descriptor suffix reads on a card statement
+pub fn charge_statement_descriptor(prefix: &str, suffix: &str) -> String {
+    format!("{}* {}", prefix, suffix).to_uppercase()
+}
+
+/// Check a webhook payload against its Stripe-Signature header, which has the
+/// form `t=<timestamp>,v1=<signature>[,v1=<signature>...]`. Each signature is
+/// an HMAC-SHA256 of `<timestamp>.<payload>`, keyed by the endpoint's secret.
+pub fn verify_webhook_signature(payload: &[u8], header: &str, secret: &str, now: i64) -> bool {
+    use data_encoding::HEXLOWER_PERMISSIVE;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let mut timestamp = None;
+    let mut signatures = vec![];
+    for part in header.split(',') {
+        let mut kv = part.trim().splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some("t"), Some(value)) => timestamp = value.parse::<i64>().ok(),
+            (Some("v1"), Some(value)) => {
+                if let Ok(signature) = HEXLOWER_PERMISSIVE.decode(value.as_bytes()) {
+                    signatures.push(signature)
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let timestamp = match timestamp {
+        Some(timestamp) if (now - timestamp).abs() <= WEBHOOK_TOLERANCE_SECONDS => timestamp,
+        _ => return false,
+    };
+
+    signatures.iter().any(|signature| {
+        let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+        mac.input(timestamp.to_string().as_bytes());
+        mac.input(b".");
+        mac.input(payload);
+        mac.verify(signature).is_ok()
+    })
+}
+
 /// The list of possible values for a RequestError's type.
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub enum ErrorType {
@@ -77,6 +238,131 @@ pub struct CreateTransfer {
     pub amount: i64,
     pub currency: stripe::Currency,
     pub destination: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub metadata: std::collections::HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CreatePayout {
+    pub amount: i64,
+    pub currency: stripe::Currency,
+    // Either "standard" or "instant"
+    pub method: String,
+    pub metadata: std::collections::HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_descriptor: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CreateRefund {
+    pub charge: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CreatedRange {
+    pub gte: i64,
+    pub lt: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ListEvents {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub created: CreatedRange,
+    pub limit: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starting_after: Option<String>,
+}
+
+/// A page of events, which are kept as JSON as they are for webhooks
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EventList {
+    pub data: Vec<serde_json::Value>,
+    pub has_more: bool,
+}
+
+/// The outcome of Stripe's risk evaluation of a charge
+#[derive(Debug, Default, PartialEq)]
+pub struct ChargeOutcome {
+    // i.e., "normal", "elevated" or "highest"
+    pub risk_level: Option<String>,
+    pub seller_message: Option<String>,
+}
+
+impl ChargeOutcome {
+    pub fn from_charge(charge: &serde_json::Value) -> Self {
+        let field = |name: &str| charge["outcome"][name].as_str().map(String::from);
+        ChargeOutcome {
+            risk_level: field("risk_level"),
+            seller_message: field("seller_message"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CreateCustomer {
+    pub source: String,
+    pub metadata: std::collections::HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CreateTokenCharge {
+    pub amount: i64,
+    pub currency: stripe::Currency,
+    pub source: String,
+    pub capture: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_descriptor_suffix: Option<String>,
+    pub metadata: std::collections::HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CreateCustomerCharge {
+    pub amount: i64,
+    pub currency: stripe::Currency,
+    pub customer: String,
+    pub capture: bool,
+    pub metadata: std::collections::HashMap<String, String>,
+}
+
+/// The outstanding verification requirements for a connect account. Stripe may
+/// restrict payouts to accounts with requirements currently due.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountRequirements {
+    pub currently_due: Vec<String>,
+    pub disabled_reason: Option<String>,
+    /// Stripe has verified the account holder's identity
+    pub identity_verified: bool,
+}
+
+impl AccountRequirements {
+    /// Extract the requirements from a serialized `stripe::Account`. Past due
+    /// requirements are included with those currently due.
+    pub fn from_account(account: &serde_json::Value) -> Self {
+        let requirements = &account["requirements"];
+
+        let mut currently_due: Vec<String> = ["past_due", "currently_due"]
+            .iter()
+            .filter_map(|key| requirements[*key].as_array())
+            .flatten()
+            .filter_map(|field| field.as_str().map(String::from))
+            .collect();
+        currently_due.sort();
+        currently_due.dedup();
+
+        Self {
+            currently_due,
+            disabled_reason: requirements["disabled_reason"].as_str().map(String::from),
+            identity_verified: account["individual"]["verification"]["status"] == "verified",
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.currently_due.is_empty() && self.disabled_reason.is_none()
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -116,6 +402,33 @@ pub enum StripeError {
     Error { err: String },
     #[fail(display = "json parser error: {}", err)]
     JsonParserError { err: String },
+    #[fail(display = "invalid webhook signature")]
+    InvalidWebhookSignature,
+}
+
+impl StripeError {
+    /// Stripe's code for the error, i.e., "balance_insufficient", or the type
+    /// of error when Stripe didn't give a code. Errors which didn't come from
+    /// Stripe's API have neither.
+    pub fn code(&self) -> Option<String> {
+        match self {
+            Self::RequestError { request_error, .. } => request_error
+                .code
+                .as_ref()
+                .and_then(|code| serde_json::to_value(code).ok())
+                .or_else(|| serde_json::to_value(&request_error.error_type).ok())
+                .and_then(|value| value.as_str().map(String::from)),
+            _ => None,
+        }
+    }
+
+    /// The bank's reason for declining, if it gave one
+    pub fn decline_code(&self) -> Option<String> {
+        match self {
+            Self::RequestError { request_error, .. } => request_error.decline_code.clone(),
+            _ => None,
+        }
+    }
 }
 
 impl From<serde_json::error::Error> for StripeError {
@@ -175,11 +488,52 @@ impl From<reqwest::Error> for StripeError {
     }
 }
 
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: stripe::RequestError,
+}
+
+/// Parse the response to an API call made without the library, as the library
+/// would: the object on success, or Stripe's error
+fn api_response<T: serde::de::DeserializeOwned>(
+    status: reqwest::StatusCode,
+    body: serde_json::Value,
+) -> Result<T, StripeError> {
+    if status.is_success() {
+        return Ok(serde_json::from_value(body)?);
+    }
+    let mut error = serde_json::from_value::<ErrorResponse>(body)?.error;
+    error.http_status = status.as_u16();
+    Err(stripe::Error::Stripe(error).into())
+}
+
+/// A Stripe API call, made once the future is first polled. It's composed into
+/// the future of the request making it, so no thread waits on Stripe.
+pub type StripeFuture<T> = Box<dyn Future<Item = T, Error = StripeError> + Send>;
+
+#[derive(Clone)]
 pub struct Stripe {
     client_secret: String,
     client: stripe::r#async::Client,
+    // For the calls made without the library, i.e., those which need an
+    // idempotency key
+    api_base: String,
     connect_client_id: String,
     redirect_uri: String,
+    log_api_calls: bool,
+    log_api_bodies: bool,
+    webhook_secret: Option<String>,
+    pub trigger_payouts: bool,
+    pub instant_payouts: bool,
+    pub persist_charges: bool,
+    scrub_fields: Vec<String>,
+    hold_risk_levels: Vec<String>,
+    pub hold_release_hours: u32,
+    pub pending_charge_expiry_hours: u32,
+    // Put before each charge's statement descriptor suffix
+    pub statement_descriptor_prefix: String,
+    // Added to the metadata of charges, customers, transfers and payouts
+    request_metadata: std::collections::HashMap<String, String>,
 }
 
 impl Stripe {
@@ -189,12 +543,128 @@ impl Stripe {
         dotenv().ok();
 
         let client_secret = var("STRIPE_API_SECRET").expect("Missing Stripe API secret key");
+        let config = config::get();
+
+        // STRIPE_API_BASE points API calls elsewhere, i.e., at stripe-mock for
+        // the end-to-end tests
+        let api_base = var("STRIPE_API_BASE").ok();
+        let client = match &api_base {
+            Some(api_base) => {
+                stripe::r#async::Client::from_url(api_base.as_str(), client_secret.clone())
+            }
+            None => stripe::r#async::Client::new(client_secret.clone()),
+        };
 
         Self {
             client_secret: client_secret.clone(),
-            client: stripe::r#async::Client::new(client_secret.clone()),
-            connect_client_id: config::CONFIG.stripe.connect_client_id.clone(),
-            redirect_uri: config::CONFIG.stripe.redirect_uri.clone(),
+            client,
+            api_base: api_base.unwrap_or_else(|| "https://api.stripe.com".into()),
+            connect_client_id: config.stripe.connect_client_id.clone(),
+            redirect_uri: config.stripe.redirect_uri.clone(),
+            log_api_calls: config.stripe.log_api_calls,
+            log_api_bodies: config.stripe.log_api_bodies,
+            webhook_secret: var("STRIPE_WEBHOOK_SECRET").ok(),
+            trigger_payouts: config.stripe.trigger_payouts,
+            instant_payouts: config.stripe.instant_payouts,
+            persist_charges: config.stripe.persist_charges,
+            scrub_fields: config.stripe.scrub_fields.clone(),
+            hold_risk_levels: config.stripe.hold_risk_levels.clone(),
+            hold_release_hours: config.stripe.hold_release_hours,
+            pending_charge_expiry_hours: config.stripe.pending_charge_expiry_hours,
+            statement_descriptor_prefix: config.stripe.statement_descriptor_prefix.clone(),
+            request_metadata: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Tag what's created in Stripe with the request that created it
+    pub fn with_context(self, context: &RequestContext) -> Self {
+        Self {
+            request_metadata: context.stripe_metadata(),
+            ..self
+        }
+    }
+
+    /// Metadata for a new Stripe object, with the request's context
+    fn metadata(&self, fields: Vec<(&str, String)>) -> std::collections::HashMap<String, String> {
+        let mut metadata = self.request_metadata.clone();
+        metadata.extend(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        metadata
+    }
+
+    /// Whether the API key is for live mode rather than test mode
+    pub fn livemode(&self) -> bool {
+        !(self.client_secret.starts_with("sk_test_") || self.client_secret.starts_with("rk_test_"))
+    }
+
+    /// Whether credits from a charge with this outcome are held
+    pub fn holds(&self, outcome: &ChargeOutcome) -> bool {
+        outcome.risk_level.as_ref().map_or(false, |risk_level| {
+            self.hold_risk_levels.contains(risk_level)
+        })
+    }
+
+    /// Scrub a token or charge for persisting, with the configured fields
+    pub fn scrub<T: serde::Serialize>(&self, value: &T) -> serde_json::Value {
+        scrub(
+            &serde_json::to_value(value).unwrap_or(serde_json::Value::Null),
+            &self.scrub_fields,
+        )
+    }
+
+    /// Log the method, path, latency and outcome of an API call. Request and
+    /// response bodies are only logged if enabled, and are always redacted.
+    /// The latency is counted towards the current RPC's Stripe time either way.
+    fn log_api_call<Req: serde::Serialize, Resp: serde::Serialize>(
+        &self,
+        method: &str,
+        path: &str,
+        request: &Req,
+        started: Instant,
+        result: &Result<Resp, StripeError>,
+    ) {
+        crate::timing::stripe_call(started.elapsed());
+
+        if !self.log_api_calls {
+            return;
+        }
+
+        let latency_ms = started.elapsed().as_millis();
+        match result {
+            Ok(_) => info!(
+                "stripe_api method={} path={} latency_ms={} outcome=ok",
+                method, path, latency_ms
+            ),
+            Err(err) => warn!(
+                "stripe_api method={} path={} latency_ms={} outcome=error error={:?}",
+                method,
+                path,
+                latency_ms,
+                err.to_string()
+            ),
+        }
+
+        if self.log_api_bodies {
+            let to_json = |body: serde_json::Result<serde_json::Value>| {
+                body.map(|body| redact(&body).to_string())
+                    .unwrap_or_else(|err| format!("<unserializable: {}>", err))
+            };
+            debug!(
+                "stripe_api method={} path={} request={} response={}",
+                method,
+                path,
+                to_json(serde_json::to_value(request)),
+                match result {
+                    Ok(response) => to_json(serde_json::to_value(response)),
+                    Err(StripeError::RequestError { request_error, .. }) => {
+                        to_json(serde_json::to_value(request_error))
+                    }
+                    Err(_) => "null".into(),
+                }
+            );
         }
     }
 
@@ -227,10 +697,33 @@ impl Stripe {
         .into()
     }
 
-    #[instrument(INFO)]
-    pub fn post_connect_code(&self, code: &str) -> Result<ConnectCredentials, StripeError> {
-        use futures::Future;
-        use tokio::executor::Executor;
+    /// Send an API call when the returned future is first polled, logging it
+    /// once it completes
+    fn call<T, Req, F>(
+        &self,
+        method: &'static str,
+        path: String,
+        request: Req,
+        call: F,
+    ) -> StripeFuture<T>
+    where
+        T: serde::Serialize + Send + 'static,
+        Req: serde::Serialize + Send + 'static,
+        F: Future<Item = T> + Send + 'static,
+        StripeError: From<F::Error>,
+    {
+        let stripe = self.clone();
+        Box::new(future::lazy(move || {
+            let started = Instant::now();
+            call.then(move |result| {
+                let result = result.map_err(StripeError::from);
+                stripe.log_api_call(method, &path, &request, started, &result);
+                result
+            })
+        }))
+    }
+
+    pub fn post_connect_code(&self, code: &str) -> StripeFuture<ConnectCredentials> {
         let client = reqwest::r#async::Client::new();
 
         let params = [
@@ -239,131 +732,346 @@ impl Stripe {
             ("grant_type", "authorization_code".into()),
         ];
 
-        let mut exec = tokio::executor::DefaultExecutor::current();
-
-        let (tx, rx) = futures::sync::oneshot::channel();
-        exec.spawn(Box::new(
+        self.call(
+            "POST",
+            "/oauth/token".into(),
+            serde_json::json!({ "grant_type": "authorization_code" }),
             client
                 .post("https://connect.stripe.com/oauth/token")
                 .form(&params)
                 .send()
-                .and_then(|mut resp| resp.json::<ConnectCredentials>())
-                .map(|credentials| credentials)
-                .then(move |r| tx.send(r).map_err(|_werr| error!("failure"))),
-        ))
-        .unwrap();
-        rx.wait().unwrap().map_err(StripeError::from)
+                .and_then(|mut resp| resp.json::<ConnectCredentials>()),
+        )
     }
 
-    #[instrument(INFO)]
-    pub fn get_login_link(&self, stripe_user_id: &str) -> Result<LoginLink, StripeError> {
-        use futures::Future;
-        use tokio::executor::Executor;
-
+    pub fn get_login_link(&self, stripe_user_id: &str) -> StripeFuture<LoginLink> {
         let path = format!("/accounts/{}/login_links", stripe_user_id);
 
-        let mut exec = tokio::executor::DefaultExecutor::current();
+        let login_link = CreateLoginLink {
+            redirect_url: self.redirect_uri.clone(),
+        };
 
-        let (tx, rx) = futures::sync::oneshot::channel();
-        exec.spawn(Box::new(
+        self.call(
+            "POST",
+            path.clone(),
+            login_link.clone(),
             self.client
-                .post_form::<LoginLink, CreateLoginLink>(
-                    &path,
-                    CreateLoginLink {
-                        redirect_url: self.redirect_uri.clone(),
-                    },
-                )
-                .then(move |r| tx.send(r))
-                .map_err(|err| error!("failure: {:?}", err)),
-        ))
-        .unwrap();
-        rx.wait().unwrap().map_err(StripeError::from)
+                .post_form::<LoginLink, CreateLoginLink>(&path, login_link),
+        )
     }
 
-    #[instrument(INFO)]
     pub fn charge(
         &self,
         token: &str,
         amount: i64,
+        currency: &str,
         client_id: &str,
         tx_id: i64,
-    ) -> Result<stripe::Charge, StripeError> {
-        use futures::Future;
-        use tokio::executor::Executor;
-
-        let token: stripe::Token = serde_json::from_str(token)?;
-        let mut params = stripe::CreateCharge::new();
-
-        params.amount = Some(amount);
-        params.source = Some(stripe::ChargeSourceParams::Token(token.id));
-        params.currency = Some(stripe::Currency::USD);
-        params.capture = Some(true);
-
-        let mut metadata = stripe::Metadata::new();
-        metadata.insert("client_id".into(), client_id.into());
-        metadata.insert("tx_id".into(), format!("{}", tx_id));
-        params.metadata = Some(metadata);
-
-        let mut exec = tokio::executor::DefaultExecutor::current();
-
-        let (tx, rx) = futures::sync::oneshot::channel();
-        exec.spawn(Box::new(
-            stripe::Charge::create(&self.client, params)
-                .then(move |r| tx.send(r))
-                .map_err(|err| error!("failure: {:?}", err)),
-        ))
-        .unwrap();
-        rx.wait().unwrap().map_err(StripeError::from)
+        statement_descriptor_suffix: Option<&str>,
+    ) -> StripeFuture<stripe::Charge> {
+        let parsed = serde_json::from_str::<stripe::Token>(token).and_then(|token| {
+            serde_json::from_value::<stripe::Currency>(serde_json::Value::String(
+                currency.to_lowercase(),
+            ))
+            .map(|currency| (token, currency))
+        });
+        let (token, currency) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => return Box::new(future::err(err.into())),
+        };
+        // The charge is posted as a form, like a customer's, as the library's
+        // CreateCharge doesn't have the statement descriptor suffix
+        let charge = CreateTokenCharge {
+            amount,
+            currency,
+            source: token.id.to_string(),
+            capture: true,
+            statement_descriptor_suffix: statement_descriptor_suffix.map(String::from),
+            metadata: self.metadata(vec![
+                ("client_id", client_id.into()),
+                ("tx_id", format!("{}", tx_id)),
+            ]),
+        };
+
+        self.call(
+            "POST",
+            "/charges".into(),
+            charge.clone(),
+            self.client
+                .post_form::<stripe::Charge, CreateTokenCharge>("/charges", charge),
+        )
+    }
+
+    /// Refund a charge in full
+    pub fn refund(&self, charge_id: &str) -> StripeFuture<stripe::Refund> {
+        let refund = CreateRefund {
+            charge: charge_id.into(),
+        };
+
+        self.call(
+            "POST",
+            "/refunds".into(),
+            refund.clone(),
+            self.client
+                .post_form::<stripe::Refund, CreateRefund>("/refunds", refund),
+        )
+    }
+
+    /// Create a customer with the card token as its default payment source, so
+    /// that it can be charged later without the client present.
+    pub fn create_customer(&self, token: &str, client_id: &str) -> StripeFuture<stripe::Customer> {
+        let token: stripe::Token = match serde_json::from_str(token) {
+            Ok(token) => token,
+            Err(err) => return Box::new(future::err(err.into())),
+        };
+
+        let metadata = self.metadata(vec![("client_id", client_id.into())]);
+
+        let customer = CreateCustomer {
+            source: token.id.to_string(),
+            metadata,
+        };
+
+        self.call(
+            "POST",
+            "/customers".into(),
+            customer.clone(),
+            self.client
+                .post_form::<stripe::Customer, CreateCustomer>("/customers", customer),
+        )
+    }
+
+    /// Charge a customer's default payment source.
+    pub fn charge_customer(
+        &self,
+        customer_id: &str,
+        amount: i64,
+        client_id: &str,
+        tx_id: i64,
+    ) -> StripeFuture<stripe::Charge> {
+        let metadata = self.metadata(vec![
+            ("client_id", client_id.into()),
+            ("tx_id", format!("{}", tx_id)),
+        ]);
+
+        let charge = CreateCustomerCharge {
+            amount,
+            currency: stripe::Currency::USD,
+            customer: customer_id.into(),
+            capture: true,
+            metadata,
+        };
+
+        self.call(
+            "POST",
+            "/charges".into(),
+            charge.clone(),
+            self.client
+                .post_form::<stripe::Charge, CreateCustomerCharge>("/charges", charge),
+        )
     }
 
-    #[instrument(INFO)]
+    /// Transfer to a connected account. Stripe makes the transfer at most once
+    /// for each idempotency key, and returns the transfer already made when
+    /// the key is reused, so a transfer which may or may not have been made
+    /// can be retried safely. The library can't send the key, so the call is
+    /// made directly.
     pub fn transfer(
         &self,
         amount: i32,
         stripe_user_id: &str,
-    ) -> Result<stripe::Transfer, StripeError> {
-        use futures::Future;
-        use tokio::executor::Executor;
+        description: Option<&str>,
+        idempotency_key: &str,
+    ) -> StripeFuture<stripe::Transfer> {
+        use reqwest::header::CONTENT_TYPE;
 
         let transfer = CreateTransfer {
             amount: i64::from(amount),
             destination: stripe_user_id.into(),
             currency: stripe::Currency::USD,
+            description: description.map(String::from),
+            metadata: self.metadata(vec![]),
+        };
+        let body = match serde_qs::to_string(&transfer) {
+            Ok(body) => body,
+            Err(err) => {
+                return Box::new(future::err(StripeError::Error {
+                    err: err.to_string(),
+                }))
+            }
         };
 
-        let mut exec = tokio::executor::DefaultExecutor::current();
+        self.call(
+            "POST",
+            "/transfer".into(),
+            transfer,
+            reqwest::r#async::Client::new()
+                .post(&format!(
+                    "{}/v1/transfers",
+                    self.api_base.trim_end_matches('/')
+                ))
+                .basic_auth(&self.client_secret, None::<&str>)
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header("Idempotency-Key", idempotency_key)
+                .body(body)
+                .send()
+                .map_err(StripeError::from)
+                .and_then(|mut resp| {
+                    let status = resp.status();
+                    resp.json::<serde_json::Value>()
+                        .map_err(StripeError::from)
+                        .and_then(move |body| api_response::<stripe::Transfer>(status, body))
+                }),
+        )
+    }
 
-        let (tx, rx) = futures::sync::oneshot::channel();
-        exec.spawn(Box::new(
-            self.client
-                .post_form::<stripe::Transfer, CreateTransfer>("/transfer", transfer)
-                .then(move |r| tx.send(r))
-                .map_err(|err| error!("failure: {:?}", err)),
-        ))
-        .unwrap();
-        rx.wait().unwrap().map_err(StripeError::from)
+    /// Pay out from a connected account's Stripe balance to its bank account
+    /// or debit card. The transfer the payout is funded by is recorded in the
+    /// payout's metadata.
+    pub fn payout(
+        &self,
+        amount: i32,
+        stripe_user_id: &str,
+        method: &str,
+        transfer_id: &str,
+        description: Option<&str>,
+        statement_descriptor: Option<&str>,
+    ) -> StripeFuture<stripe::Payout> {
+        let metadata = self.metadata(vec![("transfer_id", transfer_id.to_string())]);
+
+        let payout = CreatePayout {
+            amount: i64::from(amount),
+            currency: stripe::Currency::USD,
+            method: method.into(),
+            metadata,
+            description: description.map(String::from),
+            statement_descriptor: statement_descriptor.map(String::from),
+        };
+
+        // Payouts are made on behalf of the connected account
+        let client = self.client.with_headers(stripe::Headers {
+            stripe_account: Some(stripe_user_id.into()),
+            ..Default::default()
+        });
+
+        self.call(
+            "POST",
+            "/payouts".into(),
+            payout.clone(),
+            client.post_form::<stripe::Payout, CreatePayout>("/payouts", payout),
+        )
     }
 
-    #[instrument(INFO)]
-    pub fn get_account(&self, stripe_user_id: &str) -> Result<stripe::Account, StripeError> {
-        use futures::Future;
-        use std::str::FromStr;
-        use tokio::executor::Executor;
+    /// Verify a webhook's signature, and parse the event it carries
+    pub fn parse_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<serde_json::Value, StripeError> {
+        let secret = match &self.webhook_secret {
+            Some(secret) => secret,
+            None => {
+                return Err(StripeError::Error {
+                    err: "STRIPE_WEBHOOK_SECRET is not set".into(),
+                })
+            }
+        };
+
+        if !verify_webhook_signature(payload, signature, secret, chrono::Utc::now().timestamp()) {
+            return Err(StripeError::InvalidWebhookSignature);
+        }
+
+        Ok(serde_json::from_slice(payload)?)
+    }
+
+    /// List the events of a type (which may be a wildcard, i.e., "payout.*")
+    /// created in [created_gte, created_lt), for the platform or for a
+    /// connected account. Stripe keeps events for 30 days. All pages are
+    /// fetched, and the events are returned oldest first.
+    pub fn list_events(
+        &self,
+        stripe_account: Option<&str>,
+        event_type: &str,
+        created_gte: i64,
+        created_lt: i64,
+    ) -> StripeFuture<Vec<serde_json::Value>> {
+        let client = match stripe_account {
+            Some(stripe_account) => self.client.with_headers(stripe::Headers {
+                stripe_account: Some(stripe_account.into()),
+                ..Default::default()
+            }),
+            None => self.client.clone(),
+        };
+        let stripe = self.clone();
+        let params = ListEvents {
+            event_type: event_type.into(),
+            created: CreatedRange {
+                gte: created_gte,
+                lt: created_lt,
+            },
+            limit: 100,
+            starting_after: None,
+        };
+
+        Box::new(
+            future::loop_fn((params, vec![]), move |(params, mut events)| {
+                stripe
+                    .call(
+                        "GET",
+                        "/events".into(),
+                        params.clone(),
+                        client.get_query::<EventList, ListEvents>("/events", params.clone()),
+                    )
+                    .map(move |page| {
+                        events.extend(page.data);
+                        let starting_after = events
+                            .last()
+                            .and_then(|event| event["id"].as_str())
+                            .map(String::from);
+                        match starting_after {
+                            Some(starting_after) if page.has_more => future::Loop::Continue((
+                                ListEvents {
+                                    starting_after: Some(starting_after),
+                                    ..params
+                                },
+                                events,
+                            )),
+                            _ => future::Loop::Break(events),
+                        }
+                    })
+            })
+            .map(|mut events| {
+                // Stripe lists the newest first
+                events.reverse();
+                events
+            }),
+        )
+    }
 
-        let mut exec = tokio::executor::DefaultExecutor::current();
+    /// Fetch the platform's balance, the cheapest call which checks the API
+    /// key is valid
+    pub fn ping(&self) -> StripeFuture<serde_json::Value> {
+        self.call(
+            "GET",
+            "/balance".into(),
+            (),
+            self.client.get::<serde_json::Value>("/balance"),
+        )
+    }
+
+    pub fn get_account(&self, stripe_user_id: &str) -> StripeFuture<stripe::Account> {
+        use std::str::FromStr;
 
-        let (tx, rx) = futures::sync::oneshot::channel();
-        exec.spawn(Box::new(
+        self.call(
+            "GET",
+            format!("/accounts/{}", stripe_user_id),
+            (),
             stripe::Account::retrieve(
                 &self.client,
                 &stripe::AccountId::from_str(stripe_user_id).unwrap(),
                 &[],
-            )
-            .then(move |r| tx.send(r))
-            .map_err(|err| error!("failure: {:?}", err)),
-        ))
-        .unwrap();
-        rx.wait().unwrap().map_err(StripeError::from)
+            ),
+        )
     }
 }
 
@@ -371,50 +1079,127 @@ impl Stripe {
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
-    use futures::future;
+
+    #[test]
+    fn test_verify_webhook_signature() {
+        use data_encoding::HEXLOWER;
+        use hmac::{Hmac, Mac};
+        use sha2::Sha256;
+
+        let payload = br#"{"id":"evt_test","type":"payout.paid"}"#;
+        let secret = "whsec_test";
+        let now = 1_571_000_000;
+
+        let sign = |timestamp: i64, secret: &str| {
+            let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+            mac.input(format!("{}.", timestamp).as_bytes());
+            mac.input(payload);
+            HEXLOWER.encode(&mac.result().code())
+        };
+
+        let header = format!("t={},v1={}", now, sign(now, secret));
+        assert!(verify_webhook_signature(payload, &header, secret, now));
+        assert!(verify_webhook_signature(payload, &header, secret, now + 60));
+
+        // One of several signatures may match, during secret rotation
+        let header = format!(
+            "t={},v1={},v1={}",
+            now,
+            sign(now, "whsec_old"),
+            sign(now, secret)
+        );
+        assert!(verify_webhook_signature(payload, &header, secret, now));
+
+        // Wrong secret, tampered payload, stale timestamp, malformed headers
+        let header = format!("t={},v1={}", now, sign(now, "whsec_wrong"));
+        assert!(!verify_webhook_signature(payload, &header, secret, now));
+        let header = format!("t={},v1={}", now, sign(now, secret));
+        assert!(!verify_webhook_signature(b"{}", &header, secret, now));
+        assert!(!verify_webhook_signature(
+            payload,
+            &header,
+            secret,
+            now + 301
+        ));
+        assert!(!verify_webhook_signature(payload, "", secret, now));
+        assert!(!verify_webhook_signature(payload, "v1=abc", secret, now));
+    }
 
     #[test]
     fn test_stripe_charge() {
-        tokio::run(future::lazy(|| {
-            let stripe = Stripe::new();
-            let token = r#"
-            {
-                "id": "tok_visa",
-                "object": "token",
-                "card": {
-                    "id": "card_1EYyYcG27b2IeIO74TusmAci",
-                    "object": "card",
-                    "address_city": null,
-                    "address_country": null,
-                    "address_line1": null,
-                    "address_line1_check": null,
-                    "address_line2": null,
-                    "address_state": null,
-                    "address_zip": null,
-                    "address_zip_check": null,
-                    "brand": "Visa",
-                    "country": "US",
-                    "cvc_check": null,
-                    "dynamic_last4": null,
-                    "exp_month": 8,
-                    "exp_year": 2020,
-                    "fingerprint": "9vruG6eJZVIM6012",
-                    "funding": "credit",
-                    "last4": "4242",
-                    "metadata": {},
-                    "name": null,
-                    "tokenization_method": null
-                },
-                "client_ip": null,
-                "created": 1557594022,
-                "livemode": false,
-                "type": "card",
-                "used": false
-            }"#;
-            stripe.charge(&token, 1000, "client_id", 100).unwrap();
+        // Runs to completion on a single thread
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let stripe = Stripe::new();
+        let token = r#"
+        {
+            "id": "tok_visa",
+            "object": "token",
+            "card": {
+                "id": "card_1EYyYcG27b2IeIO74TusmAci",
+                "object": "card",
+                "address_city": null,
+                "address_country": null,
+                "address_line1": null,
+                "address_line1_check": null,
+                "address_line2": null,
+                "address_state": null,
+                "address_zip": null,
+                "address_zip_check": null,
+                "brand": "Visa",
+                "country": "US",
+                "cvc_check": null,
+                "dynamic_last4": null,
+                "exp_month": 8,
+                "exp_year": 2020,
+                "fingerprint": "9vruG6eJZVIM6012",
+                "funding": "credit",
+                "last4": "4242",
+                "metadata": {},
+                "name": null,
+                "tokenization_method": null
+            },
+            "client_ip": null,
+            "created": 1557594022,
+            "livemode": false,
+            "type": "card",
+            "used": false
+        }"#;
+        runtime
+            .block_on(stripe.charge(&token, 1000, "USD", "client_id", 100, Some("CREATORNAME")))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let request_error: RequestError = serde_json::from_value(serde_json::json!({
+            "type": "card_error",
+            "code": "card_declined",
+            "decline_code": "insufficient_funds",
+            "message": "Your card has insufficient funds."
+        }))
+        .unwrap();
+        let err = StripeError::RequestError {
+            err: "card declined".into(),
+            request_error,
+        };
+        assert_eq!(err.code(), Some("card_declined".into()));
+        assert_eq!(err.decline_code(), Some("insufficient_funds".into()));
+
+        // Without a code, the type of error
+        let err = StripeError::RequestError {
+            err: "too many requests".into(),
+            request_error: RequestError {
+                error_type: ErrorType::RateLimit,
+                ..RequestError::default()
+            },
+        };
+        assert_eq!(err.code(), Some("rate_limit_error".into()));
+        assert_eq!(err.decline_code(), None);
 
-            future::ok(())
-        }));
+        let err = StripeError::Error {
+            err: "connection reset".into(),
+        };
+        assert_eq!(err.code(), None);
     }
 
     #[test]
@@ -565,4 +1350,211 @@ mod tests {
 
         let account: stripe::Account = serde_json::from_str(account_json).unwrap();
     }
+
+    #[test]
+    fn test_redact() {
+        let credentials = serde_json::json!({
+            "access_token": "sk_test_abc123",
+            "livemode": false,
+            "refresh_token": "rt_abc123",
+            "token_type": "bearer",
+            "stripe_user_id": "acct_1EGSngG27test",
+            "scope": "express"
+        });
+        assert_eq!(
+            redact(&credentials),
+            serde_json::json!({
+                "access_token": "[REDACTED]",
+                "livemode": false,
+                "refresh_token": "[REDACTED]",
+                "token_type": "bearer",
+                "stripe_user_id": "acct_1EGSngG27test",
+                "scope": "express"
+            })
+        );
+
+        let charge = serde_json::json!({
+            "id": "ch_1EYyYcG27b2IeIO7",
+            "amount": 1000,
+            "source": {
+                "id": "card_1EYyYcG27b2IeIO74TusmAci",
+                "exp_month": 8,
+                "exp_year": 2020,
+                "fingerprint": "9vruG6eJZVIM6012",
+                "last4": "4242",
+                "name": null
+            },
+            "metadata": { "client_id": "client_id", "token": "tok_visa" }
+        });
+        assert_eq!(
+            redact(&charge),
+            serde_json::json!({
+                "id": "ch_1EYyYcG27b2IeIO7",
+                "amount": 1000,
+                "source": {
+                    "id": "card_1EYyYcG27b2IeIO74TusmAci",
+                    "exp_month": "[REDACTED]",
+                    "exp_year": "[REDACTED]",
+                    "fingerprint": "[REDACTED]",
+                    "last4": "[REDACTED]",
+                    "name": null
+                },
+                "metadata": { "client_id": "client_id", "token": "[REDACTED]" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_scrub() {
+        let token = serde_json::json!({
+            "id": "tok_1EYyYcG27b2IeIO7",
+            "card": {
+                "id": "card_1EYyYcG27b2IeIO74TusmAci",
+                "brand": "Visa",
+                "country": "US",
+                "exp_month": 8,
+                "exp_year": 2020,
+                "fingerprint": "9vruG6eJZVIM6012",
+                "last4": "4242",
+                "name": "Jane Doe",
+                "address_zip": "94107",
+                "metadata": {}
+            },
+            "client_ip": "127.0.0.1",
+            "type": "card"
+        });
+        assert_eq!(
+            scrub(
+                &token,
+                &["address_zip".to_string(), "client_ip".to_string()]
+            ),
+            serde_json::json!({
+                "id": "tok_1EYyYcG27b2IeIO7",
+                "card": {
+                    "id": "card_1EYyYcG27b2IeIO74TusmAci",
+                    "brand": "Visa",
+                    "country": "US",
+                    "metadata": {}
+                },
+                "type": "card"
+            })
+        );
+
+        let charges = serde_json::json!([{ "source": { "last4": "4242", "object": "card" } }]);
+        assert_eq!(
+            scrub(&charges, &[]),
+            serde_json::json!([{ "source": { "object": "card" } }])
+        );
+    }
+
+    #[test]
+    fn test_account_requirements() {
+        let account = serde_json::json!({
+            "id": "acct_1EGSngG27test",
+            "requirements": {
+                "current_deadline": null,
+                "currently_due": ["individual.id_number", "external_account"],
+                "disabled_reason": "requirements.past_due",
+                "eventually_due": ["individual.verification.document"],
+                "past_due": ["external_account"]
+            }
+        });
+
+        let requirements = AccountRequirements::from_account(&account);
+        assert_eq!(
+            requirements.currently_due,
+            vec!["external_account", "individual.id_number"]
+        );
+        assert_eq!(
+            requirements.disabled_reason,
+            Some("requirements.past_due".to_string())
+        );
+        assert!(!requirements.is_complete());
+        assert!(!requirements.identity_verified);
+
+        let account = serde_json::json!({
+            "id": "acct_1EGSngG27test",
+            "requirements": {
+                "current_deadline": null,
+                "currently_due": [],
+                "disabled_reason": null,
+                "eventually_due": [],
+                "past_due": []
+            },
+            "individual": {
+                "verification": { "status": "verified" }
+            }
+        });
+        let requirements = AccountRequirements::from_account(&account);
+        assert!(requirements.is_complete());
+        assert!(requirements.identity_verified);
+    }
+
+    #[test]
+    fn test_charge_outcome() {
+        let charge = serde_json::json!({
+            "id": "ch_1FZtest",
+            "outcome": {
+                "network_status": "approved_by_network",
+                "reason": null,
+                "risk_level": "elevated",
+                "risk_score": 70,
+                "seller_message": "Stripe evaluated this payment as having elevated risk.",
+                "type": "authorized"
+            }
+        });
+        assert_eq!(
+            ChargeOutcome::from_charge(&charge),
+            ChargeOutcome {
+                risk_level: Some("elevated".into()),
+                seller_message: Some(
+                    "Stripe evaluated this payment as having elevated risk.".into()
+                ),
+            }
+        );
+
+        let charge = serde_json::json!({ "id": "ch_1FZtest", "outcome": null });
+        assert_eq!(
+            ChargeOutcome::from_charge(&charge),
+            ChargeOutcome::default()
+        );
+    }
+
+    #[test]
+    fn test_is_valid_statement_descriptor() {
+        assert!(is_valid_statement_descriptor("UMPYRE PAYOUT"));
+        assert!(is_valid_statement_descriptor("0123456789012345678901"));
+        assert!(!is_valid_statement_descriptor("01234567890123456789012"));
+        assert!(!is_valid_statement_descriptor("<UMPYRE>"));
+        assert!(!is_valid_statement_descriptor("UMPYRE'S"));
+        assert!(!is_valid_statement_descriptor("UMPYRÉ"));
+    }
+
+    #[test]
+    fn test_is_valid_statement_descriptor_suffix() {
+        assert_eq!(
+            charge_statement_descriptor("UMPYRE", "CreatorName"),
+            "UMPYRE* CREATORNAME"
+        );
+        assert!(is_valid_statement_descriptor_suffix(
+            "UMPYRE",
+            "CREATORNAME"
+        ));
+        assert!(is_valid_statement_descriptor_suffix(
+            "UMPYRE",
+            "01234567890123"
+        ));
+        // With the prefix, "* " and suffix, too long
+        assert!(!is_valid_statement_descriptor_suffix(
+            "UMPYRE",
+            "012345678901234"
+        ));
+        assert!(!is_valid_statement_descriptor_suffix("UMPYRE", ""));
+        assert!(!is_valid_statement_descriptor_suffix("UMPYRE", "  "));
+        assert!(!is_valid_statement_descriptor_suffix("UMPYRE", "A*B"));
+        assert!(!is_valid_statement_descriptor_suffix("UMPYRE", "<CREATOR>"));
+        // The descriptor needs a letter somewhere
+        assert!(is_valid_statement_descriptor_suffix("UMPYRE", "1234"));
+        assert!(!is_valid_statement_descriptor_suffix("1", "1234"));
+    }
 }
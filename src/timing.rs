@@ -0,0 +1,256 @@
+//! Where each RPC's time goes. The time spent in queries and Stripe calls is
+//! added up for the thread handling the RPC, and recorded against the RPC when
+//! it finishes. RPCs which wait on Stripe are handled by a future, which may
+//! be polled by a different thread each time, so their timings are set on the
+//! thread only while it's being polled.
+use futures::{Async, Future, Poll};
+use instrumented::{prometheus, register};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+const RPC_SECONDS_BUCKETS: &[f64; 14] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+const RPC_QUERIES_BUCKETS: &[f64; 10] =
+    &[1.0, 2.0, 5.0, 10.0, 20.0, 35.0, 50.0, 100.0, 200.0, 500.0];
+
+fn make_histogram_vec(name: &str, description: &str, buckets: &[f64]) -> prometheus::HistogramVec {
+    let histogram_opts =
+        prometheus::HistogramOpts::new(name, description).buckets(buckets.to_vec());
+    let histogram = prometheus::HistogramVec::new(histogram_opts, &["rpc"]).unwrap();
+
+    register(Box::new(histogram.clone())).unwrap();
+
+    histogram
+}
+
+lazy_static! {
+    static ref RPC_TOTAL_SECONDS: prometheus::HistogramVec = make_histogram_vec(
+        "rpc_total_seconds",
+        "Time handling each RPC",
+        RPC_SECONDS_BUCKETS
+    );
+    static ref RPC_DB_SECONDS: prometheus::HistogramVec = make_histogram_vec(
+        "rpc_db_seconds",
+        "Time each RPC spent in database queries",
+        RPC_SECONDS_BUCKETS
+    );
+    static ref RPC_STRIPE_SECONDS: prometheus::HistogramVec = make_histogram_vec(
+        "rpc_stripe_seconds",
+        "Time each RPC spent in Stripe API calls",
+        RPC_SECONDS_BUCKETS
+    );
+    static ref RPC_DB_QUERIES: prometheus::HistogramVec = make_histogram_vec(
+        "rpc_db_queries",
+        "Database statements executed by each RPC, including BEGIN and COMMIT",
+        RPC_QUERIES_BUCKETS
+    );
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Timings {
+    db: Duration,
+    stripe: Duration,
+    queries: u32,
+}
+
+thread_local! {
+    // Only set while the thread is handling an RPC
+    static TIMINGS: Cell<Option<Timings>> = Cell::new(None);
+}
+
+fn record<F: FnOnce(&mut Timings)>(update: F) {
+    TIMINGS.with(|cell| {
+        if let Some(mut timings) = cell.get() {
+            update(&mut timings);
+            cell.set(Some(timings));
+        }
+    });
+}
+
+/// Time a database statement, against the RPC being handled if any
+pub fn query<T, F: FnOnce() -> T>(f: F) -> T {
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed();
+    record(|timings| {
+        timings.db += elapsed;
+        timings.queries += 1;
+    });
+    result
+}
+
+/// Add the time taken by a Stripe API call to the RPC being handled, if any
+pub fn stripe_call(elapsed: Duration) {
+    record(|timings| timings.stripe += elapsed);
+}
+
+/// Records the time an RPC took, and how much of it went to the database and
+/// to Stripe, when dropped.
+pub struct RequestTimer {
+    rpc: &'static str,
+    started: Instant,
+    // Restored when the timer is dropped, in case RPCs are ever nested
+    outer: Option<Timings>,
+}
+
+impl RequestTimer {
+    pub fn start(rpc: &'static str) -> Self {
+        Self {
+            rpc,
+            started: Instant::now(),
+            outer: TIMINGS.with(|cell| cell.replace(Some(Timings::default()))),
+        }
+    }
+}
+
+fn observe(rpc: &str, started: Instant, timings: Timings) {
+    let labels = &[rpc];
+
+    RPC_TOTAL_SECONDS
+        .with_label_values(labels)
+        .observe(started.elapsed().as_secs_f64());
+    RPC_DB_SECONDS
+        .with_label_values(labels)
+        .observe(timings.db.as_secs_f64());
+    RPC_STRIPE_SECONDS
+        .with_label_values(labels)
+        .observe(timings.stripe.as_secs_f64());
+    RPC_DB_QUERIES
+        .with_label_values(labels)
+        .observe(f64::from(timings.queries));
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        let timings = TIMINGS
+            .with(|cell| cell.replace(self.outer))
+            .unwrap_or_default();
+        observe(self.rpc, self.started, timings);
+    }
+}
+
+/// Records the time an RPC took, like `RequestTimer`, for an RPC handled by a
+/// future. It's recorded when the future completes.
+pub struct Timed<F> {
+    rpc: &'static str,
+    started: Instant,
+    timings: Timings,
+    inner: F,
+}
+
+/// Time the RPC handled by `inner`, which should do all of the handling when
+/// polled (i.e., with `future::lazy`)
+pub fn timed<F: Future>(rpc: &'static str, inner: F) -> Timed<F> {
+    Timed {
+        rpc,
+        started: Instant::now(),
+        timings: Timings::default(),
+        inner,
+    }
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        let outer = TIMINGS.with(|cell| cell.replace(Some(self.timings)));
+        let result = self.inner.poll();
+        self.timings = TIMINGS.with(|cell| cell.replace(outer)).unwrap_or_default();
+
+        match result {
+            Ok(Async::NotReady) => (),
+            _ => observe(self.rpc, self.started, self.timings),
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_timer() {
+        // Nothing is recorded outside of an RPC
+        query(|| ());
+        assert!(TIMINGS.with(|cell| cell.get()).is_none());
+
+        {
+            let _timer = RequestTimer::start("TestRequestTimer");
+            query(|| ());
+            query(|| ());
+            stripe_call(Duration::from_millis(5));
+
+            let timings = TIMINGS.with(|cell| cell.get()).unwrap();
+            assert_eq!(timings.queries, 2);
+            assert_eq!(timings.stripe, Duration::from_millis(5));
+        }
+
+        assert!(TIMINGS.with(|cell| cell.get()).is_none());
+        let labels = &["TestRequestTimer"];
+        assert_eq!(
+            RPC_TOTAL_SECONDS
+                .with_label_values(labels)
+                .get_sample_count(),
+            1
+        );
+        assert_eq!(
+            RPC_DB_QUERIES.with_label_values(labels).get_sample_sum(),
+            2.0
+        );
+        assert_eq!(
+            RPC_STRIPE_SECONDS
+                .with_label_values(labels)
+                .get_sample_sum(),
+            0.005
+        );
+    }
+
+    #[test]
+    fn test_timed() {
+        use futures::future;
+
+        let mut polls = 0;
+        let mut future = timed(
+            "TestTimed",
+            future::poll_fn(move || {
+                query(|| ());
+                polls += 1;
+                if polls < 2 {
+                    Ok(Async::NotReady)
+                } else {
+                    stripe_call(Duration::from_millis(5));
+                    Ok::<_, ()>(Async::Ready(polls))
+                }
+            }),
+        );
+
+        // Timings are only set while the future's polled, and add up across
+        // polls
+        assert_eq!(future.poll(), Ok(Async::NotReady));
+        assert!(TIMINGS.with(|cell| cell.get()).is_none());
+        assert_eq!(future.poll(), Ok(Async::Ready(2)));
+        assert!(TIMINGS.with(|cell| cell.get()).is_none());
+
+        let labels = &["TestTimed"];
+        assert_eq!(
+            RPC_TOTAL_SECONDS
+                .with_label_values(labels)
+                .get_sample_count(),
+            1
+        );
+        assert_eq!(
+            RPC_DB_QUERIES.with_label_values(labels).get_sample_sum(),
+            2.0
+        );
+        assert_eq!(
+            RPC_STRIPE_SECONDS
+                .with_label_values(labels)
+                .get_sample_sum(),
+            0.005
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! The format IDs are returned in. Requests accept client IDs and other UUIDs
+//! in either the simple (`936da01f9abd4d9d80c702af85c822a8`) or hyphenated
+//! (`936da01f-9abd-4d9d-80c7-02af85c822a8`) format, and every ID in a
+//! response is in the one set in `service.uuid_format`, so that callers can
+//! compare them as strings.
+//!
+//! `ClientId`s display in the configured format. Anything hashed, such as the
+//! ledger chain, formats IDs itself, so changing the format never changes a
+//! hash.
+use std::sync::atomic::{AtomicBool, Ordering};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UuidFormat {
+    Simple,
+    Hyphenated,
+}
+
+impl Default for UuidFormat {
+    fn default() -> Self {
+        UuidFormat::Simple
+    }
+}
+
+impl UuidFormat {
+    pub fn format(self, uuid: &Uuid) -> String {
+        match self {
+            UuidFormat::Simple => uuid.to_simple().to_string(),
+            UuidFormat::Hyphenated => uuid.to_hyphenated().to_string(),
+        }
+    }
+
+    /// The ID in this format, if it's a UUID in either. Anything else is
+    /// returned as it is.
+    pub fn canonicalize(self, id: &str) -> String {
+        match Uuid::parse_str(id) {
+            Ok(uuid) => self.format(&uuid),
+            Err(_) => id.into(),
+        }
+    }
+}
+
+static HYPHENATED: AtomicBool = AtomicBool::new(false);
+
+/// Return IDs in the configured format
+pub fn apply_config(format: UuidFormat) {
+    HYPHENATED.store(format == UuidFormat::Hyphenated, Ordering::Relaxed);
+}
+
+pub fn current() -> UuidFormat {
+    if HYPHENATED.load(Ordering::Relaxed) {
+        UuidFormat::Hyphenated
+    } else {
+        UuidFormat::Simple
+    }
+}
+
+/// The UUID in the configured format
+pub fn format(uuid: &Uuid) -> String {
+    current().format(uuid)
+}
+
+/// The ID in the configured format, if it's a UUID
+pub fn canonicalize(id: &str) -> String {
+    current().canonicalize(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let uuid = Uuid::parse_str("936da01f-9abd-4d9d-80c7-02af85c822a8").unwrap();
+        assert_eq!(
+            UuidFormat::Simple.format(&uuid),
+            "936da01f9abd4d9d80c702af85c822a8"
+        );
+        assert_eq!(
+            UuidFormat::Hyphenated.format(&uuid),
+            "936da01f-9abd-4d9d-80c7-02af85c822a8"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        for id in &[
+            "936da01f9abd4d9d80c702af85c822a8",
+            "936da01f-9abd-4d9d-80c7-02af85c822a8",
+            "936DA01F-9ABD-4D9D-80C7-02AF85C822A8",
+        ] {
+            assert_eq!(
+                UuidFormat::Simple.canonicalize(id),
+                "936da01f9abd4d9d80c702af85c822a8"
+            );
+            assert_eq!(
+                UuidFormat::Hyphenated.canonicalize(id),
+                "936da01f-9abd-4d9d-80c7-02af85c822a8"
+            );
+        }
+        // Not an ID, i.e., an empty optional field
+        assert_eq!(UuidFormat::Hyphenated.canonicalize(""), "");
+        assert_eq!(UuidFormat::Simple.canonicalize("nope"), "nope");
+    }
+}